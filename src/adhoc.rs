@@ -0,0 +1,176 @@
+//! Ad-hoc commands run concurrently across a group of hosts, in the spirit of
+//! `ansible <group> -m shell -a <cmd>`, with one collapsible result per host.
+
+use crate::config::Host;
+use crate::ssh::session::Captured;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostRun {
+    Running,
+    /// The command ran; `code` is `None` if it was cut off.
+    Done {
+        code: Option<i32>,
+        duration: Duration,
+        output: String,
+    },
+    /// The command could not be run, e.g. the host was unreachable.
+    Failed { error: String, duration: Duration },
+}
+
+impl HostRun {
+    pub fn from_captured(captured: Captured, duration: Duration) -> Self {
+        let mut output = String::from_utf8_lossy(&captured.stdout).to_string();
+        if !captured.stderr.is_empty() {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str("--- stderr ---\n");
+            output.push_str(&String::from_utf8_lossy(&captured.stderr));
+        }
+        HostRun::Done {
+            code: captured.code,
+            duration,
+            output,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        matches!(self, HostRun::Done { code: Some(0), .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HostResult {
+    pub host: String,
+    pub run: HostRun,
+    pub expanded: bool,
+}
+
+/// One command fanned out over the hosts matching `target`.
+pub struct AdhocRun {
+    /// Identifies the run, so results from a closed one are ignored.
+    pub id: u64,
+    pub target: String,
+    pub command: String,
+    pub results: Vec<HostResult>,
+    pub cursor: usize,
+}
+
+impl AdhocRun {
+    pub fn new(id: u64, target: &str, command: &str, hosts: Vec<String>) -> Self {
+        Self {
+            id,
+            target: target.to_string(),
+            command: command.to_string(),
+            results: hosts
+                .into_iter()
+                .map(|host| HostResult {
+                    host,
+                    run: HostRun::Running,
+                    expanded: false,
+                })
+                .collect(),
+            cursor: 0,
+        }
+    }
+
+    pub fn finish(&mut self, host: &str, run: HostRun) {
+        if let Some(result) = self.results.iter_mut().find(|r| r.host == host) {
+            // Failures open up on their own since they are what needs reading
+            result.expanded = !run.succeeded();
+            result.run = run;
+        }
+    }
+
+    /// (succeeded, failed, still running)
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let running = self.results.iter().filter(|r| r.run == HostRun::Running).count();
+        let ok = self.results.iter().filter(|r| r.run.succeeded()).count();
+        (ok, self.results.len() - ok - running, running)
+    }
+
+    pub fn toggle(&mut self) {
+        if let Some(result) = self.results.get_mut(self.cursor) {
+            result.expanded = !result.expanded;
+        }
+    }
+
+    pub fn set_all_expanded(&mut self, expanded: bool) {
+        for result in &mut self.results {
+            result.expanded = expanded;
+        }
+    }
+}
+
+/// Hosts whose inventory group or address matches the glob; `all` is every host.
+pub fn select_hosts(hosts: &[Host], target: &str) -> Vec<String> {
+    let pattern = if target == "all" { "*" } else { target };
+    hosts
+        .iter()
+        .filter(|h| h.matches(pattern))
+        .map(|h| h.address.clone())
+        .collect()
+}
+
+/// Whether `target` is `all` or a glob, which may reach more hosts than
+/// meant, so running a command on them is confirmed first.
+pub fn is_wide(target: &str) -> bool {
+    target == "all" || target.contains(['*', '?', '['])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(address: &str, group: &str) -> Host {
+        Host {
+            address: address.to_string(),
+            group: group.to_string(),
+            parents: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_select_hosts() {
+        let hosts = vec![host("web01", "web"), host("web02", "web"), host("db01", "db")];
+        assert_eq!(select_hosts(&hosts, "web"), ["web01", "web02"]);
+        assert_eq!(select_hosts(&hosts, "db*"), ["db01"]);
+        assert!(is_wide("all") && is_wide("web*") && is_wide("db0[12]"));
+        assert!(!is_wide("web") && !is_wide("db01"));
+        assert_eq!(select_hosts(&hosts, "all").len(), 3);
+        assert!(select_hosts(&hosts, "cache").is_empty());
+
+        // Through [prod:children]
+        let mut hosts = hosts;
+        hosts[2].parents = vec!["prod".to_string()];
+        assert_eq!(select_hosts(&hosts, "prod"), ["db01"]);
+    }
+
+    #[test]
+    fn test_results() {
+        let mut run = AdhocRun::new(1, "web", "uptime", vec!["web01".to_string(), "web02".to_string()]);
+        assert_eq!(run.counts(), (0, 0, 2));
+
+        let ok = Captured {
+            code: Some(0),
+            stdout: b"up 3 days\n".to_vec(),
+            ..Default::default()
+        };
+        run.finish("web01", HostRun::from_captured(ok, Duration::from_millis(120)));
+        let bad = Captured {
+            code: Some(1),
+            stderr: b"nope\n".to_vec(),
+            ..Default::default()
+        };
+        run.finish("web02", HostRun::from_captured(bad, Duration::from_millis(80)));
+
+        assert_eq!(run.counts(), (1, 1, 0));
+        assert!(!run.results[0].expanded);
+        assert!(run.results[1].expanded);
+        match &run.results[1].run {
+            HostRun::Done { output, .. } => assert_eq!(output, "--- stderr ---\nnope\n"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Agent mode: a shared-connection agent. `system-controller agent` keeps
+//! running, refreshing on its own and holding the SSH connections, and
+//! listens on a Unix socket. Launches with `--agent` send every remote
+//! command through it, so any number of TUIs (and `status`, `check` and
+//! `run`) share one set of connections, and start with the agent's
+//! timeline. The grid is not shared: each client still refreshes it, over
+//! the agent's connections, and runs its own actions.
+//!
+//! A client connects once per command and sends one JSON request line;
+//! the agent answers with JSON lines:
+//!
+//! - `{"request":"exec","host":..,"user":..,"command":..}` → `{"done":{"code":0,"stdout":[..],..}}`
+//! - `{"request":"stream","host":..,"user":..,"command":..}` → `{"chunk":[..]}`... then `"end"`
+//! - `{"request":"history"}` → `{"history":[{"at":..,"kind":"change","text":".."}]}`
+//!
+//! Any of them can instead be answered with `{"error":".."}`.
+
+use crate::config::SshOptions;
+use crate::scratch;
+use crate::ssh::session::{Captured, SessionManager, SessionPool};
+use crate::timeline::{self, Timeline};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+/// Socket file in the private runtime directory, unless `--agent-socket`.
+const SOCKET_NAME: &str = "agent.sock";
+
+/// Chunks of a stream held for a client that reads slower than the command
+/// writes, before the command is made to wait.
+const STREAM_BUFFER: usize = 16;
+
+static SOCKET: OnceLock<PathBuf> = OnceLock::new();
+
+/// The agent's timeline as of its last refresh.
+static HISTORY: Mutex<Vec<HistoryEntry>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "lowercase")]
+enum Request {
+    Exec {
+        host: String,
+        user: Option<String>,
+        command: String,
+    },
+    Stream {
+        host: String,
+        user: Option<String>,
+        command: String,
+    },
+    History,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Reply {
+    Done(Captured),
+    /// Raw output of a stream, as it came.
+    Chunk(Vec<u8>),
+    End,
+    History(Vec<HistoryEntry>),
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct HistoryEntry {
+    at: u64,
+    kind: String,
+    text: String,
+}
+
+/// Where the agent listens: `path`, or the runtime directory.
+pub fn socket_path(path: Option<&str>) -> Result<PathBuf> {
+    match path {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => scratch::runtime_path(SOCKET_NAME),
+    }
+}
+
+/// Send this process's remote commands through the agent at `socket`,
+/// after checking that one answers there.
+pub async fn connect(socket: PathBuf) -> Result<()> {
+    UnixStream::connect(&socket).await.with_context(|| {
+        format!(
+            "No agent listening on {} (start one with `system-controller agent`)",
+            socket.display()
+        )
+    })?;
+    log::info!("Running commands through the agent on {}", socket.display());
+    let _ = SOCKET.set(socket);
+    Ok(())
+}
+
+/// The agent to go through, if this process is its client.
+pub fn socket() -> Option<&'static Path> {
+    SOCKET.get().map(PathBuf::as_path)
+}
+
+/// Listen on `socket` in the background, running commands over the
+/// connections in `pool`. A socket left behind by an agent that is gone is
+/// replaced; a live one is an error.
+pub async fn listen(socket: &Path, pool: SessionPool, options: SshOptions) -> Result<()> {
+    if socket.exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            anyhow::bail!("An agent is already listening on {}", socket.display());
+        }
+        std::fs::remove_file(socket).with_context(|| format!("Failed to remove stale socket {}", socket.display()))?;
+    }
+    let listener = bind_private(socket).with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    let uid = std::fs::metadata(socket)?.uid();
+    log::info!("Agent listening on {}", socket.display());
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    log::warn!("Agent accept failed: {}", e);
+                    continue;
+                }
+            };
+            // Only our own user, whatever the socket's permissions turn out to be
+            match stream.peer_cred() {
+                Ok(cred) if cred.uid() == uid => {}
+                Ok(cred) => {
+                    log::warn!("Agent refused a client of uid {}", cred.uid());
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Agent refused a client it can't identify: {}", e);
+                    continue;
+                }
+            }
+            let (pool, options) = (pool.clone(), options.clone());
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, pool, options).await {
+                    log::debug!("Agent client went away: {:#}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Bind `socket` in a fresh directory only we can enter, restrict it, then
+/// move it into place, so nobody can connect before it is restricted.
+fn bind_private(socket: &Path) -> Result<UnixListener> {
+    let staging = socket.with_file_name(format!(".agent.{}", std::process::id()));
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let bound = staging.join(SOCKET_NAME);
+    let result = UnixListener::bind(&bound)
+        .and_then(|listener| {
+            std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&bound, socket)?;
+            Ok(listener)
+        })
+        .map_err(anyhow::Error::from);
+    let _ = std::fs::remove_file(&bound);
+    let _ = std::fs::remove_dir(&staging);
+    result
+}
+
+async fn handle(stream: UnixStream, pool: SessionPool, options: SshOptions) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let line = lines.next_line().await?.context("No request")?;
+    let request: Request = serde_json::from_str(&line).context("Invalid request")?;
+    log::debug!("Agent request: {:?}", request);
+    match request {
+        Request::Exec { host, user, command } => {
+            let mut session_mgr = SessionManager::pooled(&pool, user, options);
+            let reply = match session_mgr.exec(&host, &command).await {
+                Ok(captured) => Reply::Done(captured),
+                Err(e) => Reply::Error(format!("{:#}", e)),
+            };
+            send(&mut write, &reply).await?;
+        }
+        Request::Stream { host, user, command } => {
+            // Output goes out as it comes, and the command waits for a slow
+            // client; a client that hangs up stops the command
+            let (tx, mut rx) = mpsc::channel(STREAM_BUFFER);
+            let mut session_mgr = SessionManager::pooled(&pool, user, options);
+            let run = session_mgr.stream_chunks(&host, &command, tx);
+            let forward = async {
+                while let Some(chunk) = rx.recv().await {
+                    if send(&mut write, &Reply::Chunk(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+                rx.close();
+            };
+            // The sender goes with `run`, which ends the forwarding
+            let (result, _) = tokio::join!(run, forward);
+            let reply = match result {
+                Ok(()) => Reply::End,
+                Err(e) => Reply::Error(format!("{:#}", e)),
+            };
+            send(&mut write, &reply).await?;
+        }
+        Request::History => {
+            let history = HISTORY.lock().map(|h| h.clone()).unwrap_or_default();
+            send(&mut write, &Reply::History(history)).await?;
+        }
+    }
+    write.shutdown().await?;
+    Ok(())
+}
+
+async fn send(write: &mut (impl AsyncWriteExt + Unpin), reply: &Reply) -> Result<()> {
+    let mut line = serde_json::to_vec(reply)?;
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    Ok(())
+}
+
+/// Make the agent's timeline available to clients.
+pub fn publish(timeline: &Timeline) {
+    let entries = timeline
+        .entries
+        .iter()
+        .map(|e| HistoryEntry {
+            at: e.at,
+            kind: e.kind.label().to_string(),
+            text: e.text.clone(),
+        })
+        .collect();
+    if let Ok(mut history) = HISTORY.lock() {
+        *history = entries;
+    }
+}
+
+/// Send `request` to the agent and read its replies.
+async fn request(socket: &Path, request: &Request) -> Result<Lines<BufReader<OwnedReadHalf>>> {
+    let stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("Failed to reach the agent on {}", socket.display()))?;
+    let (read, mut write) = stream.into_split();
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    write.write_all(&line).await?;
+    Ok(BufReader::new(read).lines())
+}
+
+async fn next_reply(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Result<Reply> {
+    let line = lines.next_line().await?.context("The agent closed the connection")?;
+    serde_json::from_str(&line).context("Invalid reply from the agent")
+}
+
+/// Run a command through the agent, as [`SessionManager::exec`] would.
+pub async fn exec(socket: &Path, host: &str, user: Option<String>, command: &str) -> Result<Captured> {
+    let request = Request::Exec {
+        host: host.to_string(),
+        user,
+        command: command.to_string(),
+    };
+    let mut lines = self::request(socket, &request).await?;
+    match next_reply(&mut lines).await? {
+        Reply::Done(captured) => Ok(captured),
+        Reply::Error(e) => Err(anyhow::anyhow!(e)),
+        reply => anyhow::bail!("Unexpected reply from the agent: {:?}", reply),
+    }
+}
+
+/// Start streaming a command's output through the agent.
+async fn open_stream(socket: &Path, host: &str, user: Option<String>, command: &str) -> Result<Lines<BufReader<OwnedReadHalf>>> {
+    let request = Request::Stream {
+        host: host.to_string(),
+        user,
+        command: command.to_string(),
+    };
+    self::request(socket, &request).await
+}
+
+/// The next chunk of a stream, or `None` once the command has exited.
+/// Dropping `lines` hangs up, which stops the command on the agent's side.
+async fn next_chunk(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Result<Option<Vec<u8>>> {
+    match next_reply(lines).await? {
+        Reply::Chunk(chunk) => Ok(Some(chunk)),
+        Reply::End => Ok(None),
+        Reply::Error(e) => Err(anyhow::anyhow!(e)),
+        reply => anyhow::bail!("Unexpected reply from the agent: {:?}", reply),
+    }
+}
+
+/// Stream a command's lines through the agent, as
+/// [`SessionManager::stream_lines`] would.
+pub async fn stream(
+    socket: &Path,
+    host: &str,
+    user: Option<String>,
+    command: &str,
+    mut on_line: impl FnMut(String) -> bool,
+) -> Result<()> {
+    let mut lines = open_stream(socket, host, user, command).await?;
+    let mut pending = Vec::new();
+    while let Some(chunk) = next_chunk(&mut lines).await? {
+        pending.extend_from_slice(&chunk);
+        while let Some(line) = take_line(&mut pending) {
+            if !on_line(line) {
+                return Ok(());
+            }
+        }
+    }
+    // The last line may have no line break
+    if !pending.is_empty() {
+        on_line(decode_line(&pending));
+    }
+    Ok(())
+}
+
+/// Write a command's output to `file` through the agent as it comes, as
+/// [`SessionManager::stream_to_file`] would.
+pub async fn stream_to_file(socket: &Path, host: &str, user: Option<String>, command: &str, file: &Path) -> Result<u64> {
+    let mut lines = open_stream(socket, host, user, command).await?;
+    let mut out = tokio::fs::OpenOptions::new().append(true).open(file).await?;
+    let mut written = 0;
+    while let Some(chunk) = next_chunk(&mut lines).await? {
+        out.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+    }
+    out.flush().await?;
+    Ok(written)
+}
+
+/// The first complete line in `pending`, taken out of it.
+fn take_line(pending: &mut Vec<u8>) -> Option<String> {
+    let end = pending.iter().position(|&b| b == b'\n')?;
+    let line: Vec<u8> = pending.drain(..=end).collect();
+    Some(decode_line(&line))
+}
+
+/// A line as `stream_lines` hands it over: lossily decoded, without its break.
+fn decode_line(line: &[u8]) -> String {
+    String::from_utf8_lossy(line).trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// The agent's timeline, to start a client's with.
+pub async fn history(socket: &Path) -> Result<Vec<timeline::Entry>> {
+    let mut lines = request(socket, &Request::History).await?;
+    match next_reply(&mut lines).await? {
+        Reply::History(entries) => Ok(entries
+            .into_iter()
+            .filter_map(|e| {
+                Some(timeline::Entry {
+                    at: e.at,
+                    kind: timeline::Kind::from_label(&e.kind)?,
+                    text: e.text,
+                })
+            })
+            .collect()),
+        Reply::Error(e) => Err(anyhow::anyhow!(e)),
+        reply => anyhow::bail!("Unexpected reply from the agent: {:?}", reply),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol() {
+        let request = Request::Exec {
+            host: "web01".to_string(),
+            user: None,
+            command: "uptime".to_string(),
+        };
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(line, r#"{"request":"exec","host":"web01","user":null,"command":"uptime"}"#);
+        assert_eq!(serde_json::from_str::<Request>(&line).unwrap(), request);
+        assert_eq!(serde_json::from_str::<Request>(r#"{"request":"history"}"#).unwrap(), Request::History);
+
+        assert_eq!(serde_json::to_string(&Reply::End).unwrap(), r#""end""#);
+        assert_eq!(serde_json::to_string(&Reply::Chunk(b"x".to_vec())).unwrap(), r#"{"chunk":[120]}"#);
+        let done = Reply::Done(Captured {
+            code: Some(0),
+            stdout: vec![0xff, b'\n'],
+            ..Default::default()
+        });
+        // Output that isn't UTF-8 survives the trip
+        let line = serde_json::to_string(&done).unwrap();
+        assert_eq!(serde_json::from_str::<Reply>(&line).unwrap(), done);
+    }
+
+    #[test]
+    fn test_take_line() {
+        let mut pending = b"one\r\ntw".to_vec();
+        assert_eq!(take_line(&mut pending).as_deref(), Some("one"));
+        assert_eq!(take_line(&mut pending), None);
+        pending.extend_from_slice(b"o\xff\n");
+        assert_eq!(take_line(&mut pending).as_deref(), Some("two\u{fffd}"));
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_round_trip() {
+        let socket = std::env::temp_dir().join(format!("sctl_agent_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        listen(&socket, SessionPool::default(), SshOptions::default()).await.unwrap();
+        assert_eq!(std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(listen(&socket, SessionPool::default(), SshOptions::default()).await.is_err());
+
+        let mut timeline = Timeline::default();
+        timeline.push(timeline::Kind::Transition, "nginx on web01: active -> failed");
+        publish(&timeline);
+        let entries = history(&socket).await.unwrap();
+        assert_eq!(entries, timeline.entries.iter().cloned().collect::<Vec<_>>());
+        let _ = std::fs::remove_file(&socket);
+    }
+}
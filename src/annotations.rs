@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Operator annotations on host/service cells. An empty `service` refers to
+/// the host as a whole.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    #[serde(default)]
+    pub silences: Vec<Silence>,
+    #[serde(default)]
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub favorites: Vec<CellRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellRef {
+    pub host: String,
+    #[serde(default)]
+    pub service: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Silence {
+    pub host: String,
+    #[serde(default)]
+    pub service: String,
+    pub by: String,
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    pub host: String,
+    #[serde(default)]
+    pub service: String,
+    pub text: String,
+    pub by: String,
+    pub at: u64,
+}
+
+impl Annotations {
+    pub fn is_silenced(&self, host: &str, service: &str) -> bool {
+        self.silences
+            .iter()
+            .any(|s| s.host == host && (s.service.is_empty() || s.service == service))
+    }
+
+    pub fn note(&self, host: &str, service: &str) -> Option<&Note> {
+        self.notes
+            .iter()
+            .find(|n| n.host == host && n.service == service)
+    }
+
+    pub fn is_favorite(&self, host: &str, service: &str) -> bool {
+        self.favorites
+            .iter()
+            .any(|f| f.host == host && f.service == service)
+    }
+
+    pub fn toggle_silence(&mut self, host: &str, service: &str) -> bool {
+        let before = self.silences.len();
+        self.silences
+            .retain(|s| !(s.host == host && s.service == service));
+        if self.silences.len() != before {
+            return false;
+        }
+        self.silences.push(Silence {
+            host: host.to_string(),
+            service: service.to_string(),
+            by: current_user(),
+            at: now_secs(),
+        });
+        true
+    }
+
+    pub fn toggle_favorite(&mut self, host: &str, service: &str) -> bool {
+        let before = self.favorites.len();
+        self.favorites
+            .retain(|f| !(f.host == host && f.service == service));
+        if self.favorites.len() != before {
+            return false;
+        }
+        self.favorites.push(CellRef {
+            host: host.to_string(),
+            service: service.to_string(),
+        });
+        true
+    }
+
+    /// Set or, with empty text, remove the note on a cell.
+    pub fn set_note(&mut self, host: &str, service: &str, text: &str) {
+        self.notes
+            .retain(|n| !(n.host == host && n.service == service));
+        let text = text.trim();
+        if !text.is_empty() {
+            self.notes.push(Note {
+                host: host.to_string(),
+                service: service.to_string(),
+                text: text.to_string(),
+                by: current_user(),
+                at: now_secs(),
+            });
+        }
+    }
+}
+
+/// Annotations backed by an optional YAML file. The file may live on a
+/// shared filesystem: it is re-read whenever its modification time changes,
+/// and every update re-reads it before writing so concurrent operators'
+/// changes are merged rather than overwritten.
+pub struct AnnotationStore {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    pub data: Annotations,
+}
+
+impl AnnotationStore {
+    /// A store that only lives for this session.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            modified: None,
+            data: Annotations::default(),
+        }
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        let mut store = Self {
+            path: Some(PathBuf::from(path)),
+            modified: None,
+            data: Annotations::default(),
+        };
+        store.reload()?;
+        log::info!(
+            "Loaded annotations from {}: {} silences, {} notes, {} favorites",
+            path,
+            store.data.silences.len(),
+            store.data.notes.len(),
+            store.data.favorites.len()
+        );
+        Ok(store)
+    }
+
+    fn file_mtime(&self) -> Option<SystemTime> {
+        let path = self.path.as_ref()?;
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !path.exists() {
+            return Ok(());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file: {}", path.display()))?;
+        self.data = if content.trim().is_empty() {
+            Annotations::default()
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Failed to parse state file: {}", path.display()))?
+        };
+        self.modified = self.file_mtime();
+        Ok(())
+    }
+
+    /// Wait for the exclusive lock on the state file, held until the returned
+    /// file is dropped. It is a separate file, as the state file itself is
+    /// replaced on every write.
+    fn lock(&self) -> Result<Option<fs::File>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file: {}", lock_path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock state file: {}", lock_path.display()))?;
+        Ok(Some(file))
+    }
+
+    /// Pick up changes written by other operators.
+    pub fn reload_if_changed(&mut self) {
+        let mtime = self.file_mtime();
+        if mtime.is_some() && mtime != self.modified {
+            log::debug!("State file changed on disk, reloading");
+            if let Err(e) = self.reload() {
+                log::warn!("Failed to reload state file: {:#}", e);
+            }
+        }
+    }
+
+    /// Apply a change on top of the latest on-disk state and write it back.
+    /// Other operators' updates wait on a lock until this one is written, so
+    /// none of them is lost.
+    pub fn update<T>(&mut self, change: impl FnOnce(&mut Annotations) -> T) -> Result<T> {
+        let _lock = self.lock()?;
+        self.reload()?;
+        let result = change(&mut self.data);
+        if let Some(path) = &self.path {
+            let yaml = serde_yaml::to_string(&self.data)?;
+            // Write-then-rename so readers never see a partial file
+            let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+            fs::write(&tmp, yaml)
+                .with_context(|| format!("Failed to write state file: {}", tmp.display()))?;
+            fs::rename(&tmp, path)
+                .with_context(|| format!("Failed to replace state file: {}", path.display()))?;
+            self.modified = self.file_mtime();
+        }
+        Ok(result)
+    }
+}
+
+pub fn current_user() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos();
+        std::env::temp_dir().join(format!("sc_state_{}_{}.yaml", std::process::id(), nanos))
+    }
+
+    #[test]
+    fn test_toggle_silence() {
+        let mut a = Annotations::default();
+        assert!(a.toggle_silence("h1", "nginx"));
+        assert!(a.is_silenced("h1", "nginx"));
+        assert!(!a.is_silenced("h1", "redis"));
+        assert!(!a.toggle_silence("h1", "nginx"));
+        assert!(!a.is_silenced("h1", "nginx"));
+    }
+
+    #[test]
+    fn test_host_silence_covers_services() {
+        let mut a = Annotations::default();
+        a.toggle_silence("h1", "");
+        assert!(a.is_silenced("h1", "anything"));
+    }
+
+    #[test]
+    fn test_set_note_and_clear() {
+        let mut a = Annotations::default();
+        a.set_note("h1", "nginx", "  investigating  \n");
+        assert_eq!(a.note("h1", "nginx").unwrap().text, "investigating");
+        a.set_note("h1", "nginx", "");
+        assert!(a.note("h1", "nginx").is_none());
+    }
+
+    #[test]
+    fn test_store_shared_between_instances() {
+        let path = temp_path();
+        let p = path.to_str().unwrap();
+        let mut first = AnnotationStore::open(p).unwrap();
+        let mut second = AnnotationStore::open(p).unwrap();
+
+        first.update(|a| a.toggle_favorite("h1", "nginx")).unwrap();
+        // The second writer must merge, not clobber, the first one's change
+        second.update(|a| a.toggle_silence("h2", "")).unwrap();
+
+        let reread = AnnotationStore::open(p).unwrap();
+        assert!(reread.data.is_favorite("h1", "nginx"));
+        assert!(reread.data.is_silenced("h2", "redis"));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(format!("{}.lock", p));
+    }
+
+    #[test]
+    fn test_concurrent_updates_all_kept() {
+        let path = temp_path();
+        let p = path.to_str().unwrap().to_string();
+        let writers: Vec<_> = (0..4)
+            .map(|w| {
+                let p = p.clone();
+                std::thread::spawn(move || {
+                    let mut store = AnnotationStore::open(&p).unwrap();
+                    for i in 0..10 {
+                        store.update(|a| a.toggle_favorite(&format!("h{}", w), &format!("s{}", i))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(AnnotationStore::open(&p).unwrap().data.favorites.len(), 40);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(format!("{}.lock", p));
+    }
+}
@@ -1,34 +1,293 @@
-use crate::config::{Host, ServiceConfig};
-use crate::monitor::status::{build_grid, refresh_cell};
-use crate::monitor::{GridResult, HostService, ServiceStatus};
+use crate::adhoc::{self, AdhocRun, HostRun};
+use crate::agent;
+use crate::annotations::{self, AnnotationStore};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::bench::{self, BenchSpec};
+use crate::bulk::{self, BulkRun};
+use crate::config::services::{display_name, expand_specifiers, unit_name};
+use crate::clock;
+use crate::dashboard::Dashboard;
+use crate::deps::{self, DepsView};
+use crate::encoding::{self, Encoding};
+use crate::events;
+use crate::config::services::{BulkConfig, CellContent, InitialRefresh};
+use crate::config::{Config, Host, MaintenanceWindow, ServiceConfig, Severity, SshOptions};
+use crate::logging;
+use crate::macros::{self, MacroRecorder};
+use crate::metrics;
+use crate::monitor::coordinator::{self, RefreshBatch, RefreshCoordinator, Repoller};
+use crate::monitor::updates::{self, PackageUpdates};
+use crate::monitor::status::{build_grid, refresh_services, warn_unmatched, BootInfo, CellDetail, HostUpdate, ServiceCheck, SystemState};
+use crate::monitor::{GridResult, HostError, HostService, ServiceStatus};
+use crate::notify::{self, CellStates, Notifier, Transition};
+use crate::picker::HostPicker;
+use crate::plugins;
+use crate::properties::{self, PropertyView, UnitFile};
+use crate::report::{self, Report};
+use crate::scratch;
+use crate::scope::Scope;
+use crate::scripts;
+use crate::ssh::session::SessionPool;
 use crate::ssh::SessionManager;
+use crate::timeline::{self, Timeline};
+use crate::tmux;
+use crate::trace;
 use crate::tui;
 use crate::tui::event::{poll_event, AppEvent};
 use crate::tui::ui::render;
+use crate::tui::viewer::LogView;
+use crate::undo::{Edit, UndoStack};
+use crate::view::{self, Invocation, StartView, View};
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::widgets::TableState;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::process::Command;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
 
 #[derive(Debug, Clone)]
 pub enum Screen {
     Main,
+    /// Internal metrics about the controller itself.
+    Stats,
     Detail {
         host_index: usize,
         service_index: usize,
     },
+    /// Host-level commands from `host_commands:` for one host.
+    Host { host_index: usize },
+    /// Chronological log of this session's refreshes, changes and actions.
+    Timeline,
+    /// Hosts ordered by how long the last full refresh spent on them.
+    SlowHosts,
+    /// Two cells picked with `v`, whose outputs are compared side by side.
+    Compare {
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    /// Results of an ad-hoc command run across a group of hosts.
+    Adhoc,
+    /// Per-host results of a stop or restart run on a whole column.
+    Bulk,
+    /// A file followed with `tail -F`; `q` returns to the detail screen.
+    Tail {
+        host_index: usize,
+        service_index: usize,
+    },
+    /// `systemctl show` of a cell's unit; `q` returns to the detail screen.
+    Properties {
+        host_index: usize,
+        service_index: usize,
+    },
+    /// `systemctl list-dependencies` of a cell's unit, as a tree.
+    Deps {
+        host_index: usize,
+        service_index: usize,
+    },
 }
 
 pub enum RefreshResult {
-    FullGrid(GridResult),
+    FullGrid(Box<GridResult>),
+    /// A package update check of all reachable hosts finished.
+    PackageUpdates(HashMap<usize, PackageUpdates>),
+    /// One host finished during a full refresh that is still running.
+    HostRow(HostUpdate),
+    /// A refresh of one host's row alone (`L`) finished; every other host
+    /// is in `skipped`.
+    HostRefreshed(Box<GridResult>),
+    Cell {
+        host: String,
+        service: String,
+        status: ServiceStatus,
+        detail: CellDetail,
+    },
+    /// A service action finished; the cell needs re-querying.
+    ActionDone {
+        host: String,
+        service: String,
+        action: String,
+        error: Option<String>,
+    },
+    /// A line from the followed file of the tail view with this id.
+    TailLine { id: u64, line: String },
+    /// The followed file's stream stopped, with the error if it failed.
+    TailEnded { id: u64, error: Option<String> },
+    /// `systemctl show` for the property view with this id returned.
+    Properties { id: u64, result: Result<String, String> },
+    /// The files systemd reads for a cell's unit; empty if the lookup failed.
+    UnitFiles { host: String, service: String, files: Vec<UnitFile> },
+    /// Output of the denial checks started with this id, as popup lines.
+    DenialChecks { id: u64, lines: Vec<String> },
+    /// The dependency tree for the view with this id was fetched.
+    Deps { id: u64, result: Result<Vec<deps::DepNode>, String> },
+    /// One host of the ad-hoc run with this id finished.
+    AdhocResult { id: u64, host: String, run: HostRun },
+    /// One host of the bulk run with this id started or finished.
+    BulkResult { id: u64, host: String, outcome: bulk::Outcome },
+}
+
+/// An action currently running against a single host/service cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PendingAction {
+    Stopping,
+    Restarting,
+    Unmasking,
+    Killing,
+    Resetting,
+    /// A plugin action.
+    Running,
+}
+
+impl PendingAction {
+    fn from_action(action: &str) -> Self {
+        match action {
+            "stop" => PendingAction::Stopping,
+            "unmask" => PendingAction::Unmasking,
+            "reset-failed" => PendingAction::Resetting,
+            a if a.starts_with("kill") => PendingAction::Killing,
+            a if plugins::parse_action(a).is_some() => PendingAction::Running,
+            _ => PendingAction::Restarting,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PendingAction::Stopping => tui::caps::glyph("stopping…", "stopping..."),
+            PendingAction::Restarting => tui::caps::glyph("restarting…", "restarting..."),
+            PendingAction::Unmasking => tui::caps::glyph("unmasking…", "unmasking..."),
+            PendingAction::Killing => tui::caps::glyph("signalling…", "signalling..."),
+            PendingAction::Resetting => tui::caps::glyph("resetting…", "resetting..."),
+            PendingAction::Running => tui::caps::glyph("running…", "running..."),
+        }
+    }
+}
+
+/// A question the operator must answer with y/n before an action goes ahead.
+pub struct Confirm {
+    pub message: String,
+    action: ConfirmAction,
+}
+
+/// Signals offered by `K`, with what they are for.
+pub const KILL_SIGNALS: [(&str, &str); 3] = [
+    ("SIGTERM", "ask the processes to exit"),
+    ("SIGKILL", "end them at once, for a unit hanging in deactivating"),
+    ("SIGHUP", "reload or reopen logs, for daemons that handle it"),
+];
+
+/// Signal picker opened by `K` for one cell.
+pub struct KillChooser {
+    pub host: String,
+    pub service: String,
+    pub cursor: usize,
+}
+
+/// Plugin action picker opened by `X` for one cell.
+pub struct PluginChooser {
+    pub host: String,
+    pub service: String,
+    pub cursor: usize,
+}
+
+/// Text to read, e.g. a command line to copy.
+pub struct InfoPopup {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub hint: String,
+    /// Id of the denial checks whose results are still to be appended.
+    pub waiting: Option<u64>,
 }
 
+/// A single-line text prompt, e.g. for the reason behind an action.
+pub struct Prompt {
+    pub title: String,
+    pub input: String,
+    action: PromptAction,
+}
+
+impl Prompt {
+    /// Heading for the popup border.
+    pub fn heading(&self) -> &'static str {
+        match self.action {
+            PromptAction::Reason(_) => " Reason ",
+            PromptAction::SetUser { .. } => " SSH user ",
+            PromptAction::AdhocTarget | PromptAction::AdhocCommand { .. } => " Run on group ",
+            PromptAction::Scope => " Scope ",
+        }
+    }
+}
+
+enum PromptAction {
+    /// The input is the reason for this action.
+    Reason(ConfirmAction),
+    /// The input is the SSH user for one host, or for all hosts if `None`;
+    /// empty input goes back to the default.
+    SetUser { host: Option<String> },
+    /// The input is a group (or host glob) to run an ad-hoc command on.
+    AdhocTarget,
+    /// The input is the command to run on these hosts.
+    AdhocCommand { target: String, hosts: Vec<String> },
+    /// The input is the scope for `r` and bulk actions.
+    Scope,
+}
+
+enum ConfirmAction {
+    ServiceAction {
+        host: String,
+        service: String,
+        action: String,
+        reason: Option<String>,
+    },
+    /// The same action on every one of `hosts`.
+    Bulk {
+        service: String,
+        action: String,
+        hosts: Vec<String>,
+        reason: Option<String>,
+    },
+    /// `reset-failed` of every failed cell on one host.
+    HostResetFailed {
+        host: String,
+        services: Vec<String>,
+        reason: Option<String>,
+    },
+    /// An ad-hoc command on `all`, a glob of hosts or protected hosts.
+    Adhoc {
+        target: String,
+        hosts: Vec<String>,
+        command: String,
+        reason: Option<String>,
+    },
+}
+
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+/// For terminals without Unicode.
+const ASCII_SPINNER_FRAMES: [&str; 4] = ["|", "/", "-", "\\"];
+
 #[derive(Debug, Clone)]
 pub enum FlatEntry {
     Service { host_idx: usize, svc_idx: usize },
-    UnreachableHost { host_idx: usize, reason: String },
+    UnreachableHost { host_idx: usize, error: HostError },
+    /// All units on a host matched by one glob pattern, collapsed into a single row.
+    Group {
+        host_idx: usize,
+        pattern: String,
+        svc_idxs: Vec<usize>,
+        status: ServiceStatus,
+    },
+}
+
+impl FlatEntry {
+    pub fn host_idx(&self) -> usize {
+        match self {
+            FlatEntry::Service { host_idx, .. }
+            | FlatEntry::UnreachableHost { host_idx, .. }
+            | FlatEntry::Group { host_idx, .. } => *host_idx,
+        }
+    }
 }
 
 pub struct AppState {
@@ -36,14 +295,136 @@ pub struct AppState {
     pub service_configs: Vec<ServiceConfig>,
     pub service_names: Vec<String>,
     pub grid: Vec<Vec<HostService>>,
-    pub unreachable_hosts: HashMap<usize, String>,
+    pub unreachable_hosts: HashMap<usize, HostError>,
+    /// Hosts where passwordless sudo failed during the last refresh.
+    pub no_sudo_hosts: HashSet<usize>,
+    /// Hosts left out of the last full refresh (see `initial_refresh`).
+    pub skipped_hosts: HashSet<usize>,
+    /// Last known boot of each host.
+    pub boots: HashMap<usize, BootInfo>,
+    /// Hosts whose boot id changed since the refresh before the last one.
+    pub rebooted_hosts: HashSet<usize>,
+    /// Last known systemd state and job queue of each host.
+    pub system_states: HashMap<usize, SystemState>,
+    /// Command listing units to restart for updated binaries (`restart_check:`).
+    pub restart_check: Option<String>,
+    /// Units each host's restart check last named.
+    pub restart_needed: HashMap<usize, HashSet<String>>,
+    /// Time between package update checks; `None` when they are off.
+    pub updates_interval: Option<Duration>,
+    /// Asks again about cells in a transitional state (`repoll_transitional`).
+    repoller: Option<Repoller>,
+    /// Time between automatic full refreshes (`refresh_interval`).
+    pub refresh_interval: Option<Duration>,
+    /// When the last full refresh ended, or the program started.
+    refreshed_at: Instant,
+    /// Pending updates of each host from the last check.
+    pub package_updates: HashMap<usize, PackageUpdates>,
+    /// When the last update check started, and whether it is still running.
+    updates_started: Option<Instant>,
+    updates_running: bool,
+    /// Inventory indices the first full refresh is limited to.
+    initial_subset: Option<Vec<usize>>,
+    /// Hosts left out of full refreshes for the rest of the session (`x` on
+    /// the slow hosts screen).
+    pub excluded_hosts: HashSet<usize>,
+    /// Hosts whose rows are kept at the top of the main screen (`P`).
+    pub pinned_hosts: HashSet<usize>,
+    /// Service entries (by their services.yaml name) hidden from the main
+    /// screen with the column chooser, for this session only.
+    pub hidden_entries: HashSet<String>,
+    pub warnings: Vec<String>,
     pub screen: Screen,
     pub cursor: usize,
     pub table_state: TableState,
     pub detail_cursor: usize,
-    pub refreshing: bool,
+    pub refresh: RefreshCoordinator,
     pub should_quit: bool,
     pub ssh_user: Option<String>,
+    pub ssh_options: SshOptions,
+    /// Connections kept open across refreshes, views and actions.
+    sessions: SessionPool,
+    /// In-flight actions keyed by (host address, service name).
+    pub pending_actions: HashMap<(String, String), PendingAction>,
+    pub spinner_tick: usize,
+    pub status_message: Option<String>,
+    /// Collapse glob matches into one row per pattern.
+    pub group_globs: bool,
+    /// How the program was started, for `Y`.
+    pub invocation: Option<Invocation>,
+    /// The web dashboard, when `--dashboard-addr` serves one.
+    pub dashboard: Option<Dashboard>,
+    /// Statuses as glyphs rather than words (`status_glyphs:`).
+    pub status_glyphs: bool,
+    /// Words shown instead of the built-in ones (`status_words:`).
+    pub status_words: HashMap<String, String>,
+    /// What the status column shows after the status (`cell:`).
+    pub cell_content: CellContent,
+    /// Size the service and host columns to their longest entry (`w`).
+    pub fit_columns: bool,
+    /// Groups expanded to show their units, keyed by (host address, pattern).
+    pub expanded_groups: HashSet<(String, String)>,
+    /// Silences, notes and favorites, possibly shared with other operators.
+    pub annotations: AnnotationStore,
+    pub maintenance: Vec<MaintenanceWindow>,
+    /// Open confirmation popup; swallows all keys until answered.
+    pub confirm: Option<Confirm>,
+    /// Open text prompt; swallows all keys until submitted or cancelled.
+    pub prompt: Option<Prompt>,
+    /// Cursor of the open column chooser (`C`), which lists `service_configs`.
+    pub column_chooser: Option<usize>,
+    /// Open host picker (`o`), for a shell on any inventory host.
+    pub host_picker: Option<HostPicker>,
+    /// Signal picker for `systemctl kill` (`K`).
+    pub kill_chooser: Option<KillChooser>,
+    pub plugin_chooser: Option<PluginChooser>,
+    /// Read-only popup closed by any key, e.g. the view shared with `Y`.
+    pub info_popup: Option<InfoPopup>,
+    /// Hosts that `r` refreshes and bulk actions target (`G`).
+    pub scope: Scope,
+    /// Cell from `--focus` whose detail view opens once its host is refreshed.
+    focus: Option<(usize, String)>,
+    pub protected_groups: Vec<String>,
+    pub host_commands: Vec<String>,
+    /// Diagnostics run by `!` on a failed cell.
+    triage: Vec<String>,
+    pub audit: AuditLog,
+    pub notifier: Notifier,
+    /// When each cell was last restarted from this session.
+    last_restarts: HashMap<(String, String), Instant>,
+    /// The open tail view and the task streaming into it.
+    pub tail: Option<LogView>,
+    tail_task: Option<tokio::task::JoinHandle<()>>,
+    tail_seq: u64,
+    /// The open property inspector.
+    pub properties: Option<PropertyView>,
+    properties_seq: u64,
+    /// Unit file, drop-ins and environment files of the cells whose detail
+    /// view was opened since the last full refresh; `None` while fetching.
+    pub unit_files: HashMap<(String, String), Option<Vec<UnitFile>>>,
+    /// The open dependency tree.
+    pub deps: Option<DepsView>,
+    deps_seq: u64,
+    denial_seq: u64,
+    /// Running refresh and update check tasks, aborted by a cancel.
+    refresh_tasks: Vec<tokio::task::JoinHandle<()>>,
+    /// Running service action tasks, aborted by a cancel.
+    action_tasks: Vec<tokio::task::JoinHandle<()>>,
+    /// Everything that happened this session, for the timeline screen.
+    pub timeline: Timeline,
+    /// Keyboard macros recorded with `Q` and replayed with `@`.
+    pub macros: MacroRecorder,
+    /// Silences, favorites, notes and grouping changes that `z`/`Z` undo and redo.
+    pub undo: UndoStack,
+    /// Cell (host, service index) marked with `v` as the first side of a comparison.
+    pub compare_mark: Option<(usize, usize)>,
+    /// The ad-hoc run shown on the Adhoc screen.
+    pub adhoc: Option<AdhocRun>,
+    adhoc_seq: u64,
+    pub bulk_options: BulkConfig,
+    /// The bulk run shown on the Bulk screen.
+    pub bulk: Option<BulkRun>,
+    bulk_seq: u64,
 }
 
 impl AppState {
@@ -54,39 +435,341 @@ impl AppState {
             service_names: Vec::new(),
             grid: Vec::new(),
             unreachable_hosts: HashMap::new(),
+            no_sudo_hosts: HashSet::new(),
+            skipped_hosts: HashSet::new(),
+            boots: HashMap::new(),
+            rebooted_hosts: HashSet::new(),
+            system_states: HashMap::new(),
+            restart_check: None,
+            restart_needed: HashMap::new(),
+            updates_interval: None,
+            repoller: None,
+            refresh_interval: None,
+            refreshed_at: Instant::now(),
+            package_updates: HashMap::new(),
+            updates_started: None,
+            updates_running: false,
+            initial_subset: None,
+            excluded_hosts: HashSet::new(),
+            pinned_hosts: HashSet::new(),
+            hidden_entries: HashSet::new(),
+            warnings: Vec::new(),
             screen: Screen::Main,
             cursor: 0,
             table_state: TableState::default().with_selected(0),
             detail_cursor: 0,
-            refreshing: false,
+            refresh: RefreshCoordinator::new(),
             should_quit: false,
             ssh_user,
+            ssh_options: SshOptions::default(),
+            sessions: SessionPool::default(),
+            pending_actions: HashMap::new(),
+            spinner_tick: 0,
+            status_message: None,
+            group_globs: false,
+            invocation: None,
+            dashboard: None,
+            status_glyphs: false,
+            status_words: HashMap::new(),
+            cell_content: CellContent::Status,
+            fit_columns: false,
+            expanded_groups: HashSet::new(),
+            annotations: AnnotationStore::in_memory(),
+            maintenance: Vec::new(),
+            confirm: None,
+            prompt: None,
+            column_chooser: None,
+            host_picker: None,
+            info_popup: None,
+            kill_chooser: None,
+            plugin_chooser: None,
+            scope: Scope::All,
+            focus: None,
+            protected_groups: Vec::new(),
+            host_commands: Vec::new(),
+            triage: Vec::new(),
+            audit: AuditLog::default(),
+            notifier: Notifier::default(),
+            last_restarts: HashMap::new(),
+            tail: None,
+            tail_task: None,
+            tail_seq: 0,
+            properties: None,
+            properties_seq: 0,
+            deps: None,
+            deps_seq: 0,
+            denial_seq: 0,
+            unit_files: HashMap::new(),
+            refresh_tasks: Vec::new(),
+            action_tasks: Vec::new(),
+            compare_mark: None,
+            undo: UndoStack::default(),
+            macros: MacroRecorder::default(),
+            timeline: Timeline::default(),
+            adhoc: None,
+            adhoc_seq: 0,
+            bulk_options: BulkConfig::default(),
+            bulk: None,
+            bulk_seq: 0,
+        }
+    }
+
+    /// Whether a cell is silenced, manually or by a maintenance window, so its
+    /// failures don't demand attention.
+    pub fn is_silenced(&self, host: &str, service: &str) -> bool {
+        self.annotations.data.is_silenced(host, service)
+            || self.active_maintenance(host, service).is_some()
+    }
+
+    /// The maintenance window currently covering a cell, if any.
+    pub fn active_maintenance(&self, host: &str, service: &str) -> Option<&MaintenanceWindow> {
+        if self.maintenance.is_empty() {
+            return None;
+        }
+        let groups: Vec<&str> = match self.hosts.iter().find(|h| h.address == host) {
+            Some(h) => h.groups().collect(),
+            None => vec![""],
+        };
+        let now = clock::now_secs();
+        self.maintenance
+            .iter()
+            .find(|w| groups.iter().any(|g| w.covers(host, g, service)) && w.is_active(now))
+    }
+
+    /// The (host, service) a flat entry refers to; service is empty for
+    /// host-level entries.
+    pub fn entry_cell(&self, entry: &FlatEntry) -> (String, String) {
+        match entry {
+            FlatEntry::Service { host_idx, svc_idx } => {
+                let hs = &self.grid[*host_idx][*svc_idx];
+                (hs.host_address.clone(), hs.service_name.clone())
+            }
+            FlatEntry::UnreachableHost { host_idx, .. } => {
+                (self.hosts[*host_idx].address.clone(), String::new())
+            }
+            FlatEntry::Group { host_idx, pattern, .. } => {
+                (self.hosts[*host_idx].address.clone(), pattern.clone())
+            }
+        }
+    }
+
+    /// Whether the host is within the scope set with `G`.
+    pub fn in_scope(&self, host_idx: usize) -> bool {
+        self.scope.contains(&self.hosts[host_idx], host_idx, &self.pinned_hosts)
+    }
+
+    /// The protected group a host belongs to, if any, directly or through
+    /// a group containing its own.
+    pub fn protected_group(&self, host: &str) -> Option<&str> {
+        self.hosts
+            .iter()
+            .find(|h| h.address == host)?
+            .groups()
+            .find(|g| self.protected_groups.iter().any(|p| p == g))
+    }
+
+    /// Abort the running refresh, update check and service actions. Dropping
+    /// the tasks drops their SSH sessions and remote commands with them.
+    /// False if nothing was running.
+    fn cancel_in_flight(&mut self) -> bool {
+        self.refresh_tasks.retain(|t| !t.is_finished());
+        self.action_tasks.retain(|t| !t.is_finished());
+        if self.refresh_tasks.is_empty() && self.action_tasks.is_empty() {
+            return false;
+        }
+        let refreshes = self.refresh_tasks.len();
+        let actions = self.action_tasks.len();
+        for task in self.refresh_tasks.drain(..) {
+            task.abort();
+        }
+        for task in self.action_tasks.drain(..) {
+            task.abort();
+        }
+        let dropped = self.refresh.cancel();
+        self.refreshed_at = Instant::now();
+        self.updates_running = false;
+        // Every pending marker belonged to an aborted action or a dropped follow-up refresh
+        self.pending_actions.clear();
+        if let Some(run) = self.bulk.as_mut() {
+            for target in &mut run.targets {
+                let error = match target.outcome {
+                    bulk::Outcome::Queued => "cancelled before it ran",
+                    bulk::Outcome::Running => "cancelled, it may have run",
+                    _ => continue,
+                };
+                target.outcome = bulk::Outcome::Failed {
+                    error: error.to_string(),
+                    duration: Duration::ZERO,
+                };
+            }
+        }
+        let text = match (refreshes, actions) {
+            (_, 0) => "Cancelled the refresh".to_string(),
+            (0, n) => format!("Cancelled {} running actions", n),
+            (_, n) => format!("Cancelled the refresh and {} running actions", n),
+        };
+        log::warn!("{} ({} queued cell refreshes dropped)", text, dropped.len());
+        self.timeline.push(timeline::Kind::Action, text.clone());
+        self.status_message = Some(format!("{}; statuses are as last seen", text));
+        true
+    }
+
+    /// If the cell was restarted less than its `min_restart_interval` ago,
+    /// how long ago that was and the configured interval.
+    fn restart_cooldown(&self, host: &str, service: &str) -> Option<(Duration, Duration)> {
+        let interval = self
+            .grid
+            .iter()
+            .flatten()
+            .find(|hs| hs.host_address == host && hs.service_name == service)?
+            .config
+            .min_restart_interval?;
+        let elapsed = self
+            .last_restarts
+            .get(&(host.to_string(), service.to_string()))?
+            .elapsed();
+        (elapsed < interval).then_some((elapsed, interval))
+    }
+
+    /// Configured severity of a cell; hosts count as critical.
+    pub fn severity(&self, host: &str, service: &str) -> Severity {
+        if service.is_empty() {
+            return Severity::Critical;
+        }
+        self.grid
+            .iter()
+            .flatten()
+            .find(|hs| hs.host_address == host && hs.service_name == service)
+            .map(|hs| hs.config.severity)
+            .unwrap_or_default()
+    }
+
+    fn entry_severity(&self, entry: &FlatEntry) -> Severity {
+        match entry {
+            FlatEntry::Service { host_idx, svc_idx } => self.grid[*host_idx][*svc_idx].config.severity,
+            FlatEntry::UnreachableHost { .. } => Severity::Critical,
+            FlatEntry::Group { host_idx, svc_idxs, .. } => svc_idxs
+                .iter()
+                .map(|&i| self.grid[*host_idx][i].config.severity)
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn refreshing(&self) -> bool {
+        self.refresh.is_refreshing()
+    }
+
+    /// The in-flight action for a cell, if any.
+    pub fn pending_action(&self, host: &str, service: &str) -> Option<PendingAction> {
+        self.pending_actions
+            .get(&(host.to_string(), service.to_string()))
+            .copied()
+    }
+
+    /// Current spinner frame, advanced once per event loop iteration.
+    pub fn spinner(&self) -> &'static str {
+        if tui::caps::get().unicode {
+            SPINNER_FRAMES[self.spinner_tick % SPINNER_FRAMES.len()]
+        } else {
+            ASCII_SPINNER_FRAMES[self.spinner_tick % ASCII_SPINNER_FRAMES.len()]
         }
     }
 
     /// Build a flat list of entries for the main screen.
-    /// Unreachable hosts and failed services are sorted to the top.
+    /// Rows of pinned hosts come first. Then unreachable hosts and failed
+    /// services are sorted to the top, followed by degraded services, then
+    /// everything else. Within failed and degraded, more severe services come
+    /// first.
+    /// With `group_globs`, units matched by a glob collapse into one row per
+    /// pattern; expanded groups list their units directly beneath.
     pub fn flat_entries(&self) -> Vec<FlatEntry> {
         let mut failed = Vec::new();
+        let mut degraded = Vec::new();
         let mut rest = Vec::new();
 
         for (host_idx, row) in self.grid.iter().enumerate() {
-            if let Some(reason) = self.unreachable_hosts.get(&host_idx) {
-                failed.push(FlatEntry::UnreachableHost { host_idx, reason: reason.clone() });
-            } else {
-                for (svc_idx, hs) in row.iter().enumerate() {
+            if let Some(error) = self.unreachable_hosts.get(&host_idx) {
+                failed.push(FlatEntry::UnreachableHost { host_idx, error: error.clone() });
+                continue;
+            }
+
+            let mut seen_patterns: Vec<&str> = Vec::new();
+            for (svc_idx, hs) in row.iter().enumerate() {
+                if self.hidden_entries.contains(&hs.config.name_pattern) {
+                    continue;
+                }
+                if !(self.group_globs && hs.config.is_glob) {
                     let entry = FlatEntry::Service { host_idx, svc_idx };
-                    if hs.status == ServiceStatus::Failed {
-                        failed.push(entry);
-                    } else {
-                        rest.push(entry);
+                    match hs.status {
+                        _ if self.is_silenced(&hs.host_address, &hs.service_name) => rest.push(entry),
+                        ServiceStatus::Failed => failed.push(entry),
+                        ServiceStatus::Degraded | ServiceStatus::Masked => degraded.push(entry),
+                        _ => rest.push(entry),
                     }
+                    continue;
+                }
+
+                let pattern = hs.config.name_pattern.as_str();
+                if seen_patterns.contains(&pattern) {
+                    continue;
+                }
+                seen_patterns.push(pattern);
+
+                let svc_idxs: Vec<usize> = row
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.config.is_glob && other.config.name_pattern == pattern)
+                    .map(|(i, _)| i)
+                    .collect();
+                let status = ServiceStatus::worst(svc_idxs.iter().map(|&i| &row[i].status));
+                let bucket = match status {
+                    _ if self.is_silenced(&hs.host_address, pattern) => &mut rest,
+                    ServiceStatus::Failed => &mut failed,
+                    ServiceStatus::Degraded | ServiceStatus::Masked => &mut degraded,
+                    _ => &mut rest,
+                };
+                let expanded = self
+                    .expanded_groups
+                    .contains(&(hs.host_address.clone(), pattern.to_string()));
+                bucket.push(FlatEntry::Group {
+                    host_idx,
+                    pattern: pattern.to_string(),
+                    svc_idxs: svc_idxs.clone(),
+                    status,
+                });
+                if expanded {
+                    bucket.extend(
+                        svc_idxs
+                            .into_iter()
+                            .map(|svc_idx| FlatEntry::Service { host_idx, svc_idx }),
+                    );
                 }
             }
         }
 
+        // Stable, so expanded group members stay under their group row
+        failed.sort_by_key(|e| std::cmp::Reverse(self.entry_severity(e)));
+        degraded.sort_by_key(|e| std::cmp::Reverse(self.entry_severity(e)));
+        failed.extend(degraded);
         failed.extend(rest);
-        failed
+        let (mut pinned, others): (Vec<_>, Vec<_>) =
+            failed.into_iter().partition(|e| self.pinned_hosts.contains(&e.host_idx()));
+        pinned.extend(others);
+        pinned
+    }
+
+    /// Expand or collapse a glob group row.
+    fn toggle_group(&mut self, host_idx: usize, pattern: &str) {
+        let key = (self.hosts[host_idx].address.clone(), pattern.to_string());
+        if !self.expanded_groups.remove(&key) {
+            self.expanded_groups.insert(key);
+        }
+    }
+
+    /// Whether a service row is shown nested under an expanded group.
+    pub fn is_grouped_child(&self, hs: &HostService) -> bool {
+        self.group_globs && hs.config.is_glob
     }
 
     /// Total number of entries in the flat list.
@@ -118,24 +801,440 @@ impl AppState {
             }
         }
 
+        // Last, so configured items keep their positions for compare
+        let unit_files = self.extra_unit_files(host_idx, svc_idx);
+        if !unit_files.is_empty() {
+            items.push(DetailItem::Header(()));
+            for f in unit_files {
+                items.push(DetailItem::File(f.path.clone()));
+            }
+        }
+
         items
     }
 
+    /// The cell's unit files not already among its configured files.
+    pub fn extra_unit_files(&self, host_idx: usize, svc_idx: usize) -> Vec<&UnitFile> {
+        let hs = &self.grid[host_idx][svc_idx];
+        match self.unit_files.get(&(hs.host_address.clone(), hs.service_name.clone())) {
+            Some(Some(files)) => files.iter().filter(|f| !hs.config.files.contains(&f.path)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether stop/restart can work on a host, as far as the last refresh knows.
+    pub fn can_sudo(&self, host: &str) -> bool {
+        !self
+            .hosts
+            .iter()
+            .position(|h| h.address == host)
+            .is_some_and(|i| self.no_sudo_hosts.contains(&i))
+    }
+
+    /// The command producing the selected detail item's output (`cat` for files).
+    fn detail_command(&self, host_idx: usize, svc_idx: usize) -> Option<String> {
+        match self.detail_items(host_idx, svc_idx).get(self.detail_cursor)? {
+            DetailItem::File(path) => Some(format!("cat {}", path)),
+            DetailItem::Command(cmd) => Some(cmd.clone()),
+            DetailItem::Header(_) => None,
+        }
+    }
+
+    /// Files and commands two cells have in common, paired by position in
+    /// their detail lists: (label, left command, right command). Interactive
+    /// commands can't be captured and are left out.
+    pub fn compare_items(&self, left: (usize, usize), right: (usize, usize)) -> Vec<(String, String, String)> {
+        let viewable = |(h, s): (usize, usize), item: &DetailItem| match item {
+            DetailItem::File(path) => Some((path.clone(), format!("cat {}", path))),
+            DetailItem::Command(cmd) if !self.grid[h][s].config.interactive.contains(cmd) => {
+                Some((cmd.clone(), cmd.clone()))
+            }
+            _ => None,
+        };
+        self.detail_items(left.0, left.1)
+            .iter()
+            .zip(self.detail_items(right.0, right.1).iter())
+            .filter_map(|(a, b)| {
+                let (label_a, cmd_a) = viewable(left, a)?;
+                let (label_b, cmd_b) = viewable(right, b)?;
+                let label = if label_a == label_b {
+                    label_a
+                } else {
+                    format!("{}  |  {}", label_a, label_b)
+                };
+                Some((label, cmd_a, cmd_b))
+            })
+            .collect()
+    }
+
     fn detail_item_count(&self, host_idx: usize, svc_idx: usize) -> usize {
         self.detail_items(host_idx, svc_idx).len()
     }
 
-    fn apply_grid_result(&mut self, result: GridResult) {
+    /// Current status of every cell and host, for spotting transitions.
+    fn cell_states(&self) -> CellStates {
+        let mut states = CellStates::new();
+        for (host_idx, host) in self.hosts.iter().enumerate() {
+            if self.skipped_hosts.contains(&host_idx) {
+                continue;
+            }
+            let reachable = if self.unreachable_hosts.contains_key(&host_idx) {
+                "unreachable"
+            } else {
+                "reachable"
+            };
+            states.insert((host.address.clone(), String::new()), reachable.to_string());
+        }
+        for hs in self.grid.iter().flatten() {
+            states.insert(
+                (hs.host_address.clone(), hs.service_name.clone()),
+                hs.status.name().to_string(),
+            );
+        }
+        states
+    }
+
+    fn apply_grid_result(&mut self, mut result: GridResult) {
+        // The very first refresh establishes the baseline rather than transitions
+        let before = (!self.grid.is_empty()).then(|| self.cell_states());
+        self.keep_out_of_scope(&mut result);
         self.service_names = result.service_names;
         self.grid = result.grid;
         self.unreachable_hosts = result.unreachable_hosts;
-        self.refreshing = false;
+        self.no_sudo_hosts = result.no_sudo;
+        self.skipped_hosts = result.skipped;
+        self.warnings = result.warnings;
+        self.record_boots(result.boots);
+        self.record_system_states(result.system);
+        // A host whose check didn't answer this time no longer counts as needing one
+        self.restart_needed.retain(|i, _| self.skipped_hosts.contains(i));
+        self.restart_needed.extend(result.restart_needed);
+        if let Some((h, s)) = self.compare_mark {
+            if self.grid.get(h).is_none_or(|row| s >= row.len()) {
+                self.compare_mark = None;
+            }
+        }
+        for (host, service) in self.refresh.complete_full() {
+            self.pending_actions.remove(&(host, service));
+        }
+        self.refreshed_at = Instant::now();
+        self.timeline.push(
+            timeline::Kind::Refresh,
+            format!(
+                "Full refresh: {} hosts, {} unreachable",
+                self.hosts.len(),
+                self.unreachable_hosts.len()
+            ),
+        );
+        match before {
+            Some(before) => {
+                let transitions = notify::diff(&before, &self.cell_states(), |h, s| self.severity(h, s));
+                self.report_transitions(transitions);
+            }
+            None => self.notifier.baseline(&self.cell_states()),
+        }
+        self.publish_board();
         // Clamp cursor
         let len = self.flat_len();
         if len > 0 && self.cursor >= len {
             self.cursor = len - 1;
         }
     }
+
+    /// Open the detail view of the `--focus` cell, once a refresh has
+    /// covered its host.
+    fn apply_focus(&mut self) {
+        let Some((host_idx, service)) = self.focus.take() else {
+            return;
+        };
+        if self.skipped_hosts.contains(&host_idx) {
+            self.focus = Some((host_idx, service));
+            return;
+        }
+        let host = self.hosts[host_idx].address.clone();
+        if let Some(error) = self.unreachable_hosts.get(&host_idx) {
+            self.status_message = Some(format!("{} is unreachable: {}", host, error.reason()));
+            return;
+        }
+        let unit = unit_name(&service);
+        let Some(svc_idx) = self.grid[host_idx].iter().position(|hs| unit_name(&hs.service_name) == unit) else {
+            self.status_message = Some(format!("{} is not monitored on {}", service, host));
+            return;
+        };
+        log::info!("Opening detail view for focused {}:{}", host, service);
+        if let Some(pos) = self.flat_entries().iter().position(|e| {
+            matches!(e, FlatEntry::Service { host_idx: h, svc_idx: s } if *h == host_idx && *s == svc_idx)
+        }) {
+            self.cursor = pos;
+        }
+        self.screen = Screen::Detail {
+            host_index: host_idx,
+            service_index: svc_idx,
+        };
+        self.detail_cursor = 0;
+        self.status_message = None;
+    }
+
+    /// Carry the last known state of hosts outside the scope into a refresh
+    /// limited to it, instead of forgetting them.
+    fn keep_out_of_scope(&mut self, result: &mut GridResult) {
+        let kept: Vec<usize> = result
+            .skipped
+            .iter()
+            .copied()
+            .filter(|&i| !self.in_scope(i) && !self.excluded_hosts.contains(&i) && !self.skipped_hosts.contains(&i))
+            .collect();
+        for i in kept {
+            let Some(row) = self.grid.get_mut(i) else {
+                continue;
+            };
+            for hs in row.iter() {
+                if !result.service_names.contains(&hs.service_name) {
+                    result.service_names.push(hs.service_name.clone());
+                }
+            }
+            result.grid[i] = std::mem::take(row);
+            if let Some(error) = self.unreachable_hosts.remove(&i) {
+                result.unreachable_hosts.insert(i, error);
+            }
+            if self.no_sudo_hosts.contains(&i) {
+                result.no_sudo.insert(i);
+            }
+            result.skipped.remove(&i);
+        }
+    }
+
+    /// Flag hosts that came back with a different boot id. Hosts not probed
+    /// this time keep their flag and last known boot.
+    fn record_boots(&mut self, boots: HashMap<usize, BootInfo>) {
+        let mut rebooted = Vec::new();
+        for (idx, boot) in boots {
+            match self.boots.get(&idx) {
+                Some(prev) if prev.boot_id != boot.boot_id => {
+                    let address = &self.hosts[idx].address;
+                    let uptime = clock::format_duration(Duration::from_secs(boot.uptime));
+                    log::warn!("Host {} rebooted since the last refresh (up {})", address, uptime);
+                    self.timeline
+                        .push(timeline::Kind::Transition, format!("{} rebooted (up {})", address, uptime));
+                    self.rebooted_hosts.insert(idx);
+                    rebooted.push(address.clone());
+                }
+                Some(_) => {
+                    self.rebooted_hosts.remove(&idx);
+                }
+                None => {}
+            }
+            self.boots.insert(idx, boot);
+        }
+        if !rebooted.is_empty() {
+            rebooted.sort();
+            self.status_message = Some(format!("Rebooted since the last refresh: {}", rebooted.join(", ")));
+        }
+    }
+
+    /// Keep each host's systemd state, marking jobs still queued since the
+    /// last refresh as stuck and noting state changes on the timeline.
+    fn record_system_states(&mut self, states: HashMap<usize, SystemState>) {
+        for (idx, mut state) in states {
+            if let Some(prev) = self.system_states.get(&idx) {
+                state.carry_over(prev);
+                if prev.state != state.state {
+                    self.timeline.push(
+                        timeline::Kind::Transition,
+                        format!("{} system state: {} -> {}", self.hosts[idx].address, prev.state, state.state),
+                    );
+                }
+            }
+            if !state.stuck.is_empty() {
+                let units: Vec<&str> = state.stuck.iter().map(|j| j.unit.as_str()).collect();
+                log::warn!("Jobs stuck on {}: {}", self.hosts[idx].address, units.join(", "));
+            }
+            self.system_states.insert(idx, state);
+        }
+    }
+
+    /// Replace a cell's status and detail with what a cell refresh read.
+    pub fn apply_cell_status(&mut self, host: &str, service: &str, status: ServiceStatus, detail: CellDetail) {
+        self.pending_actions
+            .remove(&(host.to_string(), service.to_string()));
+        // Indices may have shifted if a full refresh landed meanwhile, so look the cell up by name
+        if let Some(hs) = self
+            .grid
+            .iter_mut()
+            .flatten()
+            .find(|hs| hs.host_address == host && hs.service_name == service)
+        {
+            hs.detail = detail;
+            let old = std::mem::replace(&mut hs.status, status);
+            let before = CellStates::from([((host.to_string(), service.to_string()), old.name().to_string())]);
+            let after = CellStates::from([((host.to_string(), service.to_string()), hs.status.name().to_string())]);
+            let severity = hs.config.severity;
+            let transitions = notify::diff(&before, &after, |_, _| severity);
+            self.report_transitions(transitions);
+            self.publish_board();
+        }
+    }
+
+    /// Time until the next automatic full refresh, when they are on and none
+    /// is running.
+    pub fn next_refresh_in(&self) -> Option<Duration> {
+        let interval = self.refresh_interval.filter(|_| !self.refreshing())?;
+        Some(interval.saturating_sub(self.refreshed_at.elapsed()))
+    }
+
+    /// A session manager on the shared connections, with the current SSH
+    /// user and options.
+    fn session_manager(&self) -> SessionManager {
+        SessionManager::pooled(&self.sessions, self.ssh_user.clone(), self.ssh_options.clone())
+    }
+
+    /// Hosts in the order a full refresh should visit them; see
+    /// [`coordinator::priority_order`].
+    fn refresh_order(&self) -> Vec<usize> {
+        let troubled: HashSet<usize> = self
+            .grid
+            .iter()
+            .enumerate()
+            .filter(|(i, row)| {
+                self.unreachable_hosts.contains_key(i) || row.iter().any(|hs| hs.status != ServiceStatus::Active)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let rows: Vec<usize> = self.flat_entries().iter().map(FlatEntry::host_idx).collect();
+        coordinator::priority_order(self.hosts.len(), &troubled, &rows, self.cursor)
+    }
+
+    /// Whether the host's restart check named this running service's unit.
+    pub fn restart_recommended(&self, hs: &HostService) -> bool {
+        hs.status == ServiceStatus::Active
+            && self
+                .hosts
+                .iter()
+                .position(|h| h.address == hs.host_address)
+                .and_then(|i| self.restart_needed.get(&i))
+                .is_some_and(|units| units.contains(&unit_name(&hs.service_name)))
+    }
+
+    /// Inventory hosts timed in the last full refresh, slowest first, with
+    /// their share of the time all hosts took.
+    pub fn slow_hosts(&self) -> Vec<(usize, Duration, f64)> {
+        metrics::snapshot()
+            .slowest_hosts()
+            .into_iter()
+            .filter_map(|(address, took, share)| {
+                let idx = self.hosts.iter().position(|h| h.address == address)?;
+                Some((idx, took, share))
+            })
+            .collect()
+    }
+
+    /// Show one host's results while the rest of a full refresh is still
+    /// running. Before the first full grid there is nothing to update, and
+    /// that refresh is the baseline anyway.
+    fn apply_host_update(&mut self, update: HostUpdate) {
+        if self.grid.len() != self.hosts.len() {
+            return;
+        }
+        let host = self.hosts[update.host_idx].address.clone();
+        let before: CellStates = self.cell_states().into_iter().filter(|((h, _), _)| *h == host).collect();
+        for hs in &update.row {
+            if !self.service_names.contains(&hs.service_name) {
+                self.service_names.push(hs.service_name.clone());
+            }
+        }
+        self.grid[update.host_idx] = update.row;
+        let row_len = self.grid[update.host_idx].len();
+        if let Screen::Detail { host_index, service_index } = self.screen {
+            if host_index == update.host_idx && service_index >= row_len {
+                self.screen = Screen::Main;
+            }
+        }
+        if self.compare_mark.is_some_and(|(h, s)| h == update.host_idx && s >= row_len) {
+            self.compare_mark = None;
+        }
+        match update.error {
+            Some(error) => self.unreachable_hosts.insert(update.host_idx, error),
+            None => self.unreachable_hosts.remove(&update.host_idx),
+        };
+        if update.no_sudo {
+            self.no_sudo_hosts.insert(update.host_idx);
+        } else {
+            self.no_sudo_hosts.remove(&update.host_idx);
+        }
+        self.skipped_hosts.remove(&update.host_idx);
+        let after: CellStates = self.cell_states().into_iter().filter(|((h, _), _)| *h == host).collect();
+        let transitions = notify::diff(&before, &after, |h, s| self.severity(h, s));
+        self.report_transitions(transitions);
+        let len = self.flat_len();
+        if len > 0 && self.cursor >= len {
+            self.cursor = len - 1;
+        }
+    }
+
+    /// Take a refresh of single hosts: their rows as during a full refresh,
+    /// plus what the probe found. The other hosts and the warnings stay as
+    /// they are, since one host says nothing about them.
+    pub fn apply_host_result(&mut self, mut result: GridResult) {
+        for host_idx in 0..self.hosts.len() {
+            if result.skipped.contains(&host_idx) {
+                continue;
+            }
+            let address = self.hosts[host_idx].address.clone();
+            let error = result.unreachable_hosts.remove(&host_idx);
+            let text = match &error {
+                Some(error) => format!("Host refresh: {} unreachable ({})", address, error.reason()),
+                None => format!("Host refresh: {}", address),
+            };
+            self.apply_host_update(HostUpdate {
+                host_idx,
+                row: std::mem::take(&mut result.grid[host_idx]),
+                error,
+                no_sudo: result.no_sudo.contains(&host_idx),
+            });
+            self.timeline.push(timeline::Kind::Refresh, text.clone());
+            self.status_message = Some(text);
+        }
+        self.record_boots(result.boots);
+        self.record_system_states(result.system);
+        self.restart_needed.retain(|i, _| result.skipped.contains(i));
+        self.restart_needed.extend(result.restart_needed);
+        self.publish_board();
+    }
+
+    /// Show the grid as it is now on the web dashboard.
+    fn publish_board(&self) {
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.publish(&self.hosts, &self.grid, &self.unreachable_hosts);
+        }
+    }
+
+    /// Send transitions to the event stream, MQTT and the timeline, and
+    /// those of cells that aren't silenced (with `m` or by a maintenance
+    /// window) to the hook, scripts and channels too. Alerts sent go on the
+    /// timeline as well.
+    fn report_transitions(&mut self, transitions: Vec<Transition>) {
+        events::transitions(&transitions);
+        self.notifier.publish(&transitions);
+        let mut routed = Vec::new();
+        for t in transitions {
+            let what = if t.service.is_empty() {
+                t.host.clone()
+            } else {
+                format!("{} on {}", t.service, t.host)
+            };
+            let silenced = self.is_silenced(&t.host, &t.service);
+            let text = format!("{}: {} -> {}{}", what, t.old, t.new, if silenced { " (silenced)" } else { "" });
+            self.timeline.push(timeline::Kind::Transition, text);
+            if silenced {
+                log::info!("Not notifying {}:{} {} -> {}: silenced", t.host, t.service, t.old, t.new);
+            } else {
+                routed.push(t);
+            }
+        }
+        for sent in self.notifier.notify(routed) {
+            self.timeline.push(timeline::Kind::Notification, sent);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -147,20 +1246,96 @@ enum DetailItem {
 
 pub async fn run(
     hosts: Vec<Host>,
-    service_configs: Vec<ServiceConfig>,
+    config: Config,
     ssh_user: Option<String>,
+    annotations: AnnotationStore,
+    audit: AuditLog,
+    start: StartView,
+    dashboard: Option<Dashboard>,
 ) -> Result<()> {
-    let mut state = AppState::new(hosts, service_configs, ssh_user);
+    let notifier = Notifier::new(&config);
+    let mut state = AppState::new(hosts, config.services, ssh_user);
+    state.annotations = annotations;
+    state.maintenance = config.maintenance;
+    state.protected_groups = config.protected_groups;
+    state.host_commands = config.host_commands;
+    state.triage = config.triage;
+    state.restart_check = config.restart_check;
+    state.updates_interval = config.package_updates.map(|u| Duration::from_secs(u.interval));
+    state.bulk_options = config.bulk;
+    state.status_glyphs = config.status_glyphs;
+    state.cell_content = config.cell;
+    state.status_words = config.status_words;
+    state.dashboard = dashboard;
+    state.repoller = (config.repoll_transitional > 0).then(|| Repoller::new(Duration::from_secs(config.repoll_transitional)));
+    state.refresh_interval = config.refresh_interval;
+    state.ssh_options = config.ssh;
+    state.audit = audit;
+    state.notifier = notifier;
+    if let Some(socket) = agent::socket() {
+        // Pick up where the agent's timeline is
+        match agent::history(socket).await {
+            Ok(entries) => state.timeline.entries.extend(entries),
+            Err(e) => log::warn!("Failed to load the agent's timeline: {:#}", e),
+        }
+    }
     let mut terminal = tui::init()?;
 
     // Set up async refresh channel
     let (refresh_tx, mut refresh_rx) = mpsc::unbounded_channel::<RefreshResult>();
 
+    if let Some((host_idx, service)) = start.focus {
+        let host = state.hosts[host_idx].address.clone();
+        log::info!("Focusing on {}:{}", host, service);
+        state.status_message = Some(format!("Loading {} on {}...", service, host));
+        state.scope = Scope::Hosts(host);
+        state.focus = Some((host_idx, service));
+    }
+    if let Some(scope) = start.scope {
+        log::info!("Scope set to {}", scope.label());
+        state.scope = scope;
+    }
+    state.pinned_hosts.extend(start.pinned);
+    state.group_globs |= start.group_globs;
+    state.invocation = start.invocation;
+
     // Initial refresh (non-blocking so the UI stays responsive)
-    log::info!("Starting initial refresh");
-    spawn_full_refresh(&mut state, &refresh_tx);
+    match config.initial_refresh {
+        InitialRefresh::All => {
+            log::info!("Starting initial refresh");
+            state.refresh.request_full();
+        }
+        InitialRefresh::None => {
+            log::info!("Skipping initial refresh");
+            state.status_message = Some("Initial refresh skipped; press r to refresh".to_string());
+        }
+        InitialRefresh::Group(group) => {
+            let selected = adhoc::select_hosts(&state.hosts, &group);
+            let subset: Vec<usize> = (0..state.hosts.len())
+                .filter(|&i| selected.contains(&state.hosts[i].address))
+                .collect();
+            if subset.is_empty() {
+                state.status_message = Some(format!("No hosts match '{}'; press r to refresh all", group));
+            } else {
+                log::info!("Starting initial refresh of {} hosts in {}", subset.len(), group);
+                state.status_message = Some(format!(
+                    "Refreshing only {} of {} hosts ({}); press r to refresh all",
+                    subset.len(),
+                    state.hosts.len(),
+                    group
+                ));
+                state.initial_subset = Some(subset);
+                state.refresh.request_full();
+            }
+        }
+    }
 
     loop {
+        let iteration_started = Instant::now();
+        dispatch_refreshes(&mut state, &refresh_tx);
+        fetch_unit_files(&mut state, &refresh_tx);
+        state.annotations.reload_if_changed();
+        state.spinner_tick = state.spinner_tick.wrapping_add(1);
         state.table_state.select(Some(state.cursor));
         terminal.draw(|f| render(f, &mut state))?;
 
@@ -173,18 +1348,100 @@ pub async fn run(
                         grid_result.service_names.len(),
                         grid_result.unreachable_hosts.len()
                     );
-                    state.apply_grid_result(grid_result);
+                    state.apply_grid_result(*grid_result);
+                    state.apply_focus();
+                    // Picks up daemon-reloads; looked up again when a detail view shows them
+                    state.unit_files.clear();
+                    // The tree's states are as live as the grid
+                    fetch_deps(&state, &refresh_tx);
+                    let _ = trace::flush();
+                }
+                RefreshResult::HostRow(update) => state.apply_host_update(update),
+                RefreshResult::HostRefreshed(result) => state.apply_host_result(*result),
+                RefreshResult::PackageUpdates(results) => {
+                    log::info!("Package update check complete for {} hosts", results.len());
+                    state.package_updates.extend(results);
+                    state.updates_running = false;
+                }
+                RefreshResult::Cell { host, service, status, detail } => {
+                    log::info!("Cell update {}:{} = {:?}", host, service, status);
+                    state.apply_cell_status(&host, &service, status, detail);
+                }
+                RefreshResult::ActionDone { host, service, action, error } => {
+                    let text = match &error {
+                        Some(e) => format!("{} {} on {} failed: {}", action, service, host, e),
+                        None => format!("{} {} on {} succeeded", action, service, host),
+                    };
+                    if error.is_none() && action == "restart" {
+                        // Restarted onto the updated binaries, until the next check says otherwise
+                        if let Some(idx) = state.hosts.iter().position(|h| h.address == host) {
+                            if let Some(units) = state.restart_needed.get_mut(&idx) {
+                                units.remove(&unit_name(&service));
+                            }
+                        }
+                    }
+                    state.timeline.push(timeline::Kind::Result, text);
+                    state.refresh.request_cell(&host, &service);
+                }
+                RefreshResult::TailLine { id, line } => {
+                    if let Some(view) = state.tail.as_mut().filter(|v| v.id == id) {
+                        view.push(line);
+                    }
+                }
+                RefreshResult::AdhocResult { id, host, run } => {
+                    if let Some(adhoc) = state.adhoc.as_mut().filter(|a| a.id == id) {
+                        adhoc.finish(&host, run);
+                    }
+                }
+                RefreshResult::BulkResult { id, host, outcome } => {
+                    if let Some(run) = state.bulk.as_mut().filter(|b| b.id == id) {
+                        run.update(&host, outcome);
+                    }
+                }
+                RefreshResult::TailEnded { id, error } => {
+                    if let Some(view) = state.tail.as_mut().filter(|v| v.id == id) {
+                        view.ended = Some(error);
+                    }
+                }
+                RefreshResult::UnitFiles { host, service, files } => {
+                    state.unit_files.insert((host, service), Some(files));
+                }
+                RefreshResult::DenialChecks { id, lines } => {
+                    if let Some(popup) = state.info_popup.as_mut().filter(|p| p.waiting == Some(id)) {
+                        // Replaces the "Checking for denials..." line
+                        popup.lines.pop();
+                        popup.lines.extend(lines);
+                        popup.waiting = None;
+                    }
+                }
+                RefreshResult::Deps { id, result } => {
+                    if let Some(view) = state.deps.as_mut().filter(|v| v.id == id) {
+                        view.load(result);
+                    }
+                }
+                RefreshResult::Properties { id, result } => {
+                    if let Some(view) = state.properties.as_mut().filter(|v| v.id == id) {
+                        view.properties = Some(result.map(|output| properties::parse(&output)));
+                        view.clamp_cursor();
+                    }
                 }
             }
         }
 
-        // Poll keyboard with 200ms timeout
+        // Poll keyboard with 200ms timeout; only the time around it counts as loop latency
+        let mut busy = iteration_started.elapsed();
         match poll_event(200)? {
             AppEvent::Key(key) => {
+                let handling_started = Instant::now();
                 handle_key(&mut state, key, &refresh_tx, &mut terminal).await?;
+                busy += handling_started.elapsed();
             }
             AppEvent::None => {}
         }
+        metrics::record(|m| {
+            m.loop_latency = busy;
+            m.loop_latency_max = m.loop_latency_max.max(busy);
+        });
 
         if state.should_quit {
             break;
@@ -192,35 +1449,282 @@ pub async fn run(
     }
 
     tui::restore()?;
+    state.session_manager().close_all().await;
+    if let Some(export) = trace::flush() {
+        let _ = export.await;
+    }
     Ok(())
 }
 
-async fn handle_key(
-    state: &mut AppState,
-    key: KeyEvent,
-    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
-    terminal: &mut tui::Tui,
+/// Monitor without the TUI, for `--events-stdout`: refresh every `interval`
+/// and let the event stream and notifications report what changed. With
+/// `agent_socket`, also serve the connections and timeline to clients.
+pub async fn run_headless(
+    hosts: Vec<Host>,
+    config: Config,
+    ssh_user: Option<String>,
+    audit: AuditLog,
+    interval: Duration,
+    agent_socket: Option<PathBuf>,
+    dashboard: Option<Dashboard>,
 ) -> Result<()> {
-    match &state.screen {
-        Screen::Main => handle_main_key(state, key, refresh_tx, terminal).await,
-        Screen::Detail {
-            host_index,
-            service_index,
-        } => {
-            let hi = *host_index;
-            let si = *service_index;
-            handle_detail_key(state, key, hi, si, refresh_tx, terminal).await
-        }
+    let notifier = Notifier::new(&config);
+    let mut state = AppState::new(hosts, config.services, ssh_user);
+    state.dashboard = dashboard;
+    state.maintenance = config.maintenance;
+    state.restart_check = config.restart_check;
+    state.ssh_options = config.ssh;
+    state.audit = audit;
+    state.notifier = notifier;
+    if let Some(socket) = &agent_socket {
+        agent::listen(socket, state.sessions.clone(), state.ssh_options.clone()).await?;
     }
-}
 
-async fn handle_main_key(
-    state: &mut AppState,
-    key: KeyEvent,
+    loop {
+        let mut session_mgr = state.session_manager();
+        let order: Vec<usize> = (0..state.hosts.len()).collect();
+        let mut grid_result = build_grid(
+            &mut session_mgr,
+            &state.hosts,
+            &state.service_configs,
+            state.restart_check.as_deref(),
+            &order,
+            |_| {},
+        )
+        .await;
+        scripts::on_refresh(&state.hosts, &mut grid_result).await;
+        warn_unmatched(&mut grid_result, &state.service_configs);
+        log::info!(
+            "Refresh complete: {} services, {} unreachable hosts",
+            grid_result.service_names.len(),
+            grid_result.unreachable_hosts.len()
+        );
+        state.apply_grid_result(grid_result);
+        agent::publish(&state.timeline);
+        let _ = trace::flush();
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    if let Some(socket) = &agent_socket {
+        let _ = std::fs::remove_file(socket);
+    }
+    state.session_manager().close_all().await;
+
+    if let Some(export) = trace::flush() {
+        let _ = export.await;
+    }
+    Ok(())
+}
+
+/// `--bench`: full refreshes of a generated fleet over the mock transport,
+/// timing each stage, then print the report.
+pub async fn run_bench(spec: BenchSpec) -> Result<()> {
+    let (hosts, configs) = spec.fleet();
+    let mut state = AppState::new(hosts, configs, None);
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(bench::SCREEN.0, bench::SCREEN.1))?;
+    let order: Vec<usize> = (0..state.hosts.len()).collect();
+    let mut timings = bench::Timings::default();
+    for _ in 0..spec.rounds {
+        let mut session_mgr = SessionManager::mocked(state.ssh_options.clone(), spec.transport());
+        let started = Instant::now();
+        let grid_result = build_grid(&mut session_mgr, &state.hosts, &state.service_configs, None, &order, |_| {}).await;
+        timings.build.push(started.elapsed());
+
+        let started = Instant::now();
+        state.apply_grid_result(grid_result);
+        timings.apply.push(started.elapsed());
+
+        let started = Instant::now();
+        terminal.draw(|f| render(f, &mut state))?;
+        timings.render.push(started.elapsed());
+    }
+    print!("{}", timings.report(&spec));
+    Ok(())
+}
+
+async fn handle_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+    terminal: &mut tui::Tui,
+) -> Result<()> {
+    let text_input = state.prompt.is_some()
+        || state.column_chooser.is_some()
+        || state.host_picker.is_some()
+        || state.info_popup.is_some()
+        || state.kill_chooser.is_some()
+        || state.plugin_chooser.is_some()
+        || state.properties.as_ref().is_some_and(|v| v.searching);
+    let mode = if state.confirm.is_some() {
+        macros::Mode::Confirm
+    } else if text_input {
+        macros::Mode::Text
+    } else {
+        macros::Mode::Normal
+    };
+    match state.macros.on_key(key, mode) {
+        macros::Outcome::Pass => {
+            dispatch_key(state, key, refresh_tx, terminal).await?;
+            if mode == macros::Mode::Confirm && state.confirm.is_none() {
+                // A replay paused here goes on only if the user said yes
+                let rest = state.macros.resume();
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) && !rest.is_empty() {
+                    replay_keys(state, rest, refresh_tx, terminal).await?;
+                }
+            }
+            Ok(())
+        }
+        macros::Outcome::Consumed(message) => {
+            if message.is_some() {
+                state.status_message = message;
+            }
+            Ok(())
+        }
+        macros::Outcome::Replay(keys) => {
+            log::info!("Replaying macro of {} keys", keys.len());
+            replay_keys(state, keys, refresh_tx, terminal).await
+        }
+    }
+}
+
+/// Handle `keys` as if typed, stopping at a confirmation for the user to
+/// answer; the rest is replayed once they confirm.
+async fn replay_keys(
+    state: &mut AppState,
+    keys: Vec<KeyEvent>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+    terminal: &mut tui::Tui,
+) -> Result<()> {
+    let mut keys = keys.into_iter();
+    while let Some(key) = keys.next() {
+        if state.should_quit {
+            break;
+        }
+        dispatch_key(state, key, refresh_tx, terminal).await?;
+        if state.confirm.is_some() {
+            state.macros.pause(keys.collect());
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn dispatch_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+    terminal: &mut tui::Tui,
+) -> Result<()> {
+    if state.prompt.is_some() {
+        handle_prompt_key(state, key, refresh_tx);
+        return Ok(());
+    }
+    if state.confirm.is_some() {
+        handle_confirm_key(state, key, refresh_tx);
+        return Ok(());
+    }
+    if state.column_chooser.is_some() {
+        handle_column_chooser_key(state, key);
+        return Ok(());
+    }
+    if state.host_picker.is_some() {
+        return handle_host_picker_key(state, key, terminal);
+    }
+    if state.info_popup.is_some() {
+        state.info_popup = None;
+        return Ok(());
+    }
+    if state.kill_chooser.is_some() {
+        handle_kill_chooser_key(state, key, refresh_tx);
+        return Ok(());
+    }
+    if state.plugin_chooser.is_some() {
+        handle_plugin_chooser_key(state, key, refresh_tx);
+        return Ok(());
+    }
+    if key.code == KeyCode::Char('Y') && matches!(state.screen, Screen::Main | Screen::Detail { .. }) {
+        share_view(state);
+        return Ok(());
+    }
+    // Ctrl+C stops whatever is running first, and only quits once nothing is
+    if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) && state.cancel_in_flight() {
+        return Ok(());
+    }
+    match &state.screen {
+        Screen::Main => handle_main_key(state, key, refresh_tx, terminal).await,
+        Screen::Stats => {
+            if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('i')) {
+                state.screen = Screen::Main;
+            }
+            Ok(())
+        }
+        Screen::Detail {
+            host_index,
+            service_index,
+        } => {
+            let hi = *host_index;
+            let si = *service_index;
+            handle_detail_key(state, key, hi, si, refresh_tx, terminal).await
+        }
+        Screen::Host { host_index } => {
+            let hi = *host_index;
+            handle_host_key(state, key, hi, terminal).await
+        }
+        Screen::Timeline => handle_timeline_key(state, key, terminal),
+        Screen::SlowHosts => {
+            handle_slow_hosts_key(state, key);
+            Ok(())
+        }
+        Screen::Compare { left, right } => {
+            let (left, right) = (*left, *right);
+            handle_compare_key(state, key, left, right, terminal).await
+        }
+        Screen::Adhoc => {
+            handle_adhoc_key(state, key);
+            Ok(())
+        }
+        Screen::Bulk => {
+            handle_bulk_key(state, key, refresh_tx);
+            Ok(())
+        }
+        Screen::Tail {
+            host_index,
+            service_index,
+        } => {
+            let (hi, si) = (*host_index, *service_index);
+            handle_tail_key(state, key, hi, si);
+            Ok(())
+        }
+        Screen::Properties {
+            host_index,
+            service_index,
+        } => {
+            let (hi, si) = (*host_index, *service_index);
+            handle_properties_key(state, key, hi, si, refresh_tx);
+            Ok(())
+        }
+        Screen::Deps {
+            host_index,
+            service_index,
+        } => {
+            let (hi, si) = (*host_index, *service_index);
+            handle_deps_key(state, key, hi, si, refresh_tx);
+            Ok(())
+        }
+    }
+}
+
+async fn handle_main_key(
+    state: &mut AppState,
+    key: KeyEvent,
     refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
     terminal: &mut tui::Tui,
 ) -> Result<()> {
     match key.code {
+        KeyCode::Esc if state.cancel_in_flight() => {}
         KeyCode::Char('q') | KeyCode::Esc => {
             log::info!("Quit requested");
             state.should_quit = true;
@@ -229,10 +1733,8 @@ async fn handle_main_key(
             log::info!("Ctrl+C quit requested");
             state.should_quit = true;
         }
-        KeyCode::Up => {
-            if state.cursor > 0 {
-                state.cursor -= 1;
-            }
+        KeyCode::Up if state.cursor > 0 => {
+            state.cursor -= 1;
         }
         KeyCode::Down => {
             let len = state.flat_len();
@@ -240,8 +1742,8 @@ async fn handle_main_key(
                 state.cursor += 1;
             }
         }
-        KeyCode::Enter => {
-            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+        KeyCode::Enter => match state.selected_entry() {
+            Some(FlatEntry::Service { host_idx, svc_idx }) => {
                 log::info!(
                     "Opening detail view for {}:{}",
                     state.hosts[host_idx].address,
@@ -253,26 +1755,212 @@ async fn handle_main_key(
                 };
                 state.detail_cursor = 0;
             }
+            Some(FlatEntry::Group { host_idx, pattern, .. }) => {
+                state.toggle_group(host_idx, &pattern);
+            }
+            _ => {}
+        },
+        KeyCode::Char('h') => {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
+                log::info!("Opening host view for {}", state.hosts[hi].address);
+                state.screen = Screen::Host { host_index: hi };
+                state.detail_cursor = 0;
+            }
+        }
+        KeyCode::Char('p') => export_report(state),
+        KeyCode::Char('a') => {
+            state.detail_cursor = state.timeline.entries.len().saturating_sub(1);
+            state.screen = Screen::Timeline;
+        }
+        KeyCode::Char('v') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                mark_for_compare(state, host_idx, svc_idx);
+            }
+        }
+        KeyCode::Char('g') => {
+            let edit = Edit::GroupGlobs(!state.group_globs);
+            apply_edit(state, &edit);
+            state.undo.record(edit);
         }
+        KeyCode::Char('z') => match state.undo.undo() {
+            Some(edit) => {
+                state.status_message = Some(format!("Undid {}", edit.describe()));
+                apply_edit(state, &edit.inverse());
+            }
+            None => state.status_message = Some("Nothing to undo".to_string()),
+        },
+        KeyCode::Char('Z') => match state.undo.redo() {
+            Some(edit) => {
+                state.status_message = Some(format!("Redid {}", edit.describe()));
+                apply_edit(state, &edit);
+            }
+            None => state.status_message = Some("Nothing to redo".to_string()),
+        },
         KeyCode::Char('r') => {
             log::info!("Full refresh requested");
-            spawn_full_refresh(state, refresh_tx);
+            state.refresh.request_full();
+        }
+        KeyCode::Char('L') => {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
+                let host = state.hosts[hi].address.clone();
+                log::info!("Refresh of {} requested", host);
+                state.refresh.request_host(&host);
+                state.status_message = Some(format!("Refreshing {}", host));
+            }
+        }
+        KeyCode::Char('i') => {
+            state.screen = Screen::Stats;
+        }
+        KeyCode::Char('C') => state.column_chooser = Some(0),
+        KeyCode::Char('o') => state.host_picker = Some(HostPicker::default()),
+        KeyCode::Char('P') => {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
+                let host = state.hosts[hi].address.clone();
+                let pinned = !state.pinned_hosts.remove(&hi);
+                if pinned {
+                    state.pinned_hosts.insert(hi);
+                }
+                log::info!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, host);
+                state.status_message = Some(format!("{} {}", if pinned { "Pinned" } else { "Unpinned" }, host));
+                // Follow the host's row to where it moved
+                if let Some(pos) = state.flat_entries().iter().position(|e| e.host_idx() == hi) {
+                    state.cursor = pos;
+                }
+            }
+        }
+        KeyCode::Char('w') => {
+            state.fit_columns = !state.fit_columns;
+            state.status_message = Some(
+                if state.fit_columns {
+                    "Columns fitted to their content"
+                } else {
+                    "Columns at their default widths"
+                }
+                .to_string(),
+            );
+        }
+        KeyCode::Char('d') => {
+            state.screen = Screen::SlowHosts;
+            state.detail_cursor = 0;
+        }
+        KeyCode::Char('+') | KeyCode::Char('-') => {
+            let by = if key.code == KeyCode::Char('+') { 1 } else { -1 };
+            state.status_message = Some(
+                logging::adjust_verbosity(by).unwrap_or_else(|| "Logging is off (start with --log, --syslog or --journald)".to_string()),
+            );
+        }
+        KeyCode::Char('u') => {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
+                let host = state.hosts[hi].address.clone();
+                let current = state.ssh_options.user_for(state.ssh_user.as_deref(), &host).unwrap_or("");
+                state.prompt = Some(Prompt {
+                    title: format!("SSH user for {} (empty for the default)", host),
+                    input: current.to_string(),
+                    action: PromptAction::SetUser { host: Some(host) },
+                });
+            }
+        }
+        KeyCode::Char('e') => {
+            let group = state
+                .selected_entry()
+                .map(|e| state.hosts[e.host_idx()].group.clone())
+                .unwrap_or_default();
+            state.prompt = Some(Prompt {
+                title: "Group or host glob to run a command on ('all' for every host)".to_string(),
+                input: group,
+                action: PromptAction::AdhocTarget,
+            });
+        }
+        KeyCode::Char('U') => {
+            state.prompt = Some(Prompt {
+                title: "SSH user for all hosts (empty for the ssh config's)".to_string(),
+                input: state.ssh_user.clone().unwrap_or_default(),
+                action: PromptAction::SetUser { host: None },
+            });
         }
         KeyCode::Char('c') => {
-            let host_idx = match state.selected_entry() {
-                Some(FlatEntry::Service { host_idx, .. }) => Some(host_idx),
-                Some(FlatEntry::UnreachableHost { host_idx, .. }) => Some(host_idx),
-                None => None,
-            };
-            if let Some(hi) = host_idx {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
                 let host = state.hosts[hi].address.clone();
-                let ssh_dest = match &state.ssh_user {
-                    Some(user) => format!("{}@{}", user, host),
-                    None => host.clone(),
-                };
-                log::info!("Opening SSH session to {}", ssh_dest);
-                suspend_and_run(terminal, &["ssh", &ssh_dest])?;
-                log::info!("Returned from SSH session to {}", ssh_dest);
+                let args = state.ssh_options.shell_args(state.ssh_user.as_deref(), &host);
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                log::info!("Opening SSH session to {}", host);
+                suspend_and_run(terminal, &args)?;
+                log::info!("Returned from SSH session to {}", host);
+            }
+        }
+        KeyCode::Char('G') => {
+            state.prompt = Some(Prompt {
+                title: "Scope for r and bulk actions: group or host glob, 'pinned', or empty for all hosts".to_string(),
+                input: state.scope.input(),
+                action: PromptAction::Scope,
+            });
+        }
+        KeyCode::Char('M') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                request_unmask(state, host_idx, svc_idx, refresh_tx);
+            }
+        }
+        KeyCode::Char('x') => {
+            if let Some(entry) = state.selected_entry() {
+                show_raw_status(state, &entry, refresh_tx);
+            }
+        }
+        KeyCode::Char('!') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                run_triage(terminal, state, host_idx, svc_idx).await?;
+            }
+        }
+        KeyCode::Char('K') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                open_kill_chooser(state, host_idx, svc_idx);
+            }
+        }
+        KeyCode::Char('X') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                open_plugin_chooser(state, host_idx, svc_idx);
+            }
+        }
+        KeyCode::Char('R') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                request_reset_failed(state, host_idx, svc_idx, refresh_tx);
+            }
+        }
+        KeyCode::Char('O') => {
+            if let Some(hi) = state.selected_entry().map(|e| e.host_idx()) {
+                open_batch_shell(state, hi, terminal)?;
+            }
+        }
+        KeyCode::Char('m') => {
+            if let Some(entry) = state.selected_entry() {
+                let (host, service) = state.entry_cell(&entry);
+                match state.annotations.update(|a| a.toggle_silence(&host, &service)) {
+                    Ok(silenced) => {
+                        log::info!("{} {}:{}", if silenced { "Silenced" } else { "Unsilenced" }, host, service);
+                        state.undo.record(Edit::Silence { host, service, silenced });
+                    }
+                    Err(e) => {
+                        log::error!("Failed to save silence: {:#}", e);
+                        state.status_message = Some(format!("Failed to save silence: {}", e));
+                    }
+                }
+            }
+        }
+        KeyCode::Char('f') => {
+            if let Some(entry) = state.selected_entry() {
+                let (host, service) = state.entry_cell(&entry);
+                match state.annotations.update(|a| a.toggle_favorite(&host, &service)) {
+                    Ok(favorite) => state.undo.record(Edit::Favorite { host, service, favorite }),
+                    Err(e) => {
+                        log::error!("Failed to save favorite: {:#}", e);
+                        state.status_message = Some(format!("Failed to save favorite: {}", e));
+                    }
+                }
+            }
+        }
+        KeyCode::Char('n') => {
+            if let Some(entry) = state.selected_entry() {
+                let (host, service) = state.entry_cell(&entry);
+                edit_note(state, terminal, &host, &service)?;
             }
         }
         KeyCode::Char('s') => {
@@ -280,7 +1968,7 @@ async fn handle_main_key(
                 let host = state.hosts[host_idx].address.clone();
                 let svc = state.grid[host_idx][svc_idx].service_name.clone();
                 log::info!("Stopping service {} on {}", svc, host);
-                run_service_action(state, &host, &svc, "stop", host_idx, svc_idx).await;
+                request_service_action(state, &host, &svc, "stop", refresh_tx);
             }
         }
         KeyCode::Char('t') => {
@@ -288,7 +1976,14 @@ async fn handle_main_key(
                 let host = state.hosts[host_idx].address.clone();
                 let svc = state.grid[host_idx][svc_idx].service_name.clone();
                 log::info!("Restarting service {} on {}", svc, host);
-                run_service_action(state, &host, &svc, "restart", host_idx, svc_idx).await;
+                request_service_action(state, &host, &svc, "restart", refresh_tx);
+            }
+        }
+        KeyCode::Char('S') | KeyCode::Char('T') => {
+            if let Some(FlatEntry::Service { host_idx, svc_idx }) = state.selected_entry() {
+                let svc = state.grid[host_idx][svc_idx].service_name.clone();
+                let action = if key.code == KeyCode::Char('S') { "stop" } else { "restart" };
+                request_bulk_action(state, &svc, action);
             }
         }
         _ => {}
@@ -316,150 +2011,2021 @@ async fn handle_detail_key(
             state.screen = Screen::Main;
             state.detail_cursor = 0;
         }
-        KeyCode::Up => {
-            if state.detail_cursor > 0 {
-                state.detail_cursor -= 1;
-            }
+        KeyCode::Up if state.detail_cursor > 0 => {
+            state.detail_cursor -= 1;
         }
-        KeyCode::Down => {
-            if state.detail_cursor + 1 < item_count {
-                state.detail_cursor += 1;
-            }
+        KeyCode::Down if state.detail_cursor + 1 < item_count => {
+            state.detail_cursor += 1;
         }
         KeyCode::Enter => {
             let items = state.detail_items(host_idx, svc_idx);
             if let Some(item) = items.get(state.detail_cursor) {
-                let host = &state.hosts[host_idx].address;
+                let host = state.hosts[host_idx].address.clone();
+                let host = &host;
                 match item {
                     DetailItem::File(path) => {
                         log::info!("Viewing file {} on {}", path, host);
                         let cmd = format!("cat {}", path);
-                        open_in_vim(terminal, host, &cmd, &state.ssh_user).await?;
+                        open_in_vim(terminal, state, host, &cmd, ViewMode::Auto).await?;
+                    }
+                    DetailItem::Command(cmd)
+                        if state.grid[host_idx][svc_idx].config.interactive.contains(cmd) =>
+                    {
+                        log::info!("Running interactive command '{}' on {}", cmd, host);
+                        let args = state.ssh_options.command_args(state.ssh_user.as_deref(), host, cmd);
+                        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                        suspend_and_run(terminal, &args)?;
+                        log::info!("Returned from interactive command '{}' on {}", cmd, host);
                     }
                     DetailItem::Command(cmd) => {
                         log::info!("Running command '{}' on {} and viewing in vim", cmd, host);
-                        open_in_vim(terminal, host, cmd, &state.ssh_user).await?;
+                        open_in_vim(terminal, state, host, cmd, ViewMode::Auto).await?;
                     }
                     DetailItem::Header(_) => {}
                 }
             }
         }
+        KeyCode::Char('x') => {
+            if let Some(cmd) = state.detail_command(host_idx, svc_idx) {
+                let host = state.hosts[host_idx].address.clone();
+                log::info!("Viewing '{}' on {} as a hexdump", cmd, host);
+                open_in_vim(terminal, state, &host, &cmd, ViewMode::Hexdump).await?;
+            }
+        }
+        KeyCode::Char('A') => {
+            if let Some(cmd) = state.detail_command(host_idx, svc_idx) {
+                let host = state.hosts[host_idx].address.clone();
+                log::info!("Streaming full output of '{}' on {}", cmd, host);
+                open_full_in_vim(terminal, state, &host, &cmd).await?;
+            }
+        }
+        KeyCode::Char('F') => {
+            let items = state.detail_items(host_idx, svc_idx);
+            if let Some(DetailItem::File(path)) = items.get(state.detail_cursor) {
+                let path = path.clone();
+                open_tail(state, host_idx, svc_idx, &path, refresh_tx);
+            }
+        }
+        KeyCode::Char('p') => open_properties(state, host_idx, svc_idx, refresh_tx),
+        KeyCode::Char('!') => run_triage(terminal, state, host_idx, svc_idx).await?,
+        KeyCode::Char('D') => {
+            let host = state.hosts[host_idx].address.clone();
+            let unit = unit_name(&state.grid[host_idx][svc_idx].service_name);
+            state.deps_seq += 1;
+            state.deps = Some(DepsView::new(state.deps_seq, &host, &unit));
+            state.screen = Screen::Deps {
+                host_index: host_idx,
+                service_index: svc_idx,
+            };
+            fetch_deps(state, refresh_tx);
+        }
         KeyCode::Char('r') => {
-            spawn_full_refresh(state, refresh_tx);
+            state.refresh.request_full();
         }
         KeyCode::Char('c') => {
             let host = state.hosts[host_idx].address.clone();
-            let ssh_dest = match &state.ssh_user {
-                Some(user) => format!("{}@{}", user, host),
-                None => host.clone(),
-            };
-            suspend_and_run(terminal, &["ssh", &ssh_dest])?;
+            let args = state.ssh_options.shell_args(state.ssh_user.as_deref(), &host);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            suspend_and_run(terminal, &args)?;
         }
         KeyCode::Char('s') => {
             let host = state.hosts[host_idx].address.clone();
             let svc = state.grid[host_idx][svc_idx].service_name.clone();
-            run_service_action(state, &host, &svc, "stop", host_idx, svc_idx).await;
+            request_service_action(state, &host, &svc, "stop", refresh_tx);
         }
         KeyCode::Char('t') => {
             let host = state.hosts[host_idx].address.clone();
             let svc = state.grid[host_idx][svc_idx].service_name.clone();
-            run_service_action(state, &host, &svc, "restart", host_idx, svc_idx).await;
+            request_service_action(state, &host, &svc, "restart", refresh_tx);
         }
+        KeyCode::Char('M') => request_unmask(state, host_idx, svc_idx, refresh_tx),
+        KeyCode::Char('K') => open_kill_chooser(state, host_idx, svc_idx),
+        KeyCode::Char('X') => open_plugin_chooser(state, host_idx, svc_idx),
+        KeyCode::Char('R') => request_reset_failed(state, host_idx, svc_idx, refresh_tx),
         _ => {}
     }
     Ok(())
 }
 
-fn spawn_full_refresh(
+/// Follow a file on the host with `tail -F`, streaming into the tail view.
+fn open_tail(
     state: &mut AppState,
+    host_idx: usize,
+    svc_idx: usize,
+    path: &str,
     refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
 ) {
-    if state.refreshing {
-        return;
-    }
-    state.refreshing = true;
+    let host = state.hosts[host_idx].address.clone();
+    log::info!("Following {} on {}", path, host);
+    state.tail_seq += 1;
+    let id = state.tail_seq;
+    state.tail = Some(LogView::new(id, format!("tail -F {} on {}", path, host)));
+    state.screen = Screen::Tail {
+        host_index: host_idx,
+        service_index: svc_idx,
+    };
 
-    let hosts = state.hosts.clone();
-    let configs = state.service_configs.clone();
-    let ssh_user = state.ssh_user.clone();
+    let cmd = format!("tail -n 200 -F {}", path);
+    let mut session_mgr = state.session_manager();
     let tx = refresh_tx.clone();
+    state.tail_task = Some(tokio::spawn(async move {
+        let result = session_mgr
+            .stream_lines(&host, &cmd, |line| tx.send(RefreshResult::TailLine { id, line }).is_ok())
+            .await;
+        let error = result.err().map(|e| format!("{:#}", e));
+        let _ = tx.send(RefreshResult::TailEnded { id, error });
+    }));
+}
+
+/// Open the property inspector and fetch `systemctl show` for the cell.
+fn open_properties(
+    state: &mut AppState,
+    host_idx: usize,
+    svc_idx: usize,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let host = state.hosts[host_idx].address.clone();
+    let unit = unit_name(&state.grid[host_idx][svc_idx].service_name);
+    state.properties_seq += 1;
+    let id = state.properties_seq;
+    state.properties = Some(PropertyView::new(id, format!("{} on {}", unit, host)));
+    state.screen = Screen::Properties {
+        host_index: host_idx,
+        service_index: svc_idx,
+    };
 
+    let cmd = format!("systemctl show '{}'", unit);
+    let mut session_mgr = state.session_manager();
+    let tx = refresh_tx.clone();
     tokio::spawn(async move {
-        let mut session_mgr = SessionManager::new(ssh_user);
-        let grid_result = build_grid(&mut session_mgr, &hosts, &configs).await;
-        let _ = tx.send(RefreshResult::FullGrid(grid_result));
-        session_mgr.close_all().await;
+        let result = session_mgr.run_command(&host, &cmd).await.map_err(|e| format!("{:#}", e));
+        let _ = tx.send(RefreshResult::Properties { id, result });
     });
 }
 
-async fn run_service_action(
+fn handle_properties_key(
     state: &mut AppState,
-    host: &str,
-    service: &str,
-    action: &str,
+    key: KeyEvent,
     host_idx: usize,
     svc_idx: usize,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
 ) {
-    let mut session_mgr = SessionManager::new(state.ssh_user.clone());
-    let cmd = format!("sudo systemctl {} {}", action, service);
-    match session_mgr.run_command(host, &cmd).await {
-        Ok(_) => log::info!("Service action '{}' succeeded for {} on {}", action, service, host),
-        Err(e) => log::error!("Service action '{}' failed for {} on {}: {}", action, service, host, e),
+    let Some(view) = state.properties.as_mut() else {
+        return;
+    };
+    if view.searching {
+        match key.code {
+            KeyCode::Enter => view.searching = false,
+            KeyCode::Esc => {
+                view.searching = false;
+                view.query.clear();
+            }
+            KeyCode::Backspace => {
+                view.query.pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => view.query.push(c),
+            _ => {}
+        }
+        view.clamp_cursor();
+        return;
     }
-
-    // Refresh the cell
-    let status = refresh_cell(&mut session_mgr, host, service).await;
-    log::info!("Status after {} for {}:{} = {:?}", action, host, service, status);
-    if host_idx < state.grid.len() && svc_idx < state.grid[host_idx].len() {
-        state.grid[host_idx][svc_idx].status = status;
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.properties = None;
+            state.screen = Screen::Detail {
+                host_index: host_idx,
+                service_index: svc_idx,
+            };
+        }
+        KeyCode::Char('/') => {
+            view.searching = true;
+            view.query.clear();
+        }
+        KeyCode::Char('a') => {
+            view.show_all = !view.show_all;
+            view.cursor = 0;
+        }
+        KeyCode::Char('r') => open_properties(state, host_idx, svc_idx, refresh_tx),
+        KeyCode::Up => view.cursor = view.cursor.saturating_sub(1),
+        KeyCode::Down => {
+            view.cursor += 1;
+            view.clamp_cursor();
+        }
+        KeyCode::PageUp => view.cursor = view.cursor.saturating_sub(20),
+        KeyCode::PageDown => {
+            view.cursor += 20;
+            view.clamp_cursor();
+        }
+        KeyCode::Enter => {
+            if let Some((name, value)) = view.visible().get(view.cursor).copied().cloned() {
+                state.info_popup = Some(InfoPopup {
+                    title: name.clone(),
+                    lines: properties::value_lines(&name, &value),
+                    hint: "any key:close".to_string(),
+                    waiting: None,
+                });
+            }
+        }
+        _ => {}
     }
-    session_mgr.close_all().await;
-}
-
-async fn open_in_vim(
-    terminal: &mut tui::Tui,
-    host: &str,
-    cmd: &str,
-    ssh_user: &Option<String>,
-) -> Result<()> {
-    // Run the command on the remote host, write output to a temp file, open in vim
-    let mut session_mgr = SessionManager::new(ssh_user.clone());
-    let output = session_mgr
-        .run_command(host, cmd)
-        .await
-        .unwrap_or_else(|e| format!("Error: {}", e));
-    session_mgr.close_all().await;
-
-    let tmp = std::env::temp_dir().join(format!(
-        "sctl-{}-{}.txt",
-        host.replace('.', "_"),
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-    ));
-
-    std::fs::write(&tmp, &output)?;
-
-    suspend_and_run(terminal, &["vim", "-R", tmp.to_str().unwrap()])?;
-
-    let _ = std::fs::remove_file(&tmp);
-    Ok(())
 }
 
-fn suspend_and_run(terminal: &mut tui::Tui, args: &[&str]) -> Result<()> {
-    tui::suspend()?;
+/// Look up the unit files of the cell in the open detail view, once.
+fn fetch_unit_files(state: &mut AppState, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let Screen::Detail { host_index, service_index } = state.screen else {
+        return;
+    };
+    let Some(hs) = state.grid.get(host_index).and_then(|row| row.get(service_index)) else {
+        return;
+    };
+    let key = (hs.host_address.clone(), hs.service_name.clone());
+    if state.unit_files.contains_key(&key) || state.unreachable_hosts.contains_key(&host_index) {
+        return;
+    }
+    state.unit_files.insert(key.clone(), None);
 
-    let status = Command::new(args[0])
-        .args(&args[1..])
-        .status();
+    let (host, service) = key;
+    let cmd = format!("systemctl show --property={} '{}'", properties::FILE_PROPERTIES, unit_name(&service));
+    let mut session_mgr = state.session_manager();
+    let tx = refresh_tx.clone();
+    tokio::spawn(async move {
+        let files = match session_mgr.run_command(&host, &cmd).await {
+            Ok(output) => properties::unit_files(&output),
+            Err(e) => {
+                log::warn!("Failed to look up unit files of {} on {}: {:#}", service, host, e);
+                Vec::new()
+            }
+        };
+        let _ = tx.send(RefreshResult::UnitFiles { host, service, files });
+    });
+}
 
-    match status {
-        Ok(_) => {}
-        Err(e) => eprintln!("Failed to run {:?}: {}", args, e),
-    }
+/// (Re)fetch the open dependency tree with the states of its units.
+fn fetch_deps(state: &AppState, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let Some(view) = &state.deps else {
+        return;
+    };
+    let (id, host, cmd) = (view.id, view.host.clone(), deps::script(&view.unit));
+    let mut session_mgr = state.session_manager();
+    let tx = refresh_tx.clone();
+    tokio::spawn(async move {
+        let result = session_mgr
+            .run_command(&host, &cmd)
+            .await
+            .map(|output| deps::parse(&output))
+            .map_err(|e| format!("{:#}", e));
+        let _ = tx.send(RefreshResult::Deps { id, result });
+    });
+}
 
-    let new_terminal = tui::resume()?;
-    *terminal = new_terminal;
+fn handle_deps_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    host_idx: usize,
+    svc_idx: usize,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let Some(view) = state.deps.as_mut() else {
+        return;
+    };
+    let visible = view.visible();
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.deps = None;
+            state.screen = Screen::Detail {
+                host_index: host_idx,
+                service_index: svc_idx,
+            };
+        }
+        KeyCode::Up if view.cursor > 0 => view.cursor -= 1,
+        KeyCode::Down if view.cursor + 1 < visible.len() => view.cursor += 1,
+        KeyCode::Enter | KeyCode::Char(' ') => {
+            if let Some(&i) = visible.get(view.cursor) {
+                view.toggle(i);
+            }
+        }
+        KeyCode::Char('+') => view.set_all(false),
+        KeyCode::Char('-') => view.set_all(true),
+        KeyCode::Char('r') => fetch_deps(state, refresh_tx),
+        _ => {}
+    }
+}
+
+async fn handle_host_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    host_idx: usize,
+    terminal: &mut tui::Tui,
+) -> Result<()> {
+    let host = state.hosts[host_idx].address.clone();
+    let selected = state.host_commands.get(state.detail_cursor).cloned();
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.screen = Screen::Main;
+            state.detail_cursor = 0;
+        }
+        KeyCode::Up if state.detail_cursor > 0 => {
+            state.detail_cursor -= 1;
+        }
+        KeyCode::Down if state.detail_cursor + 1 < state.host_commands.len() => {
+            state.detail_cursor += 1;
+        }
+        KeyCode::Enter => {
+            if let Some(cmd) = selected {
+                log::info!("Running host command '{}' on {} and viewing in vim", cmd, host);
+                open_in_vim(terminal, state, &host, &cmd, ViewMode::Auto).await?;
+            }
+        }
+        KeyCode::Char('x') => {
+            if let Some(cmd) = selected {
+                open_in_vim(terminal, state, &host, &cmd, ViewMode::Hexdump).await?;
+            }
+        }
+        KeyCode::Char('A') => {
+            if let Some(cmd) = selected {
+                open_full_in_vim(terminal, state, &host, &cmd).await?;
+            }
+        }
+        KeyCode::Char('R') => request_host_reset_failed(state, host_idx),
+        KeyCode::Char('c') => {
+            let args = state.ssh_options.shell_args(state.ssh_user.as_deref(), &host);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            suspend_and_run(terminal, &args)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Write a Markdown incident report to the working directory.
+fn export_report(state: &mut AppState) {
+    let cells: Vec<report::CellRow> = state
+        .grid
+        .iter()
+        .flatten()
+        .map(|hs| report::CellRow {
+            host: hs.host_address.clone(),
+            service: hs.service_name.clone(),
+            status: hs.status.name().to_string(),
+            silenced: state.is_silenced(&hs.host_address, &hs.service_name),
+        })
+        .collect();
+    let mut unreachable: Vec<(String, String)> = state
+        .unreachable_hosts
+        .iter()
+        .map(|(i, e)| (state.hosts[*i].address.clone(), e.reason().to_string()))
+        .collect();
+    unreachable.sort();
+    let user = annotations::current_user();
+    let report = Report {
+        now: clock::now_secs(),
+        user: &user,
+        cells: &cells,
+        unreachable: &unreachable,
+        timeline: &state.timeline,
+        audit: &state.audit.entries,
+        notes: &state.annotations.data.notes,
+    };
+    let path = report.file_name();
+    match std::fs::write(&path, report.to_markdown()) {
+        Ok(()) => {
+            log::info!("Wrote incident report to {}", path);
+            state.status_message = Some(format!("Report written to {}", path));
+        }
+        Err(e) => {
+            log::error!("Failed to write report {}: {}", path, e);
+            state.status_message = Some(format!("Failed to write {}: {}", path, e));
+        }
+    }
+}
+
+fn handle_timeline_key(state: &mut AppState, key: KeyEvent, terminal: &mut tui::Tui) -> Result<()> {
+    let last = state.timeline.entries.len().saturating_sub(1);
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('a') => {
+            state.screen = Screen::Main;
+            state.detail_cursor = 0;
+        }
+        KeyCode::Up => state.detail_cursor = state.detail_cursor.saturating_sub(1),
+        KeyCode::Down => state.detail_cursor = (state.detail_cursor + 1).min(last),
+        KeyCode::PageUp => state.detail_cursor = state.detail_cursor.saturating_sub(20),
+        KeyCode::PageDown => state.detail_cursor = (state.detail_cursor + 20).min(last),
+        KeyCode::Home => state.detail_cursor = 0,
+        KeyCode::End => state.detail_cursor = last,
+        KeyCode::Enter => {
+            let tmp = scratch::TempFile::create("timeline", ".txt", state.timeline.to_text().as_bytes())?;
+            suspend_and_run(terminal, &["vim", "-R", &tmp.path().to_string_lossy()])?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_slow_hosts_key(state: &mut AppState, key: KeyEvent) {
+    let slow = state.slow_hosts();
+    let selected = slow.get(state.detail_cursor).map(|(idx, _, _)| *idx);
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('d') => {
+            state.screen = Screen::Main;
+            state.detail_cursor = 0;
+        }
+        KeyCode::Up => state.detail_cursor = state.detail_cursor.saturating_sub(1),
+        KeyCode::Down => state.detail_cursor = (state.detail_cursor + 1).min(slow.len().saturating_sub(1)),
+        KeyCode::Enter => {
+            if let Some(host_index) = selected {
+                state.screen = Screen::Host { host_index };
+                state.detail_cursor = 0;
+            }
+        }
+        KeyCode::Char('x') => {
+            if let Some(idx) = selected {
+                let address = &state.hosts[idx].address;
+                let msg = if state.excluded_hosts.remove(&idx) {
+                    format!("{} will be refreshed again from the next full refresh", address)
+                } else {
+                    state.excluded_hosts.insert(idx);
+                    format!("{} excluded from full refreshes for this session", address)
+                };
+                log::info!("{}", msg);
+                state.status_message = Some(msg);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// First `v` marks a cell, the second opens the comparison with it.
+fn mark_for_compare(state: &mut AppState, host_idx: usize, svc_idx: usize) {
+    let hs = &state.grid[host_idx][svc_idx];
+    let label = format!("{} on {}", hs.service_name, hs.host_address);
+    match state.compare_mark.take() {
+        None => {
+            state.status_message = Some(format!("Marked {} for comparison; press v on another cell", label));
+            state.compare_mark = Some((host_idx, svc_idx));
+        }
+        Some(mark) if mark == (host_idx, svc_idx) => {
+            state.status_message = Some("Comparison mark cleared".to_string());
+        }
+        Some(mark) => {
+            let items = state.compare_items(mark, (host_idx, svc_idx));
+            if items.is_empty() {
+                state.status_message = Some("Nothing in common to compare".to_string());
+                return;
+            }
+            log::info!("Comparing {:?} with {:?}", mark, (host_idx, svc_idx));
+            // Start on the status, which is what usually explains the difference
+            state.detail_cursor = items
+                .iter()
+                .position(|(_, cmd, _)| cmd.starts_with("systemctl status"))
+                .unwrap_or(0);
+            state.status_message = None;
+            state.screen = Screen::Compare {
+                left: mark,
+                right: (host_idx, svc_idx),
+            };
+        }
+    }
+}
+
+async fn handle_compare_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    left: (usize, usize),
+    right: (usize, usize),
+    terminal: &mut tui::Tui,
+) -> Result<()> {
+    let items = state.compare_items(left, right);
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.screen = Screen::Main;
+            state.detail_cursor = 0;
+        }
+        KeyCode::Up if state.detail_cursor > 0 => {
+            state.detail_cursor -= 1;
+        }
+        KeyCode::Down if state.detail_cursor + 1 < items.len() => {
+            state.detail_cursor += 1;
+        }
+        KeyCode::Enter => {
+            if let Some((_, cmd_left, cmd_right)) = items.get(state.detail_cursor) {
+                let host_left = state.hosts[left.0].address.clone();
+                let host_right = state.hosts[right.0].address.clone();
+                open_compare_in_vim(terminal, state, (&host_left, cmd_left), (&host_right, cmd_right)).await?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_tail_key(state: &mut AppState, key: KeyEvent, host_idx: usize, svc_idx: usize) {
+    let Some(view) = state.tail.as_mut() else {
+        return;
+    };
+    let page = view.height.max(1);
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            if let Some(task) = state.tail_task.take() {
+                task.abort();
+            }
+            state.tail = None;
+            state.screen = Screen::Detail {
+                host_index: host_idx,
+                service_index: svc_idx,
+            };
+        }
+        KeyCode::Char('F') => view.toggle_follow(),
+        KeyCode::Up => view.scroll_up(1),
+        KeyCode::Down => view.scroll_down(1),
+        KeyCode::PageUp => view.scroll_up(page),
+        KeyCode::PageDown => view.scroll_down(page),
+        KeyCode::End => view.follow = true,
+        _ => {}
+    }
+}
+
+fn handle_confirm_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let Some(confirm) = state.confirm.take() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') => match confirm.action {
+            ConfirmAction::ServiceAction { host, service, action, reason } => {
+                log::info!("Confirmed '{}' for {}:{}", action, host, service);
+                spawn_service_action(state, &host, &service, &action, reason.as_deref(), refresh_tx);
+            }
+            ConfirmAction::Bulk { service, action, hosts, reason } => {
+                log::info!("Confirmed '{}' of {} on {} hosts", action, service, hosts.len());
+                start_bulk(state, &service, &action, hosts, reason.as_deref(), refresh_tx);
+            }
+            ConfirmAction::HostResetFailed { host, services, reason } => {
+                log::info!("Confirmed reset-failed of {} units on {}", services.len(), host);
+                for service in services {
+                    spawn_service_action(state, &host, &service, "reset-failed", reason.as_deref(), refresh_tx);
+                }
+            }
+            ConfirmAction::Adhoc { target, hosts, command, reason } => {
+                log::info!("Confirmed '{}' on {} hosts in {}", command, hosts.len(), target);
+                start_adhoc(state, &target, hosts, &command, reason.as_deref(), refresh_tx);
+            }
+        },
+        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+            log::info!("Cancelled: {}", confirm.message);
+            state.status_message = Some("Cancelled".to_string());
+        }
+        // Anything else leaves the question open
+        _ => state.confirm = Some(confirm),
+    }
+}
+
+fn handle_prompt_key(
+    state: &mut AppState,
+    key: KeyEvent,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let Some(mut prompt) = state.prompt.take() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Esc => {
+            log::info!("Cancelled: {}", prompt.title);
+            state.status_message = Some("Cancelled".to_string());
+            return;
+        }
+        KeyCode::Enter => {
+            let input = prompt.input.trim().to_string();
+            match prompt.action {
+                PromptAction::SetUser { host } => {
+                    set_ssh_user(state, host, input);
+                    return;
+                }
+                PromptAction::AdhocTarget => {
+                    let hosts = adhoc::select_hosts(&state.hosts, &input);
+                    if hosts.is_empty() {
+                        state.status_message = Some(format!("No hosts match '{}'", input));
+                        return;
+                    }
+                    state.prompt = Some(Prompt {
+                        title: format!("Command to run on {} ({} hosts)", input, hosts.len()),
+                        input: String::new(),
+                        action: PromptAction::AdhocCommand { target: input, hosts },
+                    });
+                    return;
+                }
+                PromptAction::Scope => {
+                    let scope = Scope::parse(&input);
+                    let count = scope.indices(&state.hosts, &state.pinned_hosts).len();
+                    if count == 0 {
+                        state.status_message = Some(format!("No hosts in {}", scope.label()));
+                        return;
+                    }
+                    log::info!("Scope set to {} ({} hosts)", scope.label(), count);
+                    state.status_message = Some(format!("Scope: {} ({} hosts)", scope.label(), count));
+                    state.scope = scope;
+                }
+                PromptAction::AdhocCommand { .. } if input.is_empty() => {
+                    state.status_message = Some("A command is required".to_string());
+                }
+                PromptAction::AdhocCommand { target, hosts } => {
+                    request_adhoc(state, target, hosts, input, refresh_tx);
+                    return;
+                }
+                PromptAction::Reason(_) if input.is_empty() => {
+                    state.status_message = Some("A reason is required".to_string());
+                }
+                PromptAction::Reason(ConfirmAction::ServiceAction { host, service, action, .. }) => {
+                    confirm_or_spawn(state, &host, &service, &action, Some(input), refresh_tx);
+                    return;
+                }
+                PromptAction::Reason(ConfirmAction::Bulk { service, action, hosts, .. }) => {
+                    confirm_bulk(state, &service, &action, hosts, Some(input));
+                    return;
+                }
+                PromptAction::Reason(ConfirmAction::HostResetFailed { host, services, .. }) => {
+                    confirm_host_reset_failed(state, host, services, Some(input));
+                    return;
+                }
+                PromptAction::Reason(ConfirmAction::Adhoc { target, hosts, command, .. }) => {
+                    confirm_adhoc(state, target, hosts, command, Some(input));
+                    return;
+                }
+            }
+        }
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            prompt.input.push(c);
+        }
+        _ => {}
+    }
+    state.prompt = Some(prompt);
+}
+
+/// Start an ad-hoc command. If any of the hosts is in a protected group a
+/// reason is asked for first; then it is confirmed, as it is on `all` or a
+/// glob reaching more than one host. Otherwise it runs right away.
+fn request_adhoc(
+    state: &mut AppState,
+    target: String,
+    hosts: Vec<String>,
+    command: String,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let mut protected: Vec<&str> = hosts.iter().filter_map(|h| state.protected_group(h)).collect();
+    protected.sort();
+    protected.dedup();
+    if !protected.is_empty() {
+        log::info!("Running '{}' in {} includes protected groups {:?}, asking for a reason", command, target, protected);
+        state.prompt = Some(Prompt {
+            title: format!("Reason to run '{}' on {} hosts ({} protected)", command, hosts.len(), protected.join(", ")),
+            input: String::new(),
+            action: PromptAction::Reason(ConfirmAction::Adhoc {
+                target,
+                hosts,
+                command,
+                reason: None,
+            }),
+        });
+        return;
+    }
+    if hosts.len() > 1 && adhoc::is_wide(&target) {
+        confirm_adhoc(state, target, hosts, command, None);
+        return;
+    }
+    start_adhoc(state, &target, hosts, &command, None, refresh_tx);
+}
+
+fn confirm_adhoc(state: &mut AppState, target: String, hosts: Vec<String>, command: String, reason: Option<String>) {
+    state.confirm = Some(Confirm {
+        message: format!("Run '{}' on {} hosts in {}?", command, hosts.len(), target),
+        action: ConfirmAction::Adhoc {
+            target,
+            hosts,
+            command,
+            reason,
+        },
+    });
+}
+
+/// Run a command on every host concurrently, at most `bulk.concurrency` at a
+/// time, and show the results as they come in.
+fn start_adhoc(
+    state: &mut AppState,
+    target: &str,
+    hosts: Vec<String>,
+    cmd: &str,
+    reason: Option<&str>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let action = format!("exec:{:?}", cmd);
+    let recorded = state.audit.entries.len();
+    for host in &hosts {
+        if let Err(e) = state.audit.record(AuditEntry::new(host, "", &action, reason)) {
+            log::error!("Failed to write audit log: {:#}", e);
+            // None of the hosts run it, including those already recorded
+            state.audit.entries.truncate(recorded);
+            state.status_message = Some(format!("Audit log failed, not running '{}': {}", cmd, e));
+            return;
+        }
+    }
+    log::info!("Running '{}' on {} hosts in {}", cmd, hosts.len(), target);
+    let mut text = format!("'{}' on {} hosts in {}", cmd, hosts.len(), target);
+    if let Some(reason) = reason {
+        text.push_str(&format!(" ({})", reason));
+    }
+    state.timeline.push(timeline::Kind::Exec, text);
+    state.adhoc_seq += 1;
+    let id = state.adhoc_seq;
+    let permits = Arc::new(Semaphore::new(state.bulk_options.concurrency.max(1)));
+    for host in &hosts {
+        let mut session_mgr = state.session_manager();
+        let host = host.clone();
+        let cmd = cmd.to_string();
+        let tx = refresh_tx.clone();
+        let permits = permits.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+            let started = Instant::now();
+            let run = match session_mgr.exec(&host, &cmd).await {
+                Ok(captured) => HostRun::from_captured(captured, started.elapsed()),
+                Err(e) => HostRun::Failed {
+                    error: format!("{:#}", e),
+                    duration: started.elapsed(),
+                },
+            };
+            let _ = tx.send(RefreshResult::AdhocResult { id, host, run });
+        });
+    }
+    state.adhoc = Some(AdhocRun::new(id, target, cmd, hosts));
+    state.screen = Screen::Adhoc;
+}
+
+fn handle_adhoc_key(state: &mut AppState, key: KeyEvent) {
+    let Some(run) = state.adhoc.as_mut() else {
+        state.screen = Screen::Main;
+        return;
+    };
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.adhoc = None;
+            state.screen = Screen::Main;
+        }
+        KeyCode::Up if run.cursor > 0 => run.cursor -= 1,
+        KeyCode::Down if run.cursor + 1 < run.results.len() => run.cursor += 1,
+        KeyCode::Enter | KeyCode::Char(' ') => run.toggle(),
+        KeyCode::Char('+') => run.set_all_expanded(true),
+        KeyCode::Char('-') => run.set_all_expanded(false),
+        _ => {}
+    }
+}
+
+fn handle_bulk_key(state: &mut AppState, key: KeyEvent, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let Some(run) = state.bulk.as_mut() else {
+        state.screen = Screen::Main;
+        return;
+    };
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state.should_quit = true;
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            state.bulk = None;
+            state.screen = Screen::Main;
+        }
+        KeyCode::Up if run.cursor > 0 => run.cursor -= 1,
+        KeyCode::Down if run.cursor + 1 < run.targets.len() => run.cursor += 1,
+        KeyCode::Char(' ') => run.toggle_selected(),
+        KeyCode::Char('r') => {
+            let hosts = run.retry_hosts();
+            if hosts.is_empty() {
+                state.status_message = Some("Nothing failed to retry".to_string());
+                return;
+            }
+            run.requeue(&hosts);
+            let (id, service, action, reason) = (run.id, run.service.clone(), run.action.clone(), run.reason.clone());
+            log::info!("Retrying '{}' of {} on {} hosts", action, service, hosts.len());
+            spawn_bulk(state, id, &service, &action, hosts, reason.as_deref(), refresh_tx);
+        }
+        _ => {}
+    }
+}
+
+fn handle_host_picker_key(state: &mut AppState, key: KeyEvent, terminal: &mut tui::Tui) -> Result<()> {
+    let Some(picker) = state.host_picker.as_mut() else {
+        return Ok(());
+    };
+    match key.code {
+        KeyCode::Esc => state.host_picker = None,
+        KeyCode::Up if picker.cursor > 0 => picker.cursor -= 1,
+        KeyCode::Down if picker.cursor + 1 < picker.matches(&state.hosts).len() => picker.cursor += 1,
+        KeyCode::Backspace => {
+            picker.query.pop();
+            picker.cursor = 0;
+        }
+        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            picker.query.push(c);
+            picker.cursor = 0;
+        }
+        KeyCode::Enter => {
+            let Some(hi) = picker.matches(&state.hosts).get(picker.cursor).copied() else {
+                return Ok(());
+            };
+            state.host_picker = None;
+            let host = state.hosts[hi].address.clone();
+            let args = state.ssh_options.shell_args(state.ssh_user.as_deref(), &host);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            log::info!("Opening SSH session to {} from the host picker", host);
+            suspend_and_run(terminal, &args)?;
+            log::info!("Returned from SSH session to {}", host);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn handle_column_chooser_key(state: &mut AppState, key: KeyEvent) {
+    let Some(cursor) = state.column_chooser else {
+        return;
+    };
+    match key.code {
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') | KeyCode::Char('C') => {
+            state.column_chooser = None;
+            let len = state.flat_len();
+            state.cursor = state.cursor.min(len.saturating_sub(1));
+        }
+        KeyCode::Up if cursor > 0 => state.column_chooser = Some(cursor - 1),
+        KeyCode::Down if cursor + 1 < state.service_configs.len() => state.column_chooser = Some(cursor + 1),
+        KeyCode::Char(' ') => {
+            let Some(service) = state.service_configs.get(cursor).map(|c| c.name_pattern.clone()) else {
+                return;
+            };
+            if state.hidden_entries.remove(&service) {
+                log::info!("Showed {}", service);
+            } else {
+                log::info!("Hid {}", service);
+                state.hidden_entries.insert(service);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Switch the SSH user for one host or for all of them. Open sessions are
+/// to the old destination, so the next command reconnects everything affected.
+fn set_ssh_user(state: &mut AppState, host: Option<String>, user: String) {
+    let user = (!user.is_empty()).then_some(user);
+    match (host, user) {
+        (Some(host), Some(user)) => {
+            log::info!("Using SSH user {} for {}", user, host);
+            state.status_message = Some(format!("Connecting to {} as {}", host, user));
+            state.ssh_options.users.insert(host, user);
+        }
+        (Some(host), None) => {
+            log::info!("Using the default SSH user for {}", host);
+            state.status_message = Some(format!("Connecting to {} as the default user", host));
+            state.ssh_options.users.remove(&host);
+        }
+        (None, user) => {
+            log::info!("Using SSH user {:?} for all hosts", user);
+            state.status_message = Some(match &user {
+                Some(user) => format!("Connecting to all hosts as {}", user),
+                None => "Connecting to all hosts as the ssh config's user".to_string(),
+            });
+            state.ssh_user = user;
+            state.ssh_options.users.clear();
+        }
+    }
+    state.refresh.request_full();
+}
+
+/// Start a stop/restart. Hosts in protected groups first ask for a reason
+/// that goes into the audit log.
+fn request_service_action(
+    state: &mut AppState,
+    host: &str,
+    service: &str,
+    action: &str,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let derived = state
+        .grid
+        .iter()
+        .flatten()
+        .any(|hs| hs.host_address == host && hs.service_name == service && hs.config.derived);
+    if derived {
+        state.status_message = Some(format!("{} on {} is derived by a script; there is no unit to {}", service, host, action));
+        return;
+    }
+    // Plugin actions run on the controller, so the host's sudo doesn't matter
+    if plugins::parse_action(action).is_none() && !state.can_sudo(host) {
+        log::warn!("Not running {} of {} on {}: no passwordless sudo", action, service, host);
+        let user = state
+            .ssh_options
+            .user_for(state.ssh_user.as_deref(), host)
+            .unwrap_or("the SSH user")
+            .to_string();
+        state.status_message = Some(format!(
+            "Cannot {} {} on {}: `sudo -n` fails for {} (grant NOPASSWD for systemctl)",
+            action, service, host, user
+        ));
+        return;
+    }
+    if let Some(group) = state.protected_group(host) {
+        log::info!("{} is in protected group {}, asking for a reason", host, group);
+        state.prompt = Some(Prompt {
+            title: format!("Reason to {} {} on {} ({} is protected)", action, service, host, group),
+            input: String::new(),
+            action: PromptAction::Reason(ConfirmAction::ServiceAction {
+                host: host.to_string(),
+                service: service.to_string(),
+                action: action.to_string(),
+                reason: None,
+            }),
+        });
+        return;
+    }
+    confirm_or_spawn(state, host, service, action, None, refresh_tx);
+}
+
+fn open_kill_chooser(state: &mut AppState, host_idx: usize, svc_idx: usize) {
+    let hs = &state.grid[host_idx][svc_idx];
+    state.kill_chooser = Some(KillChooser {
+        host: hs.host_address.clone(),
+        service: hs.service_name.clone(),
+        cursor: 0,
+    });
+}
+
+fn handle_kill_chooser_key(state: &mut AppState, key: KeyEvent, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let Some(chooser) = state.kill_chooser.as_mut() else {
+        return;
+    };
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => state.kill_chooser = None,
+        KeyCode::Up if chooser.cursor > 0 => chooser.cursor -= 1,
+        KeyCode::Down if chooser.cursor + 1 < KILL_SIGNALS.len() => chooser.cursor += 1,
+        KeyCode::Enter => {
+            let Some(chooser) = state.kill_chooser.take() else {
+                return;
+            };
+            let action = format!("kill --signal={}", KILL_SIGNALS[chooser.cursor].0);
+            request_service_action(state, &chooser.host, &chooser.service, &action, refresh_tx);
+        }
+        _ => {}
+    }
+}
+
+fn open_plugin_chooser(state: &mut AppState, host_idx: usize, svc_idx: usize) {
+    if plugins::actions().is_empty() {
+        state.status_message = Some("No plugin provides actions".to_string());
+        return;
+    }
+    let hs = &state.grid[host_idx][svc_idx];
+    state.plugin_chooser = Some(PluginChooser {
+        host: hs.host_address.clone(),
+        service: hs.service_name.clone(),
+        cursor: 0,
+    });
+}
+
+fn handle_plugin_chooser_key(state: &mut AppState, key: KeyEvent, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let Some(chooser) = state.plugin_chooser.as_mut() else {
+        return;
+    };
+    let actions = plugins::actions();
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => state.plugin_chooser = None,
+        KeyCode::Up if chooser.cursor > 0 => chooser.cursor -= 1,
+        KeyCode::Down if chooser.cursor + 1 < actions.len() => chooser.cursor += 1,
+        KeyCode::Enter => {
+            let Some(chooser) = state.plugin_chooser.take() else {
+                return;
+            };
+            let Some((plugin, action)) = actions.get(chooser.cursor) else {
+                return;
+            };
+            let action = plugins::action_id(&plugin.name, &action.name);
+            request_service_action(state, &chooser.host, &chooser.service, &action, refresh_tx);
+        }
+        _ => {}
+    }
+}
+
+/// Unmask the cell's unit, after a confirmation.
+fn request_unmask(state: &mut AppState, host_idx: usize, svc_idx: usize, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let hs = &state.grid[host_idx][svc_idx];
+    if hs.status != ServiceStatus::Masked {
+        state.status_message = Some(format!("{} on {} is not masked", hs.service_name, hs.host_address));
+        return;
+    }
+    let (host, service) = (hs.host_address.clone(), hs.service_name.clone());
+    request_service_action(state, &host, &service, "unmask", refresh_tx);
+}
+
+/// Clear the failed state of the cell's unit, so it stops sorting to the top.
+fn request_reset_failed(state: &mut AppState, host_idx: usize, svc_idx: usize, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let hs = &state.grid[host_idx][svc_idx];
+    if hs.status != ServiceStatus::Failed {
+        state.status_message = Some(format!("{} on {} is not failed", hs.service_name, hs.host_address));
+        return;
+    }
+    let (host, service) = (hs.host_address.clone(), hs.service_name.clone());
+    request_service_action(state, &host, &service, "reset-failed", refresh_tx);
+}
+
+/// `reset-failed` every failed cell on the host, after a confirmation. Each
+/// unit is audited and refreshed on its own, as if reset one by one.
+fn request_host_reset_failed(state: &mut AppState, host_idx: usize) {
+    let host = state.hosts[host_idx].address.clone();
+    let services: Vec<String> = state
+        .grid
+        .get(host_idx)
+        .into_iter()
+        .flatten()
+        .filter(|hs| hs.status == ServiceStatus::Failed)
+        .filter(|hs| !state.pending_actions.contains_key(&(host.clone(), hs.service_name.clone())))
+        .map(|hs| hs.service_name.clone())
+        .collect();
+    if services.is_empty() {
+        state.status_message = Some(format!("No failed service on {}", host));
+        return;
+    }
+    if !state.can_sudo(&host) {
+        state.status_message = Some(format!("Cannot reset-failed on {}: `sudo -n` fails", host));
+        return;
+    }
+    if let Some(group) = state.protected_group(&host) {
+        log::info!("{} is in protected group {}, asking for a reason", host, group);
+        state.prompt = Some(Prompt {
+            title: format!("Reason to reset-failed {} units on {} ({} is protected)", services.len(), host, group),
+            input: String::new(),
+            action: PromptAction::Reason(ConfirmAction::HostResetFailed {
+                host,
+                services,
+                reason: None,
+            }),
+        });
+        return;
+    }
+    confirm_host_reset_failed(state, host, services, None);
+}
+
+fn confirm_host_reset_failed(state: &mut AppState, host: String, services: Vec<String>, reason: Option<String>) {
+    state.confirm = Some(Confirm {
+        message: format!("reset-failed {} on {}?", services.join(", "), host),
+        action: ConfirmAction::HostResetFailed { host, services, reason },
+    });
+}
+
+/// Start a stop/restart of a service on every reachable host that has it.
+/// Always confirmed, and a reason is asked for first if any of the hosts is
+/// in a protected group.
+fn request_bulk_action(state: &mut AppState, service: &str, action: &str) {
+    let hosts: Vec<String> = (0..state.hosts.len())
+        .filter(|i| !state.unreachable_hosts.contains_key(i) && !state.excluded_hosts.contains(i))
+        .filter(|&i| state.in_scope(i))
+        .filter(|&i| {
+            state
+                .grid
+                .get(i)
+                .is_some_and(|row| row.iter().any(|hs| hs.service_name == service && !hs.config.derived))
+        })
+        .map(|i| state.hosts[i].address.clone())
+        .collect();
+    if hosts.is_empty() {
+        state.status_message = Some(format!("No reachable host in {} has {}", state.scope.label(), service));
+        return;
+    }
+    let mut protected: Vec<&str> = hosts.iter().filter_map(|h| state.protected_group(h)).collect();
+    protected.sort();
+    protected.dedup();
+    if !protected.is_empty() {
+        log::info!("Bulk {} of {} includes protected groups {:?}, asking for a reason", action, service, protected);
+        state.prompt = Some(Prompt {
+            title: format!(
+                "Reason to {} {} on {} hosts ({} protected)",
+                action,
+                service,
+                hosts.len(),
+                protected.join(", ")
+            ),
+            input: String::new(),
+            action: PromptAction::Reason(ConfirmAction::Bulk {
+                service: service.to_string(),
+                action: action.to_string(),
+                hosts,
+                reason: None,
+            }),
+        });
+        return;
+    }
+    confirm_bulk(state, service, action, hosts, None);
+}
+
+fn confirm_bulk(state: &mut AppState, service: &str, action: &str, hosts: Vec<String>, reason: Option<String>) {
+    let mut message = format!("{} {} on {} hosts?", action, service, hosts.len());
+    if action == "restart" {
+        let cooling = hosts.iter().filter(|h| state.restart_cooldown(h, service).is_some()).count();
+        if cooling > 0 {
+            message.push_str(&format!(" {} of them were restarted within the minimum interval.", cooling));
+        }
+    }
+    let no_sudo = hosts.iter().filter(|h| !state.can_sudo(h)).count();
+    if no_sudo > 0 {
+        message.push_str(&format!(" {} lack passwordless sudo and will fail.", no_sudo));
+    }
+    state.confirm = Some(Confirm {
+        message,
+        action: ConfirmAction::Bulk {
+            service: service.to_string(),
+            action: action.to_string(),
+            hosts,
+            reason,
+        },
+    });
+}
+
+/// Run a confirmed bulk action and show its results on the Bulk screen.
+fn start_bulk(
+    state: &mut AppState,
+    service: &str,
+    action: &str,
+    hosts: Vec<String>,
+    reason: Option<&str>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    state.bulk_seq += 1;
+    let id = state.bulk_seq;
+    state.bulk = Some(BulkRun::new(id, action, service, reason, hosts.clone()));
+    spawn_bulk(state, id, service, action, hosts, reason, refresh_tx);
+    state.status_message = None;
+    state.screen = Screen::Bulk;
+}
+
+/// Run the action on each host concurrently, at most `bulk.concurrency` at
+/// a time. Every host goes through the same pending marker, audit entry and
+/// follow-up cell refresh as a single action, and reports its outcome to the
+/// bulk run with this id.
+fn spawn_bulk(
+    state: &mut AppState,
+    id: u64,
+    service: &str,
+    action: &str,
+    hosts: Vec<String>,
+    reason: Option<&str>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    state.action_tasks.retain(|t| !t.is_finished());
+    let permits = Arc::new(Semaphore::new(state.bulk_options.concurrency.max(1)));
+    let timeout = Duration::from_secs(state.bulk_options.timeout);
+    for host in hosts {
+        let refused = if !state.can_sudo(&host) {
+            Some("no passwordless sudo".to_string())
+        } else {
+            begin_action(state, &host, service, action, reason).err()
+        };
+        if let Some(error) = refused {
+            let outcome = bulk::Outcome::Failed {
+                error,
+                duration: Duration::ZERO,
+            };
+            if let Some(run) = state.bulk.as_mut().filter(|b| b.id == id) {
+                run.update(&host, outcome);
+            }
+            continue;
+        }
+
+        let session_mgr = state.session_manager();
+        let service = service.to_string();
+        let action = action.to_string();
+        let permits = permits.clone();
+        let tx = refresh_tx.clone();
+        let task = tokio::spawn(async move {
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+            let _ = tx.send(RefreshResult::BulkResult {
+                id,
+                host: host.clone(),
+                outcome: bulk::Outcome::Running,
+            });
+            let started = Instant::now();
+            let result = tokio::time::timeout(timeout, run_action(session_mgr, &host, &service, &action)).await;
+            let duration = started.elapsed();
+            let (outcome, error) = match result {
+                Ok(None) => (bulk::Outcome::Ok(duration), None),
+                Ok(Some(error)) => (
+                    bulk::Outcome::Failed {
+                        error: error.clone(),
+                        duration,
+                    },
+                    Some(error),
+                ),
+                Err(_) => {
+                    log::error!("Service action '{}' timed out for {} on {}", action, service, host);
+                    (
+                        bulk::Outcome::TimedOut(duration),
+                        Some(format!("timed out after {}", clock::format_duration(timeout))),
+                    )
+                }
+            };
+            let _ = tx.send(RefreshResult::BulkResult {
+                id,
+                host: host.clone(),
+                outcome,
+            });
+            let _ = tx.send(RefreshResult::ActionDone { host, service, action, error });
+        });
+        state.action_tasks.push(task);
+    }
+}
+
+/// Run the action, asking for confirmation first if it is a restart within
+/// the service's `min_restart_interval`.
+fn confirm_or_spawn(
+    state: &mut AppState,
+    host: &str,
+    service: &str,
+    action: &str,
+    reason: Option<String>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    if action == "restart" {
+        if let Some((elapsed, interval)) = state.restart_cooldown(host, service) {
+            log::warn!(
+                "{} on {} was restarted {} ago (min interval {}), asking for override",
+                service,
+                host,
+                clock::format_duration(elapsed),
+                clock::format_duration(interval)
+            );
+            state.confirm = Some(Confirm {
+                message: format!(
+                    "{} on {} was restarted {} ago (minimum interval {}). Restart anyway?",
+                    service,
+                    host,
+                    clock::format_duration(elapsed),
+                    clock::format_duration(interval)
+                ),
+                action: ConfirmAction::ServiceAction {
+                    host: host.to_string(),
+                    service: service.to_string(),
+                    action: action.to_string(),
+                    reason,
+                },
+            });
+            return;
+        }
+    }
+    let question = match action.strip_prefix("kill --signal=") {
+        Some(signal) => Some(format!("Send {} to every process of {} on {}?", signal, service, host)),
+        None if plugins::parse_action(action).is_some() => Some(format!(
+            "Run {} for {} on {}?",
+            plugin_action_label(action),
+            service,
+            host
+        )),
+        None if action == "unmask" => Some(format!(
+            "Unmask {} on {}? It can then be started again, by hand or as a dependency.",
+            service, host
+        )),
+        None => None,
+    };
+    if let Some(message) = question {
+        state.confirm = Some(Confirm {
+            message,
+            action: ConfirmAction::ServiceAction {
+                host: host.to_string(),
+                service: service.to_string(),
+                action: action.to_string(),
+                reason,
+            },
+        });
+        return;
+    }
+    spawn_service_action(state, host, service, action, reason.as_deref(), refresh_tx);
+}
+
+/// Hand any work queued in the refresh coordinator to background tasks.
+/// This is the only place refresh SSH sessions are created.
+fn dispatch_refreshes(state: &mut AppState, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    if state.repoller.is_some() && !state.refresh.is_refreshing() {
+        repoll_transitional(state);
+    }
+    if state.next_refresh_in().is_some_and(|d| d.is_zero()) {
+        log::debug!("Automatic refresh due");
+        state.refresh.request_full();
+    }
+    while let Some(batch) = state.refresh.next_batch() {
+        spawn_refresh_batch(state, batch, refresh_tx);
+    }
+    if let Some(interval) = state.updates_interval {
+        // Wait for the first full grid so unreachable hosts are known
+        let due = !state.updates_running
+            && state.grid.len() == state.hosts.len()
+            && state.updates_started.is_none_or(|t| t.elapsed() >= interval);
+        if due {
+            spawn_update_check(state, refresh_tx);
+        }
+    }
+}
+
+/// Ask again about cells that were activating, deactivating or reloading,
+/// backing off while they don't settle.
+fn repoll_transitional(state: &mut AppState) {
+    let cells: Vec<(String, String)> = state
+        .grid
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| state.in_scope(i) && !state.excluded_hosts.contains(&i))
+        .flat_map(|(_, row)| row)
+        .filter(|hs| hs.status.is_transitional() && !hs.config.derived)
+        .filter(|hs| state.pending_action(&hs.host_address, &hs.service_name).is_none())
+        .map(|hs| (hs.host_address.clone(), hs.service_name.clone()))
+        .collect();
+    let Some(repoller) = &mut state.repoller else {
+        return;
+    };
+    for (host, service) in repoller.due(cells, Instant::now()) {
+        log::debug!("Re-polling {}:{} until it settles", host, service);
+        state.refresh.request_cell(&host, &service);
+    }
+}
+
+/// Check reachable hosts for pending package updates in the background.
+fn spawn_update_check(state: &mut AppState, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let indices: Vec<usize> = (0..state.hosts.len())
+        .filter(|i| !state.unreachable_hosts.contains_key(i) && !state.excluded_hosts.contains(i))
+        .collect();
+    log::info!("Checking {} hosts for package updates", indices.len());
+    state.updates_started = Some(Instant::now());
+    state.updates_running = true;
+    let hosts = state.hosts.clone();
+    let mut session_mgr = state.session_manager();
+    let tx = refresh_tx.clone();
+    let task = tokio::spawn(async move {
+        let results = updates::check_hosts(&mut session_mgr, &hosts, &indices).await;
+        let _ = tx.send(RefreshResult::PackageUpdates(results));
+    });
+    state.refresh_tasks.push(task);
+}
+
+fn spawn_refresh_batch(
+    state: &mut AppState,
+    batch: RefreshBatch,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    let subset = if !batch.full {
+        None
+    } else if let Some(initial) = state.initial_subset.take() {
+        Some(initial)
+    } else if state.excluded_hosts.is_empty() && state.scope == Scope::All {
+        None
+    } else {
+        Some((0..state.hosts.len()).filter(|&i| !state.excluded_hosts.contains(&i) && state.in_scope(i)).collect())
+    };
+    let total = state.hosts.len();
+    let hosts: Vec<Host> = match &subset {
+        Some(indices) => indices.iter().map(|&i| state.hosts[i].clone()).collect(),
+        None => state.hosts.clone(),
+    };
+    let configs = state.service_configs.clone();
+    let restart_check = state.restart_check.clone();
+    let mut session_mgr = state.session_manager();
+    let tx = refresh_tx.clone();
+
+    // Cells backed by a custom check need it to be re-queried
+    let cells: Vec<(String, Vec<ServiceCheck>)> = batch
+        .cells
+        .into_iter()
+        .map(|(host, services)| {
+            let services = services
+                .into_iter()
+                .map(|service| {
+                    let check = state
+                        .grid
+                        .iter()
+                        .flatten()
+                        .find(|hs| hs.host_address == host && hs.service_name == service)
+                        .and_then(|hs| hs.config.check.clone());
+                    (service, check)
+                })
+                .collect();
+            (host, services)
+        })
+        .collect();
+
+    // Rows rebuilt on their own, by inventory index
+    let single: Vec<(usize, Host)> = batch
+        .hosts
+        .iter()
+        .filter_map(|address| state.hosts.iter().position(|h| &h.address == address))
+        .map(|i| (i, state.hosts[i].clone()))
+        .collect();
+
+    // Priority order, as positions within the subset when there is one
+    let order = match &subset {
+        _ if !batch.full => Vec::new(),
+        Some(indices) => state
+            .refresh_order()
+            .into_iter()
+            .filter_map(|i| indices.iter().position(|&j| j == i))
+            .collect(),
+        None => state.refresh_order(),
+    };
+
+    state.refresh_tasks.retain(|t| !t.is_finished());
+    let task = tokio::spawn(async move {
+        if batch.full {
+            let host_tx = tx.clone();
+            let on_host = |mut update: HostUpdate| {
+                if let Some(indices) = &subset {
+                    update.host_idx = indices[update.host_idx];
+                }
+                let _ = host_tx.send(RefreshResult::HostRow(update));
+            };
+            let mut grid_result = build_grid(&mut session_mgr, &hosts, &configs, restart_check.as_deref(), &order, on_host).await;
+            scripts::on_refresh(&hosts, &mut grid_result).await;
+            match subset {
+                Some(indices) => grid_result = grid_result.spread(&indices, total),
+                None => warn_unmatched(&mut grid_result, &configs),
+            }
+            let _ = tx.send(RefreshResult::FullGrid(Box::new(grid_result)));
+        }
+        for (idx, host) in single {
+            let hosts = std::slice::from_ref(&host);
+            let mut result = build_grid(&mut session_mgr, hosts, &configs, restart_check.as_deref(), &[0], |_| {}).await;
+            scripts::on_refresh(hosts, &mut result).await;
+            let _ = tx.send(RefreshResult::HostRefreshed(Box::new(result.spread(&[idx], total))));
+        }
+        for (host, services) in cells {
+            log::debug!("Refreshing {} cells on {}", services.len(), host);
+            let statuses = refresh_services(&mut session_mgr, &host, &services).await;
+            for ((service, _), (status, detail)) in services.into_iter().zip(statuses) {
+                let _ = tx.send(RefreshResult::Cell {
+                    host: host.clone(),
+                    service,
+                    status,
+                    detail,
+                });
+            }
+        }
+    });
+    state.refresh_tasks.push(task);
+}
+
+/// Run a service action in the background, marking the cell as pending until
+/// the follow-up cell refresh (queued through the coordinator) arrives. A second action on the same cell is
+/// refused while one is in flight. Every action that goes ahead is audited.
+fn spawn_service_action(
+    state: &mut AppState,
+    host: &str,
+    service: &str,
+    action: &str,
+    reason: Option<&str>,
+    refresh_tx: &mpsc::UnboundedSender<RefreshResult>,
+) {
+    if let Err(message) = begin_action(state, host, service, action, reason) {
+        state.status_message = Some(message);
+        return;
+    }
+    state.status_message = None;
+
+    let session_mgr = state.session_manager();
+    let host = host.to_string();
+    let service = service.to_string();
+    let action = action.to_string();
+    let tx = refresh_tx.clone();
+
+    state.action_tasks.retain(|t| !t.is_finished());
+    let task = tokio::spawn(async move {
+        let error = run_action(session_mgr, &host, &service, &action).await;
+        let _ = tx.send(RefreshResult::ActionDone { host, service, action, error });
+    });
+    state.action_tasks.push(task);
+}
+
+/// Bookkeeping before an action runs: refuse it if the cell already has one
+/// in flight or the audit log can't be written, otherwise mark the cell
+/// pending and record the action. The error is for the status bar.
+fn begin_action(state: &mut AppState, host: &str, service: &str, action: &str, reason: Option<&str>) -> Result<(), String> {
+    let key = (host.to_string(), service.to_string());
+    if let Some(pending) = state.pending_actions.get(&key) {
+        log::warn!(
+            "Ignoring '{}' for {}:{} — already {}",
+            action,
+            host,
+            service,
+            pending.label()
+        );
+        return Err(format!("{} on {} is already {}", service, host, pending.label()));
+    }
+    if let Err(e) = state.audit.record(AuditEntry::new(host, service, action, reason)) {
+        log::error!("Failed to write audit log: {:#}", e);
+        return Err(format!("Audit log failed, not running {}: {}", action, e));
+    }
+    if action == "restart" {
+        state.last_restarts.insert(key.clone(), Instant::now());
+    }
+    state
+        .pending_actions
+        .insert(key, PendingAction::from_action(action));
+    state.timeline.push(
+        timeline::Kind::Action,
+        match reason {
+            Some(reason) => format!("{} {} on {} ({})", action, service, host, reason),
+            None => format!("{} {} on {}", action, service, host),
+        },
+    );
+    Ok(())
+}
+
+/// A plugin action's label from the plugin, or the action string itself.
+fn plugin_action_label(action: &str) -> String {
+    plugins::actions()
+        .into_iter()
+        .find(|(p, a)| plugins::action_id(&p.name, &a.name) == action)
+        .map_or_else(|| action.to_string(), |(p, a)| format!("{} ({})", a.label, p.name))
+}
+
+/// `systemctl <action>` the service, or the plugin action; the error if it failed.
+async fn run_action(mut session_mgr: SessionManager, host: &str, service: &str, action: &str) -> Option<String> {
+    if let Some((plugin, name)) = plugins::parse_action(action) {
+        return match plugins::run_action(plugin, name, host, service).await {
+            Ok(message) => {
+                log::info!("Plugin action '{}' succeeded for {} on {}: {}", action, service, host, message);
+                None
+            }
+            Err(e) => {
+                log::error!("Plugin action '{}' failed for {} on {}: {:#}", action, service, host, e);
+                Some(format!("{:#}", e))
+            }
+        };
+    }
+    let cmd = format!("sudo systemctl {} '{}'", action, unit_name(service));
+    let error = match session_mgr.run_command(host, &cmd).await {
+        Ok(_) => {
+            log::info!("Service action '{}' succeeded for {} on {}", action, service, host);
+            None
+        }
+        Err(e) => {
+            log::error!("Service action '{}' failed for {} on {}: {}", action, service, host, e);
+            Some(format!("{:#}", e))
+        }
+    };
+    error
+}
+
+/// How command output is shown in vim.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ViewMode {
+    /// Decoded as text, or as a hexdump if it looks binary.
+    Auto,
+    Hexdump,
+}
+
+async fn open_in_vim(terminal: &mut tui::Tui, state: &mut AppState, host: &str, cmd: &str, mode: ViewMode) -> Result<()> {
+    // Run the command on the remote host, write output to a temp file, open in vim
+    let mut session_mgr = state.session_manager();
+    let result = session_mgr.run_command_captured(host, cmd).await;
+    let truncated = result.as_ref().is_ok_and(|c| c.truncated);
+
+    let (mut output, note) = output_text(host, cmd, result.map(|c| c.stdout), mode);
+    if note.is_some() {
+        state.status_message = note;
+    }
+    if truncated && mode == ViewMode::Auto {
+        output.push_str("[press A on this item in the detail screen to stream the full output to a file]\n");
+    }
+
+    let tmp = scratch::TempFile::create(host, ".txt", output.as_bytes())?;
+    suspend_and_run(terminal, &["vim", "-R", &tmp.path().to_string_lossy()])?;
+    Ok(())
+}
+
+/// Command output as text for vim, plus a note for the status bar when it
+/// had to be decoded or shown as a hexdump.
+fn output_text(host: &str, cmd: &str, result: Result<Vec<u8>>, mode: ViewMode) -> (String, Option<String>) {
+    match result {
+        Ok(bytes) => {
+            let detected = encoding::detect(&bytes);
+            if mode == ViewMode::Hexdump || detected == Encoding::Binary {
+                let note = (detected == Encoding::Binary && mode == ViewMode::Auto)
+                    .then(|| "Output looks binary; showing a hexdump".to_string());
+                (encoding::hexdump(&bytes), note)
+            } else {
+                let mut note = None;
+                if detected != Encoding::Utf8 {
+                    log::info!("Decoding output of '{}' on {} as {}", cmd, host, detected.name());
+                    note = Some(format!("Decoded from {}", detected.name()));
+                }
+                (encoding::decode(&bytes, detected), note)
+            }
+        }
+        Err(e) => (format!("Error: {}", e), None),
+    }
+}
+
+/// Run a command on each of two hosts concurrently and open both outputs
+/// side by side in vim's diff mode.
+async fn open_compare_in_vim(
+    terminal: &mut tui::Tui,
+    state: &mut AppState,
+    left: (&str, &str),
+    right: (&str, &str),
+) -> Result<()> {
+    let run = |host: String, cmd: String| {
+        let mut session_mgr = state.session_manager();
+        async move {
+            let result = session_mgr.run_command_raw(&host, &cmd).await;
+            output_text(&host, &cmd, result, ViewMode::Auto).0
+        }
+    };
+    let (out_left, out_right) = tokio::join!(
+        run(left.0.to_string(), left.1.to_string()),
+        run(right.0.to_string(), right.1.to_string())
+    );
+    let tmp_left = scratch::TempFile::create(left.0, ".txt", out_left.as_bytes())?;
+    let tmp_right = scratch::TempFile::create(right.0, ".txt", out_right.as_bytes())?;
+    suspend_and_run(
+        terminal,
+        &[
+            "vim",
+            "-R",
+            "-d",
+            &tmp_left.path().to_string_lossy(),
+            &tmp_right.path().to_string_lossy(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Stream a command's whole output to a scratch file without the capture
+/// limit, then open it in vim.
+async fn open_full_in_vim(terminal: &mut tui::Tui, state: &mut AppState, host: &str, cmd: &str) -> Result<()> {
+    let tmp = scratch::TempFile::create(host, ".txt", b"")?;
+    state.status_message = Some(format!("Streaming full output of '{}'...", cmd));
+    terminal.draw(|f| render(f, state))?;
+
+    let mut session_mgr = state.session_manager();
+    let result = session_mgr.stream_to_file(host, cmd, tmp.path()).await;
+    state.status_message = None;
+    match result {
+        Ok(bytes) => {
+            log::info!("Streamed {} bytes of '{}' on {}", bytes, cmd, host);
+            suspend_and_run(terminal, &["vim", "-R", &tmp.path().to_string_lossy()])?;
+        }
+        Err(e) => {
+            log::error!("Streaming '{}' on {} failed: {:#}", cmd, host, e);
+            state.status_message = Some(format!("Streaming failed: {}", e));
+        }
+    }
+    Ok(())
+}
+
+/// Run the `triage` diagnostics for a failed cell on its host and open their
+/// combined output in vim.
+async fn run_triage(terminal: &mut tui::Tui, state: &mut AppState, host_idx: usize, svc_idx: usize) -> Result<()> {
+    let hs = &state.grid[host_idx][svc_idx];
+    if !matches!(hs.status, ServiceStatus::Failed | ServiceStatus::Error(_)) {
+        state.status_message = Some(format!(
+            "{} on {} has not failed; triage is for failed cells",
+            hs.service_name, hs.host_address
+        ));
+        return Ok(());
+    }
+    if state.triage.is_empty() {
+        state.status_message = Some("No triage commands configured".to_string());
+        return Ok(());
+    }
+    let (host, service) = (hs.host_address.clone(), hs.service_name.clone());
+    let commands: Vec<String> = state.triage.iter().map(|c| expand_specifiers(c, &service)).collect();
+    log::info!("Running {} triage commands for {} on {}", commands.len(), service, host);
+    state.status_message = Some(format!("Triaging {} on {}...", service, host));
+    terminal.draw(|f| render(f, state))?;
+
+    let mut report = format!("Triage of {} on {} at {}\n", service, host, clock::format_iso(clock::now_secs()));
+    let mut session_mgr = state.session_manager();
+    for cmd in &commands {
+        report.push_str(&format!("\n===== $ {} =====\n", cmd));
+        match session_mgr.exec(&host, cmd).await {
+            Ok(captured) => {
+                report.push_str(&String::from_utf8_lossy(&captured.stdout));
+                report.push_str(&String::from_utf8_lossy(&captured.stderr));
+                match captured.code {
+                    Some(0) => {}
+                    Some(code) => report.push_str(&format!("[exit {}]\n", code)),
+                    None => report.push_str("[cut off]\n"),
+                }
+            }
+            Err(e) => report.push_str(&format!("[failed to run: {:#}]\n", e)),
+        }
+    }
+    state.status_message = None;
+    state.timeline.push(timeline::Kind::Exec, format!("triage {} on {}", service, host));
+
+    let tmp = scratch::TempFile::create(&format!("triage-{}", host), ".txt", report.as_bytes())?;
+    suspend_and_run(terminal, &["vim", "-R", &tmp.path().to_string_lossy()])?;
+    Ok(())
+}
+
+/// Edit the note on a cell in vim; saving an empty file removes the note.
+fn edit_note(state: &mut AppState, terminal: &mut tui::Tui, host: &str, service: &str) -> Result<()> {
+    let current = state
+        .annotations
+        .data
+        .note(host, service)
+        .map(|n| n.text.clone())
+        .unwrap_or_default();
+
+    let tmp = scratch::TempFile::create(&format!("note-{}", host), ".txt", current.as_bytes())?;
+    suspend_and_run(terminal, &["vim", &tmp.path().to_string_lossy()])?;
+    let text = std::fs::read_to_string(tmp.path()).unwrap_or_else(|_| current.clone());
+    drop(tmp);
+    if text.trim() == current.trim() {
+        return Ok(());
+    }
+
+    log::info!("Updating note on {}:{}", host, service);
+    match state.annotations.update(|a| a.set_note(host, service, &text)) {
+        Ok(()) => state.undo.record(Edit::Note {
+            host: host.to_string(),
+            service: service.to_string(),
+            before: current,
+            after: text,
+        }),
+        Err(e) => {
+            log::error!("Failed to save note: {:#}", e);
+            state.status_message = Some(format!("Failed to save note: {}", e));
+        }
+    }
+    Ok(())
+}
+
+/// Apply an edit from the undo stack (or a fresh one) to the state.
+fn apply_edit(state: &mut AppState, edit: &Edit) {
+    if let Edit::GroupGlobs(on) = edit {
+        state.group_globs = *on;
+        log::info!("Glob grouping {}", if *on { "on" } else { "off" });
+        let len = state.flat_len();
+        if len > 0 && state.cursor >= len {
+            state.cursor = len - 1;
+        }
+        return;
+    }
+    log::info!("Applying {}", edit.describe());
+    if let Err(e) = state.annotations.update(|a| edit.apply(a)) {
+        log::error!("Failed to save annotations: {:#}", e);
+        state.status_message = Some(format!("Failed to save: {}", e));
+    }
+}
+
+/// Show what systemd said about the selected cell, verbatim.
+fn show_raw_status(state: &mut AppState, entry: &FlatEntry, refresh_tx: &mpsc::UnboundedSender<RefreshResult>) {
+    let FlatEntry::Service { host_idx, svc_idx } = *entry else {
+        state.status_message = Some("Select a service row to see its raw status".to_string());
+        return;
+    };
+    let hs = &state.grid[host_idx][svc_idx];
+    let mut lines: Vec<String> = match &hs.detail.raw {
+        // `systemctl show` properties are tab separated
+        Some(raw) => raw.split('\t').filter(|f| !f.is_empty()).map(str::to_string).collect(),
+        None if hs.config.check.is_some() => vec!["Status comes from a custom check, not systemd".to_string()],
+        None => vec!["No output from systemd recorded for this cell".to_string()],
+    };
+    let mut waiting = None;
+    if hs.status == ServiceStatus::Failed && !hs.config.denial_checks.is_empty() {
+        state.denial_seq += 1;
+        let id = state.denial_seq;
+        waiting = Some(id);
+        lines.extend([String::new(), "Checking for denials...".to_string()]);
+
+        let host = hs.host_address.clone();
+        let commands = hs.config.denial_checks.clone();
+        let mut session_mgr = state.session_manager();
+        let tx = refresh_tx.clone();
+        tokio::spawn(async move {
+            let mut lines = Vec::new();
+            for cmd in &commands {
+                lines.push(format!("$ {}", cmd));
+                lines.extend(match session_mgr.exec(&host, cmd).await {
+                    Ok(captured) if !captured.stdout.is_empty() => {
+                        String::from_utf8_lossy(&captured.stdout).lines().map(|l| format!("  {}", l)).collect()
+                    }
+                    Ok(captured) if captured.code == Some(0) || captured.stderr.is_empty() => vec!["  (nothing found)".to_string()],
+                    Ok(captured) => vec![format!("  failed: {}", String::from_utf8_lossy(&captured.stderr).trim())],
+                    Err(e) => vec![format!("  failed: {:#}", e)],
+                });
+            }
+            let _ = tx.send(RefreshResult::DenialChecks { id, lines });
+        });
+    }
+    state.info_popup = Some(InfoPopup {
+        title: format!(" {} on {}: {} ", hs.service_name, hs.host_address, hs.status.name()),
+        lines,
+        hint: "any key:close".to_string(),
+        waiting,
+    });
+}
+
+/// Show the command line reproducing the current view, and copy it to the
+/// clipboard where the terminal allows.
+fn share_view(state: &mut AppState) {
+    let mut pinned: Vec<usize> = state.pinned_hosts.iter().copied().collect();
+    pinned.sort();
+    let focus = match state.screen {
+        Screen::Detail { host_index, service_index } => state
+            .grid
+            .get(host_index)
+            .and_then(|row| row.get(service_index))
+            .map(|hs| (state.hosts[host_index].address.as_str(), display_name(&hs.service_name))),
+        _ => None,
+    };
+    let view = View {
+        scope: &state.scope,
+        pinned: pinned.iter().map(|&i| state.hosts[i].address.as_str()).collect(),
+        group_globs: state.group_globs,
+        focus,
+    };
+    let Some(invocation) = &state.invocation else {
+        return;
+    };
+    let command = view.command_line(invocation);
+    log::info!("Shared view: {}", command);
+    view::copy_to_clipboard(&command);
+    state.info_popup = Some(InfoPopup {
+        title: " Share view ".to_string(),
+        lines: vec![command],
+        hint: "Copied to the clipboard if the terminal allows (OSC 52)  any key:close".to_string(),
+        waiting: None,
+    });
+}
+
+/// Shells to every reachable host in the group of `host_idx`, in one tmux
+/// window with synchronized panes.
+fn open_batch_shell(state: &mut AppState, host_idx: usize, terminal: &mut tui::Tui) -> Result<()> {
+    let group = state.hosts[host_idx].group.clone();
+    let (reachable, unreachable): (Vec<usize>, Vec<usize>) = (0..state.hosts.len())
+        .filter(|&i| state.hosts[i].group == group)
+        .partition(|i| !state.unreachable_hosts.contains_key(i));
+    let commands: Vec<Vec<String>> = reachable
+        .iter()
+        .map(|&i| state.ssh_options.shell_args(state.ssh_user.as_deref(), &state.hosts[i].address))
+        .collect();
+    if commands.is_empty() {
+        state.status_message = Some(format!("No reachable hosts in {}", group));
+        return Ok(());
+    }
+    // tmux doesn't allow '.' or ':' in session names
+    let name = format!("sctl-{}", group.replace(['.', ':'], "_"));
+    let nested = tmux::nested();
+    let args = tmux::batch_args(&name, &commands, nested);
+    log::info!("Opening tmux window {} with shells to {} hosts", name, commands.len());
+    if nested {
+        // A new window in the current session; we keep running in ours
+        match Command::new(&args[0]).args(&args[1..]).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => log::error!("tmux exited with {}", status),
+            Err(e) => log::error!("Failed to run tmux: {}", e),
+        }
+    } else {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        suspend_and_run(terminal, &args)?;
+        log::info!("Returned from tmux session {}", name);
+    }
+    state.status_message = Some(if unreachable.is_empty() {
+        format!("Opened shells to {} hosts in {}", commands.len(), group)
+    } else {
+        format!(
+            "Opened shells to {} hosts in {} ({} unreachable skipped)",
+            commands.len(),
+            group,
+            unreachable.len()
+        )
+    });
+    Ok(())
+}
+
+fn suspend_and_run(terminal: &mut tui::Tui, args: &[&str]) -> Result<()> {
+    tui::suspend()?;
+
+    let status = Command::new(args[0])
+        .args(&args[1..])
+        .status();
+
+    match status {
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to run {:?}: {}", args, e),
+    }
+
+    let new_terminal = tui::resume()?;
+    *terminal = new_terminal;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::testing::fixture;
+
+    fn press(state: &mut AppState, keys: &str, tx: &mpsc::UnboundedSender<RefreshResult>) {
+        for c in keys.chars() {
+            let code = if c == '\n' { KeyCode::Enter } else { KeyCode::Char(c) };
+            handle_prompt_key(state, KeyEvent::new(code, KeyModifiers::NONE), tx);
+        }
+    }
+
+    #[test]
+    fn test_adhoc_on_protected_group_asks_reason() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut state = fixture();
+        state.protected_groups = vec!["web".to_string()];
+        state.prompt = Some(Prompt {
+            title: String::new(),
+            input: String::new(),
+            action: PromptAction::AdhocTarget,
+        });
+        // A plain group name is not wide, but web is protected
+        press(&mut state, "web\nuptime\n", &tx);
+        assert!(state.confirm.is_none());
+        assert_eq!(state.prompt.as_ref().map(Prompt::heading), Some(" Reason "));
+
+        press(&mut state, "\n", &tx);
+        assert_eq!(state.status_message.as_deref(), Some("A reason is required"));
+        press(&mut state, "CHG-1\n", &tx);
+        assert!(state.prompt.is_none());
+        let Some(Confirm { action: ConfirmAction::Adhoc { hosts, reason, .. }, .. }) = &state.confirm else {
+            panic!("expected a confirmation");
+        };
+        assert_eq!(hosts, &["web01", "web02"]);
+        assert_eq!(reason.as_deref(), Some("CHG-1"));
+    }
+
+    #[test]
+    fn test_silenced_transitions_are_not_notified() {
+        use crate::config::maintenance::Schedule;
+        use crate::config::notifications::{NotificationChannel, NotificationRoute, WebhookConfig};
+
+        let mut state = fixture();
+        state.notifier = Notifier::new(&Config {
+            notifications: vec![NotificationRoute {
+                channel: NotificationChannel::Webhook(WebhookConfig {
+                    url: "http://127.0.0.1:9/".to_string(),
+                    link: None,
+                }),
+                min_severity: Severity::Info,
+            }],
+            ..Config::default()
+        });
+        state.annotations.data.toggle_silence("web01", "app");
+        state.maintenance = vec![MaintenanceWindow {
+            name: "nightly".to_string(),
+            hosts: vec!["web02".to_string()],
+            services: Vec::new(),
+            schedule: Schedule::Range { from: 0, until: u64::MAX },
+        }];
+        let failed = |host: &str| Transition {
+            host: host.to_string(),
+            service: "app".to_string(),
+            old: "active".to_string(),
+            new: "failed".to_string(),
+            severity: Severity::Critical,
+        };
+        state.report_transitions(vec![failed("web01"), failed("web02")]);
+
+        let kinds: Vec<_> = state.timeline.entries.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [timeline::Kind::Transition, timeline::Kind::Transition]);
+        assert!(state.timeline.entries[0].text.ends_with("(silenced)"));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::annotations::current_user;
+use crate::clock;
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// One destructive action taken by an operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub at: u64,
+    pub user: String,
+    pub host: String,
+    pub service: String,
+    pub action: String,
+    /// Required for hosts in protected groups.
+    pub reason: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(host: &str, service: &str, action: &str, reason: Option<&str>) -> Self {
+        Self {
+            at: clock::now_secs(),
+            user: current_user(),
+            host: host.to_string(),
+            service: service.to_string(),
+            action: action.to_string(),
+            reason: reason.map(str::to_string),
+        }
+    }
+
+    /// A single `key=value` line; the reason is quoted so it may contain spaces.
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{} user={} host={} service={} action={}",
+            clock::format_iso(self.at),
+            self.user,
+            self.host,
+            self.service,
+            self.action
+        );
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!(" reason={:?}", reason));
+        }
+        line
+    }
+}
+
+/// Append-only record of actions. Entries from this session are also kept in
+/// memory once recorded; the file is optional.
+#[derive(Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    pub entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn open(path: &str) -> Self {
+        Self {
+            path: Some(PathBuf::from(path)),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Record an action before it runs. On error the entry is not kept, as
+    /// the caller won't run the action.
+    pub fn record(&mut self, entry: AuditEntry) -> Result<()> {
+        log::info!("Audit: {}", entry.to_line());
+        let line = entry.to_line();
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open audit log: {}", path.display()))?;
+            writeln!(file, "{}", line)
+                .with_context(|| format!("Failed to write audit log: {}", path.display()))?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_line() {
+        let entry = AuditEntry {
+            at: 1709214300,
+            user: "alice".to_string(),
+            host: "db01".to_string(),
+            service: "postgresql".to_string(),
+            action: "restart".to_string(),
+            reason: Some("CHG-1234 \"config reload\"".to_string()),
+        };
+        assert_eq!(
+            entry.to_line(),
+            "2024-02-29T13:45:00Z user=alice host=db01 service=postgresql action=restart reason=\"CHG-1234 \\\"config reload\\\"\""
+        );
+    }
+
+    #[test]
+    fn test_record_appends_to_file() {
+        let path = std::env::temp_dir().join(format!("sc_audit_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut audit = AuditLog::open(path.to_str().unwrap());
+        audit.record(AuditEntry::new("h1", "nginx", "stop", None)).unwrap();
+        audit.record(AuditEntry::new("h1", "nginx", "restart", Some("flapping"))).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.lines().nth(1).unwrap().ends_with("action=restart reason=\"flapping\""));
+        assert_eq!(audit.entries.len(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_record_without_file_keeps_reason() {
+        let mut audit = AuditLog::default();
+        audit.record(AuditEntry::new("db01", "postgresql", "restart", Some("CHG-1234"))).unwrap();
+        assert_eq!(audit.entries[0].reason.as_deref(), Some("CHG-1234"));
+    }
+
+    #[test]
+    fn test_record_failure_keeps_no_entry() {
+        let path = std::env::temp_dir().join(format!("sc_audit_missing_{}", std::process::id())).join("audit.log");
+        let mut audit = AuditLog::open(path.to_str().unwrap());
+        assert!(audit.record(AuditEntry::new("h1", "nginx", "stop", None)).is_err());
+        assert!(audit.entries.is_empty());
+    }
+}
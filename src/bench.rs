@@ -0,0 +1,223 @@
+//! Load test of the refresh pipeline (`--bench hosts=500 services=20`): full
+//! refreshes of a generated fleet answered by a mock transport, with the time
+//! spent building, applying and rendering the grid, to catch performance
+//! regressions before a release.
+
+use crate::config::{Host, ServiceConfig};
+use crate::monitor::status::{PROBE_COMMAND, STATUS_MARKER};
+use crate::ssh::mock::MockTransport;
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Size of the terminal the grid is rendered to.
+pub const SCREEN: (u16, u16) = (200, 50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchSpec {
+    pub hosts: usize,
+    pub services: usize,
+    /// Full refreshes measured.
+    pub rounds: usize,
+    /// Delay of every mock command.
+    pub latency: Duration,
+}
+
+impl Default for BenchSpec {
+    fn default() -> Self {
+        BenchSpec {
+            hosts: 100,
+            services: 10,
+            rounds: 5,
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+impl BenchSpec {
+    /// `key=value` arguments: `hosts`, `services`, `rounds` and `latency`
+    /// (milliseconds). Missing ones keep their defaults.
+    pub fn parse(args: &[String]) -> Result<BenchSpec> {
+        let mut spec = BenchSpec::default();
+        for arg in args {
+            let (key, value) = arg
+                .split_once('=')
+                .with_context(|| format!("Invalid --bench argument '{}' (expected key=value)", arg))?;
+            let n: usize = value
+                .parse()
+                .with_context(|| format!("Invalid --bench value for {}: '{}'", key, value))?;
+            match key {
+                "hosts" => spec.hosts = n,
+                "services" => spec.services = n,
+                "rounds" => spec.rounds = n.max(1),
+                "latency" => spec.latency = Duration::from_millis(n as u64),
+                _ => anyhow::bail!("Unknown --bench argument '{}' (expected hosts, services, rounds or latency)", key),
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Hosts with IP addresses, so nothing is resolved, in groups of 50, and
+    /// one service entry per column.
+    pub fn fleet(&self) -> (Vec<Host>, Vec<ServiceConfig>) {
+        let hosts = (0..self.hosts)
+            .map(|i| Host {
+                address: format!("10.{}.{}.{}", i / 65536 % 256, i / 256 % 256, i % 256),
+                group: format!("bench{:02}", i / 50),
+                parents: Vec::new(),
+            })
+            .collect();
+        let services = (1..=self.services)
+            .map(|i| ServiceConfig {
+                name_pattern: format!("bench{:02}", i),
+                ..Default::default()
+            })
+            .collect();
+        (hosts, services)
+    }
+
+    pub fn transport(&self) -> MockTransport {
+        MockTransport::new(self.latency, Arc::new(respond))
+    }
+}
+
+/// What a healthy-looking host would answer: every probe passes, and a few
+/// percent of units are failed, stopped or starting.
+fn respond(host: &str, cmd: &str) -> String {
+    let mut out = String::new();
+    if cmd.starts_with(PROBE_COMMAND) {
+        out.push_str(&format!("sudo=yes\nboot_id=bench-{}\nuptime=86400\nsystem=running\n", host));
+    }
+    if cmd.contains(STATUS_MARKER) {
+        out.push_str(STATUS_MARKER);
+        out.push('\n');
+    }
+    for unit in requested_units(cmd) {
+        let (active, sub) = match hash(host, unit) % 100 {
+            0..2 => ("failed", "failed"),
+            2..5 => ("inactive", "dead"),
+            5 => ("activating", "start"),
+            _ => ("active", "running"),
+        };
+        out.push_str(&format!(
+            "{}\tId={}\tLoadState=loaded\tActiveState={}\tSubState={}\tActiveEnterTimestampMonotonic=1000000\tNRestarts=0\t\n",
+            unit, unit, active, sub
+        ));
+    }
+    out
+}
+
+/// The units a status script asks about: `for u in 'a.service' 'b.service'; do`.
+fn requested_units(cmd: &str) -> Vec<&str> {
+    cmd.split_once("for u in ")
+        .and_then(|(_, rest)| rest.split_once("; do"))
+        .map(|(units, _)| units.split_whitespace().map(|u| u.trim_matches('\'')).collect())
+        .unwrap_or_default()
+}
+
+fn hash(host: &str, unit: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (host, unit).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Measured times, one per round.
+#[derive(Debug, Default)]
+pub struct Timings {
+    pub build: Vec<Duration>,
+    pub apply: Vec<Duration>,
+    pub render: Vec<Duration>,
+}
+
+impl Timings {
+    pub fn report(&self, spec: &BenchSpec) -> String {
+        let mut out = format!(
+            "{} hosts x {} services, {} rounds, {}ms latency, {}x{} screen\n",
+            spec.hosts,
+            spec.services,
+            spec.rounds,
+            spec.latency.as_millis(),
+            SCREEN.0,
+            SCREEN.1
+        );
+        out.push_str(&format!("{:<8} {:>10} {:>10} {:>10}\n", "", "min", "median", "max"));
+        for (name, samples) in [("build", &self.build), ("apply", &self.apply), ("render", &self.render)] {
+            let mut sorted = samples.clone();
+            sorted.sort();
+            let (Some(min), Some(max)) = (sorted.first(), sorted.last()) else {
+                continue;
+            };
+            out.push_str(&format!(
+                "{:<8} {:>10} {:>10} {:>10}\n",
+                name,
+                millis(*min),
+                millis(sorted[sorted.len() / 2]),
+                millis(*max)
+            ));
+        }
+        out
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.2}ms", d.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::status::fetch_statuses;
+    use crate::monitor::ServiceStatus;
+    use crate::ssh::SessionManager;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let spec = BenchSpec::parse(&args("hosts=500 services=20 latency=5")).unwrap();
+        assert_eq!((spec.hosts, spec.services, spec.rounds), (500, 20, 5));
+        assert_eq!(spec.latency, Duration::from_millis(5));
+        assert_eq!(BenchSpec::parse(&[]).unwrap(), BenchSpec::default());
+        assert!(BenchSpec::parse(&args("hosts")).is_err());
+        assert!(BenchSpec::parse(&args("hosts=many")).is_err());
+        assert!(BenchSpec::parse(&args("cells=10")).is_err());
+    }
+
+    #[test]
+    fn test_fleet() {
+        let spec = BenchSpec::parse(&args("hosts=300 services=3")).unwrap();
+        let (hosts, services) = spec.fleet();
+        assert_eq!(hosts[299].address, "10.0.1.43");
+        assert_eq!(hosts[299].group, "bench05");
+        assert_eq!(services[2].name_pattern, "bench03");
+    }
+
+    #[tokio::test]
+    async fn test_transport() {
+        let spec = BenchSpec::default();
+        let mut session_mgr = SessionManager::mocked(Default::default(), spec.transport());
+        let names: Vec<String> = (0..200).map(|i| format!("app{}", i)).collect();
+        let statuses: Vec<ServiceStatus> =
+            fetch_statuses(&mut session_mgr, "10.0.0.1", &names).await.into_iter().map(|(s, _)| s).collect();
+        assert_eq!(statuses.len(), 200);
+        assert!(statuses.iter().all(|s| !matches!(s, ServiceStatus::Unknown | ServiceStatus::Error(_))));
+        assert!(statuses.contains(&ServiceStatus::Active));
+        assert!(statuses.contains(&ServiceStatus::Failed));
+    }
+
+    #[test]
+    fn test_report() {
+        let timings = Timings {
+            build: vec![Duration::from_millis(30), Duration::from_millis(10), Duration::from_millis(20)],
+            ..Default::default()
+        };
+        let report = timings.report(&BenchSpec::default());
+        assert!(report.starts_with("100 hosts x 10 services, 5 rounds"));
+        assert!(report.contains("build       10.00ms    20.00ms    30.00ms"));
+        assert!(!report.contains("render"));
+    }
+}
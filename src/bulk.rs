@@ -0,0 +1,154 @@
+//! Bulk actions (`S`/`T` on the main screen): a stop or restart of one
+//! service on every host that has it, run a bounded number at a time, with a
+//! result per host that can be retried.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Waiting for a free slot.
+    Queued,
+    Running,
+    Ok(Duration),
+    Failed { error: String, duration: Duration },
+    /// No answer within the bulk `timeout`; the action may still have happened.
+    TimedOut(Duration),
+}
+
+impl Outcome {
+    fn retryable(&self) -> bool {
+        matches!(self, Outcome::Failed { .. } | Outcome::TimedOut(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub host: String,
+    pub outcome: Outcome,
+    /// Marked with space for a selective retry.
+    pub selected: bool,
+}
+
+/// One action fanned out over the hosts of a column.
+pub struct BulkRun {
+    /// Identifies the run, so results from a closed one are ignored.
+    pub id: u64,
+    pub action: String,
+    pub service: String,
+    /// Reason given for a protected group, reused by retries.
+    pub reason: Option<String>,
+    pub targets: Vec<Target>,
+    pub cursor: usize,
+}
+
+impl BulkRun {
+    pub fn new(id: u64, action: &str, service: &str, reason: Option<&str>, hosts: Vec<String>) -> Self {
+        Self {
+            id,
+            action: action.to_string(),
+            service: service.to_string(),
+            reason: reason.map(str::to_string),
+            targets: hosts
+                .into_iter()
+                .map(|host| Target {
+                    host,
+                    outcome: Outcome::Queued,
+                    selected: false,
+                })
+                .collect(),
+            cursor: 0,
+        }
+    }
+
+    pub fn update(&mut self, host: &str, outcome: Outcome) {
+        if let Some(target) = self.targets.iter_mut().find(|t| t.host == host) {
+            target.outcome = outcome;
+        }
+    }
+
+    /// (ok, failed, timed out, queued or running)
+    pub fn counts(&self) -> (usize, usize, usize, usize) {
+        let mut counts = (0, 0, 0, 0);
+        for target in &self.targets {
+            match target.outcome {
+                Outcome::Ok(_) => counts.0 += 1,
+                Outcome::Failed { .. } => counts.1 += 1,
+                Outcome::TimedOut(_) => counts.2 += 1,
+                Outcome::Queued | Outcome::Running => counts.3 += 1,
+            }
+        }
+        counts
+    }
+
+    pub fn toggle_selected(&mut self) {
+        if let Some(target) = self.targets.get_mut(self.cursor) {
+            target.selected = !target.selected;
+        }
+    }
+
+    /// Hosts a retry goes to: the selected ones that failed or timed out, or
+    /// all of those if none are selected.
+    pub fn retry_hosts(&self) -> Vec<String> {
+        let retryable = self.targets.iter().filter(|t| t.outcome.retryable());
+        let selected: Vec<String> = retryable.clone().filter(|t| t.selected).map(|t| t.host.clone()).collect();
+        if !selected.is_empty() {
+            return selected;
+        }
+        retryable.map(|t| t.host.clone()).collect()
+    }
+
+    /// Queue these hosts again for a retry.
+    pub fn requeue(&mut self, hosts: &[String]) {
+        for target in &mut self.targets {
+            if hosts.contains(&target.host) {
+                target.outcome = Outcome::Queued;
+                target.selected = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run() -> BulkRun {
+        let hosts = ["web01", "web02", "web03", "web04"].map(String::from).to_vec();
+        let mut run = BulkRun::new(1, "restart", "nginx", None, hosts);
+        run.update("web01", Outcome::Ok(Duration::from_secs(1)));
+        run.update(
+            "web02",
+            Outcome::Failed {
+                error: "exit 1".to_string(),
+                duration: Duration::from_secs(1),
+            },
+        );
+        run.update("web03", Outcome::TimedOut(Duration::from_secs(60)));
+        run.update("web04", Outcome::Running);
+        run
+    }
+
+    #[test]
+    fn test_counts() {
+        assert_eq!(run().counts(), (1, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_retry_hosts() {
+        let mut run = run();
+        assert_eq!(run.retry_hosts(), vec!["web02", "web03"]);
+        // Selecting a host that succeeded or is still running doesn't narrow the retry
+        run.toggle_selected();
+        run.cursor = 3;
+        run.toggle_selected();
+        assert_eq!(run.retry_hosts(), vec!["web02", "web03"]);
+        run.cursor = 2;
+        run.toggle_selected();
+        assert_eq!(run.retry_hosts(), vec!["web03"]);
+
+        run.requeue(&run.retry_hosts());
+        assert_eq!(run.targets[2].outcome, Outcome::Queued);
+        assert!(!run.targets[2].selected);
+        assert_eq!(run.counts(), (1, 1, 0, 2));
+    }
+}
@@ -0,0 +1,287 @@
+//! Command line: an optional subcommand, then options and the inventory and
+//! services files. Options are declared once in [`OPTIONS`], which drives
+//! both parsing and `--help`.
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+
+/// What to do once the inventory and services are loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The interactive grid; the default without a subcommand.
+    Tui,
+    /// Print the grid once and exit.
+    Status,
+    /// Exit 2 if a service failed, 4 if a host is unreachable, 6 if both.
+    Check,
+    /// Run a shell command on the hosts matching `target` and print the output.
+    Run { target: String, command: String },
+    /// Keep refreshing and serve the SSH connections to `--agent` clients.
+    Agent,
+}
+
+const COMMANDS: [(&str, &str); 5] = [
+    ("tui", "Interactive grid (the default)"),
+    ("status", "Print the grid once and exit"),
+    ("check", "Exit 2 if a service failed, 4 if a host is unreachable, 6 if both"),
+    ("run <hosts> <command>", "Run a command on the hosts matching a group or glob, print the output"),
+    ("agent", "Keep running, holding the SSH connections for clients started with --agent"),
+];
+
+pub struct Opt {
+    pub name: &'static str,
+    /// Placeholder of the value, `None` for flags.
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+const fn opt(name: &'static str, value: &'static str, help: &'static str) -> Opt {
+    Opt {
+        name,
+        value: Some(value),
+        help,
+    }
+}
+
+const fn flag(name: &'static str, help: &'static str) -> Opt {
+    Opt { name, value: None, help }
+}
+
+pub const OPTIONS: [Opt; 32] = [
+    opt("log", "file", "Write diagnostic logs to a file"),
+    opt("log-level", "level", "Default log level (error, warn, info, debug, trace)"),
+    opt("log-filter", "spec", "Log levels per module, e.g. info,ssh=debug"),
+    opt("log-time-format", "fmt", "strftime-style layout of log timestamps"),
+    flag("syslog", "Send logs to syslog"),
+    flag("journald", "Send logs to journald"),
+    opt("user", "username", "SSH user for every host"),
+    opt("port", "port", "SSH port for every host"),
+    opt("timeout", "secs", "SSH connect timeout"),
+    opt("format", "table|json|csv", "Output of status (default table)"),
+    flag("forward-agent", "Forward the SSH agent to interactive sessions"),
+    flag("forward-x11", "Forward X11 to interactive sessions"),
+    opt("state-file", "path", "Keep notes, silences and hidden services in this file"),
+    opt("audit-log", "path", "Record every service action in this file"),
+    opt("otlp-endpoint", "url", "Export trace spans as OTLP/HTTP JSON"),
+    opt("metrics-addr", "host:port", "Serve Prometheus metrics"),
+    opt("dashboard-addr", "host:port", "Serve a read-only web dashboard of the grid"),
+    opt("temp-dir", "path", "Directory for scratch files"),
+    flag("no-initial-refresh", "Start with an empty board until r is pressed"),
+    opt("initial-group", "group", "Refresh only the matching hosts at startup"),
+    opt("refresh-interval", "duration", "Full refresh this often"),
+    flag("events-stdout", "Monitor without the TUI, printing events as JSON lines"),
+    opt("interval", "secs", "Refresh interval of --events-stdout and agent"),
+    flag("agent", "Run remote commands through a running agent"),
+    opt("agent-socket", "path", "Socket of the agent (default in the runtime directory)"),
+    flag("plain", "ASCII only, for limited terminals"),
+    opt("extra-hosts", "[user@]host[:port],...", "Hosts in addition to, or instead of, an inventory"),
+    opt("focus", "host:service", "Start on the detail view of one cell"),
+    opt("scope", "glob|pinned", "Start with the grid limited to these hosts"),
+    opt("pin", "host,...", "Pin hosts to the top"),
+    flag("group-globs", "Start with glob matches grouped"),
+    flag("help", "Show this help"),
+];
+
+/// The parsed command line.
+#[derive(Debug, Default)]
+pub struct Cli {
+    pub command: Option<Command>,
+    values: HashMap<&'static str, String>,
+    flags: HashSet<&'static str>,
+    /// `key=value` arguments following `--bench`, if given.
+    pub bench: Option<Vec<String>>,
+    /// Inventory and services files.
+    pub files: Vec<String>,
+}
+
+impl Cli {
+    pub fn parse(args: &[String]) -> Result<Cli> {
+        let mut cli = Cli::default();
+        let mut rest = args.iter().peekable();
+        if let Some(first) = rest.peek().filter(|a| !a.starts_with('-')) {
+            let command = match first.as_str() {
+                "tui" => Some(Command::Tui),
+                "status" => Some(Command::Status),
+                "check" => Some(Command::Check),
+                "agent" => Some(Command::Agent),
+                "run" => Some(Command::Run {
+                    target: String::new(),
+                    command: String::new(),
+                }),
+                // A file; no subcommand
+                _ => None,
+            };
+            if command.is_some() {
+                rest.next();
+            }
+            cli.command = command;
+        }
+        let mut positional = Vec::new();
+        while let Some(arg) = rest.next() {
+            if arg == "-h" {
+                cli.flags.insert("help");
+                continue;
+            }
+            if arg == "--bench" {
+                // Takes the key=value arguments that follow it
+                let mut spec = Vec::new();
+                while let Some(a) = rest.next_if(|a| a.contains('=') && !a.starts_with("--")) {
+                    spec.push(a.clone());
+                }
+                cli.bench = Some(spec);
+                continue;
+            }
+            let Some(name) = arg.strip_prefix("--") else {
+                positional.push(arg.clone());
+                continue;
+            };
+            // --name=value as well as --name value
+            let (name, inline) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (name, None),
+            };
+            let opt = OPTIONS
+                .iter()
+                .find(|o| o.name == name)
+                .with_context(|| format!("Unknown option --{}", name))?;
+            match (opt.value, inline) {
+                (Some(_), Some(value)) => {
+                    cli.values.insert(opt.name, value.to_string());
+                }
+                (Some(placeholder), None) => {
+                    let value = rest
+                        .next()
+                        .with_context(|| format!("--{} needs a value: <{}>", name, placeholder))?;
+                    cli.values.insert(opt.name, value.clone());
+                }
+                (None, Some(_)) => anyhow::bail!("--{} takes no value", name),
+                (None, None) => {
+                    cli.flags.insert(opt.name);
+                }
+            }
+        }
+        // Rather than silently ignore them
+        if cli.values.contains_key("format") && cli.command() != Command::Status {
+            anyhow::bail!("--format only applies to status");
+        }
+        if cli.flags.contains("events-stdout") && cli.command() != Command::Tui {
+            anyhow::bail!("--events-stdout replaces the TUI, it can't be used with a command");
+        }
+        if cli.flags.contains("events-stdout") && cli.values.contains_key("focus") {
+            anyhow::bail!("--focus opens a detail view, which --events-stdout doesn't have");
+        }
+        if let Some(Command::Run { target, command }) = &mut cli.command {
+            if positional.len() < 2 {
+                anyhow::bail!("run needs the hosts and the command: run <hosts> <command> [<inventory.ini>] <services.yaml>");
+            }
+            *target = positional.remove(0);
+            *command = positional.remove(0);
+        }
+        cli.files = positional;
+        Ok(cli)
+    }
+
+    /// Value of an option taking one.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        debug_assert!(OPTIONS.iter().any(|o| o.name == name && o.value.is_some()), "no option --{}", name);
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        debug_assert!(OPTIONS.iter().any(|o| o.name == name && o.value.is_none()), "no flag --{}", name);
+        self.flags.contains(name)
+    }
+
+    pub fn command(&self) -> Command {
+        self.command.clone().unwrap_or(Command::Tui)
+    }
+}
+
+/// `--help` text, from [`COMMANDS`] and [`OPTIONS`].
+pub fn usage(program: &str) -> String {
+    let mut out = format!("Usage: {} [<command>] [options] [<inventory.ini>] <services.yaml>\n", program);
+    out.push_str(&format!("       {} --bench [hosts=<n>] [services=<n>] [rounds=<n>] [latency=<ms>]\n\nCommands:\n", program));
+    for (name, help) in COMMANDS {
+        out.push_str(&format!("  {:<24} {}\n", name, help));
+    }
+    out.push_str("\nOptions:\n");
+    for opt in &OPTIONS {
+        let left = match opt.value {
+            Some(value) => format!("--{} <{}>", opt.name, value),
+            None => format!("--{}", opt.name),
+        };
+        if left.len() > 34 {
+            out.push_str(&format!("  {}\n  {:<34} {}\n", left, "", opt.help));
+        } else {
+            out.push_str(&format!("  {:<34} {}\n", left, opt.help));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Cli> {
+        Cli::parse(&s.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse() {
+        let cli = parse("--log /tmp/x.log --syslog --user admin inventory.ini services.yaml").unwrap();
+        assert_eq!(cli.command(), Command::Tui);
+        assert_eq!(cli.value("log"), Some("/tmp/x.log"));
+        assert_eq!(cli.value("user"), Some("admin"));
+        assert_eq!(cli.value("port"), None);
+        assert!(cli.flag("syslog"));
+        assert!(!cli.flag("journald"));
+        assert_eq!(cli.files, vec!["inventory.ini", "services.yaml"]);
+
+        assert!(parse("--log").is_err());
+        assert!(parse("--bogus services.yaml").is_err());
+
+        let cli = parse("--log=/tmp/x.log --log-filter=info,ssh=debug services.yaml").unwrap();
+        assert_eq!(cli.value("log"), Some("/tmp/x.log"));
+        assert_eq!(cli.value("log-filter"), Some("info,ssh=debug"));
+        assert_eq!(parse("--extra-hosts= services.yaml").unwrap().value("extra-hosts"), Some(""));
+        assert!(parse("--syslog=yes services.yaml").is_err());
+    }
+
+    #[test]
+    fn test_subcommands() {
+        assert_eq!(parse("status --timeout 5 services.yaml").unwrap().command(), Command::Status);
+        let cli = parse("run web* uptime inventory.ini services.yaml").unwrap();
+        assert_eq!(
+            cli.command(),
+            Command::Run {
+                target: "web*".to_string(),
+                command: "uptime".to_string(),
+            }
+        );
+        assert_eq!(cli.files, vec!["inventory.ini", "services.yaml"]);
+        assert!(parse("run web*").is_err());
+        // Only the first argument can be a subcommand
+        assert_eq!(parse("services.yaml status").unwrap().files, vec!["services.yaml", "status"]);
+
+        // Options of other commands are refused, not ignored
+        assert_eq!(parse("status --format json services.yaml").unwrap().value("format"), Some("json"));
+        assert!(parse("check --format json services.yaml").is_err());
+        assert!(parse("--format json services.yaml").is_err());
+        assert!(parse("--events-stdout services.yaml").unwrap().flag("events-stdout"));
+        assert!(parse("status --events-stdout services.yaml").is_err());
+        assert!(parse("--events-stdout --focus web01:nginx services.yaml").is_err());
+
+        let cli = parse("--bench hosts=10 rounds=2 --plain").unwrap();
+        assert_eq!(cli.bench, Some(vec!["hosts=10".to_string(), "rounds=2".to_string()]));
+        assert!(cli.flag("plain"));
+    }
+
+    #[test]
+    fn test_usage() {
+        let usage = usage("system-controller");
+        assert!(usage.contains("  status                   Print the grid once and exit\n"));
+        assert!(usage.contains("  --log <file>                       Write diagnostic logs to a file\n"));
+        assert!(usage.contains("  --extra-hosts <[user@]host[:port],...>\n"));
+    }
+}
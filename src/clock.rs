@@ -0,0 +1,250 @@
+//! Calendar arithmetic and timestamp formatting on Unix timestamps, in UTC.
+//! Kept dependency-free; the logger and every exported format use it.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A broken-down UTC time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 = Monday ... 6 = Sunday
+    pub weekday: u32,
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Days since 1970-01-01 for a civil date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn from_unix(secs: u64) -> DateTime {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    DateTime {
+        year,
+        month,
+        day,
+        hour: (rem / 3600) as u32,
+        minute: ((rem / 60) % 60) as u32,
+        second: (rem % 60) as u32,
+        // 1970-01-01 was a Thursday
+        weekday: ((days + 3).rem_euclid(7)) as u32,
+    }
+}
+
+pub fn to_unix(year: i64, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+    let days = days_from_civil(year, month, day);
+    (days * 86400 + hour as i64 * 3600 + minute as i64 * 60).max(0) as u64
+}
+
+/// Format as ISO 8601 in UTC, e.g. `2024-02-29T13:45:00Z`.
+pub fn format_iso(secs: u64) -> String {
+    format_time(secs, 0, "%Y-%m-%dT%H:%M:%SZ")
+}
+
+/// Default timestamp layout: ISO 8601 with milliseconds, in UTC.
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// Format a UTC time with a small strftime subset: `%Y %m %d %H %M %S`,
+/// `%.3f` (milliseconds), `%j` (day of year), `%a` (weekday), `%z` (`+0000`),
+/// `%Z` (`UTC`) and `%%`. Unknown directives are copied through.
+pub fn format_time(secs: u64, millis: u32, fmt: &str) -> String {
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let dt = from_unix(secs);
+    let mut out = String::with_capacity(fmt.len() + 8);
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", dt.year)),
+            Some('m') => out.push_str(&format!("{:02}", dt.month)),
+            Some('d') => out.push_str(&format!("{:02}", dt.day)),
+            Some('H') => out.push_str(&format!("{:02}", dt.hour)),
+            Some('M') => out.push_str(&format!("{:02}", dt.minute)),
+            Some('S') => out.push_str(&format!("{:02}", dt.second)),
+            Some('j') => {
+                let doy = days_from_civil(dt.year, dt.month, dt.day) - days_from_civil(dt.year, 1, 1) + 1;
+                out.push_str(&format!("{:03}", doy));
+            }
+            Some('a') => out.push_str(DAYS[dt.weekday as usize]),
+            Some('z') => out.push_str("+0000"),
+            Some('Z') => out.push_str("UTC"),
+            Some('%') => out.push('%'),
+            Some('.') if chars.peek() == Some(&'3') => {
+                chars.next();
+                if chars.peek() == Some(&'f') {
+                    chars.next();
+                    out.push_str(&format!(".{:03}", millis));
+                } else {
+                    out.push_str("%.3");
+                }
+            }
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Parse `YYYY-MM-DD HH:MM` (a `T` separator is also accepted) as UTC.
+pub fn parse_datetime(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (date, time) = s.split_once([' ', 'T'])?;
+    let mut d = date.split('-');
+    let year = d.next()?.parse().ok()?;
+    let month = d.next()?.parse().ok()?;
+    let day = d.next()?.parse().ok()?;
+    let minutes = parse_hhmm(time)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(to_unix(year, month, day, minutes / 60, minutes % 60))
+}
+
+/// Parse `HH:MM` into minutes after midnight.
+pub fn parse_hhmm(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let h: u32 = h.parse().ok()?;
+    let m: u32 = m.parse().ok()?;
+    if h > 23 || m > 59 {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
+/// Parse a duration such as `90`, `30s`, `5m`, `2h` or `1d`; bare numbers are seconds.
+pub fn parse_duration(s: &str) -> Option<std::time::Duration> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = num.parse().ok()?;
+    let secs = match unit.trim() {
+        "s" | "sec" | "secs" => n,
+        "m" | "min" | "mins" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86400,
+        _ => return None,
+    };
+    Some(std::time::Duration::from_secs(secs))
+}
+
+/// Compact human-readable duration, e.g. `42s`, `5m`, `1h30m`.
+pub fn format_duration(d: std::time::Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else if secs % 3600 / 60 == 0 {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}h{}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch() {
+        let dt = from_unix(0);
+        assert_eq!((dt.year, dt.month, dt.day), (1970, 1, 1));
+        assert_eq!(dt.weekday, 3);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // 2024-02-29 13:45 UTC, a Thursday
+        let secs = to_unix(2024, 2, 29, 13, 45);
+        assert_eq!(secs, 1709214300);
+        let dt = from_unix(secs);
+        assert_eq!((dt.year, dt.month, dt.day, dt.hour, dt.minute), (2024, 2, 29, 13, 45));
+        assert_eq!(dt.weekday, 3);
+    }
+
+    #[test]
+    fn test_format_iso() {
+        assert_eq!(format_iso(1709214300 + 7), "2024-02-29T13:45:07Z");
+    }
+
+    #[test]
+    fn test_format_time() {
+        let secs = 1709214300 + 7;
+        assert_eq!(format_time(secs, 42, DEFAULT_TIME_FORMAT), "2024-02-29T13:45:07.042Z");
+        assert_eq!(format_time(secs, 0, "%a %j %H:%M %z %Z %%"), "Thu 060 13:45 +0000 UTC %");
+        assert_eq!(format_time(secs, 0, "%q %.3x"), "%q %.3x");
+    }
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(parse_datetime("2024-02-29 13:45"), Some(1709214300));
+        assert_eq!(parse_datetime("2024-02-29T13:45"), Some(1709214300));
+        assert_eq!(parse_datetime("2024-13-01 00:00"), None);
+        assert_eq!(parse_datetime("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("5w"), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        use std::time::Duration;
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(300)), "5m");
+        assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m");
+    }
+
+    #[test]
+    fn test_parse_hhmm() {
+        assert_eq!(parse_hhmm("02:30"), Some(150));
+        assert_eq!(parse_hhmm("24:00"), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Subcommands that refresh once, print and exit, for scripts, cron and CI
+//! rather than a person at a terminal. Each returns the process exit code.
+
+use crate::adhoc::{self, HostRun};
+use crate::config::{Config, Host};
+use crate::monitor::status::build_grid;
+use crate::monitor::{GridResult, HostError, HostService, ServiceStatus};
+use crate::scripts;
+use crate::ssh::SessionManager;
+use anyhow::Result;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Exit code bit of `check` for a failed service. Errors such as a bad
+/// services file exit 1, so they can't pass for either.
+pub const EXIT_FAILED: i32 = 2;
+/// Exit code bit of `check` for an unreachable host.
+pub const EXIT_UNREACHABLE: i32 = 4;
+
+/// One full refresh of every host, as the TUI's first one.
+async fn refresh(hosts: &[Host], config: &Config, ssh_user: Option<String>) -> GridResult {
+    let mut session_mgr = SessionManager::new(ssh_user, config.ssh.clone());
+    let order: Vec<usize> = (0..hosts.len()).collect();
+    let mut result = build_grid(
+        &mut session_mgr,
+        hosts,
+        &config.services,
+        config.restart_check.as_deref(),
+        &order,
+        |_| {},
+    )
+    .await;
+    scripts::on_refresh(hosts, &mut result).await;
+    session_mgr.close_all().await;
+    result
+}
+
+/// Output of `status`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Format> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => anyhow::bail!("Invalid --format {} (table, json or csv)", s),
+        }
+    }
+}
+
+/// `status`: the grid as a table, JSON or CSV.
+pub async fn status(hosts: Vec<Host>, config: Config, ssh_user: Option<String>, format: Format) -> Result<i32> {
+    let result = refresh(&hosts, &config, ssh_user).await;
+    match format {
+        Format::Table => print!("{}", table(&hosts, &result, &config.status_words)),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&grid_json(&hosts, &result.grid, &result.unreachable_hosts))?),
+        Format::Csv => print!("{}", csv(&hosts, &result)),
+    }
+    Ok(0)
+}
+
+/// `check`: the problems found, and an exit code saying which kinds there
+/// were: [`EXIT_FAILED`], [`EXIT_UNREACHABLE`], both added up, or 0.
+pub async fn check(hosts: Vec<Host>, config: Config, ssh_user: Option<String>) -> Result<i32> {
+    let result = refresh(&hosts, &config, ssh_user).await;
+    let problems = problems(&hosts, &result);
+    if problems.is_empty() {
+        let cells: usize = result.grid.iter().map(Vec::len).sum();
+        println!("OK: {} services on {} hosts", cells, hosts.len());
+        return Ok(0);
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Ok(check_code(&result))
+}
+
+fn check_code(result: &GridResult) -> i32 {
+    let mut code = 0;
+    if result.grid.iter().flatten().any(|hs| hs.status == ServiceStatus::Failed) {
+        code |= EXIT_FAILED;
+    }
+    if !result.unreachable_hosts.is_empty() {
+        code |= EXIT_UNREACHABLE;
+    }
+    code
+}
+
+/// `run`: the command on every matching host, as many at once as a refresh
+/// visits (`ssh.parallel`), then each host's output in inventory order.
+/// Exit 1 if it failed anywhere.
+pub async fn run(hosts: Vec<Host>, config: Config, ssh_user: Option<String>, target: &str, command: &str) -> Result<i32> {
+    let selected = adhoc::select_hosts(&hosts, target);
+    if selected.is_empty() {
+        anyhow::bail!("No host matches {}", target);
+    }
+    let session_mgr = SessionManager::new(ssh_user, config.ssh.clone());
+    let permits = Arc::new(Semaphore::new(config.ssh.parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    for host in selected.clone() {
+        let mut session_mgr = session_mgr.clone();
+        let command = command.to_string();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let started = Instant::now();
+            let run = match session_mgr.exec(&host, &command).await {
+                Ok(captured) => HostRun::from_captured(captured, started.elapsed()),
+                Err(e) => HostRun::Failed {
+                    error: format!("{:#}", e),
+                    duration: started.elapsed(),
+                },
+            };
+            (host, run)
+        });
+    }
+    let mut runs = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        runs.extend(joined.ok());
+    }
+    session_mgr.clone().close_all().await;
+    runs.sort_by_key(|(host, _)| selected.iter().position(|h| h == host));
+    let mut code = 0;
+    for (host, run) in &runs {
+        match run {
+            HostRun::Done { code: exit, duration, output } => {
+                let exit = exit.map_or("cut off".to_string(), |c| format!("exit {}", c));
+                println!("== {} ({}, {:.1}s)", host, exit, duration.as_secs_f64());
+                print!("{}", output);
+                if !output.is_empty() && !output.ends_with('\n') {
+                    println!();
+                }
+            }
+            HostRun::Failed { error, .. } => println!("== {} (failed: {})", host, error),
+            HostRun::Running => {}
+        }
+        if !run.succeeded() {
+            code = 1;
+        }
+    }
+    Ok(code)
+}
+
+/// Host, service and status columns, unreachable hosts with the reason.
+fn table(hosts: &[Host], result: &GridResult, words: &HashMap<String, String>) -> String {
+    let mut rows: Vec<[String; 3]> = Vec::new();
+    for (i, host) in hosts.iter().enumerate() {
+        if let Some(error) = result.unreachable_hosts.get(&i) {
+            rows.push([host.address.clone(), "-".to_string(), format!("unreachable ({})", error.reason())]);
+            continue;
+        }
+        for hs in result.grid.get(i).into_iter().flatten() {
+            rows.push([host.address.clone(), hs.service_name.clone(), hs.status.word(words).to_string()]);
+        }
+    }
+    let header = ["HOST".to_string(), "SERVICE".to_string(), "STATUS".to_string()];
+    let width = |col: usize| rows.iter().chain([&header]).map(|r| r[col].chars().count()).max().unwrap_or(0);
+    let (host_width, service_width) = (width(0), width(1));
+    let mut out = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        out.push_str(&format!("{:<hw$}  {:<sw$}  {}\n", row[0], row[1], row[2], hw = host_width, sw = service_width));
+    }
+    out
+}
+
+/// Every host with its services, or why it was unreachable. Statuses are
+/// the stable names scripts and notifications use, not `status_words`.
+/// Also served by the web dashboard.
+pub fn grid_json(hosts: &[Host], grid: &[Vec<HostService>], unreachable: &HashMap<usize, HostError>) -> serde_json::Value {
+    let hosts: Vec<serde_json::Value> = hosts
+        .iter()
+        .enumerate()
+        .map(|(i, host)| {
+            if let Some(error) = unreachable.get(&i) {
+                return json!({"host": host.address, "group": host.group, "unreachable": error.reason()});
+            }
+            let services: Vec<serde_json::Value> = grid
+                .get(i)
+                .into_iter()
+                .flatten()
+                .map(|hs| {
+                    let mut service = json!({"service": hs.service_name, "status": hs.status.name()});
+                    if let ServiceStatus::Error(e) = &hs.status {
+                        service["error"] = json!(e);
+                    }
+                    service
+                })
+                .collect();
+            json!({"host": host.address, "group": host.group, "services": services})
+        })
+        .collect();
+    json!({ "hosts": hosts })
+}
+
+/// One row per cell and per unreachable host, with a header.
+fn csv(hosts: &[Host], result: &GridResult) -> String {
+    let mut out = String::from("host,group,service,status,error\n");
+    let mut row = |fields: [&str; 5]| {
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    };
+    for (i, host) in hosts.iter().enumerate() {
+        if let Some(error) = result.unreachable_hosts.get(&i) {
+            row([&host.address, &host.group, "", "unreachable", error.reason()]);
+            continue;
+        }
+        for hs in result.grid.get(i).into_iter().flatten() {
+            let error = match &hs.status {
+                ServiceStatus::Error(e) => e.as_str(),
+                _ => "",
+            };
+            row([&host.address, &host.group, &hs.service_name, hs.status.name(), error]);
+        }
+    }
+    out
+}
+
+/// Quoted if it holds a comma, quote or line break, as RFC 4180 has it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Failed services and unreachable hosts, one line each.
+fn problems(hosts: &[Host], result: &GridResult) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, host) in hosts.iter().enumerate() {
+        if let Some(error) = result.unreachable_hosts.get(&i) {
+            problems.push(format!("UNREACHABLE {}: {}", host.address, error.reason()));
+        }
+        for hs in result.grid.get(i).into_iter().flatten() {
+            if hs.status == ServiceStatus::Failed {
+                problems.push(format!("FAILED {} on {}", hs.service_name, host.address));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServiceConfig;
+
+    fn fixture() -> (Vec<Host>, GridResult) {
+        let host = |address: &str| Host {
+            address: address.to_string(),
+            group: "web".to_string(),
+            parents: Vec::new(),
+        };
+        let hs = |host: &str, service: &str, status| HostService {
+            host_address: host.to_string(),
+            service_name: service.to_string(),
+            config: ServiceConfig::default(),
+            status,
+            detail: Default::default(),
+        };
+        let result = GridResult {
+            service_names: vec!["nginx".to_string(), "app".to_string()],
+            grid: vec![
+                vec![hs("web01", "nginx", ServiceStatus::Active), hs("web01", "app", ServiceStatus::Failed)],
+                Vec::new(),
+            ],
+            unreachable_hosts: [(1, HostError::Timeout)].into(),
+            warnings: Vec::new(),
+            no_sudo: Default::default(),
+            skipped: Default::default(),
+            boots: Default::default(),
+            system: Default::default(),
+            restart_needed: Default::default(),
+        };
+        (vec![host("web01"), host("db01")], result)
+    }
+
+    #[test]
+    fn test_table() {
+        let (hosts, result) = fixture();
+        assert_eq!(
+            table(&hosts, &result, &HashMap::new()),
+            "HOST   SERVICE  STATUS\n\
+             web01  nginx    active\n\
+             web01  app      FAILED\n\
+             db01   -        unreachable (connection request timed out)\n"
+        );
+        let words = HashMap::from([("failed".to_string(), "down".to_string())]);
+        assert!(table(&hosts, &result, &words).contains("web01  app      down\n"));
+    }
+
+    #[test]
+    fn test_json_and_csv() {
+        let (hosts, mut result) = fixture();
+        result.grid[0][0].status = ServiceStatus::Error("ssh: broken pipe, retrying".to_string());
+        let json = grid_json(&hosts, &result.grid, &result.unreachable_hosts);
+        assert_eq!(
+            json["hosts"][0]["services"][0],
+            json!({"service": "nginx", "status": "error", "error": "ssh: broken pipe, retrying"})
+        );
+        assert_eq!(json["hosts"][0]["services"][1]["status"], "failed");
+        assert_eq!(json["hosts"][1], json!({"host": "db01", "group": "web", "unreachable": "connection request timed out"}));
+
+        assert_eq!(
+            csv(&hosts, &result),
+            "host,group,service,status,error\n\
+             web01,web,nginx,error,\"ssh: broken pipe, retrying\"\n\
+             web01,web,app,failed,\n\
+             db01,web,,unreachable,connection request timed out\n"
+        );
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_problems() {
+        let (hosts, mut result) = fixture();
+        assert_eq!(problems(&hosts, &result), vec!["FAILED app on web01", "UNREACHABLE db01: connection request timed out"]);
+        assert_eq!(check_code(&result), EXIT_FAILED | EXIT_UNREACHABLE);
+        result.unreachable_hosts.clear();
+        assert_eq!(check_code(&result), EXIT_FAILED);
+        result.grid[0][1].status = ServiceStatus::Active;
+        assert_eq!(check_code(&result), 0);
+        result.unreachable_hosts.insert(1, HostError::Timeout);
+        assert_eq!(check_code(&result), EXIT_UNREACHABLE);
+    }
+}
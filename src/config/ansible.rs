@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Settings from the `[defaults]` section of an `ansible.cfg`, used where
+/// nothing more specific was given so the controller connects the same way
+/// the playbooks do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnsibleDefaults {
+    pub remote_user: Option<String>,
+    pub private_key_file: Option<String>,
+    pub inventory: Option<String>,
+    /// SSH connection timeout in seconds.
+    pub timeout: Option<u64>,
+}
+
+/// Read `ansible.cfg` from `dir` if there is one.
+pub fn load(dir: &Path) -> Result<Option<AnsibleDefaults>> {
+    let path = dir.join("ansible.cfg");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let defaults = parse(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(defaults))
+}
+
+fn parse(content: &str) -> Result<AnsibleDefaults> {
+    let mut defaults = AnsibleDefaults::default();
+    let mut in_defaults = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            in_defaults = &line[1..line.len() - 1] == "defaults";
+            continue;
+        }
+        if !in_defaults {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "remote_user" => defaults.remote_user = Some(value),
+            "private_key_file" => defaults.private_key_file = Some(expand_home(&value)),
+            "inventory" | "hostfile" => {
+                // Several comma-separated sources are allowed; only the first is used
+                let first = value.split(',').next().unwrap_or("").trim();
+                defaults.inventory = Some(expand_home(first));
+            }
+            "timeout" => {
+                let secs = value
+                    .parse()
+                    .with_context(|| format!("Invalid timeout: {}", value))?;
+                defaults.timeout = Some(secs);
+            }
+            _ => {}
+        }
+    }
+    Ok(defaults)
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let cfg = "# comment\n[defaults]\ninventory = hosts.ini, extra.ini\nremote_user = deploy\nprivate_key_file = /keys/deploy\ntimeout = 15\nforks = 20\n\n[ssh_connection]\nremote_user = ignored\n";
+        let d = parse(cfg).unwrap();
+        assert_eq!(d.inventory.as_deref(), Some("hosts.ini"));
+        assert_eq!(d.remote_user.as_deref(), Some("deploy"));
+        assert_eq!(d.private_key_file.as_deref(), Some("/keys/deploy"));
+        assert_eq!(d.timeout, Some(15));
+    }
+
+    #[test]
+    fn test_parse_invalid_timeout() {
+        assert!(parse("[defaults]\ntimeout = soon\n").is_err());
+        assert_eq!(parse("[privilege_escalation]\nbecome = true\n").unwrap(), AnsibleDefaults::default());
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let dir = std::env::temp_dir().join(format!("sc_ansible_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(load(&dir).unwrap(), None);
+        std::fs::write(dir.join("ansible.cfg"), "[defaults]\nremote_user = ops\n").unwrap();
+        assert_eq!(load(&dir).unwrap().unwrap().remote_user.as_deref(), Some("ops"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,4 +1,6 @@
 use anyhow::{Context, Result};
+use glob_match::glob_match;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 
 #[cfg(test)]
@@ -30,14 +32,54 @@ impl Drop for TempIni {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 #[allow(dead_code)]
 pub struct Host {
     pub address: String,
     pub group: String,
+    /// Groups containing `group` through `[<group>:children]`, nearest first.
+    pub parents: Vec<String>,
+}
+
+impl Host {
+    /// The host's own group, then the groups containing it.
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.group.as_str()).chain(self.parents.iter().map(String::as_str))
+    }
+
+    /// Whether the glob matches the host's group or a group containing it.
+    pub fn in_group(&self, pattern: &str) -> bool {
+        self.groups().any(|g| g == pattern || glob_match(pattern, g))
+    }
+
+    /// Whether the pattern names the host, its group or a containing group,
+    /// or is a glob matching one of them. Exact names are compared first, as
+    /// `[` in `[::1]:2222` would start a character class in a glob.
+    pub fn matches(&self, pattern: &str) -> bool {
+        self.address == pattern || self.in_group(pattern) || glob_match(pattern, &self.address)
+    }
+}
+
+/// Split an inventory address into the host name and an optional SSH port:
+/// `host:2222`, `10.0.0.5:2222` or `[::1]:2222`. Bare IPv6 addresses have no port.
+pub fn split_host_port(address: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = address.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            let port = after.strip_prefix(':').and_then(|p| p.parse().ok());
+            return (host, port);
+        }
+    }
+    match address.split_once(':') {
+        Some((host, port)) if !port.contains(':') => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (address, None),
+        },
+        _ => (address, None),
+    }
 }
 
 fn is_ip_address(s: &str) -> bool {
+    let s = split_host_port(s).0;
     // Match IPv4: digits and dots, at least one dot
     if s.contains('.') {
         return s.chars().all(|c| c.is_ascii_digit() || c == '.');
@@ -45,7 +87,26 @@ fn is_ip_address(s: &str) -> bool {
     false
 }
 
+/// The host address on an inventory line, with `ansible_port` folded in as
+/// `host:port` unless the address already carries a port.
 fn extract_address(line: &str) -> Option<String> {
+    let address = extract_host(line)?;
+    let port = line
+        .split_whitespace()
+        .find_map(|t| t.strip_prefix("ansible_port="));
+    match port {
+        Some(port) if split_host_port(&address).1.is_none() => {
+            if address.contains(':') {
+                Some(format!("[{}]:{}", address, port))
+            } else {
+                Some(format!("{}:{}", address, port))
+            }
+        }
+        _ => Some(address),
+    }
+}
+
+fn extract_host(line: &str) -> Option<String> {
     let tokens: Vec<&str> = line.split_whitespace().collect();
     if tokens.is_empty() {
         return None;
@@ -84,6 +145,10 @@ pub fn parse_inventory(path: &str) -> Result<Vec<Host>> {
 
     let mut hosts = Vec::new();
     let mut current_group = String::from("ungrouped");
+    // Parent group of the [<parent>:children] section being read
+    let mut children_of: Option<String> = None;
+    // Child group -> the groups listing it as a child
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
 
     for line in content.lines() {
         let line = line.trim();
@@ -96,13 +161,20 @@ pub fn parse_inventory(path: &str) -> Result<Vec<Host>> {
         // Section header
         if line.starts_with('[') && line.ends_with(']') {
             current_group = line[1..line.len() - 1].to_string();
-            // Skip meta-groups like [group:children] or [group:vars]
+            children_of = current_group.strip_suffix(":children").map(String::from);
+            // Skip other meta-groups like [group:vars]
             if current_group.contains(':') {
                 current_group = String::from("_skip");
             }
             continue;
         }
 
+        if let Some(parent) = &children_of {
+            let child = line.split_whitespace().next().unwrap_or(line);
+            parents.entry(child.to_string()).or_default().push(parent.clone());
+            continue;
+        }
+
         if current_group == "_skip" {
             continue;
         }
@@ -111,25 +183,115 @@ pub fn parse_inventory(path: &str) -> Result<Vec<Host>> {
             hosts.push(Host {
                 address,
                 group: current_group.clone(),
+                parents: Vec::new(),
             });
         }
     }
 
     anyhow::ensure!(!hosts.is_empty(), "No hosts found in inventory file: {}", path);
 
-    for host in &hosts {
-        log::debug!("Inventory host: {} (group: {})", host.address, host.group);
+    for host in &mut hosts {
+        host.parents = ancestors(&host.group, &parents);
+        log::debug!("Inventory host: {} (group: {}, parents: {:?})", host.address, host.group, host.parents);
     }
 
     Ok(hosts)
 }
 
+/// Every group containing `group`, directly or through other groups,
+/// nearest first. A group that contains itself through a cycle is left out.
+fn ancestors(group: &str, parents: &HashMap<String, Vec<String>>) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut seen = HashSet::from([group]);
+    let mut queue = VecDeque::from([group]);
+    while let Some(g) = queue.pop_front() {
+        for parent in parents.get(g).into_iter().flatten() {
+            if seen.insert(parent) {
+                found.push(parent.clone());
+                queue.push_back(parent);
+            }
+        }
+    }
+    found
+}
+
+/// Group of the hosts given with `--extra-hosts`.
+pub const EXTRA_GROUP: &str = "extra";
+
+/// Hosts from a `--extra-hosts` list: comma separated, each `host`,
+/// `host:port` or `user@host:port`, with the user if one was given.
+pub fn parse_extra_hosts(list: &str) -> Result<Vec<(Host, Option<String>)>> {
+    let mut hosts = Vec::new();
+    for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (user, address) = match entry.split_once('@') {
+            Some((user, address)) => (Some(user.to_string()), address),
+            None => (None, entry),
+        };
+        let (host, port) = split_host_port(address);
+        anyhow::ensure!(
+            !host.is_empty() && user.as_deref() != Some(""),
+            "Invalid host in --extra-hosts: {}",
+            entry
+        );
+        anyhow::ensure!(
+            port.is_some() || !address.ends_with(':'),
+            "Invalid port in --extra-hosts: {}",
+            entry
+        );
+        hosts.push((
+            Host {
+                address: address.to_string(),
+                group: EXTRA_GROUP.to_string(),
+                parents: Vec::new(),
+            },
+            user,
+        ));
+    }
+    anyhow::ensure!(!hosts.is_empty(), "No hosts given with --extra-hosts");
+    Ok(hosts)
+}
+
+/// The cell named by `--focus host:service`: the inventory index of the host
+/// (its address, or the name without the port) and the service.
+pub fn parse_focus(spec: &str, hosts: &[Host]) -> Result<(usize, String)> {
+    let (host, service) = spec
+        .rsplit_once(':')
+        .filter(|(h, s)| !h.is_empty() && !s.is_empty())
+        .with_context(|| format!("--focus takes host:service, got '{}'", spec))?;
+    let idx = find_host(hosts, host).with_context(|| format!("--focus: {} is not in the inventory", host))?;
+    Ok((idx, service.to_string()))
+}
+
+/// Inventory index of a host given by its address, or its name without the port.
+pub fn find_host(hosts: &[Host], name: &str) -> Option<usize> {
+    hosts
+        .iter()
+        .position(|h| h.address == name)
+        .or_else(|| hosts.iter().position(|h| split_host_port(&h.address).0 == name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     // --- is_ip_address ---
 
+    #[test]
+    fn test_host_matches() {
+        let host = |address: &str| Host {
+            address: address.to_string(),
+            group: "web".to_string(),
+            parents: vec!["prod".to_string()],
+        };
+        assert!(host("web01").matches("web0*"));
+        assert!(host("web01").matches("prod"));
+        assert!(!host("web01").matches("db*"));
+        // Brackets are taken literally when they name the host exactly
+        assert!(host("[::1]:2222").matches("[::1]:2222"));
+        assert!(host("[fe80::1]").matches("[fe80::1]"));
+        assert!(!host("[fe80::1]").matches("[fe80::2]"));
+    }
+
     #[test]
     fn test_is_ip_valid() {
         assert!(is_ip_address("192.168.1.1"));
@@ -144,8 +306,33 @@ mod tests {
         assert!(!is_ip_address("myhost"));
     }
 
+    // --- split_host_port ---
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("web01"), ("web01", None));
+        assert_eq!(split_host_port("web01:2222"), ("web01", Some(2222)));
+        assert_eq!(split_host_port("10.0.0.5:2222"), ("10.0.0.5", Some(2222)));
+        assert_eq!(split_host_port("[fe80::1]:2222"), ("fe80::1", Some(2222)));
+        assert_eq!(split_host_port("fe80::1"), ("fe80::1", None));
+        assert_eq!(split_host_port("web01:ssh"), ("web01:ssh", None));
+    }
+
     // --- extract_address ---
 
+    #[test]
+    fn test_extract_address_with_port() {
+        assert_eq!(extract_address("abc 10.0.0.5:2222"), Some("10.0.0.5:2222".to_string()));
+        assert_eq!(
+            extract_address("myhost ansible_host=10.0.0.5 ansible_port=2200"),
+            Some("10.0.0.5:2200".to_string())
+        );
+        assert_eq!(
+            extract_address("web01:2222 ansible_port=2200"),
+            Some("web01:2222".to_string())
+        );
+    }
+
     #[test]
     fn test_extract_address_ansible_host() {
         let addr = extract_address("myhost ansible_host=10.0.0.5");
@@ -217,6 +404,27 @@ mod tests {
         assert_eq!(hosts[0].address, "10.0.0.1");
     }
 
+    #[test]
+    fn test_parse_inventory_children() {
+        let f = TempIni::new(
+            "[web]\nweb01\n[db]\ndb01\n[monitoring]\nmon01\n\
+             [prod:children]\nweb\ndb\n[eu:children]\nprod\n[backend:children]\ndb\n\
+             [loop:children]\nloop\n[loop]\nloop01\n",
+        );
+        let hosts = parse_inventory(f.path()).unwrap();
+        assert_eq!(hosts.len(), 4);
+        assert_eq!(hosts[0].parents, vec!["prod", "eu"]);
+        assert_eq!(hosts[1].parents, vec!["prod", "backend", "eu"]);
+        assert!(hosts[2].parents.is_empty());
+        assert!(hosts[3].parents.is_empty());
+
+        assert!(hosts[1].in_group("db"));
+        assert!(hosts[1].in_group("eu"));
+        assert!(hosts[0].in_group("pro*"));
+        assert!(!hosts[0].in_group("backend"));
+        assert!(!hosts[2].in_group("prod"));
+    }
+
     #[test]
     fn test_parse_inventory_ignores_comments_and_blanks() {
         let f = TempIni::new(
@@ -277,4 +485,43 @@ mod tests {
         assert_eq!(hosts[3].address, "10.10.44.55");
         assert_eq!(hosts[3].group, "other hosts");
     }
+
+    #[test]
+    fn test_parse_extra_hosts() {
+        let hosts = parse_extra_hosts("web01, admin@10.0.0.5:2222,root@[::1]:22,").unwrap();
+        let got: Vec<(&str, &str, Option<&str>)> = hosts
+            .iter()
+            .map(|(h, u)| (h.address.as_str(), h.group.as_str(), u.as_deref()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("web01", "extra", None),
+                ("10.0.0.5:2222", "extra", Some("admin")),
+                ("[::1]:22", "extra", Some("root")),
+            ]
+        );
+        assert!(parse_extra_hosts("").is_err());
+        assert!(parse_extra_hosts("@web01").is_err());
+        assert!(parse_extra_hosts("admin@").is_err());
+        assert!(parse_extra_hosts("web01:").is_err());
+    }
+
+    #[test]
+    fn test_parse_focus() {
+        let hosts: Vec<Host> = ["web01", "db01:2222"]
+            .iter()
+            .map(|a| Host {
+                address: a.to_string(),
+                group: "g".to_string(),
+                parents: Vec::new(),
+            })
+            .collect();
+        assert_eq!(parse_focus("web01:nginx", &hosts).unwrap(), (0, "nginx".to_string()));
+        assert_eq!(parse_focus("db01:2222:postgresql", &hosts).unwrap(), (1, "postgresql".to_string()));
+        assert_eq!(parse_focus("db01:postgresql", &hosts).unwrap(), (1, "postgresql".to_string()));
+        assert!(parse_focus("web02:nginx", &hosts).is_err());
+        assert!(parse_focus("web01", &hosts).is_err());
+        assert!(parse_focus("web01:", &hosts).is_err());
+    }
 }
@@ -0,0 +1,186 @@
+use crate::clock;
+use anyhow::{Context, Result};
+use glob_match::glob_match;
+use serde::Deserialize;
+
+/// A period during which matching cells are treated as silenced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    /// Host address or group globs; empty matches every host.
+    pub hosts: Vec<String>,
+    /// Service name globs; empty matches every service.
+    pub services: Vec<String>,
+    pub schedule: Schedule,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// Every day (or only on `days`, 0 = Monday) between two times of day.
+    /// A range whose end is before its start wraps past midnight.
+    Daily {
+        start: u32,
+        end: u32,
+        days: Vec<u32>,
+    },
+    /// A one-off window between two absolute times.
+    Range { from: u64, until: u64 },
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MaintenanceEntry {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    hosts: Vec<String>,
+    #[serde(default)]
+    services: Vec<String>,
+    #[serde(default)]
+    daily: Option<String>,
+    #[serde(default)]
+    days: Vec<String>,
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    until: Option<String>,
+}
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+impl MaintenanceEntry {
+    pub(crate) fn into_window(self, index: usize) -> Result<MaintenanceWindow> {
+        let name = self
+            .name
+            .unwrap_or_else(|| format!("maintenance #{}", index + 1));
+
+        let schedule = match (self.daily, self.from, self.until) {
+            (Some(daily), None, None) => {
+                let (start, end) = daily
+                    .split_once('-')
+                    .and_then(|(s, e)| Some((clock::parse_hhmm(s)?, clock::parse_hhmm(e)?)))
+                    .with_context(|| format!("Invalid daily range in {}: {} (expected HH:MM-HH:MM)", name, daily))?;
+                let days = self
+                    .days
+                    .iter()
+                    .map(|d| {
+                        let d = d.to_lowercase();
+                        WEEKDAYS
+                            .iter()
+                            .position(|w| d.starts_with(w))
+                            .map(|p| p as u32)
+                            .with_context(|| format!("Invalid day in {}: {}", name, d))
+                    })
+                    .collect::<Result<Vec<u32>>>()?;
+                Schedule::Daily { start, end, days }
+            }
+            (None, Some(from), Some(until)) => {
+                let parse = |s: &str| {
+                    clock::parse_datetime(s).with_context(|| {
+                        format!("Invalid time in {}: {} (expected YYYY-MM-DD HH:MM)", name, s)
+                    })
+                };
+                Schedule::Range {
+                    from: parse(&from)?,
+                    until: parse(&until)?,
+                }
+            }
+            _ => anyhow::bail!(
+                "Maintenance window {} needs either `daily` or both `from` and `until`",
+                name
+            ),
+        };
+
+        Ok(MaintenanceWindow {
+            name,
+            hosts: self.hosts,
+            services: self.services,
+            schedule,
+        })
+    }
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is in effect at the given Unix time (UTC).
+    pub fn is_active(&self, now: u64) -> bool {
+        match &self.schedule {
+            Schedule::Range { from, until } => *from <= now && now < *until,
+            Schedule::Daily { start, end, days } => {
+                let dt = clock::from_unix(now);
+                let minute = dt.hour * 60 + dt.minute;
+                let (in_range, day) = if start <= end {
+                    (*start <= minute && minute < *end, dt.weekday)
+                } else if minute >= *start {
+                    (true, dt.weekday)
+                } else {
+                    // After midnight: the window belongs to the day it started on
+                    (minute < *end, (dt.weekday + 6) % 7)
+                };
+                in_range && (days.is_empty() || days.contains(&day))
+            }
+        }
+    }
+
+    /// Whether the window covers a cell. Host patterns match either the
+    /// address or the inventory group.
+    pub fn covers(&self, host: &str, group: &str, service: &str) -> bool {
+        let host_ok = self.hosts.is_empty()
+            || self
+                .hosts
+                .iter()
+                .any(|p| glob_match(p, host) || glob_match(p, group));
+        let service_ok = self.services.is_empty()
+            || self.services.iter().any(|p| glob_match(p, service));
+        host_ok && service_ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(yaml: &str) -> Result<MaintenanceWindow> {
+        let entry: MaintenanceEntry = serde_yaml::from_str(yaml).unwrap();
+        entry.into_window(0)
+    }
+
+    #[test]
+    fn test_daily_window() {
+        let w = entry("daily: \"02:00-03:30\"\n").unwrap();
+        // 2024-02-29 is a Thursday
+        assert!(w.is_active(clock::to_unix(2024, 2, 29, 2, 15)));
+        assert!(!w.is_active(clock::to_unix(2024, 2, 29, 3, 30)));
+        assert!(!w.is_active(clock::to_unix(2024, 2, 29, 12, 0)));
+    }
+
+    #[test]
+    fn test_daily_window_wraps_midnight_and_days() {
+        let w = entry("daily: \"23:00-01:00\"\ndays: [thu]\n").unwrap();
+        assert!(w.is_active(clock::to_unix(2024, 2, 29, 23, 30)));
+        // 00:30 Friday still belongs to Thursday's window
+        assert!(w.is_active(clock::to_unix(2024, 3, 1, 0, 30)));
+        assert!(!w.is_active(clock::to_unix(2024, 3, 1, 23, 30)));
+    }
+
+    #[test]
+    fn test_range_window() {
+        let w = entry("from: \"2024-02-29 22:00\"\nuntil: \"2024-03-01 02:00\"\n").unwrap();
+        assert!(w.is_active(clock::to_unix(2024, 3, 1, 1, 0)));
+        assert!(!w.is_active(clock::to_unix(2024, 3, 1, 2, 0)));
+    }
+
+    #[test]
+    fn test_invalid_windows() {
+        assert!(entry("daily: \"2am\"\n").is_err());
+        assert!(entry("from: \"2024-02-29 22:00\"\n").is_err());
+        assert!(entry("daily: \"02:00-03:00\"\ndays: [someday]\n").is_err());
+    }
+
+    #[test]
+    fn test_covers() {
+        let w = entry("hosts: [\"web*\", db]\nservices: [\"app*\"]\ndaily: \"00:00-01:00\"\n").unwrap();
+        assert!(w.covers("web01", "frontend", "app-api"));
+        assert!(w.covers("10.0.0.5", "db", "app"));
+        assert!(!w.covers("web01", "frontend", "nginx"));
+        assert!(!w.covers("cache01", "cache", "app"));
+    }
+}
@@ -1,5 +1,12 @@
+pub mod ansible;
 pub mod inventory;
+pub mod maintenance;
+pub mod notifications;
 pub mod services;
+pub mod ssh;
 
 pub use inventory::Host;
-pub use services::ServiceConfig;
+pub use maintenance::MaintenanceWindow;
+pub use notifications::{NotificationChannel, NotificationRoute};
+pub use services::{Config, ServiceConfig, Severity};
+pub use ssh::SshOptions;
@@ -0,0 +1,179 @@
+use super::services::Severity;
+use anyhow::Result;
+use serde::Deserialize;
+
+/// A notification channel plus the least severe alert it wants.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NotificationRoute {
+    #[serde(flatten)]
+    pub channel: NotificationChannel,
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Info
+}
+
+/// Where alerts are sent, selected with `channel:` in services.yaml.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "channel", rename_all = "lowercase")]
+pub enum NotificationChannel {
+    Email(EmailConfig),
+    /// Generic JSON POST with the raw transition fields.
+    Webhook(WebhookConfig),
+    /// Slack incoming webhook with a colored attachment.
+    Slack(WebhookConfig),
+    /// Mattermost incoming webhook; same attachment format as Slack.
+    Mattermost(WebhookConfig),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Link attached to the message; `{host}` and `{service}` are substituted.
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EmailConfig {
+    pub server: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: TlsMode,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the SMTP password, so it stays out of the file.
+    #[serde(default)]
+    pub password_env: Option<String>,
+}
+
+/// MQTT broker that gets a retained message per cell on every change,
+/// configured with a top-level `mqtt:` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MqttConfig {
+    pub server: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the broker password.
+    #[serde(default)]
+    pub password_env: Option<String>,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_topic_prefix() -> String {
+    "sctl".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsMode {
+    /// Plain connection upgraded with STARTTLS, which must succeed.
+    #[default]
+    Starttls,
+    /// TLS from the first byte (SMTPS).
+    Tls,
+    None,
+}
+
+impl EmailConfig {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls {
+            TlsMode::Tls => 465,
+            TlsMode::Starttls => 587,
+            TlsMode::None => 25,
+        })
+    }
+}
+
+impl NotificationChannel {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            NotificationChannel::Email(email) => {
+                if email.to.is_empty() {
+                    anyhow::bail!("Email notification from {} has no recipients", email.from);
+                }
+            }
+            NotificationChannel::Webhook(hook)
+            | NotificationChannel::Slack(hook)
+            | NotificationChannel::Mattermost(hook) => {
+                if !hook.url.starts_with("http://") && !hook.url.starts_with("https://") {
+                    anyhow::bail!("Webhook URL must be http(s): {}", hook.url);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_email_channel() {
+        let yaml = "channel: email\nserver: smtp.example.com\nfrom: sctl@example.com\nto: [ops@example.com]\n";
+        let channel: NotificationChannel = serde_yaml::from_str(yaml).unwrap();
+        let NotificationChannel::Email(email) = &channel else {
+            panic!("expected email channel");
+        };
+        assert_eq!(email.tls, TlsMode::Starttls);
+        assert_eq!(email.port(), 587);
+        assert!(channel.validate().is_ok());
+    }
+
+    #[test]
+    fn test_email_without_recipients_rejected() {
+        let yaml = "channel: email\nserver: smtp\nfrom: a@b\nto: []\ntls: tls\n";
+        let channel: NotificationChannel = serde_yaml::from_str(yaml).unwrap();
+        assert!(channel.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_mqtt_defaults() {
+        let mqtt: MqttConfig = serde_yaml::from_str("server: broker.lan\n").unwrap();
+        assert_eq!(mqtt.port, 1883);
+        assert_eq!(mqtt.topic_prefix, "sctl");
+        assert_eq!(mqtt.username, None);
+    }
+
+    #[test]
+    fn test_parse_slack_channel() {
+        let yaml = "channel: slack\nurl: https://hooks.slack.com/services/T/B/x\nlink: \"https://grafana/d?host={host}\"\n";
+        let channel: NotificationChannel = serde_yaml::from_str(yaml).unwrap();
+        assert!(matches!(&channel, NotificationChannel::Slack(h) if h.link.is_some()));
+        assert!(channel.validate().is_ok());
+
+        let bad: NotificationChannel = serde_yaml::from_str("channel: mattermost\nurl: hooks.local\n").unwrap();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_route_min_severity() {
+        let yaml = "channel: webhook\nurl: https://pager.example.com\nmin_severity: critical\n";
+        let route: NotificationRoute = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(route.min_severity, Severity::Critical);
+        assert!(matches!(route.channel, NotificationChannel::Webhook(_)));
+
+        let yaml = "channel: email\nserver: smtp\nport: 2525\nfrom: a@b\nto: [c@d]\n";
+        let route: NotificationRoute = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(route.min_severity, Severity::Info);
+        assert!(matches!(route.channel, NotificationChannel::Email(e) if e.port() == 2525));
+    }
+
+    #[test]
+    fn test_unknown_channel_rejected() {
+        assert!(serde_yaml::from_str::<NotificationChannel>("channel: pigeon\n").is_err());
+    }
+}
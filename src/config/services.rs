@@ -1,19 +1,411 @@
+use super::inventory::split_host_port;
+use super::maintenance::{MaintenanceEntry, MaintenanceWindow};
+use super::notifications::{MqttConfig, NotificationRoute};
+use super::ssh::SshOptions;
+use crate::clock;
+use crate::monitor::ServiceStatus;
+use crate::tmux::quote;
 use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ServiceConfig {
     pub name_pattern: String,
     pub files: Vec<String>,
     pub commands: Vec<String>,
+    /// Commands from `commands` that need a terminal; run with `ssh -t`
+    /// instead of being captured into the viewer.
+    pub interactive: Vec<String>,
     pub is_glob: bool,
+    /// Patterns removed from this entry's glob matches.
+    pub exclude: Vec<String>,
+    /// Maximum number of units a glob may expand to on one host: the
+    /// entry's `max_matches:`, else `max_glob_matches:`, else
+    /// [`DEFAULT_MAX_GLOB_MATCHES`]. 0 means no cap.
+    pub max_matches: usize,
+    /// Custom check that determines the status instead of systemd.
+    pub check: Option<CheckConfig>,
+    /// Restarting again within this interval needs an explicit override.
+    pub min_restart_interval: Option<Duration>,
+    pub severity: Severity,
+    /// Commands looking for security denials, run when the raw status of a
+    /// failed cell is shown.
+    pub denial_checks: Vec<String>,
+    /// Other entries that matched the same unit on a host, whose files and
+    /// commands were merged into this one.
+    pub merged: Vec<String>,
+    /// A cell added by a script rather than a unit, with nothing to act on.
+    pub derived: bool,
+}
+
+/// How much a service's failure matters; drives alert routing and sorting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// A command run on the host whose exit code and output map to a status.
+/// Status values are the words understood by `ServiceStatus::from_str`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckConfig {
+    pub command: String,
+    /// Exit code → status, e.g. `0: active, 2: degraded`.
+    pub exit_codes: Vec<(i32, String)>,
+    /// Output substring → status; checked before exit codes, first match wins.
+    pub output: Vec<(String, String)>,
+    /// Status for anything unmatched (`else:`); defaults to active on exit 0, failed otherwise.
+    pub default: Option<String>,
+    /// Set for `type: cert`, whose status comes from days left instead.
+    pub cert: Option<CertCheck>,
+    /// Set for `type: last_run`, whose status comes from the time since.
+    pub last_run: Option<LastRunCheck>,
+    /// Set for a `type` no built-in check has, evaluated by a plugin.
+    pub plugin: Option<PluginCheck>,
+}
+
+/// A check evaluated by the plugin that provides its `type`, on the controller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginCheck {
+    pub kind: String,
+    /// The check's `options:`, passed to the plugin as is.
+    pub options: serde_json::Value,
+}
+
+/// Where a last-run check reads when the job last ran.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LastRunSource {
+    /// A systemd timer's last trigger.
+    Timer(String),
+    /// A file the job touches, e.g. anacron's `/var/spool/anacron/cron.daily`.
+    File(String),
+}
+
+/// A scheduled job that must have run recently (`type: last_run`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastRunCheck {
+    pub source: LastRunSource,
+    pub max_age: Duration,
+}
+
+impl LastRunCheck {
+    /// Shell command printing the host's clock and the last run, both as
+    /// Unix seconds, so clock skew with the controller doesn't matter.
+    pub fn command(&self) -> String {
+        let last = match &self.source {
+            LastRunSource::Timer(timer) => {
+                let unit = if UNIT_SUFFIXES.iter().any(|suffix| timer.ends_with(suffix)) {
+                    timer.clone()
+                } else {
+                    format!("{}.timer", timer)
+                };
+                format!("systemctl show -p LastTriggerUSec --value --timestamp=unix {} 2>/dev/null", quote(&unit))
+            }
+            LastRunSource::File(path) => format!("stat -c %Y {} 2>/dev/null", quote(path)),
+        };
+        format!("echo now=$(date +%s); echo last=$({})", last)
+    }
+}
+
+/// Where a certificate check connects to its target from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CertFrom {
+    /// The monitored host, with its openssl.
+    #[default]
+    Host,
+    /// The machine running the controller.
+    Controller,
+}
+
+/// A TLS certificate whose expiry is checked (`type: cert`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertCheck {
+    /// `host:port`; port 443 if none is given.
+    pub target: String,
+    pub from: CertFrom,
+    /// Degraded with fewer days left than this.
+    pub warn_days: i64,
+    /// Failed with fewer days left than this.
+    pub critical_days: i64,
+}
+
+/// Seconds `openssl s_client` gets to fetch a certificate; it would
+/// otherwise hang on a target that accepts the connection but never answers.
+const CERT_TIMEOUT_SECS: u32 = 10;
+
+impl CertCheck {
+    /// Shell command printing the certificate's `notAfter=` line.
+    pub fn command(&self) -> String {
+        let (name, port) = split_host_port(&self.target);
+        let port = port.unwrap_or(443);
+        let connect = if name.contains(':') {
+            format!("[{}]:{}", name, port)
+        } else {
+            format!("{}:{}", name, port)
+        };
+        format!(
+            "echo | timeout {} openssl s_client -connect {} -servername {} 2>/dev/null | openssl x509 -noout -enddate",
+            CERT_TIMEOUT_SECS,
+            quote(&connect),
+            quote(name)
+        )
+    }
+}
+
+/// Everything read from services.yaml.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub services: Vec<ServiceConfig>,
+    pub maintenance: Vec<MaintenanceWindow>,
+    /// Inventory groups whose hosts need a typed reason before destructive actions.
+    pub protected_groups: Vec<String>,
+    /// Local command run on every status transition.
+    pub on_change: Option<String>,
+    pub notifications: Vec<NotificationRoute>,
+    pub mqtt: Option<MqttConfig>,
+    /// Commands about the host itself, listed in the host detail view.
+    pub host_commands: Vec<String>,
+    /// Command run on each host at refresh that lists the units needing a
+    /// restart to pick up updated binaries or libraries.
+    pub restart_check: Option<String>,
+    /// Check hosts for pending package updates and show them in a column.
+    pub package_updates: Option<PackageUpdatesConfig>,
+    pub bulk: BulkConfig,
+    /// Show statuses as one-character glyphs instead of words.
+    pub status_glyphs: bool,
+    pub cell: CellContent,
+    /// Words shown for statuses instead of the built-in ones, by status name.
+    pub status_words: HashMap<String, String>,
+    /// Seconds before the first re-query of cells that are activating,
+    /// deactivating or reloading, doubling after each; 0 (the default) turns
+    /// this off.
+    pub repoll_transitional: u64,
+    /// Time between automatic full refreshes; `None` refreshes only on `r`.
+    pub refresh_interval: Option<Duration>,
+    pub initial_refresh: InitialRefresh,
+    /// Diagnostics run on the host of a failed cell by the triage key.
+    pub triage: Vec<String>,
+    /// Directory of plugin executables; `plugins::DEFAULT_DIR` if unset.
+    pub plugins_dir: Option<String>,
+    /// Executables told about refreshes and transitions, see `scripts`.
+    pub scripts: Vec<String>,
+    pub ssh: SshOptions,
+}
+
+
+fn default_triage() -> Vec<String> {
+    [
+        "systemctl status --no-pager -l '%n'",
+        "journalctl -u '%n' -n 50 --no-pager",
+        "df -h",
+        "journalctl -k --since -1d --no-pager | grep -iE 'out of memory|oom-kill|killed process' | tail -n 20",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// The `bulk:` section, for actions run on a whole column.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BulkConfig {
+    /// Hosts acted on at the same time.
+    pub concurrency: usize,
+    /// Seconds before a host's action is given up as timed out.
+    pub timeout: u64,
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            timeout: 120,
+        }
+    }
+}
+
+/// The `package_updates:` section.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PackageUpdatesConfig {
+    /// Seconds between checks; package lists change slowly.
+    pub interval: u64,
+}
+
+impl Default for PackageUpdatesConfig {
+    fn default() -> Self {
+        Self { interval: 3600 }
+    }
+}
+
+/// What to refresh at startup, from `initial_refresh:` or the command line.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(from = "String")]
+pub enum InitialRefresh {
+    #[default]
+    All,
+    /// Start with an empty grid until `r` is pressed.
+    None,
+    /// Only hosts whose inventory group or address matches the glob.
+    Group(String),
+}
+
+impl From<String> for InitialRefresh {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "all" => InitialRefresh::All,
+            "none" => InitialRefresh::None,
+            _ => InitialRefresh::Group(s),
+        }
+    }
+}
+
+/// What the status column shows after the status, from `cell:`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellContent {
+    /// The status alone.
+    #[default]
+    Status,
+    /// How long an active unit has been up.
+    Uptime,
+    /// How often systemd restarted the unit (`NRestarts`).
+    Restarts,
+    /// The first line of a custom check's output.
+    CheckOutput,
+}
+
+/// Glob expansion cap when neither `max_glob_matches:` nor an entry's
+/// `max_matches:` sets one.
+pub const DEFAULT_MAX_GLOB_MATCHES: usize = 50;
+
+/// Unit types systemd understands; a name without one of these suffixes is a service.
+const UNIT_SUFFIXES: &[&str] = &[
+    ".service", ".socket", ".timer", ".target", ".path", ".mount", ".automount", ".swap",
+    ".slice", ".scope", ".device",
+];
+
+/// The unit type of a name, e.g. "timer" for `foo.timer`, "service" when no suffix is given.
+pub fn unit_type(name: &str) -> &str {
+    UNIT_SUFFIXES
+        .iter()
+        .find(|suffix| name.ends_with(*suffix))
+        .map(|suffix| &suffix[1..])
+        .unwrap_or("service")
+}
+
+/// Full systemd unit name: appends `.service` unless the name already carries a unit suffix.
+pub fn unit_name(name: &str) -> String {
+    if UNIT_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        name.to_string()
+    } else {
+        format!("{}.service", name)
+    }
+}
+
+/// Name shown in the UI: services drop their implicit suffix, other unit types keep theirs.
+pub fn display_name(unit: &str) -> &str {
+    unit.strip_suffix(".service").unwrap_or(unit)
+}
+
+/// Split a templated unit such as `getty@tty1` into its prefix and instance.
+pub fn template_parts(name: &str) -> Option<(&str, &str)> {
+    let (prefix, rest) = name.split_once('@')?;
+    let instance = UNIT_SUFFIXES
+        .iter()
+        .find_map(|suffix| rest.strip_suffix(suffix))
+        .unwrap_or(rest);
+    Some((prefix, instance))
+}
+
+/// Substitute the systemd specifiers `%n` (full unit name), `%i` (instance)
+/// and `%p` (prefix) in a configured file path or command for a concrete
+/// unit. Non-template units have an empty instance and their full name as
+/// prefix, as in systemd.
+pub fn expand_specifiers(text: &str, name: &str) -> String {
+    let (prefix, instance) = template_parts(name).unwrap_or((display_name(name), ""));
+    text.replace("%n", &unit_name(name)).replace("%i", instance).replace("%p", prefix)
+}
+
+/// Normalise a configured pattern to its display form. A bare template such
+/// as `getty@` or `getty@.service` can't be queried itself, so it expands to
+/// all of its instances.
+fn normalize_pattern(name: &str) -> String {
+    let name = display_name(name);
+    match name.strip_suffix('@') {
+        Some(_) => format!("{}*", name),
+        None => match name.split_once("@.") {
+            Some((template, suffix)) => format!("{}@*.{}", template, suffix),
+            None => name.to_string(),
+        },
+    }
 }
 
 #[derive(Deserialize)]
 struct ServicesFile {
     services: HashMap<String, ServiceEntry>,
+    #[serde(default)]
+    max_glob_matches: Option<usize>,
+    #[serde(default)]
+    maintenance: Vec<MaintenanceEntry>,
+    #[serde(default)]
+    groups: HashMap<String, GroupEntry>,
+    #[serde(default)]
+    on_change: Option<String>,
+    #[serde(default)]
+    notifications: Vec<NotificationRoute>,
+    #[serde(default)]
+    mqtt: Option<MqttConfig>,
+    #[serde(default)]
+    host_commands: Vec<String>,
+    #[serde(default)]
+    restart_check: Option<String>,
+    #[serde(default)]
+    package_updates: Option<PackageUpdatesConfig>,
+    #[serde(default)]
+    bulk: BulkConfig,
+    #[serde(default)]
+    status_glyphs: bool,
+    #[serde(default)]
+    cell: CellContent,
+    #[serde(default)]
+    status_words: HashMap<String, String>,
+    #[serde(default)]
+    repoll_transitional: u64,
+    #[serde(default)]
+    refresh_interval: Option<serde_yaml::Value>,
+    #[serde(default)]
+    initial_refresh: InitialRefresh,
+    #[serde(default = "default_triage")]
+    triage: Vec<String>,
+    #[serde(default)]
+    plugins_dir: Option<String>,
+    #[serde(default)]
+    scripts: Vec<String>,
+    #[serde(default)]
+    ssh: SshOptions,
+}
+
+#[derive(Deserialize)]
+struct GroupEntry {
+    #[serde(default)]
+    protected: bool,
 }
 
 #[derive(Deserialize)]
@@ -21,29 +413,223 @@ struct ServiceEntry {
     #[serde(default)]
     files: Vec<String>,
     #[serde(default)]
-    commands: Vec<String>,
+    commands: Vec<CommandEntry>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    max_matches: Option<usize>,
+    #[serde(default)]
+    check: Option<CheckEntry>,
+    #[serde(default)]
+    min_restart_interval: Option<serde_yaml::Value>,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default)]
+    denial_checks: Vec<String>,
+}
+
+/// Built-in `denial_checks`, by name: recent SELinux denials from the audit
+/// log, and AppArmor denials from the kernel log.
+const DENIAL_PRESETS: [(&str, &str); 2] = [
+    (
+        "selinux",
+        "sudo -n ausearch -m AVC,USER_AVC,SELINUX_ERR -ts recent -i 2>/dev/null | tail -n 20",
+    ),
+    (
+        "apparmor",
+        "sudo -n dmesg -T 2>/dev/null | grep 'apparmor=\"DENIED\"' | tail -n 20",
+    ),
+];
+
+/// A `denial_checks` entry as a command: a preset name, or a command as is.
+fn denial_command(entry: &str) -> String {
+    DENIAL_PRESETS
+        .iter()
+        .find(|(name, _)| *name == entry)
+        .map_or(entry, |(_, command)| *command)
+        .to_string()
 }
 
-pub fn parse_services(path: &str) -> Result<Vec<ServiceConfig>> {
+/// A command is either a plain string or `{ command: ..., interactive: true }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CommandEntry {
+    Plain(String),
+    Detailed {
+        command: String,
+        #[serde(default)]
+        interactive: bool,
+    },
+}
+
+#[derive(Deserialize)]
+struct CheckEntry {
+    /// `command` (default), `cert`, `last_run` or a type from a plugin.
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    exit_codes: serde_yaml::Mapping,
+    #[serde(default)]
+    output: serde_yaml::Mapping,
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    from: CertFrom,
+    #[serde(default)]
+    warn_days: Option<i64>,
+    #[serde(default)]
+    critical_days: Option<i64>,
+    #[serde(default)]
+    timer: Option<String>,
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    max_age: Option<serde_yaml::Value>,
+    #[serde(default)]
+    options: serde_yaml::Mapping,
+}
+
+impl CheckEntry {
+    fn into_config(self, service: &str) -> Result<CheckConfig> {
+        match self.kind.as_deref() {
+            None | Some("command") => {}
+            Some("cert") => {
+                let cert = CertCheck {
+                    target: self
+                        .target
+                        .with_context(|| format!("Certificate check for {} needs a target", service))?,
+                    from: self.from,
+                    warn_days: self.warn_days.unwrap_or(30),
+                    critical_days: self.critical_days.unwrap_or(7),
+                };
+                return Ok(CheckConfig {
+                    command: cert.command(),
+                    cert: Some(cert),
+                    ..Default::default()
+                });
+            }
+            Some("last_run") => {
+                let source = match (self.timer, self.file) {
+                    (Some(timer), None) => LastRunSource::Timer(timer),
+                    (None, Some(file)) => LastRunSource::File(file),
+                    _ => anyhow::bail!("Last-run check for {} needs either a timer or a file", service),
+                };
+                let max_age = self
+                    .max_age
+                    .as_ref()
+                    .and_then(yaml_string)
+                    .and_then(|s| clock::parse_duration(&s))
+                    .with_context(|| format!("Last-run check for {} needs a valid max_age", service))?;
+                let last_run = LastRunCheck { source, max_age };
+                return Ok(CheckConfig {
+                    command: last_run.command(),
+                    last_run: Some(last_run),
+                    ..Default::default()
+                });
+            }
+            Some(other) => {
+                let options = serde_json::to_value(&self.options)
+                    .with_context(|| format!("Invalid options in {} check for {}", other, service))?;
+                return Ok(CheckConfig {
+                    plugin: Some(PluginCheck {
+                        kind: other.to_string(),
+                        options,
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+        let mut check = CheckConfig {
+            command: self
+                .command
+                .with_context(|| format!("Check for {} needs a command", service))?,
+            ..Default::default()
+        };
+        for (key, value) in &self.exit_codes {
+            let status = yaml_string(value)
+                .with_context(|| format!("Invalid check status for {}: {:?}", service, value))?;
+            match key {
+                serde_yaml::Value::Number(n) if n.as_i64().is_some() => {
+                    check.exit_codes.push((n.as_i64().unwrap() as i32, status));
+                }
+                serde_yaml::Value::String(k) if k == "else" => check.default = Some(status),
+                other => anyhow::bail!("Invalid exit code in check for {}: {:?}", service, other),
+            }
+        }
+        for (key, value) in &self.output {
+            let needle = yaml_string(key)
+                .with_context(|| format!("Invalid output match for {}: {:?}", service, key))?;
+            let status = yaml_string(value)
+                .with_context(|| format!("Invalid check status for {}: {:?}", service, value))?;
+            check.output.push((needle, status));
+        }
+        Ok(check)
+    }
+}
+
+fn yaml_string(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub fn parse_config(path: &str) -> Result<Config> {
     let content = fs::read_to_string(path)
         .with_context(|| format!("Failed to read services file: {}", path))?;
 
     let file: ServicesFile = serde_yaml::from_str(&content)
         .with_context(|| format!("Failed to parse services YAML: {}", path))?;
 
+    let default_max = file.max_glob_matches.unwrap_or(DEFAULT_MAX_GLOB_MATCHES);
     let mut configs: Vec<ServiceConfig> = file
         .services
         .into_iter()
         .map(|(name, entry)| {
+            let name = normalize_pattern(&name);
             let is_glob = name.contains('*') || name.contains('?') || name.contains('[');
-            ServiceConfig {
+            let check = entry.check.map(|c| c.into_config(&name)).transpose()?;
+            let min_restart_interval = entry
+                .min_restart_interval
+                .map(|v| {
+                    yaml_string(&v)
+                        .and_then(|s| clock::parse_duration(&s))
+                        .with_context(|| format!("Invalid min_restart_interval for {}: {:?}", name, v))
+                })
+                .transpose()?;
+            let mut commands = Vec::new();
+            let mut interactive = Vec::new();
+            for c in entry.commands {
+                match c {
+                    CommandEntry::Plain(command) => commands.push(command),
+                    CommandEntry::Detailed { command, interactive: i } => {
+                        if i {
+                            interactive.push(command.clone());
+                        }
+                        commands.push(command);
+                    }
+                }
+            }
+            Ok(ServiceConfig {
                 name_pattern: name,
                 files: entry.files,
-                commands: entry.commands,
+                commands,
+                interactive,
                 is_glob,
-            }
+                exclude: entry.exclude.iter().map(|e| normalize_pattern(e)).collect(),
+                max_matches: entry.max_matches.unwrap_or(default_max),
+                check,
+                min_restart_interval,
+                severity: entry.severity,
+                denial_checks: entry.denial_checks.iter().map(|c| denial_command(c)).collect(),
+                merged: Vec::new(),
+                derived: false,
+            })
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     configs.sort_by(|a, b| a.name_pattern.cmp(&b.name_pattern));
 
@@ -57,7 +643,67 @@ pub fn parse_services(path: &str) -> Result<Vec<ServiceConfig>> {
         );
     }
 
-    Ok(configs)
+    let maintenance = file
+        .maintenance
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| entry.into_window(i))
+        .collect::<Result<Vec<_>>>()?;
+    for window in &maintenance {
+        log::debug!("Maintenance window: {} ({:?})", window.name, window.schedule);
+    }
+
+    for route in &file.notifications {
+        route.channel.validate()?;
+    }
+    if let Some(name) = file.status_words.keys().find(|k| !ServiceStatus::NAMES.contains(&k.as_str())) {
+        anyhow::bail!(
+            "Unknown status '{}' in status_words (expected one of: {})",
+            name,
+            ServiceStatus::NAMES.join(", ")
+        );
+    }
+
+    let refresh_interval = file
+        .refresh_interval
+        .map(|v| {
+            yaml_string(&v)
+                .and_then(|s| clock::parse_duration(&s))
+                .with_context(|| format!("Invalid refresh_interval: {:?}", v))
+        })
+        .transpose()?
+        .filter(|d| !d.is_zero());
+
+    let mut protected_groups: Vec<String> = file
+        .groups
+        .into_iter()
+        .filter(|(_, g)| g.protected)
+        .map(|(name, _)| name)
+        .collect();
+    protected_groups.sort();
+
+    Ok(Config {
+        services: configs,
+        maintenance,
+        protected_groups,
+        on_change: file.on_change,
+        notifications: file.notifications,
+        mqtt: file.mqtt,
+        host_commands: file.host_commands,
+        restart_check: file.restart_check,
+        package_updates: file.package_updates,
+        bulk: file.bulk,
+        status_glyphs: file.status_glyphs,
+        cell: file.cell,
+        status_words: file.status_words,
+        repoll_transitional: file.repoll_transitional,
+        refresh_interval,
+        initial_refresh: file.initial_refresh,
+        triage: file.triage,
+        plugins_dir: file.plugins_dir,
+        scripts: file.scripts,
+        ssh: file.ssh,
+    })
 }
 
 #[cfg(test)]
@@ -93,7 +739,7 @@ mod tests {
     #[test]
     fn test_parse_services_single_entry() {
         let f = TempYaml::new("services:\n  nginx:\n    commands:\n      - nginx -T\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert_eq!(configs.len(), 1);
         assert_eq!(configs[0].name_pattern, "nginx");
         assert_eq!(configs[0].commands, vec!["nginx -T"]);
@@ -103,37 +749,47 @@ mod tests {
     #[test]
     fn test_parse_services_glob_star() {
         let f = TempYaml::new("services:\n  nginx*:\n    commands:\n      - nginx -T\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert!(configs[0].is_glob);
     }
 
     #[test]
     fn test_parse_services_glob_question_mark() {
         let f = TempYaml::new("services:\n  nginx?:\n    commands: []\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert!(configs[0].is_glob);
     }
 
     #[test]
     fn test_parse_services_glob_bracket() {
         let f = TempYaml::new("services:\n  \"nginx[0-9]\":\n    commands: []\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert!(configs[0].is_glob);
     }
 
     #[test]
     fn test_parse_services_non_glob() {
         let f = TempYaml::new("services:\n  sshd:\n    commands: []\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert!(!configs[0].is_glob);
     }
 
+    #[test]
+    fn test_parse_interactive_commands() {
+        let f = TempYaml::new(
+            "services:\n  redis:\n    commands:\n      - redis-cli info\n      - command: redis-cli\n        interactive: true\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].commands, vec!["redis-cli info", "redis-cli"]);
+        assert_eq!(configs[0].interactive, vec!["redis-cli"]);
+    }
+
     #[test]
     fn test_parse_services_files_and_commands() {
         let f = TempYaml::new(
             "services:\n  nginx:\n    files:\n      - /etc/nginx/nginx.conf\n    commands:\n      - nginx -T\n",
         );
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert_eq!(configs[0].files, vec!["/etc/nginx/nginx.conf"]);
         assert_eq!(configs[0].commands, vec!["nginx -T"]);
     }
@@ -143,7 +799,7 @@ mod tests {
         let f = TempYaml::new(
             "services:\n  zebra:\n    commands: []\n  alpha:\n    commands: []\n  middle:\n    commands: []\n",
         );
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         let names: Vec<&str> = configs.iter().map(|c| c.name_pattern.as_str()).collect();
         assert_eq!(names, vec!["alpha", "middle", "zebra"]);
     }
@@ -151,27 +807,339 @@ mod tests {
     #[test]
     fn test_parse_services_empty_defaults() {
         let f = TempYaml::new("services:\n  bare:\n");
-        let configs = parse_services(f.path()).unwrap();
+        let configs = parse_config(f.path()).unwrap().services;
         assert_eq!(configs[0].files, Vec::<String>::new());
         assert_eq!(configs[0].commands, Vec::<String>::new());
     }
 
+    #[test]
+    fn test_unit_name_helpers() {
+        assert_eq!(unit_name("nginx"), "nginx.service");
+        assert_eq!(unit_name("nginx.service"), "nginx.service");
+        assert_eq!(unit_name("backup.timer"), "backup.timer");
+        assert_eq!(unit_type("backup.timer"), "timer");
+        assert_eq!(unit_type("getty@tty1"), "service");
+        assert_eq!(display_name("nginx.service"), "nginx");
+        assert_eq!(display_name("backup.timer"), "backup.timer");
+    }
+
+    #[test]
+    fn test_template_parts() {
+        assert_eq!(template_parts("getty@tty1"), Some(("getty", "tty1")));
+        assert_eq!(template_parts("sock@a.socket"), Some(("sock", "a")));
+        assert_eq!(template_parts("nginx"), None);
+    }
+
+    #[test]
+    fn test_expand_specifiers() {
+        assert_eq!(
+            expand_specifiers("/etc/app/%i.conf", "app@customerX"),
+            "/etc/app/customerX.conf"
+        );
+        assert_eq!(expand_specifiers("%p-ctl status", "app@x"), "app-ctl status");
+        assert_eq!(expand_specifiers("/etc/%p/%i", "nginx"), "/etc/nginx/");
+        assert_eq!(expand_specifiers("journalctl -u %n", "backup.timer"), "journalctl -u backup.timer");
+    }
+
+    #[test]
+    fn test_parse_services_unit_suffixes() {
+        let f = TempYaml::new(
+            "services:\n  nginx.service:\n  backup.timer:\n  \"app@*.service\":\n  getty@.service:\n  \"sock@.socket\":\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        let names: Vec<(&str, bool)> = configs
+            .iter()
+            .map(|c| (c.name_pattern.as_str(), c.is_glob))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("app@*", true),
+                ("backup.timer", false),
+                ("getty@*", true),
+                ("nginx", false),
+                ("sock@*.socket", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_services_exclude() {
+        let f = TempYaml::new(
+            "services:\n  s*:\n    exclude:\n      - snapd*\n      - systemd-*.service\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].exclude, vec!["snapd*", "systemd-*"]);
+    }
+
+    #[test]
+    fn test_parse_services_max_matches() {
+        let f = TempYaml::new("services:\n  a*:\n  b*:\n    max_matches: 5\n");
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].max_matches, DEFAULT_MAX_GLOB_MATCHES);
+        assert_eq!(configs[1].max_matches, 5);
+
+        let f = TempYaml::new("max_glob_matches: 10\nservices:\n  a*:\n  b*:\n    max_matches: 0\n");
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].max_matches, 10);
+        assert_eq!(configs[1].max_matches, 0);
+    }
+
+    #[test]
+    fn test_parse_services_check() {
+        let f = TempYaml::new(
+            "services:\n  myapp:\n    check:\n      command: /usr/local/bin/check_myapp\n      exit_codes:\n        0: active\n        2: degraded\n        else: failed\n      output:\n        WARN: degraded\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        let check = configs[0].check.as_ref().unwrap();
+        assert_eq!(check.command, "/usr/local/bin/check_myapp");
+        assert_eq!(
+            check.exit_codes,
+            vec![(0, "active".to_string()), (2, "degraded".to_string())]
+        );
+        assert_eq!(check.output, vec![("WARN".to_string(), "degraded".to_string())]);
+        assert_eq!(check.default.as_deref(), Some("failed"));
+    }
+
+    #[test]
+    fn test_parse_services_cert_check() {
+        let f = TempYaml::new(
+            "services:\n  web-cert:\n    check:\n      type: cert\n      target: www.example.com\n      from: controller\n      warn_days: 20\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        let check = configs[0].check.as_ref().unwrap();
+        let cert = check.cert.as_ref().unwrap();
+        assert_eq!((cert.from, cert.warn_days, cert.critical_days), (CertFrom::Controller, 20, 7));
+        assert!(check
+            .command
+            .contains("timeout 10 openssl s_client -connect www.example.com:443 -servername www.example.com 2>"));
+        let hostile = CertCheck {
+            target: "x;reboot".to_string(),
+            ..cert.clone()
+        };
+        assert!(hostile.command().contains("-connect 'x;reboot:443' -servername 'x;reboot' 2>"));
+
+        let f = TempYaml::new("services:\n  web-cert:\n    check:\n      type: cert\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_services_plugin_check() {
+        let f = TempYaml::new(
+            "services:\n  web:\n    check:\n      type: consul\n      options:\n        service: web\n        min_passing: 2\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        let plugin = configs[0].check.as_ref().unwrap().plugin.as_ref().unwrap();
+        assert_eq!(plugin.kind, "consul");
+        assert_eq!(plugin.options, serde_json::json!({"service": "web", "min_passing": 2}));
+    }
+
+    #[test]
+    fn test_parse_services_last_run_check() {
+        let f = TempYaml::new(
+            "services:\n  backup:\n    check:\n      type: last_run\n      timer: backup\n      max_age: 26h\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        let check = configs[0].check.as_ref().unwrap();
+        let last_run = check.last_run.as_ref().unwrap();
+        assert_eq!(last_run.source, LastRunSource::Timer("backup".to_string()));
+        assert_eq!(last_run.max_age, Duration::from_secs(26 * 3600));
+        assert!(check.command.contains("--timestamp=unix backup.timer 2>"));
+
+        let quoted = LastRunCheck {
+            source: LastRunSource::File("/var/run/it's; rm -rf ~".to_string()),
+            max_age: Duration::from_secs(60),
+        };
+        assert!(quoted.command().contains("stat -c %Y '/var/run/it'\\''s; rm -rf ~' 2>"));
+
+        let both = "services:\n  b:\n    check:\n      type: last_run\n      timer: b\n      file: /x\n      max_age: 1h\n";
+        assert!(parse_config(TempYaml::new(both).path()).is_err());
+        let no_age = "services:\n  b:\n    check:\n      type: last_run\n      file: /x\n";
+        assert!(parse_config(TempYaml::new(no_age).path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_services_check_bad_exit_code_fails() {
+        let f = TempYaml::new(
+            "services:\n  myapp:\n    check:\n      command: true\n      exit_codes:\n        zero: active\n",
+        );
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_services_min_restart_interval() {
+        let f = TempYaml::new(
+            "services:\n  postgresql:\n    min_restart_interval: 5m\n  redis:\n    min_restart_interval: 30\n  nginx:\n",
+        );
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].min_restart_interval, None);
+        assert_eq!(configs[1].min_restart_interval, Some(Duration::from_secs(300)));
+        assert_eq!(configs[2].min_restart_interval, Some(Duration::from_secs(30)));
+
+        let f = TempYaml::new("services:\n  nginx:\n    min_restart_interval: soon\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_services_severity() {
+        let f = TempYaml::new("services:\n  postgresql:\n    severity: critical\n  nginx:\n");
+        let configs = parse_config(f.path()).unwrap().services;
+        assert_eq!(configs[0].severity, Severity::Warning);
+        assert_eq!(configs[1].severity, Severity::Critical);
+
+        let f = TempYaml::new("services:\n  nginx:\n    severity: urgent\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_services_denial_checks() {
+        let f = TempYaml::new("services:\n  nginx:\n    denial_checks: [apparmor, \"grep -c denied /var/log/app.log\"]\n");
+        let configs = parse_config(f.path()).unwrap().services;
+        assert!(configs[0].denial_checks[0].contains("apparmor=\"DENIED\""));
+        assert_eq!(configs[0].denial_checks[1], "grep -c denied /var/log/app.log");
+    }
+
+    #[test]
+    fn test_parse_config_maintenance() {
+        let f = TempYaml::new(
+            "services:\n  app:\nmaintenance:\n  - name: nightly\n    services: [app]\n    daily: \"02:00-03:00\"\n",
+        );
+        let config = parse_config(f.path()).unwrap();
+        assert_eq!(config.maintenance.len(), 1);
+        assert_eq!(config.maintenance[0].name, "nightly");
+    }
+
+    #[test]
+    fn test_parse_config_protected_groups() {
+        let f = TempYaml::new(
+            "services:\n  app:\ngroups:\n  production:\n    protected: true\n  staging:\n    protected: false\n",
+        );
+        let config = parse_config(f.path()).unwrap();
+        assert_eq!(config.protected_groups, vec!["production".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_config_host_commands() {
+        let f = TempYaml::new("services:\n  app:\nhost_commands:\n  - df -h\n  - dmesg -T | tail\n");
+        let config = parse_config(f.path()).unwrap();
+        assert_eq!(config.host_commands, vec!["df -h", "dmesg -T | tail"]);
+        assert_eq!(config.restart_check, None);
+    }
+
+    #[test]
+    fn test_parse_config_restart_check() {
+        let f = TempYaml::new("services:\n  app:\nrestart_check: sudo needs-restarting -s\n");
+        let config = parse_config(f.path()).unwrap();
+        assert_eq!(config.restart_check.as_deref(), Some("sudo needs-restarting -s"));
+    }
+
+    #[test]
+    fn test_parse_config_package_updates() {
+        let f = TempYaml::new("services:\n  app:\npackage_updates: {}\n");
+        assert_eq!(parse_config(f.path()).unwrap().package_updates.unwrap().interval, 3600);
+        let f = TempYaml::new("services:\n  app:\npackage_updates:\n  interval: 600\n");
+        assert_eq!(parse_config(f.path()).unwrap().package_updates.unwrap().interval, 600);
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().package_updates, None);
+    }
+
+    #[test]
+    fn test_parse_config_status_glyphs() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert!(!parse_config(f.path()).unwrap().status_glyphs);
+        let f = TempYaml::new("services:\n  app:\nstatus_glyphs: true\n");
+        assert!(parse_config(f.path()).unwrap().status_glyphs);
+    }
+
+    #[test]
+    fn test_parse_config_cell() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().cell, CellContent::Status);
+        let f = TempYaml::new("services:\n  app:\ncell: check_output\n");
+        assert_eq!(parse_config(f.path()).unwrap().cell, CellContent::CheckOutput);
+        let f = TempYaml::new("services:\n  app:\ncell: color\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_status_words() {
+        let f = TempYaml::new("services:\n  app:\nstatus_words:\n  active: OK\n  failed: AUSGEFALLEN\n  not-found: fehlt\n");
+        let words = parse_config(f.path()).unwrap().status_words;
+        assert_eq!(words.get("failed").map(String::as_str), Some("AUSGEFALLEN"));
+        assert_eq!(words.len(), 3);
+        let f = TempYaml::new("services:\n  app:\nstatus_words:\n  running: OK\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_repoll_transitional() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().repoll_transitional, 0);
+        let f = TempYaml::new("services:\n  app:\nrepoll_transitional: 2\n");
+        assert_eq!(parse_config(f.path()).unwrap().repoll_transitional, 2);
+    }
+
+    #[test]
+    fn test_parse_config_refresh_interval() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().refresh_interval, None);
+        let f = TempYaml::new("services:\n  app:\nrefresh_interval: 30\n");
+        assert_eq!(parse_config(f.path()).unwrap().refresh_interval, Some(Duration::from_secs(30)));
+        let f = TempYaml::new("services:\n  app:\nrefresh_interval: 5m\n");
+        assert_eq!(parse_config(f.path()).unwrap().refresh_interval, Some(Duration::from_secs(300)));
+        let f = TempYaml::new("services:\n  app:\nrefresh_interval: 0\n");
+        assert_eq!(parse_config(f.path()).unwrap().refresh_interval, None);
+        let f = TempYaml::new("services:\n  app:\nrefresh_interval: often\n");
+        assert!(parse_config(f.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_config_triage() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().triage.len(), 4);
+        let f = TempYaml::new("services:\n  app:\ntriage:\n  - systemctl status %n\n");
+        assert_eq!(parse_config(f.path()).unwrap().triage, vec!["systemctl status %n"]);
+    }
+
+    #[test]
+    fn test_parse_config_bulk() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().bulk, BulkConfig::default());
+        let f = TempYaml::new("services:\n  app:\nbulk:\n  concurrency: 20\n");
+        let bulk = parse_config(f.path()).unwrap().bulk;
+        assert_eq!((bulk.concurrency, bulk.timeout), (20, 120));
+    }
+
+    #[test]
+    fn test_parse_config_initial_refresh() {
+        let f = TempYaml::new("services:\n  app:\n");
+        assert_eq!(parse_config(f.path()).unwrap().initial_refresh, InitialRefresh::All);
+        let f = TempYaml::new("services:\n  app:\ninitial_refresh: none\n");
+        assert_eq!(parse_config(f.path()).unwrap().initial_refresh, InitialRefresh::None);
+        let f = TempYaml::new("services:\n  app:\ninitial_refresh: web*\n");
+        assert_eq!(
+            parse_config(f.path()).unwrap().initial_refresh,
+            InitialRefresh::Group("web*".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_services_missing_file_fails() {
-        assert!(parse_services("/tmp/nonexistent_sc_test_xyz.yaml").is_err());
+        assert!(parse_config("/tmp/nonexistent_sc_test_xyz.yaml").is_err());
     }
 
     #[test]
     fn test_parse_services_invalid_yaml_fails() {
         let f = TempYaml::new("this is not: valid: yaml: at: all\n  broken\n");
-        assert!(parse_services(f.path()).is_err());
+        assert!(parse_config(f.path()).is_err());
     }
 
     /// Validates the actual services.yaml used by run-test.sh.
     #[test]
     fn test_parse_services_yaml() {
-        let configs = parse_services("services.yaml")
-            .expect("services.yaml should parse successfully");
+        let configs = parse_config("services.yaml")
+            .expect("services.yaml should parse successfully")
+            .services;
 
         // 5 entries: nginx*, postgresql*, redis*, s*, c*
         assert_eq!(configs.len(), 5, "expected 5 service configs");
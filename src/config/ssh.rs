@@ -0,0 +1,288 @@
+use super::inventory::split_host_port;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Connection settings from the `ssh:` section of services.yaml.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SshOptions {
+    /// Port for hosts whose address doesn't carry one; ssh's default if unset.
+    pub port: Option<u16>,
+    /// Cheap reachability check before the SSH handshake.
+    pub probe: ProbeMode,
+    #[serde(with = "millis")]
+    pub probe_timeout: Duration,
+    /// Pass `-A` to the interactive shell.
+    pub forward_agent: bool,
+    /// Pass `-X` to the interactive shell.
+    pub forward_x11: bool,
+    /// Bytes of output kept from a remote command; the rest is cut off.
+    #[serde(with = "size")]
+    pub max_output: usize,
+    /// Private key used for every connection (`-i`); ssh's own choice if unset.
+    pub identity_file: Option<String>,
+    /// Seconds to wait for a connection; 2 if unset.
+    pub connect_timeout: Option<u64>,
+    /// Derive each host's connect timeout from its observed latency.
+    pub adaptive_timeout: Option<AdaptiveTimeout>,
+    /// Retries of commands that failed in transit rather than on the host.
+    pub retry: RetryPolicy,
+    /// Hosts refreshed at the same time in a full refresh.
+    pub parallel: usize,
+    /// SSH users chosen at runtime for individual hosts, overriding `--user`.
+    #[serde(skip)]
+    pub users: HashMap<String, String>,
+}
+
+impl Default for SshOptions {
+    fn default() -> Self {
+        Self {
+            port: None,
+            probe: ProbeMode::None,
+            probe_timeout: Duration::from_millis(500),
+            forward_agent: false,
+            forward_x11: false,
+            max_output: 5 * 1024 * 1024,
+            identity_file: None,
+            connect_timeout: None,
+            adaptive_timeout: None,
+            retry: RetryPolicy::default(),
+            parallel: 16,
+            users: HashMap::new(),
+        }
+    }
+}
+
+impl SshOptions {
+    /// The user to log in to a host as: its runtime override, else `default`.
+    pub fn user_for<'a>(&'a self, default: Option<&'a str>, address: &str) -> Option<&'a str> {
+        self.users.get(address).map(String::as_str).or(default)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout.unwrap_or(2))
+    }
+
+    /// Argument list for an interactive `ssh` to an inventory address.
+    pub fn shell_args(&self, user: Option<&str>, address: &str) -> Vec<String> {
+        let (host, port) = split_host_port(address);
+        let user = self.user_for(user, address);
+        let mut args = vec!["ssh".to_string()];
+        if self.forward_agent {
+            args.push("-A".to_string());
+        }
+        if self.forward_x11 {
+            args.push("-X".to_string());
+        }
+        if let Some(key) = &self.identity_file {
+            args.push("-i".to_string());
+            args.push(key.clone());
+        }
+        if let Some(port) = port.or(self.port) {
+            args.push("-p".to_string());
+            args.push(port.to_string());
+        }
+        args.push(match user {
+            Some(user) => format!("{}@{}", user, host),
+            None => host.to_string(),
+        });
+        args
+    }
+
+    /// Argument list for running an interactive command on a host with a tty.
+    pub fn command_args(&self, user: Option<&str>, address: &str, cmd: &str) -> Vec<String> {
+        let mut args = self.shell_args(user, address);
+        args.insert(1, "-t".to_string());
+        args.push(cmd.to_string());
+        args
+    }
+}
+
+/// How often and how patiently to retry after a transport failure.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after it.
+    #[serde(with = "millis")]
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (from 0), at most 10 seconds.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(Duration::from_secs(10))
+    }
+}
+
+/// Bounds in seconds for adaptive connect timeouts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveTimeout {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Default for AdaptiveTimeout {
+    fn default() -> Self {
+        Self { min: 1, max: 30 }
+    }
+}
+
+impl AdaptiveTimeout {
+    pub fn min(&self) -> Duration {
+        Duration::from_secs(self.min)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_secs(self.max.max(self.min))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeMode {
+    #[default]
+    None,
+    /// TCP connect to the SSH port.
+    Tcp,
+    /// One `ping`; needs ICMP to be allowed through.
+    Icmp,
+}
+
+/// Durations written as milliseconds (`probe_timeout: 300`).
+mod millis {
+    use serde::{Deserialize, Deserializer};
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(d)?))
+    }
+}
+
+/// Parse a byte size such as `5000`, `512K`, `5MB` or `1G` (binary multiples).
+pub fn parse_size(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let n: usize = num.parse().ok()?;
+    let factor = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    n.checked_mul(factor)
+}
+
+/// Sizes written as a byte count or with a unit (`max_output: 5MB`).
+mod size {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<usize, D::Error> {
+        let value = serde_yaml::Value::deserialize(d)?;
+        let text = match &value {
+            serde_yaml::Value::Number(n) => n.to_string(),
+            serde_yaml::Value::String(s) => s.clone(),
+            _ => return Err(D::Error::custom("expected a size such as 5MB")),
+        };
+        super::parse_size(&text).ok_or_else(|| D::Error::custom(format!("invalid size: {}", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ssh_options() {
+        let opts: SshOptions = serde_yaml::from_str("probe: tcp\nprobe_timeout: 300\n").unwrap();
+        assert_eq!(opts.probe, ProbeMode::Tcp);
+        assert_eq!(opts.probe_timeout, Duration::from_millis(300));
+
+        let opts: SshOptions = serde_yaml::from_str("probe: icmp\n").unwrap();
+        assert_eq!(opts.probe_timeout, Duration::from_millis(500));
+        assert!(serde_yaml::from_str::<SshOptions>("probe: carrier-pigeon\n").is_err());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let opts: SshOptions = serde_yaml::from_str("retry:\n  attempts: 3\n  backoff: 100\n").unwrap();
+        let delays: Vec<u128> = (0..3).map(|n| opts.retry.delay(n).as_millis()).collect();
+        assert_eq!((opts.retry.attempts, delays), (3, vec![100, 200, 400]));
+        assert_eq!(opts.retry.delay(20), Duration::from_secs(10));
+        assert_eq!(SshOptions::default().retry.attempts, 1);
+        assert_eq!(SshOptions::default().parallel, 16);
+        let opts: SshOptions = serde_yaml::from_str("parallel: 4\n").unwrap();
+        assert_eq!(opts.parallel, 4);
+    }
+
+    #[test]
+    fn test_parse_adaptive_timeout() {
+        let opts: SshOptions = serde_yaml::from_str("adaptive_timeout:\n  max: 20\n").unwrap();
+        let bounds = opts.adaptive_timeout.unwrap();
+        assert_eq!((bounds.min(), bounds.max()), (Duration::from_secs(1), Duration::from_secs(20)));
+        let opts: SshOptions = serde_yaml::from_str("adaptive_timeout: {}\n").unwrap();
+        assert_eq!(opts.adaptive_timeout, Some(AdaptiveTimeout::default()));
+        assert_eq!(SshOptions::default().adaptive_timeout, None);
+    }
+
+    #[test]
+    fn test_shell_args() {
+        let opts = SshOptions { port: Some(2200), ..SshOptions::default() };
+        assert_eq!(opts.shell_args(None, "web01"), ["ssh", "-p", "2200", "web01"]);
+        assert_eq!(opts.shell_args(Some("ops"), "web01:2222"), ["ssh", "-p", "2222", "ops@web01"]);
+        assert_eq!(SshOptions::default().shell_args(None, "[fe80::1]"), ["ssh", "fe80::1"]);
+
+        let mut opts = SshOptions::default();
+        opts.users.insert("db01".to_string(), "postgres".to_string());
+        assert_eq!(opts.shell_args(Some("ops"), "db01"), ["ssh", "postgres@db01"]);
+        assert_eq!(opts.shell_args(Some("ops"), "web01"), ["ssh", "ops@web01"]);
+
+        let opts = SshOptions { identity_file: Some("/keys/deploy".to_string()), ..SshOptions::default() };
+        assert_eq!(opts.shell_args(None, "web01"), ["ssh", "-i", "/keys/deploy", "web01"]);
+
+        let opts: SshOptions = serde_yaml::from_str("forward_agent: true\nforward_x11: true\n").unwrap();
+        assert_eq!(opts.shell_args(None, "web01"), ["ssh", "-A", "-X", "web01"]);
+    }
+
+    #[test]
+    fn test_command_args() {
+        let opts = SshOptions::default();
+        assert_eq!(
+            opts.command_args(Some("ops"), "db01:2222", "psql"),
+            ["ssh", "-t", "-p", "2222", "ops@db01", "psql"]
+        );
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("5000"), Some(5000));
+        assert_eq!(parse_size("512K"), Some(512 * 1024));
+        assert_eq!(parse_size("5MB"), Some(5 * 1024 * 1024));
+        assert_eq!(parse_size("1 gb"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_size("lots"), None);
+
+        let opts: SshOptions = serde_yaml::from_str("max_output: 1M\n").unwrap();
+        assert_eq!(opts.max_output, 1024 * 1024);
+        assert_eq!(SshOptions::default().max_output, 5 * 1024 * 1024);
+        assert!(serde_yaml::from_str::<SshOptions>("max_output: huge\n").is_err());
+    }
+}
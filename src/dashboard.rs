@@ -0,0 +1,196 @@
+//! Read-only web dashboard (`--dashboard-addr`): the grid as an HTML page
+//! that reloads itself, and as JSON at `/api/grid`, for people who won't
+//! run the TUI. The app publishes the grid here after each refresh, and it
+//! is only turned into JSON when somebody asks for it.
+
+use crate::clock;
+use crate::commands;
+use crate::config::Host;
+use crate::http::{self, Response};
+use crate::monitor::{HostError, HostService};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Seconds between reloads of the page.
+const RELOAD_SECS: u32 = 10;
+
+/// The grid as of a completed refresh.
+struct Snapshot {
+    hosts: Vec<Host>,
+    grid: Vec<Vec<HostService>>,
+    unreachable: HashMap<usize, HostError>,
+    refreshed: u64,
+}
+
+/// A served dashboard, which shows what was last published to it.
+#[derive(Clone, Default)]
+pub struct Dashboard(Arc<Mutex<Option<Arc<Snapshot>>>>);
+
+impl Dashboard {
+    /// Replace what the dashboard shows.
+    pub fn publish(&self, hosts: &[Host], grid: &[Vec<HostService>], unreachable: &HashMap<usize, HostError>) {
+        let snapshot = Snapshot {
+            hosts: hosts.to_vec(),
+            grid: grid.to_vec(),
+            unreachable: unreachable.clone(),
+            refreshed: clock::now_secs(),
+        };
+        if let Ok(mut current) = self.0.lock() {
+            *current = Some(Arc::new(snapshot));
+        }
+    }
+
+    /// The last published grid, as [`commands::grid_json`] plus `refreshed`.
+    fn board(&self) -> Option<Value> {
+        let snapshot = self.0.lock().ok()?.clone()?;
+        let mut board = commands::grid_json(&snapshot.hosts, &snapshot.grid, &snapshot.unreachable);
+        board["refreshed"] = json!(clock::format_iso(snapshot.refreshed));
+        Some(board)
+    }
+}
+
+/// Serve the dashboard on `addr` in the background.
+pub async fn serve(addr: &str) -> Result<Dashboard> {
+    let dashboard = Dashboard::default();
+    let served = dashboard.clone();
+    http::serve(addr, move |path| match path {
+        "/" => Response::ok("text/html; charset=utf-8", page(served.board().as_ref())),
+        "/api/grid" => Response::ok(
+            "application/json",
+            served.board().map_or_else(|| "null".to_string(), |b| b.to_string()),
+        ),
+        _ => Response::not_found(),
+    })
+    .await?;
+    Ok(dashboard)
+}
+
+/// The grid as a table: a row per host, a column per service, each cell
+/// colored by its status.
+fn page(board: Option<&Value>) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"{}\">\
+         <title>system-controller</title><style>{}</style></head><body>\n",
+        RELOAD_SECS, STYLE
+    );
+    let Some(board) = board else {
+        out.push_str("<p>Waiting for the first refresh.</p>\n</body></html>\n");
+        return out;
+    };
+    let hosts = board["hosts"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let mut services: Vec<&str> = Vec::new();
+    for service in hosts.iter().filter_map(|h| h["services"].as_array()).flatten() {
+        let name = service["service"].as_str().unwrap_or_default();
+        if !services.contains(&name) {
+            services.push(name);
+        }
+    }
+    let unreachable = hosts.iter().filter(|h| h.get("unreachable").is_some()).count();
+    let _ = writeln!(
+        out,
+        "<p>{} hosts, {} unreachable. Refreshed {}.</p>",
+        hosts.len(),
+        unreachable,
+        escape(board["refreshed"].as_str().unwrap_or_default())
+    );
+    out.push_str("<table>\n<tr><th>Host</th>");
+    for service in &services {
+        let _ = write!(out, "<th>{}</th>", escape(service));
+    }
+    out.push_str("</tr>\n");
+    for host in hosts {
+        let _ = write!(
+            out,
+            "<tr><th title=\"{}\">{}</th>",
+            escape(host["group"].as_str().unwrap_or_default()),
+            escape(host["host"].as_str().unwrap_or_default())
+        );
+        if let Some(reason) = host["unreachable"].as_str() {
+            let _ = write!(
+                out,
+                "<td class=\"unreachable\" colspan=\"{}\">unreachable: {}</td>",
+                services.len().max(1),
+                escape(reason)
+            );
+        } else {
+            let row = host["services"].as_array().map(Vec::as_slice).unwrap_or_default();
+            for service in &services {
+                match row.iter().find(|s| s["service"] == *service) {
+                    Some(cell) => {
+                        let status = cell["status"].as_str().unwrap_or_default();
+                        let text = cell["error"].as_str().unwrap_or(status);
+                        let _ = write!(out, "<td class=\"{}\" title=\"{}\">{}</td>", status, escape(text), escape(text));
+                    }
+                    None => out.push_str("<td></td>"),
+                }
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+const STYLE: &str = "body{font-family:monospace;margin:1em}\
+table{border-collapse:collapse}th,td{border:1px solid #ccc;padding:2px 8px;text-align:left;white-space:nowrap}\
+td{max-width:20em;overflow:hidden;text-overflow:ellipsis}\
+.active{background:#c8f0c8}.failed,.error,.unreachable{background:#f4b4b4}.degraded{background:#f8d8a0}\
+.activating,.deactivating,.reloading{background:#c8dcf8}.inactive,.masked,.not-found,.unknown{background:#e4e4e4}";
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish() {
+        let dashboard = Dashboard::default();
+        assert_eq!(dashboard.board(), None);
+        let hosts = [Host {
+            address: "db01".to_string(),
+            group: "db".to_string(),
+            ..Default::default()
+        }];
+        dashboard.clone().publish(&hosts, &[Vec::new()], &HashMap::from([(0, HostError::Timeout)]));
+        let board = dashboard.board().unwrap();
+        assert_eq!(board["hosts"][0]["host"], "db01");
+        assert!(board["hosts"][0]["unreachable"].is_string());
+        assert!(board["refreshed"].is_string());
+    }
+
+    #[test]
+    fn test_page() {
+        assert!(page(None).contains("Waiting for the first refresh"));
+
+        let board = json!({
+            "refreshed": "2026-10-15T09:12:44Z",
+            "hosts": [
+                {"host": "web01", "group": "web", "services": [
+                    {"service": "nginx", "status": "active"},
+                    {"service": "app", "status": "error", "error": "<timeout>"},
+                ]},
+                {"host": "web02", "group": "web", "services": [{"service": "app", "status": "failed"}]},
+                {"host": "db01", "group": "db", "unreachable": "connection refused"},
+            ],
+        });
+        let page = page(Some(&board));
+        assert!(page.contains("<p>3 hosts, 1 unreachable. Refreshed 2026-10-15T09:12:44Z.</p>"));
+        assert!(page.contains("<tr><th>Host</th><th>nginx</th><th>app</th></tr>"));
+        assert!(page.contains(
+            "<tr><th title=\"web\">web01</th><td class=\"active\" title=\"active\">active</td>\
+             <td class=\"error\" title=\"&lt;timeout&gt;\">&lt;timeout&gt;</td></tr>"
+        ));
+        // web02 has no nginx
+        assert!(page.contains("<th title=\"web\">web02</th><td></td><td class=\"failed\""));
+        assert!(page.contains("<td class=\"unreachable\" colspan=\"2\">unreachable: connection refused</td>"));
+    }
+}
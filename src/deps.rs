@@ -0,0 +1,238 @@
+//! Dependency tree (`D` on the detail screen): `systemctl list-dependencies`
+//! of the cell's unit, with the state of every unit in it, to trace why a
+//! unit won't start.
+
+use crate::monitor::status::load_status;
+use crate::monitor::ServiceStatus;
+use std::collections::{HashMap, HashSet};
+
+const STATES_MARKER: &str = "@@sctl:states";
+
+/// One command printing the tree, then the states of the units in it.
+pub fn script(unit: &str) -> String {
+    format!(
+        "deps=$(systemctl list-dependencies --plain --no-pager '{}'); printf '%s\\n' \"$deps\"; echo {}; \
+         systemctl show --property=Id,LoadState,ActiveState $(printf '%s\\n' \"$deps\" | awk '{{print $NF}}' | sort -u)",
+        unit, STATES_MARKER
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepNode {
+    pub unit: String,
+    /// 0 for the unit itself.
+    pub depth: usize,
+    pub status: ServiceStatus,
+}
+
+/// Parse [`script`] output. `--plain` indents each level by two spaces.
+pub fn parse(output: &str) -> Vec<DepNode> {
+    let (tree, states) = output.split_once(STATES_MARKER).unwrap_or((output, ""));
+    let states = parse_states(states);
+    tree.lines()
+        .filter_map(|line| {
+            // Older systemd versions print bullets even in plain mode
+            let unit = line.split_whitespace().last()?;
+            let indent = line.len() - line.trim_start().len();
+            Some(DepNode {
+                unit: unit.to_string(),
+                depth: indent / 2,
+                status: states.get(unit).cloned().unwrap_or(ServiceStatus::Unknown),
+            })
+        })
+        .collect()
+}
+
+/// `systemctl show` blocks, separated by blank lines, keyed by `Id`.
+fn parse_states(output: &str) -> HashMap<String, ServiceStatus> {
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|l| l.strip_prefix(name).and_then(|l| l.strip_prefix('=')))
+                    .unwrap_or("")
+            };
+            let id = field("Id");
+            if id.is_empty() {
+                return None;
+            }
+            let status = load_status(field("LoadState")).unwrap_or_else(|| ServiceStatus::from_str(field("ActiveState")));
+            Some((id.to_string(), status))
+        })
+        .collect()
+}
+
+/// The tree for one cell, filled in when the command returns.
+pub struct DepsView {
+    pub id: u64,
+    pub host: String,
+    pub unit: String,
+    /// `None` while loading.
+    pub nodes: Option<Result<Vec<DepNode>, String>>,
+    /// Nodes whose children are hidden.
+    pub collapsed: HashSet<usize>,
+    /// Position within the visible nodes.
+    pub cursor: usize,
+}
+
+impl DepsView {
+    pub fn new(id: u64, host: &str, unit: &str) -> Self {
+        DepsView {
+            id,
+            host: host.to_string(),
+            unit: unit.to_string(),
+            nodes: None,
+            collapsed: HashSet::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Take a fetched tree. Collapsed nodes stay collapsed if the tree has
+    /// the same units as before, so a reload only updates the states.
+    pub fn load(&mut self, result: Result<Vec<DepNode>, String>) {
+        let same = match (&self.nodes, &result) {
+            (Some(Ok(old)), Ok(new)) => old.iter().map(|n| &n.unit).eq(new.iter().map(|n| &n.unit)),
+            _ => false,
+        };
+        if !same {
+            self.collapsed.clear();
+            self.cursor = 0;
+        }
+        self.nodes = Some(result);
+    }
+
+    fn all(&self) -> &[DepNode] {
+        match &self.nodes {
+            Some(Ok(nodes)) => nodes,
+            _ => &[],
+        }
+    }
+
+    /// Indices of the children and further descendants of node `i`.
+    fn descendants(&self, i: usize) -> std::ops::Range<usize> {
+        let nodes = self.all();
+        let end = (i + 1..nodes.len()).find(|&j| nodes[j].depth <= nodes[i].depth).unwrap_or(nodes.len());
+        i + 1..end
+    }
+
+    pub fn has_children(&self, i: usize) -> bool {
+        !self.descendants(i).is_empty()
+    }
+
+    /// Indices of the nodes not hidden under a collapsed one.
+    pub fn visible(&self) -> Vec<usize> {
+        let mut visible = Vec::new();
+        let mut i = 0;
+        while i < self.all().len() {
+            visible.push(i);
+            i = if self.collapsed.contains(&i) { self.descendants(i).end } else { i + 1 };
+        }
+        visible
+    }
+
+    /// Number of descendants and the worst state among them, shown on a
+    /// collapsed node so a failure under it isn't missed.
+    pub fn hidden_summary(&self, i: usize) -> (usize, ServiceStatus) {
+        let nodes = self.all();
+        let range = self.descendants(i);
+        let worst = ServiceStatus::worst(nodes[range.clone()].iter().map(|n| &n.status));
+        (range.len(), worst)
+    }
+
+    pub fn toggle(&mut self, i: usize) {
+        if !self.collapsed.remove(&i) && self.has_children(i) {
+            self.collapsed.insert(i);
+        }
+    }
+
+    /// Collapse every node with children below the unit itself, or expand all.
+    pub fn set_all(&mut self, collapsed: bool) {
+        self.collapsed.clear();
+        if collapsed {
+            self.collapsed = (1..self.all().len()).filter(|&i| self.has_children(i)).collect();
+        }
+        self.cursor = self.cursor.min(self.visible().len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+nginx.service
+  system.slice
+  sysinit.target
+    dev-hugepages.mount
+    systemd-journald.service
+  network-online.target
+    NetworkManager-wait-online.service
+@@sctl:states
+Id=nginx.service
+LoadState=loaded
+ActiveState=failed
+
+Id=system.slice
+LoadState=loaded
+ActiveState=active
+
+Id=sysinit.target
+LoadState=loaded
+ActiveState=active
+
+Id=systemd-journald.service
+LoadState=loaded
+ActiveState=active
+
+Id=network-online.target
+LoadState=loaded
+ActiveState=inactive
+
+Id=NetworkManager-wait-online.service
+LoadState=masked
+ActiveState=inactive
+";
+
+    #[test]
+    fn test_parse() {
+        let nodes = parse(OUTPUT);
+        assert_eq!(nodes.len(), 7);
+        assert_eq!(
+            nodes[0],
+            DepNode {
+                unit: "nginx.service".to_string(),
+                depth: 0,
+                status: ServiceStatus::Failed,
+            }
+        );
+        assert_eq!(nodes[3].depth, 2);
+        // Not in the show output
+        assert_eq!(nodes[3].status, ServiceStatus::Unknown);
+        assert_eq!(nodes[6].status, ServiceStatus::Masked);
+        assert_eq!(parse("● nginx.service\n  ● x.target\n")[1].unit, "x.target");
+    }
+
+    #[test]
+    fn test_collapse() {
+        let mut view = DepsView::new(1, "web01", "nginx.service");
+        view.load(Ok(parse(OUTPUT)));
+        assert_eq!(view.visible(), (0..7).collect::<Vec<_>>());
+        view.toggle(2);
+        assert_eq!(view.visible(), vec![0, 1, 2, 5, 6]);
+        assert_eq!(view.hidden_summary(5), (1, ServiceStatus::Masked));
+        // Leaves can't be collapsed
+        view.toggle(1);
+        assert!(!view.collapsed.contains(&1));
+
+        // A reload of the same tree keeps what is collapsed
+        view.load(Ok(parse(OUTPUT)));
+        assert_eq!(view.visible(), vec![0, 1, 2, 5, 6]);
+
+        view.set_all(true);
+        assert_eq!(view.visible(), vec![0, 1, 2, 5]);
+        view.set_all(false);
+        assert_eq!(view.visible().len(), 7);
+    }
+}
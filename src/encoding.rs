@@ -0,0 +1,138 @@
+//! Making remote file contents displayable: detecting binary data and
+//! decoding the common non-UTF-8 encodings found on older hosts.
+
+/// How a blob of remote output was interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Windows-1252, a superset of the printable part of Latin-1.
+    Windows1252,
+    Binary,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "Windows-1252",
+            Encoding::Binary => "binary",
+        }
+    }
+}
+
+/// How much of the input is sniffed for binary content.
+const SNIFF_LEN: usize = 8192;
+
+pub fn detect(bytes: &[u8]) -> Encoding {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Encoding::Utf16Le;
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Encoding::Utf16Be;
+    }
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    // NUL bytes, or a lot of control characters, mean it isn't text
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0C | 0x1B))
+        .count();
+    if sample.contains(&0) || control * 10 > sample.len() {
+        return Encoding::Binary;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => Encoding::Utf8,
+        // A sequence cut off by the end of a truncated capture is still UTF-8
+        Err(e) if e.error_len().is_none() => Encoding::Utf8,
+        Err(_) => Encoding::Windows1252,
+    }
+}
+
+/// Windows-1252 characters for 0x80..=0x9F; the rest match Latin-1.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    match encoding {
+        Encoding::Utf8 | Encoding::Binary => String::from_utf8_lossy(bytes).to_string(),
+        Encoding::Windows1252 => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            })
+            .collect(),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units: Vec<u16> = bytes[2..]
+                .chunks_exact(2)
+                .map(|c| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([c[0], c[1]]),
+                    _ => u16::from_be_bytes([c[0], c[1]]),
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+    }
+}
+
+/// `hexdump -C` style listing: offset, 16 bytes in hex, and printable ASCII.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 + 16);
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        out.push_str(&format!("{:08x}  ", i * 16));
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(chunk.iter().map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    out.push_str(&format!("{:08x}\n", bytes.len()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"plain text\n"), Encoding::Utf8);
+        assert_eq!(detect("caf\u{e9}\n".as_bytes()), Encoding::Utf8);
+        assert_eq!(detect(b"caf\xe9\n"), Encoding::Windows1252);
+        assert_eq!(detect(b"\x7fELF\x02\x01\x01\x00\x00"), Encoding::Binary);
+        assert_eq!(detect(b"\xff\xfeh\x00i\x00"), Encoding::Utf16Le);
+        // A capture cut off in the middle of a character
+        assert_eq!(detect(&"caf\u{e9}".as_bytes()[..4]), Encoding::Utf8);
+        assert_eq!(detect(b""), Encoding::Utf8);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"caf\xe9 \x805", Encoding::Windows1252), "café €5");
+        assert_eq!(decode(b"\xff\xfeh\x00i\x00", Encoding::Utf16Le), "hi");
+        assert_eq!(decode(b"\xfe\xff\x00h\x00i", Encoding::Utf16Be), "hi");
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"ABCDEFGHIJKLMNOP\x00\x01");
+        assert_eq!(
+            dump,
+            "00000000  41 42 43 44 45 46 47 48  49 4a 4b 4c 4d 4e 4f 50  |ABCDEFGHIJKLMNOP|\n\
+             00000010  00 01                                             |..|\n\
+             00000012\n"
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! JSON Lines event stream on stdout (`--events-stdout`) for other processes
+//! to consume: status transitions and host reachability changes, one object
+//! per line as they happen. This mode has no TUI, so it runs no actions.
+
+use crate::clock;
+use crate::notify::Transition;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn emit(event: Value) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut out = std::io::stdout().lock();
+    // A consumer going away is not our problem; keep monitoring
+    let _ = writeln!(out, "{}", event);
+    let _ = out.flush();
+}
+
+pub fn transitions(transitions: &[Transition]) {
+    for t in transitions {
+        emit(transition_event(t, clock::now_secs()));
+    }
+}
+
+fn transition_event(t: &Transition, now: u64) -> Value {
+    if t.service.is_empty() {
+        json!({
+            "ts": clock::format_iso(now),
+            "event": "host",
+            "host": t.host,
+            "old": t.old,
+            "new": t.new,
+        })
+    } else {
+        json!({
+            "ts": clock::format_iso(now),
+            "event": "status",
+            "host": t.host,
+            "service": t.service,
+            "old": t.old,
+            "new": t.new,
+            "severity": t.severity.name(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Severity;
+
+    fn transition(service: &str, old: &str, new: &str) -> Transition {
+        Transition {
+            host: "web01".to_string(),
+            service: service.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+            severity: Severity::Critical,
+        }
+    }
+
+    #[test]
+    fn test_transition_events() {
+        let status = transition_event(&transition("nginx", "active", "failed"), 0);
+        assert_eq!(
+            status.to_string(),
+            r#"{"event":"status","host":"web01","new":"failed","old":"active","service":"nginx","severity":"critical","ts":"1970-01-01T00:00:00Z"}"#
+        );
+        let host = transition_event(&transition("", "reachable", "unreachable"), 0);
+        assert_eq!(host["event"], "host");
+        assert!(host.get("service").is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! A deliberately tiny HTTP/1.0 server for read-only endpoints. Each
+//! connection gets one response and is closed.
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// Most bytes of request line plus headers read before answering 431.
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// How long a client gets to send its request line and headers.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "not found")
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: format!("{}\n", message),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            431 => "Request Header Fields Too Large",
+            _ => "Error",
+        };
+        format!(
+            "HTTP/1.0 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+        .into_bytes()
+    }
+}
+
+/// The path of a `GET` request line, without any query string.
+fn request_path(line: &str) -> Option<&str> {
+    let mut parts = line.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+    let target = parts.next()?;
+    Some(target.split('?').next().unwrap_or(target))
+}
+
+/// Read the request line and skip the headers after it, reading at most
+/// `MAX_REQUEST_BYTES`. `None` when the request doesn't fit.
+async fn read_request_line<R: AsyncRead + Unpin>(read: R) -> std::io::Result<Option<String>> {
+    let mut reader = BufReader::new(read.take(MAX_REQUEST_BYTES));
+    let mut request = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        if reader.get_ref().limit() == 0 {
+            return Ok(None);
+        }
+        if request.is_empty() {
+            request = line.clone();
+        }
+        // A blank line ends the headers; a line without a newline is EOF
+        if line.trim_end().is_empty() || !line.ends_with('\n') {
+            return Ok(Some(request));
+        }
+    }
+}
+
+/// Bind `addr` and answer GET requests with `handler(path)` in the
+/// background. Returns the bound address, which tells the port when `addr`
+/// asks for port 0.
+pub async fn serve<F>(addr: &str, handler: F) -> Result<SocketAddr>
+where
+    F: Fn(&str) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    let local = listener.local_addr()?;
+    log::info!("HTTP endpoint listening on {}", local);
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    log::warn!("HTTP accept failed: {}", e);
+                    continue;
+                }
+            };
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let response = match tokio::time::timeout(READ_TIMEOUT, read_request_line(read)).await {
+                    Err(_) => Response::error(408, "request timed out"),
+                    Ok(Err(_)) => return,
+                    Ok(Ok(None)) => Response::error(431, "request too large"),
+                    Ok(Ok(Some(line))) => match request_path(&line) {
+                        Some(path) => {
+                            log::debug!("HTTP GET {} from {}", path, peer);
+                            handler(path)
+                        }
+                        None => Response::error(405, "only GET is supported"),
+                    },
+                };
+                let _ = write.write_all(&response.to_bytes()).await;
+                let _ = write.shutdown().await;
+            });
+        }
+    });
+    Ok(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_path() {
+        assert_eq!(request_path("GET /metrics HTTP/1.1\r\n"), Some("/metrics"));
+        assert_eq!(request_path("GET /?x=1 HTTP/1.1\r\n"), Some("/"));
+        assert_eq!(request_path("POST /metrics HTTP/1.1\r\n"), None);
+        assert_eq!(request_path(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_request_line() {
+        let request = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n".as_slice();
+        assert_eq!(read_request_line(request).await.unwrap().as_deref(), Some("GET / HTTP/1.1\r\n"));
+        assert_eq!(read_request_line(b"GET /".as_slice()).await.unwrap().as_deref(), Some("GET /"));
+
+        let endless = format!("GET / HTTP/1.1\r\nX-Pad: {}\r\n\r\n", "a".repeat(MAX_REQUEST_BYTES as usize));
+        assert_eq!(read_request_line(endless.as_bytes()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_serve_round_trip() {
+        let addr = serve("127.0.0.1:0", |path| Response::ok("text/plain", format!("path={}", path)))
+            .await
+            .unwrap();
+        let get = |request: String| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("GET /metrics HTTP/1.1\r\n\r\n".to_string()).await;
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.ends_with("path=/metrics"));
+
+        let response = get(format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_REQUEST_BYTES as usize))).await;
+        assert!(response.starts_with("HTTP/1.0 431 "));
+    }
+}
@@ -1,66 +1,351 @@
-use log::{Level, Log, Metadata, Record};
+use crate::clock;
+use log::{Level, LevelFilter, Log, Metadata, Record};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::sync::Mutex;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicI8, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-struct FileLogger {
-    file: Mutex<File>,
+/// Levels from least to most verbose, for shifting at runtime.
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// `--log-filter` directives such as `ssh=debug,monitor=info` or plain
+/// `warn`. Module paths are relative to the crate, so `ssh` covers
+/// `system_controller::ssh::session`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Debug,
+            directives: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut filter = Self::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (module, level) = match part.split_once('=') {
+                Some((m, l)) => (Some(m.trim()), l.trim()),
+                None => (None, part),
+            };
+            let level: LevelFilter = level
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid log level in filter: {}", part))?;
+            match module {
+                Some(m) => filter.directives.push((m.replace('.', "::"), level)),
+                None => filter.default = level,
+            }
+        }
+        // Most specific first
+        filter.directives.sort_by_key(|d| std::cmp::Reverse(d.0.len()));
+        Ok(filter)
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let target = target
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(target);
+        self.directives
+            .iter()
+            .find(|(module, _)| {
+                target == module
+                    || target
+                        .strip_prefix(module.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|(_, l)| *l)
+            .fold(self.default, Ord::max)
+    }
+}
+
+fn shift(level: LevelFilter, by: i8) -> LevelFilter {
+    let idx = LEVELS.iter().position(|l| *l == level).unwrap_or(0) as i8;
+    LEVELS[(idx + by).clamp(0, LEVELS.len() as i8 - 1) as usize]
+}
+
+/// Where log records go; any combination may be active.
+pub struct LogOutputs {
+    pub file: Option<String>,
+    /// Timestamp layout for the file; syslog and journald stamp records themselves.
+    pub time_format: Option<String>,
+    /// RFC 3164 messages on `/dev/log`.
+    pub syslog: bool,
+    /// journald's native protocol, which keeps the target as a field.
+    pub journald: bool,
+    /// Lines as in the file on stderr, for commands without a TUI.
+    pub stderr: bool,
 }
 
-impl Log for FileLogger {
+impl LogOutputs {
+    pub fn any(&self) -> bool {
+        self.file.is_some() || self.syslog || self.journald || self.stderr
+    }
+}
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const IDENTIFIER: &str = "system-controller";
+
+struct Logger {
+    file: Option<Mutex<File>>,
+    stderr: bool,
+    syslog: Option<UnixDatagram>,
+    journald: Option<UnixDatagram>,
+    filter: LogFilter,
+}
+
+/// Runtime verbosity adjustment applied on top of every filter level.
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+/// Index into LEVELS of the filter's default level, once a logger is installed.
+static DEFAULT_LEVEL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// syslog severity for a log level.
+fn syslog_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// A line of the log file (and of stderr), stamped with `time`.
+fn file_line(time: &str, level: Level, target: &str, message: &str) -> String {
+    format!("{} [{}] {}: {}", time, level, target, message)
+}
+
+fn syslog_message(level: Level, target: &str, message: &str) -> String {
+    // Facility 1 (user)
+    format!(
+        "<{}>{}[{}]: {}: {}",
+        8 + syslog_priority(level),
+        IDENTIFIER,
+        std::process::id(),
+        target,
+        message
+    )
+}
+
+/// A journald native protocol datagram. Values containing newlines use the
+/// length-prefixed binary form.
+fn journald_message(level: Level, target: &str, message: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let priority = syslog_priority(level).to_string();
+    for (key, value) in [
+        ("PRIORITY", priority.as_str()),
+        ("SYSLOG_IDENTIFIER", IDENTIFIER),
+        ("TARGET", target),
+        ("MESSAGE", message),
+    ] {
+        buf.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            buf.push(b'\n');
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            buf.push(b'=');
+        }
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+    buf
+}
+
+fn connect(path: &str) -> anyhow::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket
+        .connect(path)
+        .map_err(|e| anyhow::anyhow!("Failed to connect to {}: {}", path, e))?;
+    Ok(socket)
+}
+
+impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Debug
+        let level = shift(
+            self.filter.level_for(metadata.target()),
+            VERBOSITY.load(Ordering::Relaxed),
+        );
+        metadata.level() <= level
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            if let Ok(mut file) = self.file.lock() {
-                let now = chrono_now();
-                let _ = writeln!(
-                    file,
-                    "{} [{}] {}: {}",
-                    now,
-                    record.level(),
-                    record.target(),
-                    record.args()
-                );
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let line = file_line(&timestamp(), record.level(), record.target(), &record.args().to_string());
+                let _ = writeln!(file, "{}", line);
             }
         }
+        if self.stderr {
+            eprintln!("{}", file_line(&timestamp(), record.level(), record.target(), &record.args().to_string()));
+        }
+        if self.syslog.is_none() && self.journald.is_none() {
+            return;
+        }
+        let message = record.args().to_string();
+        if let Some(socket) = &self.syslog {
+            let _ = socket.send(syslog_message(record.level(), record.target(), &message).as_bytes());
+        }
+        if let Some(socket) = &self.journald {
+            let _ = socket.send(&journald_message(record.level(), record.target(), &message));
+        }
     }
 
     fn flush(&self) {
-        if let Ok(mut file) = self.file.lock() {
-            let _ = file.flush();
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
         }
     }
 }
 
-fn chrono_now() -> String {
-    use std::time::SystemTime;
+/// strftime-style layout for log file timestamps, see `clock::format_time`.
+static TIME_FORMAT: OnceLock<String> = OnceLock::new();
+
+fn timestamp() -> String {
     let duration = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    let secs = duration.as_secs();
-    let hours = (secs / 3600) % 24;
-    let mins = (secs / 60) % 60;
-    let s = secs % 60;
-    let millis = duration.subsec_millis();
-    format!("{:02}:{:02}:{:02}.{:03}", hours, mins, s, millis)
-}
-
-pub fn init(path: &str) -> anyhow::Result<()> {
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(path)?;
-
-    let logger = FileLogger {
-        file: Mutex::new(file),
+    let fmt = TIME_FORMAT
+        .get()
+        .map(String::as_str)
+        .unwrap_or(clock::DEFAULT_TIME_FORMAT);
+    clock::format_time(duration.as_secs(), duration.subsec_millis(), fmt)
+}
+
+pub fn init(outputs: &LogOutputs, filter: LogFilter) -> anyhow::Result<()> {
+    let file = match &outputs.file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+    if let Some(fmt) = &outputs.time_format {
+        let _ = TIME_FORMAT.set(fmt.clone());
+    }
+    let syslog = outputs.syslog.then(|| connect(SYSLOG_SOCKET)).transpose()?;
+    let journald = outputs.journald.then(|| connect(JOURNALD_SOCKET)).transpose()?;
+
+    let max_level = filter.max_level();
+    let filter_default = filter.default;
+    let logger = Logger {
+        file,
+        stderr: outputs.stderr,
+        syslog,
+        journald,
+        filter,
     };
 
     log::set_boxed_logger(Box::new(logger))
-        .map(|()| log::set_max_level(log::LevelFilter::Debug))
+        .map(|()| log::set_max_level(max_level))
         .map_err(|e| anyhow::anyhow!("Failed to set logger: {}", e))?;
+    DEFAULT_LEVEL.store(
+        LEVELS.iter().position(|l| *l == filter_default).unwrap_or(0),
+        Ordering::Relaxed,
+    );
 
     Ok(())
 }
+
+/// Make logging more (positive) or less (negative) verbose without
+/// restarting. Returns a description of the new setting, or None when
+/// logging is off.
+pub fn adjust_verbosity(by: i8) -> Option<String> {
+    let default = *LEVELS.get(DEFAULT_LEVEL.load(Ordering::Relaxed))?;
+    let span = LEVELS.len() as i8 - 1;
+    let new = (VERBOSITY.load(Ordering::Relaxed) + by).clamp(-span, span);
+    VERBOSITY.store(new, Ordering::Relaxed);
+    // Let every record reach the logger, which applies the shifted filter
+    log::set_max_level(LevelFilter::Trace);
+    log::warn!("Log verbosity changed to {:+}", new);
+    Some(format!(
+        "Log level {} ({:+})",
+        shift(default, new).as_str().to_lowercase(),
+        new
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        let f = LogFilter::parse("warn, ssh=debug,monitor::status=trace").unwrap();
+        assert_eq!(f.default, LevelFilter::Warn);
+        assert_eq!(f.level_for("system_controller::ssh::session"), LevelFilter::Debug);
+        assert_eq!(f.level_for("system_controller::monitor::status"), LevelFilter::Trace);
+        assert_eq!(f.level_for("system_controller::monitor::coordinator"), LevelFilter::Warn);
+        assert_eq!(f.level_for("system_controller::sshx"), LevelFilter::Warn);
+        assert_eq!(f.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_invalid_filter() {
+        assert!(LogFilter::parse("ssh=loud").is_err());
+    }
+
+    #[test]
+    fn test_file_line() {
+        let at = |fmt| clock::format_time(1709214307, 42, fmt);
+        assert_eq!(
+            file_line(&at(clock::DEFAULT_TIME_FORMAT), Level::Info, "system_controller::app", "started"),
+            "2024-02-29T13:45:07.042Z [INFO] system_controller::app: started"
+        );
+        assert_eq!(
+            file_line(&at("%H:%M:%S%.3f"), Level::Warn, "t", "slow"),
+            "13:45:07.042 [WARN] t: slow"
+        );
+        assert_eq!(
+            file_line(&at("%a %j %z"), Level::Error, "t", "down"),
+            "Thu 060 +0000 [ERROR] t: down"
+        );
+    }
+
+    #[test]
+    fn test_syslog_message() {
+        let msg = syslog_message(Level::Warn, "system_controller::ssh", "timed out");
+        assert!(msg.starts_with("<12>system-controller["));
+        assert!(msg.ends_with("]: system_controller::ssh: timed out"));
+    }
+
+    #[test]
+    fn test_journald_message() {
+        let msg = journald_message(Level::Info, "t", "one");
+        assert_eq!(msg, b"PRIORITY=6\nSYSLOG_IDENTIFIER=system-controller\nTARGET=t\nMESSAGE=one\n");
+
+        let msg = journald_message(Level::Error, "t", "a\nb");
+        assert!(msg.ends_with(b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n"));
+    }
+
+    #[test]
+    fn test_shift_clamps() {
+        assert_eq!(shift(LevelFilter::Info, 1), LevelFilter::Debug);
+        assert_eq!(shift(LevelFilter::Trace, 3), LevelFilter::Trace);
+        assert_eq!(shift(LevelFilter::Error, -5), LevelFilter::Off);
+    }
+}
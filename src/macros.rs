@@ -0,0 +1,162 @@
+//! Vim-style keyboard macros: `Q<a-z>` starts recording into a register, `Q`
+//! stops, `@<a-z>` replays and `@@` repeats the last replay. (`q` is taken
+//! by quit/back, hence the capital.) Answers to confirmations are never
+//! recorded: a replay stops at each one and waits for the user.
+
+use crossterm::event::{KeyCode, KeyEvent};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Awaiting {
+    Record,
+    Replay,
+}
+
+/// What the key is typed into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal,
+    /// A prompt or chooser, where `Q` and `@` are ordinary keys.
+    Text,
+    /// A confirmation: the key is neither a macro key nor recorded.
+    Confirm,
+}
+
+/// What the caller should do with a key after the recorder has seen it.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// Not a macro key: handle it as usual.
+    Pass,
+    /// Used by the recorder, with a message for the status bar.
+    Consumed(Option<String>),
+    /// Handle these keys in order, as if typed.
+    Replay(Vec<KeyEvent>),
+}
+
+#[derive(Default)]
+pub struct MacroRecorder {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    awaiting: Option<Awaiting>,
+    last_replayed: Option<char>,
+    /// The rest of a replay stopped at a confirmation.
+    paused: Vec<KeyEvent>,
+}
+
+impl MacroRecorder {
+    /// Register being recorded into, for the status bar.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(reg, _)| *reg)
+    }
+
+    /// Look at a key before it is handled.
+    pub fn on_key(&mut self, key: KeyEvent, mode: Mode) -> Outcome {
+        if let Some(awaiting) = self.awaiting.take() {
+            return self.register_key(awaiting, key);
+        }
+        match key.code {
+            KeyCode::Char('Q') if mode == Mode::Normal => match self.recording.take() {
+                Some((reg, keys)) => {
+                    let msg = format!("Recorded {} keys into @{}", keys.len(), reg);
+                    self.registers.insert(reg, keys);
+                    Outcome::Consumed(Some(msg))
+                }
+                None => {
+                    self.awaiting = Some(Awaiting::Record);
+                    Outcome::Consumed(Some("Record into register (a-z)?".to_string()))
+                }
+            },
+            KeyCode::Char('@') if mode == Mode::Normal => {
+                self.awaiting = Some(Awaiting::Replay);
+                Outcome::Consumed(None)
+            }
+            _ => {
+                if let Some((_, keys)) = self.recording.as_mut().filter(|_| mode != Mode::Confirm) {
+                    keys.push(key);
+                }
+                Outcome::Pass
+            }
+        }
+    }
+
+    /// Hold the rest of a replay until the confirmation it reached is answered.
+    pub fn pause(&mut self, rest: Vec<KeyEvent>) {
+        self.paused = rest;
+    }
+
+    /// The rest of a paused replay, to carry on with once confirmed.
+    pub fn resume(&mut self) -> Vec<KeyEvent> {
+        std::mem::take(&mut self.paused)
+    }
+
+    fn register_key(&mut self, awaiting: Awaiting, key: KeyEvent) -> Outcome {
+        let reg = match (awaiting, key.code) {
+            (_, KeyCode::Char(c)) if c.is_ascii_lowercase() => c,
+            (Awaiting::Replay, KeyCode::Char('@')) => match self.last_replayed {
+                Some(reg) => reg,
+                None => return Outcome::Consumed(Some("No macro replayed yet".to_string())),
+            },
+            _ => return Outcome::Consumed(Some("Macro cancelled".to_string())),
+        };
+        match awaiting {
+            Awaiting::Record => {
+                self.recording = Some((reg, Vec::new()));
+                Outcome::Consumed(Some(format!("Recording @{}; Q to stop", reg)))
+            }
+            Awaiting::Replay => {
+                let Some(keys) = self.registers.get(&reg).cloned() else {
+                    return Outcome::Consumed(Some(format!("Register @{} is empty", reg)));
+                };
+                self.last_replayed = Some(reg);
+                // Replaying while recording makes the replay part of the new macro
+                if let Some((_, recording)) = self.recording.as_mut() {
+                    recording.extend(keys.iter().copied());
+                }
+                Outcome::Replay(keys)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut m = MacroRecorder::default();
+        assert!(matches!(m.on_key(key('Q'), Mode::Normal), Outcome::Consumed(_)));
+        assert!(matches!(m.on_key(key('a'), Mode::Normal), Outcome::Consumed(_)));
+        assert_eq!(m.recording(), Some('a'));
+        assert_eq!(m.on_key(key('t'), Mode::Normal), Outcome::Pass);
+        // The answer to the confirmation is handled but not recorded
+        assert_eq!(m.on_key(key('y'), Mode::Confirm), Outcome::Pass);
+        assert_eq!(m.on_key(key('Q'), Mode::Text), Outcome::Pass);
+        assert!(matches!(m.on_key(key('Q'), Mode::Normal), Outcome::Consumed(_)));
+        assert_eq!(m.recording(), None);
+
+        assert!(matches!(m.on_key(key('@'), Mode::Normal), Outcome::Consumed(None)));
+        assert_eq!(m.on_key(key('a'), Mode::Normal), Outcome::Replay(vec![key('t'), key('Q')]));
+        m.on_key(key('@'), Mode::Normal);
+        assert_eq!(m.on_key(key('@'), Mode::Normal), Outcome::Replay(vec![key('t'), key('Q')]));
+    }
+
+    #[test]
+    fn test_empty_and_cancelled_registers() {
+        let mut m = MacroRecorder::default();
+        m.on_key(key('@'), Mode::Normal);
+        assert_eq!(
+            m.on_key(key('b'), Mode::Normal),
+            Outcome::Consumed(Some("Register @b is empty".to_string()))
+        );
+        m.on_key(key('Q'), Mode::Normal);
+        assert_eq!(m.on_key(key('1'), Mode::Normal), Outcome::Consumed(Some("Macro cancelled".to_string())));
+        assert_eq!(m.recording(), None);
+        assert_eq!(m.on_key(key('Q'), Mode::Text), Outcome::Pass);
+    }
+}
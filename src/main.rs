@@ -1,80 +1,287 @@
+mod adhoc;
+mod agent;
+mod annotations;
 mod app;
+mod audit;
+mod bench;
+mod bulk;
+mod cli;
+mod clock;
+mod commands;
 mod config;
+mod deps;
+mod encoding;
+mod dashboard;
+mod events;
+mod http;
 mod logging;
+mod macros;
+mod metrics;
 mod monitor;
+mod notify;
+mod picker;
+mod plugins;
+mod properties;
+mod report;
+mod scratch;
+mod scope;
+mod scripts;
 mod ssh;
+mod timeline;
+mod tmux;
+mod trace;
 mod tui;
+mod undo;
+mod view;
 
 use anyhow::{Context, Result};
 use std::env;
 use std::panic;
 
-fn print_usage(program: &str) {
-    eprintln!("Usage: {} [--log <logfile>] [--user <username>] <inventory.ini> <services.yaml>", program);
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     // Set up panic hook to restore terminal
     let original_hook = panic::take_hook();
     panic::set_hook(Box::new(move |panic_info| {
         let _ = crate::tui::restore();
+        scratch::cleanup_all();
         original_hook(panic_info);
     }));
 
     let args: Vec<String> = env::args().collect();
-
-    // Parse optional --log <file>, --user <username>, and positional args
-    let mut log_file: Option<String> = None;
-    let mut ssh_user: Option<String> = None;
-    let mut positional = Vec::new();
-    let mut i = 1;
-    while i < args.len() {
-        if args[i] == "--log" {
-            if i + 1 >= args.len() {
-                print_usage(&args[0]);
-                std::process::exit(1);
-            }
-            log_file = Some(args[i + 1].clone());
-            i += 2;
-        } else if args[i] == "--user" {
-            if i + 1 >= args.len() {
-                print_usage(&args[0]);
-                std::process::exit(1);
-            }
-            ssh_user = Some(args[i + 1].clone());
-            i += 2;
-        } else {
-            positional.push(args[i].clone());
-            i += 1;
+    let cli = match cli::Cli::parse(&args[1..]) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::usage(&args[0]));
+            std::process::exit(1);
         }
+    };
+    if cli.flag("help") {
+        print!("{}", cli::usage(&args[0]));
+        return Ok(());
     }
 
-    if positional.len() != 2 {
-        print_usage(&args[0]);
+    if let Some(ref spec) = cli.bench {
+        return app::run_bench(bench::BenchSpec::parse(spec)?).await;
+    }
+
+    let extra_hosts = cli.value("extra-hosts");
+    let mut positional = cli.files.clone();
+    // ansible.cfg in the working directory supplies defaults, like for ansible itself
+    let ansible = config::ansible::load(std::path::Path::new("."))?;
+    if positional.len() == 1 && extra_hosts.is_none() {
+        if let Some(inventory) = ansible.as_ref().and_then(|a| a.inventory.clone()) {
+            positional.insert(0, inventory);
+        }
+    }
+    // With --extra-hosts the inventory is optional
+    if positional.len() != 2 && !(extra_hosts.is_some() && positional.len() == 1) {
+        eprint!("{}", cli::usage(&args[0]));
         std::process::exit(1);
     }
 
-    if let Some(ref path) = log_file {
-        logging::init(path).context("Failed to initialize logging")?;
+    let mut log_outputs = logging::LogOutputs {
+        file: cli.value("log").map(String::from),
+        time_format: cli.value("log-time-format").map(String::from),
+        syslog: cli.flag("syslog"),
+        journald: cli.flag("journald"),
+        stderr: false,
+    };
+    let level_given = cli.value("log-level").is_some() || cli.value("log-filter").is_some();
+    if level_given && !log_outputs.any() {
+        // Without a TUI on the terminal, a level alone means logs on stderr
+        if cli.command() == cli::Command::Tui && !cli.flag("events-stdout") {
+            anyhow::bail!("--log-level and --log-filter need --log, --syslog or --journald, as the TUI uses the terminal");
+        }
+        log_outputs.stderr = true;
+    }
+    if log_outputs.any() {
+        // --log-filter comes last, so a bare level in it wins over --log-level
+        let spec: Vec<&str> = [cli.value("log-level"), cli.value("log-filter")].into_iter().flatten().collect();
+        let filter = if spec.is_empty() {
+            logging::LogFilter::default()
+        } else {
+            logging::LogFilter::parse(&spec.join(",")).context("Invalid --log-level or --log-filter")?
+        };
+        logging::init(&log_outputs, filter).context("Failed to initialize logging")?;
         log::info!("system-controller starting");
     }
+    if let Some(ref defaults) = ansible {
+        log::info!("Using defaults from ansible.cfg: {:?}", defaults);
+    }
+
+    let caps = if cli.flag("plain") { tui::caps::Capabilities::PLAIN } else { tui::caps::from_env() };
+    if caps.limited() {
+        log::info!("Limited terminal: {:?}", caps);
+    }
+    tui::caps::set(caps);
+
+    if let Some(dir) = cli.value("temp-dir") {
+        scratch::set_base(dir);
+    }
+    scratch::sweep_stale();
 
-    let inventory_path = &positional[0];
-    let services_path = &positional[1];
+    if let Some(endpoint) = cli.value("otlp-endpoint") {
+        log::info!("Exporting traces to {}", endpoint);
+        trace::init_otlp(endpoint);
+    }
+
+    if let Some(addr) = cli.value("metrics-addr") {
+        http::serve(addr, |path| match path {
+            "/metrics" => http::Response::ok(
+                "text/plain; version=0.0.4",
+                metrics::snapshot().to_prometheus(),
+            ),
+            _ => http::Response::not_found(),
+        })
+        .await?;
+    }
 
-    log::info!("Parsing inventory: {}", inventory_path);
-    let hosts = config::inventory::parse_inventory(inventory_path)
-        .context("Failed to parse inventory")?;
+    let dashboard = match cli.value("dashboard-addr") {
+        Some(addr) => Some(dashboard::serve(addr).await?),
+        None => None,
+    };
+
+    let (inventory_path, services_path) = match positional.as_slice() {
+        [inventory, services] => (Some(inventory), services),
+        [services] => (None, services),
+        _ => unreachable!("checked above"),
+    };
+
+    let mut hosts = Vec::new();
+    if let Some(inventory_path) = inventory_path {
+        log::info!("Parsing inventory: {}", inventory_path);
+        hosts = config::inventory::parse_inventory(inventory_path)
+            .context("Failed to parse inventory")?;
+    }
+    let mut extra_users = Vec::new();
+    if let Some(list) = extra_hosts {
+        for (host, user) in config::inventory::parse_extra_hosts(list)? {
+            if hosts.iter().any(|h| h.address == host.address) {
+                log::info!("Extra host {} is already in the inventory", host.address);
+            } else {
+                log::debug!("Extra host: {}", host.address);
+                hosts.push(host.clone());
+            }
+            if let Some(user) = user {
+                extra_users.push((host.address, user));
+            }
+        }
+    }
     log::info!("Loaded {} hosts", hosts.len());
+    let mut start = view::StartView {
+        focus: match cli.value("focus") {
+            Some(spec) => Some(config::inventory::parse_focus(spec, &hosts)?),
+            None => None,
+        },
+        scope: cli.value("scope").map(scope::Scope::parse),
+        pinned: Vec::new(),
+        group_globs: cli.flag("group-globs"),
+        invocation: Some(view::Invocation {
+            inventory: inventory_path.cloned(),
+            services: services_path.clone(),
+            extra_hosts: extra_hosts.map(String::from),
+        }),
+    };
+    for name in cli.value("pin").iter().flat_map(|p| p.split(',')).map(str::trim).filter(|n| !n.is_empty()) {
+        let idx = config::inventory::find_host(&hosts, name)
+            .with_context(|| format!("--pin: {} is not in the inventory", name))?;
+        start.pinned.push(idx);
+    }
 
     log::info!("Parsing services config: {}", services_path);
-    let service_configs = config::services::parse_services(services_path)
+    let mut config = config::services::parse_config(services_path)
         .context("Failed to parse services config")?;
-    log::info!("Loaded {} service configs", service_configs.len());
+    if let Some(port) = cli.value("port") {
+        config.ssh.port = Some(port.parse().context("Invalid --port")?);
+    }
+    if let Some(secs) = cli.value("timeout") {
+        config.ssh.connect_timeout = Some(secs.parse().context("Invalid --timeout")?);
+    }
+    let mut ssh_user = cli.value("user").map(String::from);
+    if let Some(ref defaults) = ansible {
+        if ssh_user.is_none() {
+            ssh_user = defaults.remote_user.clone();
+        }
+        if config.ssh.identity_file.is_none() {
+            config.ssh.identity_file = defaults.private_key_file.clone();
+        }
+        if config.ssh.connect_timeout.is_none() {
+            config.ssh.connect_timeout = defaults.timeout;
+        }
+    }
+    if let Some(spec) = cli.value("refresh-interval") {
+        let interval = clock::parse_duration(spec).context("Invalid --refresh-interval")?;
+        config.refresh_interval = (!interval.is_zero()).then_some(interval);
+    }
+    if cli.flag("no-initial-refresh") {
+        config.initial_refresh = config::services::InitialRefresh::None;
+    } else if let Some(group) = cli.value("initial-group") {
+        config.initial_refresh = config::services::InitialRefresh::Group(group.to_string());
+    }
+    if start.focus.is_some() {
+        // The focused host is all that is refreshed at first anyway
+        config.initial_refresh = config::services::InitialRefresh::All;
+    }
+    plugins::load(config.plugins_dir.as_deref()).await.context("Failed to load plugins")?;
+    plugins::validate(&config.services)?;
+    scripts::load(&config.scripts)?;
+    config.ssh.users.extend(extra_users);
+    config.ssh.forward_agent |= cli.flag("forward-agent");
+    config.ssh.forward_x11 |= cli.flag("forward-x11");
+    log::info!(
+        "Loaded {} service configs, {} maintenance windows",
+        config.services.len(),
+        config.maintenance.len()
+    );
+
+    let annotations = match cli.value("state-file") {
+        Some(path) => annotations::AnnotationStore::open(path)
+            .context("Failed to open state file")?,
+        None => annotations::AnnotationStore::in_memory(),
+    };
 
-    app::run(hosts, service_configs, ssh_user).await?;
+    let audit = match cli.value("audit-log") {
+        Some(path) => audit::AuditLog::open(path),
+        None => audit::AuditLog::default(),
+    };
+
+    if cli.flag("agent") {
+        if cli.command() == cli::Command::Agent {
+            anyhow::bail!("--agent starts a client of an agent, not an agent");
+        }
+        agent::connect(agent::socket_path(cli.value("agent-socket"))?).await?;
+    }
+
+    let interval = match cli.value("interval") {
+        Some(secs) => std::time::Duration::from_secs(secs.parse().context("Invalid --interval")?),
+        None => std::time::Duration::from_secs(30),
+    };
+    let result = match cli.command() {
+        cli::Command::Tui if cli.flag("events-stdout") => {
+            // stdout carries the event stream, so there is no TUI in this mode
+            events::enable();
+            app::run_headless(hosts, config, ssh_user, audit, interval, None, dashboard).await.map(|_| 0)
+        }
+        cli::Command::Agent => {
+            let socket = agent::socket_path(cli.value("agent-socket"))?;
+            app::run_headless(hosts, config, ssh_user, audit, interval, Some(socket), dashboard).await.map(|_| 0)
+        }
+        cli::Command::Tui => app::run(hosts, config, ssh_user, annotations, audit, start, dashboard).await.map(|_| 0),
+        cli::Command::Status => {
+            let format = commands::Format::parse(cli.value("format").unwrap_or("table"))?;
+            commands::status(hosts, config, ssh_user, format).await
+        }
+        cli::Command::Check => commands::check(hosts, config, ssh_user).await,
+        cli::Command::Run { target, command } => commands::run(hosts, config, ssh_user, &target, &command).await,
+    };
+    scratch::cleanup_all();
+    // The one-shot subcommands report their outcome through the exit status
+    let code = result?;
 
     log::info!("system-controller exiting");
+    if code != 0 {
+        std::process::exit(code);
+    }
     Ok(())
 }
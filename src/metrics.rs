@@ -0,0 +1,168 @@
+//! Counters and timings about the controller itself, shown in the stats
+//! screen and served in Prometheus text format with `--metrics-addr`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    pub refreshes_total: u64,
+    pub last_refresh: Duration,
+    /// Time the last full refresh spent on each host.
+    pub host_refresh: BTreeMap<String, Duration>,
+    pub ssh_connect_failures: BTreeMap<String, u64>,
+    /// Cached sessions found dead before reuse and reopened.
+    pub ssh_reconnects_total: u64,
+    pub commands_total: u64,
+    pub command_errors_total: u64,
+    /// Commands run again after a transport failure.
+    pub command_retries_total: u64,
+    /// Time the UI loop spent working (not waiting for input) in its last iteration.
+    pub loop_latency: Duration,
+    pub loop_latency_max: Duration,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    refreshes_total: 0,
+    last_refresh: Duration::ZERO,
+    host_refresh: BTreeMap::new(),
+    ssh_connect_failures: BTreeMap::new(),
+    ssh_reconnects_total: 0,
+    commands_total: 0,
+    command_errors_total: 0,
+    command_retries_total: 0,
+    loop_latency: Duration::ZERO,
+    loop_latency_max: Duration::ZERO,
+});
+
+/// Update the global metrics.
+pub fn record(f: impl FnOnce(&mut Metrics)) {
+    if let Ok(mut m) = METRICS.lock() {
+        f(&mut m);
+    }
+}
+
+pub fn snapshot() -> Metrics {
+    METRICS.lock().map(|m| m.clone()).unwrap_or_default()
+}
+
+impl Metrics {
+    pub fn command_error_rate(&self) -> f64 {
+        if self.commands_total == 0 {
+            0.0
+        } else {
+            self.command_errors_total as f64 / self.commands_total as f64
+        }
+    }
+
+    /// Hosts of the last refresh, slowest first, each with its share of the
+    /// time all hosts took together.
+    pub fn slowest_hosts(&self) -> Vec<(String, Duration, f64)> {
+        let total: Duration = self.host_refresh.values().sum();
+        let mut hosts: Vec<(String, Duration, f64)> = self
+            .host_refresh
+            .iter()
+            .map(|(host, &d)| {
+                let share = if total.is_zero() { 0.0 } else { d.as_secs_f64() / total.as_secs_f64() };
+                (host.clone(), d, share)
+            })
+            .collect();
+        hosts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        hosts
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str| {
+            let _ = writeln!(out, "# HELP sctl_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE sctl_{} {}", name, kind);
+        };
+        metric("refreshes_total", "counter", "Full refreshes completed.");
+        metric("refresh_duration_seconds", "gauge", "Duration of the last full refresh.");
+        metric("host_refresh_duration_seconds", "gauge", "Time the last full refresh spent per host.");
+        metric("ssh_connect_failures_total", "counter", "Failed SSH connection attempts per host.");
+        metric("ssh_reconnects_total", "counter", "SSH sessions found dead before reuse and reopened.");
+        metric("commands_total", "counter", "Remote commands run.");
+        metric("command_errors_total", "counter", "Remote commands that failed.");
+        metric("command_retries_total", "counter", "Remote commands retried after a transport failure.");
+        metric("event_loop_latency_seconds", "gauge", "Work time of the last UI loop iteration.");
+        metric("event_loop_latency_max_seconds", "gauge", "Largest UI loop work time seen.");
+
+        // Samples follow the headers, grouped per metric
+        let mut lines = vec![
+            format!("sctl_refreshes_total {}", self.refreshes_total),
+            format!("sctl_refresh_duration_seconds {:.3}", self.last_refresh.as_secs_f64()),
+        ];
+        for (host, d) in &self.host_refresh {
+            lines.push(format!(
+                "sctl_host_refresh_duration_seconds{{host=\"{}\"}} {:.3}",
+                escape_label(host),
+                d.as_secs_f64()
+            ));
+        }
+        for (host, n) in &self.ssh_connect_failures {
+            lines.push(format!(
+                "sctl_ssh_connect_failures_total{{host=\"{}\"}} {}",
+                escape_label(host),
+                n
+            ));
+        }
+        lines.push(format!("sctl_ssh_reconnects_total {}", self.ssh_reconnects_total));
+        lines.push(format!("sctl_commands_total {}", self.commands_total));
+        lines.push(format!("sctl_command_errors_total {}", self.command_errors_total));
+        lines.push(format!("sctl_command_retries_total {}", self.command_retries_total));
+        lines.push(format!("sctl_event_loop_latency_seconds {:.6}", self.loop_latency.as_secs_f64()));
+        lines.push(format!(
+            "sctl_event_loop_latency_max_seconds {:.6}",
+            self.loop_latency_max.as_secs_f64()
+        ));
+        for line in lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prometheus_output() {
+        let mut m = Metrics {
+            refreshes_total: 2,
+            commands_total: 10,
+            command_errors_total: 1,
+            ..Default::default()
+        };
+        m.host_refresh.insert("web01".to_string(), Duration::from_millis(1500));
+        m.ssh_connect_failures.insert("db\"01".to_string(), 3);
+        let out = m.to_prometheus();
+        assert!(out.contains("# TYPE sctl_refreshes_total counter\n"));
+        assert!(out.contains("sctl_refreshes_total 2\n"));
+        assert!(out.contains("sctl_ssh_reconnects_total 0\n"));
+        assert!(out.contains("sctl_host_refresh_duration_seconds{host=\"web01\"} 1.500\n"));
+        assert!(out.contains("sctl_ssh_connect_failures_total{host=\"db\\\"01\"} 3\n"));
+        assert_eq!(m.command_error_rate(), 0.1);
+    }
+
+    #[test]
+    fn test_slowest_hosts() {
+        let mut m = Metrics::default();
+        assert!(m.slowest_hosts().is_empty());
+        m.host_refresh.insert("web01".to_string(), Duration::from_millis(100));
+        m.host_refresh.insert("db01".to_string(), Duration::from_millis(300));
+        m.host_refresh.insert("web02".to_string(), Duration::from_millis(100));
+        let slow = m.slowest_hosts();
+        let names: Vec<&str> = slow.iter().map(|(h, _, _)| h.as_str()).collect();
+        assert_eq!(names, ["db01", "web01", "web02"]);
+        assert_eq!(slow[0].2, 0.6);
+    }
+}
@@ -0,0 +1,143 @@
+//! Certificate expiry checks (`type: cert`): the days left on a TLS
+//! certificate mapped to a status, with openssl run on the host or locally.
+
+use super::ServiceStatus;
+use crate::clock;
+use crate::config::services::{CertCheck, CertFrom};
+use super::status::CellDetail;
+use crate::ssh::SessionManager;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OnceCell};
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Expiry from `openssl x509 -enddate` output (`notAfter=Jun  1 12:00:00 2025 GMT`).
+fn parse_not_after(output: &str) -> Option<u64> {
+    let date = output.lines().find_map(|l| l.trim().strip_prefix("notAfter="))?;
+    let fields: Vec<&str> = date.split_whitespace().collect();
+    let [month, day, time, year, ..] = fields[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut hms = time.split(':').map(|p| p.parse::<u32>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+    Some(clock::to_unix(year.parse().ok()?, month, day.parse().ok()?, hour, minute) + second as u64)
+}
+
+/// Whole days from `now` to `expiry`, negative once expired.
+fn days_until(expiry: u64, now: u64) -> i64 {
+    (expiry as i64 - now as i64).div_euclid(86400)
+}
+
+fn status_for(days: i64, cert: &CertCheck) -> ServiceStatus {
+    if days < cert.critical_days {
+        ServiceStatus::Failed
+    } else if days < cert.warn_days {
+        ServiceStatus::Degraded
+    } else {
+        ServiceStatus::Active
+    }
+}
+
+/// Output of the certificate commands run on the controller, by target, so
+/// a `from: controller` check configured for every host connects once per
+/// refresh rather than once per host.
+#[derive(Clone, Default)]
+pub struct ControllerCache(Arc<Mutex<HashMap<String, Arc<CachedOutput>>>>);
+
+/// Filled by whichever host gets there first; the others wait for it.
+type CachedOutput = OnceCell<Result<String, String>>;
+
+impl ControllerCache {
+    /// The output of `cmd`, run for the first host that asks.
+    async fn output(&self, cmd: &str) -> anyhow::Result<String> {
+        let cell = self.0.lock().await.entry(cmd.to_string()).or_default().clone();
+        cell.get_or_init(|| async {
+            tokio::process::Command::new("sh")
+                .args(["-c", cmd])
+                .output()
+                .await
+                .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .clone()
+        .map_err(anyhow::Error::msg)
+    }
+}
+
+/// Fetch the certificate's expiry and map the days left to a status.
+pub async fn run(
+    session_mgr: &mut SessionManager,
+    host: &str,
+    service: &str,
+    cert: &CertCheck,
+    cache: &ControllerCache,
+) -> (ServiceStatus, CellDetail) {
+    let cmd = cert.command();
+    let output = match cert.from {
+        CertFrom::Host => session_mgr.run_command(host, &cmd).await,
+        CertFrom::Controller => cache.output(&cmd).await,
+    };
+    let expiry = match output {
+        Ok(output) => parse_not_after(&output),
+        Err(e) => {
+            log::error!("Certificate check of {} for {}:{} failed to run: {:#}", cert.target, host, service, e);
+            return (ServiceStatus::Error(e.to_string()), CellDetail::default());
+        }
+    };
+    let Some(expiry) = expiry else {
+        log::warn!("No certificate read from {} for {}:{}", cert.target, host, service);
+        return (ServiceStatus::Error(format!("no certificate from {}", cert.target)), CellDetail::default());
+    };
+    let days = days_until(expiry, clock::now_secs());
+    log::debug!("Certificate of {} expires in {} days", cert.target, days);
+    let note = if days < 0 {
+        format!("expired {}d ago", -days)
+    } else {
+        format!("expires in {}d", days)
+    };
+    (status_for(days, cert), CellDetail::from_note(note))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_not_after() {
+        let expiry = parse_not_after("notAfter=Feb 29 13:45:10 2024 GMT\n").unwrap();
+        assert_eq!(expiry, 1709214310);
+        assert_eq!(parse_not_after(""), None);
+        assert_eq!(parse_not_after("notAfter=garbage\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_controller_cache() {
+        let cache = ControllerCache::default();
+        let cmd = "echo $$";
+        let first = cache.output(cmd).await.unwrap();
+        assert!(!first.is_empty());
+        // The same shell's pid: it ran once
+        assert_eq!(cache.output(cmd).await.unwrap(), first);
+        assert_ne!(ControllerCache::default().output(cmd).await.unwrap(), first);
+    }
+
+    #[test]
+    fn test_days_and_status() {
+        let cert = CertCheck {
+            target: "www.example.com".to_string(),
+            from: CertFrom::Host,
+            warn_days: 30,
+            critical_days: 7,
+        };
+        let now = 1_700_000_000;
+        assert_eq!(days_until(now + 86400 * 10 + 5, now), 10);
+        assert_eq!(days_until(now - 5, now), -1);
+        assert_eq!(status_for(45, &cert), ServiceStatus::Active);
+        assert_eq!(status_for(10, &cert), ServiceStatus::Degraded);
+        assert_eq!(status_for(3, &cert), ServiceStatus::Failed);
+        assert_eq!(status_for(-1, &cert), ServiceStatus::Failed);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Longest wait between re-queries of a cell stuck in a transitional state.
+const REPOLL_MAX_DELAY: Duration = Duration::from_secs(60);
+/// Re-queries of a cell before it is left to the regular refreshes.
+const REPOLL_ATTEMPTS: u32 = 10;
+
+/// Coalesces refresh requests from every code path (manual refresh, service
+/// actions, timers) into the smallest set of SSH work.
+///
+/// Rules:
+/// - Only one full refresh runs at a time; full requests while one is in
+///   flight queue a single further one, as the running refresh may predate
+///   whatever prompted them.
+/// - Cell refreshes are grouped per host so each host needs one status query.
+/// - Cells and hosts queued when a full refresh is dispatched are absorbed into it.
+/// - Cells and hosts requested while a full refresh is in flight wait for it to finish,
+///   as the running refresh may predate whatever prompted the request.
+#[derive(Debug, Default)]
+pub struct RefreshCoordinator {
+    full_pending: bool,
+    full_in_flight: bool,
+    cells_pending: BTreeMap<String, BTreeSet<String>>,
+    hosts_pending: BTreeSet<String>,
+    absorbed: Vec<(String, String)>,
+}
+
+/// Work to perform, produced by [`RefreshCoordinator::next_batch`].
+#[derive(Debug, Default, PartialEq)]
+pub struct RefreshBatch {
+    pub full: bool,
+    /// Host address → services to re-query on that host.
+    pub cells: Vec<(String, Vec<String>)>,
+    /// Hosts whose whole row is rebuilt, as in a full refresh.
+    pub hosts: Vec<String>,
+}
+
+impl RefreshCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn request_full(&mut self) {
+        if self.full_in_flight {
+            log::debug!("Full refresh already in flight, queueing one more");
+        }
+        self.full_pending = true;
+    }
+
+    pub fn request_cell(&mut self, host: &str, service: &str) {
+        self.cells_pending
+            .entry(host.to_string())
+            .or_default()
+            .insert(service.to_string());
+    }
+
+    /// Rebuild one host's row (globs, probe and every status) without
+    /// touching the other hosts.
+    pub fn request_host(&mut self, host: &str) {
+        self.hosts_pending.insert(host.to_string());
+    }
+
+    /// True while a full refresh is queued or running.
+    pub fn is_refreshing(&self) -> bool {
+        self.full_pending || self.full_in_flight
+    }
+
+    /// Take the next batch of work, or None if there is nothing to dispatch.
+    pub fn next_batch(&mut self) -> Option<RefreshBatch> {
+        if self.full_in_flight {
+            return None;
+        }
+
+        if self.full_pending {
+            self.full_pending = false;
+            self.full_in_flight = true;
+            for (host, services) in std::mem::take(&mut self.cells_pending) {
+                for service in services {
+                    self.absorbed.push((host.clone(), service));
+                }
+            }
+            self.hosts_pending.clear();
+            return Some(RefreshBatch {
+                full: true,
+                ..Default::default()
+            });
+        }
+
+        if self.cells_pending.is_empty() && self.hosts_pending.is_empty() {
+            return None;
+        }
+
+        let cells = std::mem::take(&mut self.cells_pending)
+            .into_iter()
+            .map(|(host, services)| (host, services.into_iter().collect()))
+            .collect();
+        Some(RefreshBatch {
+            full: false,
+            cells,
+            hosts: std::mem::take(&mut self.hosts_pending).into_iter().collect(),
+        })
+    }
+
+    /// Mark the in-flight full refresh as finished. Returns the cell requests
+    /// it absorbed so callers can settle anything waiting on them.
+    pub fn complete_full(&mut self) -> Vec<(String, String)> {
+        self.full_in_flight = false;
+        std::mem::take(&mut self.absorbed)
+    }
+
+    /// Forget the in-flight full refresh and everything queued, after its
+    /// task was aborted. Returns the cell requests dropped with it.
+    pub fn cancel(&mut self) -> Vec<(String, String)> {
+        self.full_pending = false;
+        let mut dropped = self.complete_full();
+        self.hosts_pending.clear();
+        for (host, services) in std::mem::take(&mut self.cells_pending) {
+            for service in services {
+                dropped.push((host.clone(), service));
+            }
+        }
+        dropped
+    }
+}
+
+/// Decides when to ask again about cells that are activating, deactivating
+/// or reloading: `interval` after they were first seen so, then twice as
+/// long each time up to [`REPOLL_MAX_DELAY`]. A cell still not settled after
+/// [`REPOLL_ATTEMPTS`] re-queries is given up on until it settles.
+#[derive(Debug)]
+pub struct Repoller {
+    interval: Duration,
+    cells: HashMap<(String, String), Backoff>,
+}
+
+#[derive(Debug)]
+struct Backoff {
+    next: Instant,
+    delay: Duration,
+    attempts: u32,
+}
+
+impl Repoller {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            cells: HashMap::new(),
+        }
+    }
+
+    /// Of the cells now in a transitional state, the ones to ask about
+    /// again. Cells no longer among them are forgotten.
+    pub fn due(&mut self, transitional: Vec<(String, String)>, now: Instant) -> Vec<(String, String)> {
+        let current: HashSet<&(String, String)> = transitional.iter().collect();
+        self.cells.retain(|cell, _| current.contains(cell));
+        let mut due = Vec::new();
+        for cell in transitional {
+            let backoff = self.cells.entry(cell.clone()).or_insert(Backoff {
+                next: now + self.interval,
+                delay: self.interval,
+                attempts: 0,
+            });
+            if now < backoff.next || backoff.attempts >= REPOLL_ATTEMPTS {
+                continue;
+            }
+            backoff.attempts += 1;
+            backoff.delay = (backoff.delay * 2).min(REPOLL_MAX_DELAY);
+            backoff.next = now + backoff.delay;
+            due.push(cell);
+        }
+        due
+    }
+}
+
+/// Order in which a full refresh visits hosts, so what matters most updates
+/// first: hosts with problems at the last refresh, then hosts by how close
+/// their rows (`rows[i]` is the host of display row `i`) are to the cursor,
+/// then any host without a row, in inventory order.
+pub fn priority_order(total: usize, troubled: &HashSet<usize>, rows: &[usize], cursor: usize) -> Vec<usize> {
+    let mut distance: Vec<usize> = vec![usize::MAX; total];
+    for (pos, &host) in rows.iter().enumerate() {
+        if host < total {
+            distance[host] = distance[host].min(pos.abs_diff(cursor));
+        }
+    }
+    let mut order: Vec<usize> = (0..total).collect();
+    order.sort_by_key(|&h| (!troubled.contains(&h), distance[h], h));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repoll_backoff() {
+        let cell = ("web01".to_string(), "nginx".to_string());
+        let mut repoller = Repoller::new(Duration::from_secs(2));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        assert!(repoller.due(vec![cell.clone()], at(0)).is_empty());
+        assert_eq!(repoller.due(vec![cell.clone()], at(2)), vec![cell.clone()]);
+        // Then after 4s, 8s, ...
+        assert!(repoller.due(vec![cell.clone()], at(5)).is_empty());
+        assert_eq!(repoller.due(vec![cell.clone()], at(6)).len(), 1);
+        assert!(repoller.due(vec![cell.clone()], at(13)).is_empty());
+        assert_eq!(repoller.due(vec![cell.clone()], at(14)).len(), 1);
+
+        // Capped, and given up on after REPOLL_ATTEMPTS
+        let mut polls = 3;
+        for secs in (15..2000).step_by(1) {
+            polls += repoller.due(vec![cell.clone()], at(secs)).len();
+        }
+        assert_eq!(polls, REPOLL_ATTEMPTS as usize);
+        assert_eq!(repoller.cells[&cell].delay, REPOLL_MAX_DELAY);
+
+        // Settling forgets it, so it starts over
+        assert!(repoller.due(Vec::new(), at(2000)).is_empty());
+        assert!(repoller.cells.is_empty());
+        assert!(repoller.due(vec![cell.clone()], at(2000)).is_empty());
+        assert_eq!(repoller.due(vec![cell.clone()], at(2002)).len(), 1);
+    }
+
+    #[test]
+    fn test_priority_order() {
+        // Rows: host 3, host 0, host 0, host 2; cursor on the last row; host 1 has no row
+        let rows = [3, 0, 0, 2];
+        assert_eq!(priority_order(5, &HashSet::new(), &rows, 3), vec![2, 0, 3, 1, 4]);
+        assert_eq!(priority_order(5, &HashSet::from([4]), &rows, 0), vec![4, 3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn test_idle_has_no_batch() {
+        let mut c = RefreshCoordinator::new();
+        assert_eq!(c.next_batch(), None);
+        assert!(!c.is_refreshing());
+    }
+
+    #[test]
+    fn test_cells_grouped_per_host() {
+        let mut c = RefreshCoordinator::new();
+        c.request_cell("h1", "nginx");
+        c.request_cell("h2", "redis");
+        c.request_cell("h1", "sshd");
+        c.request_cell("h1", "nginx");
+        let batch = c.next_batch().unwrap();
+        assert!(!batch.full);
+        assert_eq!(
+            batch.cells,
+            vec![
+                ("h1".to_string(), vec!["nginx".to_string(), "sshd".to_string()]),
+                ("h2".to_string(), vec!["redis".to_string()]),
+            ]
+        );
+        assert_eq!(c.next_batch(), None);
+    }
+
+    #[test]
+    fn test_full_absorbs_queued_cells() {
+        let mut c = RefreshCoordinator::new();
+        c.request_cell("h1", "nginx");
+        c.request_full();
+        let batch = c.next_batch().unwrap();
+        assert!(batch.full);
+        assert!(batch.cells.is_empty());
+        assert_eq!(c.complete_full(), vec![("h1".to_string(), "nginx".to_string())]);
+        assert_eq!(c.next_batch(), None);
+    }
+
+    #[test]
+    fn test_host_rows() {
+        let mut c = RefreshCoordinator::new();
+        c.request_host("h2");
+        c.request_cell("h1", "nginx");
+        c.request_host("h2");
+        let batch = c.next_batch().unwrap();
+        assert!(!batch.full);
+        assert_eq!(batch.hosts, vec!["h2".to_string()]);
+        assert_eq!(batch.cells.len(), 1);
+        assert!(!c.is_refreshing());
+
+        // Waits for a full refresh in flight, and is covered by a queued one
+        c.request_full();
+        c.next_batch();
+        c.request_host("h1");
+        assert_eq!(c.next_batch(), None);
+        c.complete_full();
+        assert_eq!(c.next_batch().unwrap().hosts, vec!["h1".to_string()]);
+        c.request_host("h1");
+        c.request_full();
+        assert!(c.next_batch().unwrap().hosts.is_empty());
+    }
+
+    #[test]
+    fn test_full_while_in_flight_queues_one_more() {
+        let mut c = RefreshCoordinator::new();
+        c.request_full();
+        assert!(c.next_batch().unwrap().full);
+        c.request_full();
+        c.request_full();
+        assert_eq!(c.next_batch(), None);
+        c.complete_full();
+        assert!(c.next_batch().unwrap().full);
+        c.complete_full();
+        assert_eq!(c.next_batch(), None);
+        assert!(!c.is_refreshing());
+    }
+
+    #[test]
+    fn test_cancel_drops_everything() {
+        let mut c = RefreshCoordinator::new();
+        c.request_cell("h1", "nginx");
+        c.request_full();
+        c.next_batch();
+        c.request_cell("h2", "redis");
+        c.request_full();
+        assert_eq!(
+            c.cancel(),
+            vec![
+                ("h1".to_string(), "nginx".to_string()),
+                ("h2".to_string(), "redis".to_string()),
+            ]
+        );
+        assert!(!c.is_refreshing());
+        assert_eq!(c.next_batch(), None);
+        // Nothing left behind to block the next refresh
+        c.request_full();
+        assert!(c.next_batch().unwrap().full);
+    }
+
+    #[test]
+    fn test_cells_wait_for_in_flight_full() {
+        let mut c = RefreshCoordinator::new();
+        c.request_full();
+        c.next_batch();
+        c.request_cell("h1", "nginx");
+        assert_eq!(c.next_batch(), None);
+        assert!(c.complete_full().is_empty());
+        let batch = c.next_batch().unwrap();
+        assert!(!batch.full);
+        assert_eq!(batch.cells.len(), 1);
+    }
+}
@@ -0,0 +1,76 @@
+//! Last-run checks (`type: last_run`): a cron job or systemd timer must have
+//! run within `max_age`, judged by the timer's last trigger or a file's
+//! modification time.
+
+use super::status::CellDetail;
+use super::ServiceStatus;
+use crate::clock;
+use crate::config::services::LastRunCheck;
+use crate::ssh::SessionManager;
+use std::time::Duration;
+
+/// Host clock and last run from the check command's output; `last` is
+/// `None` when the job never ran (or the file doesn't exist).
+fn parse_output(output: &str) -> Option<(u64, Option<u64>)> {
+    let value = |key: &str| output.lines().find_map(|l| l.trim().strip_prefix(key)?.strip_prefix('='));
+    let now = value("now")?.parse().ok()?;
+    // Timers print `@1709002801`, or nothing when they never triggered
+    let last = value("last")
+        .map(|v| v.trim_start_matches('@'))
+        .and_then(|v| v.parse().ok())
+        .filter(|&t: &u64| t > 0);
+    Some((now, last))
+}
+
+/// Active if the job ran within `max_age`, failed if it is overdue or never ran.
+fn status_for(now: u64, last: Option<u64>, max_age: Duration) -> (ServiceStatus, String) {
+    match last {
+        Some(last) => {
+            let age = Duration::from_secs(now.saturating_sub(last));
+            let note = format!("ran {} ago", clock::format_duration(age));
+            let status = if age > max_age { ServiceStatus::Failed } else { ServiceStatus::Active };
+            (status, note)
+        }
+        None => (ServiceStatus::Failed, "never ran".to_string()),
+    }
+}
+
+pub async fn run(session_mgr: &mut SessionManager, host: &str, service: &str, check: &LastRunCheck) -> (ServiceStatus, CellDetail) {
+    let output = match session_mgr.run_command(host, &check.command()).await {
+        Ok(output) => output,
+        Err(e) => {
+            log::error!("Last-run check for {}:{} failed to run: {:#}", host, service, e);
+            return (ServiceStatus::Error(e.to_string()), CellDetail::default());
+        }
+    };
+    let Some((now, last)) = parse_output(&output) else {
+        log::warn!("Unexpected last-run check output for {}:{}: {}", host, service, output.trim());
+        return (ServiceStatus::Error("unreadable last-run check output".to_string()), CellDetail::default());
+    };
+    let (status, note) = status_for(now, last, check.max_age);
+    log::debug!("Last-run check for {}:{}: {} = {:?}", host, service, note, status);
+    (status, CellDetail::from_note(note))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(parse_output("now=1000\nlast=@900\n"), Some((1000, Some(900))));
+        assert_eq!(parse_output("now=1000\nlast=950\n"), Some((1000, Some(950))));
+        assert_eq!(parse_output("now=1000\nlast=\n"), Some((1000, None)));
+        assert_eq!(parse_output("now=1000\nlast=0\n"), Some((1000, None)));
+        assert_eq!(parse_output("last=5\n"), None);
+    }
+
+    #[test]
+    fn test_status_for() {
+        let day = Duration::from_secs(86400);
+        let (status, note) = status_for(100_000, Some(100_000 - 3 * 3600), day);
+        assert_eq!((status, note.as_str()), (ServiceStatus::Active, "ran 3h ago"));
+        assert_eq!(status_for(100_000, Some(0), day).0, ServiceStatus::Failed);
+        assert_eq!(status_for(100_000, None, day), (ServiceStatus::Failed, "never ran".to_string()));
+    }
+}
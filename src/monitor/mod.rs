@@ -1,3 +1,7 @@
+pub mod cert;
+pub mod coordinator;
+pub mod last_run;
 pub mod status;
+pub mod updates;
 
-pub use status::{GridResult, HostService, ServiceStatus};
+pub use status::{GridResult, HostError, HostService, ServiceStatus};
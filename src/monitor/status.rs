@@ -1,7 +1,16 @@
+use crate::config::services::{display_name, expand_specifiers, unit_name, unit_type, CheckConfig, PluginCheck};
+use super::{cert, last_run};
+use crate::clock;
 use crate::config::{Host, ServiceConfig};
-use crate::ssh::SessionManager;
+use crate::metrics;
+use crate::plugins;
+use crate::ssh::{resolve, SessionManager};
+use crate::trace::Span;
 use glob_match::glob_match;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinSet;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServiceStatus {
@@ -9,16 +18,46 @@ pub enum ServiceStatus {
     Active,
     Inactive,
     Failed,
+    /// Working but unhappy, e.g. a custom check reporting a warning.
+    Degraded,
+    /// Starting up; systemd's `activating`.
+    Activating,
+    /// Shutting down; systemd's `deactivating`.
+    Deactivating,
+    /// Reloading its configuration; systemd's `reloading` (or `refreshing`).
+    Reloading,
+    /// Linked to /dev/null, so it can't be started until unmasked.
+    Masked,
     NotFound,
     Error(String),
 }
 
 impl ServiceStatus {
+    /// Every [`name`](Self::name) whose display word can be replaced; errors
+    /// show their message.
+    pub const NAMES: [&'static str; 10] = [
+        "active",
+        "inactive",
+        "failed",
+        "degraded",
+        "activating",
+        "deactivating",
+        "reloading",
+        "masked",
+        "not-found",
+        "unknown",
+    ];
+
     pub fn from_str(s: &str) -> Self {
         match s.trim() {
             "active" => ServiceStatus::Active,
             "inactive" => ServiceStatus::Inactive,
             "failed" => ServiceStatus::Failed,
+            "degraded" | "warning" => ServiceStatus::Degraded,
+            "activating" => ServiceStatus::Activating,
+            "deactivating" => ServiceStatus::Deactivating,
+            "reloading" | "refreshing" => ServiceStatus::Reloading,
+            "masked" => ServiceStatus::Masked,
             "not-found" | "not found" => ServiceStatus::NotFound,
             "" => ServiceStatus::Unknown,
             other => {
@@ -31,12 +70,74 @@ impl ServiceStatus {
         }
     }
 
+    /// Rank used for worst-of aggregation; higher is worse.
+    pub fn severity(&self) -> u8 {
+        match self {
+            ServiceStatus::Active => 0,
+            ServiceStatus::Reloading => 1,
+            ServiceStatus::Activating => 2,
+            ServiceStatus::Deactivating => 3,
+            ServiceStatus::NotFound => 4,
+            ServiceStatus::Inactive => 5,
+            ServiceStatus::Masked => 6,
+            ServiceStatus::Degraded => 7,
+            ServiceStatus::Unknown => 8,
+            ServiceStatus::Error(_) => 9,
+            ServiceStatus::Failed => 10,
+        }
+    }
+
+    /// On its way to another state, so worth asking again shortly.
+    pub fn is_transitional(&self) -> bool {
+        matches!(
+            self,
+            ServiceStatus::Activating | ServiceStatus::Deactivating | ServiceStatus::Reloading
+        )
+    }
+
+    /// The worst of a set of statuses, or Unknown if there are none.
+    pub fn worst<'a>(statuses: impl IntoIterator<Item = &'a ServiceStatus>) -> ServiceStatus {
+        statuses
+            .into_iter()
+            .max_by_key(|s| s.severity())
+            .cloned()
+            .unwrap_or(ServiceStatus::Unknown)
+    }
+
+    /// Stable lowercase name for scripts and notifications.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServiceStatus::Unknown => "unknown",
+            ServiceStatus::Active => "active",
+            ServiceStatus::Inactive => "inactive",
+            ServiceStatus::Failed => "failed",
+            ServiceStatus::Degraded => "degraded",
+            ServiceStatus::Activating => "activating",
+            ServiceStatus::Deactivating => "deactivating",
+            ServiceStatus::Reloading => "reloading",
+            ServiceStatus::Masked => "masked",
+            ServiceStatus::NotFound => "not-found",
+            ServiceStatus::Error(_) => "error",
+        }
+    }
+
+    /// The word shown for this status: its entry in `words` (the
+    /// `status_words:` setting, by [`name`](Self::name)) or the built-in one.
+    pub fn word<'a>(&'a self, words: &'a HashMap<String, String>) -> &'a str {
+        words.get(self.name()).map_or_else(|| self.display(), String::as_str)
+    }
+
     pub fn display(&self) -> &str {
         match self {
             ServiceStatus::Unknown => "???",
             ServiceStatus::Active => "active",
             ServiceStatus::Inactive => "inactive",
             ServiceStatus::Failed => "FAILED",
+            ServiceStatus::Degraded => "degraded",
+            ServiceStatus::Activating => "activating",
+            ServiceStatus::Deactivating => "deactivating",
+            ServiceStatus::Reloading => "reloading",
+            ServiceStatus::Masked => "masked",
             ServiceStatus::NotFound => "not found",
             ServiceStatus::Error(e) => e.as_str(),
         }
@@ -49,55 +150,102 @@ pub struct HostService {
     pub service_name: String,
     pub config: ServiceConfig,
     pub status: ServiceStatus,
+    pub detail: CellDetail,
 }
 
-/// Expand glob patterns by querying systemctl list-units on the host.
-/// Returns the list of concrete service names for this host.
-pub async fn expand_globs(
-    session_mgr: &mut SessionManager,
-    host: &Host,
+/// What was read along with a cell's status, for showing it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellDetail {
+    /// What systemd said about the unit, verbatim: its `list-units` line,
+    /// or its `systemctl show` properties.
+    pub raw: Option<String>,
+    /// What `raw` says beyond the status, parsed when it was read.
+    pub facts: UnitFacts,
+    /// First non-empty line of a command or plugin check's output.
+    pub check_output: Option<String>,
+    /// Why a check has the status it has (`expires in 10d`), shown next to it.
+    pub check_note: Option<String>,
+}
+
+impl CellDetail {
+    fn from_raw(line: &str) -> Self {
+        Self {
+            raw: Some(line.to_string()),
+            facts: parse_facts(line),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn from_note(note: String) -> Self {
+        Self {
+            check_note: Some(note),
+            ..Default::default()
+        }
+    }
+
+    fn from_check_output(output: &str) -> Self {
+        let first = output.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("");
+        Self {
+            check_output: Some(first.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The status a unit's load state decides regardless of its active state.
+pub fn load_status(load: &str) -> Option<ServiceStatus> {
+    match load {
+        "not-found" => Some(ServiceStatus::NotFound),
+        "masked" => Some(ServiceStatus::Masked),
+        _ => None,
+    }
+}
+
+const UNITS_MARKER: &str = "@@sctl:units";
+pub const STATUS_MARKER: &str = "@@sctl:status";
+
+/// Parse `systemctl list-units --plain --no-legend` output into display
+/// names (see [`display_name`]) and their active state.
+fn parse_unit_list(output: &str) -> Vec<(String, ServiceStatus)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // Older systemd versions prefix failed units with a bullet even in plain mode
+            let mut tokens = line.split_whitespace().skip_while(|t| *t == "●");
+            let unit = tokens.next()?;
+            let load = tokens.next().unwrap_or("");
+            let active = tokens.next().unwrap_or("");
+            let name = display_name(unit).to_string();
+            let status = load_status(load).unwrap_or_else(|| ServiceStatus::from_str(active));
+            Some((name, status))
+        })
+        .collect()
+}
+
+/// Expand glob patterns against the units present on a host.
+/// Returns the list of concrete service names for this host. Globs matching
+/// more than their `max_matches` are truncated and reported in `warnings`.
+/// A unit matched by several entries appears once, with the first entry's
+/// settings and the files and commands of all of them.
+pub fn expand_globs(
+    host: &str,
+    unit_names: &[String],
     service_configs: &[ServiceConfig],
+    warnings: &mut Vec<String>,
 ) -> Vec<(String, ServiceConfig)> {
     let mut results = Vec::new();
 
-    // Check if any configs are globs
-    let has_globs = service_configs.iter().any(|c| c.is_glob);
-
-    // If we have globs, fetch the unit list once
-    let unit_list: Vec<String> = if has_globs {
-        log::debug!("Fetching unit list from {} for glob expansion", host.address);
-        match session_mgr
-            .run_command(
-                &host.address,
-                "systemctl list-units --type=service --all --no-legend --no-pager",
-            )
-            .await
-        {
-            Ok(output) => {
-                let units: Vec<String> = output
-                    .lines()
-                    .filter_map(|line| {
-                        let unit = line.split_whitespace().next()?;
-                        Some(unit.strip_suffix(".service").unwrap_or(unit).to_string())
-                    })
-                    .collect();
-                log::debug!("Found {} units on {}", units.len(), host.address);
-                units
-            }
-            Err(e) => {
-                log::error!("Failed to list units on {}: {}", host.address, e);
-                Vec::new()
-            }
-        }
-    } else {
-        Vec::new()
-    };
-
     for config in service_configs {
         if config.is_glob {
-            let mut matched: Vec<String> = unit_list
+            // Match on full unit names so `s*` only matches services, not `snap.timer`
+            let pattern = unit_name(&config.name_pattern);
+            let excludes: Vec<String> = config.exclude.iter().map(|e| unit_name(e)).collect();
+            let mut matched: Vec<String> = unit_names
                 .iter()
-                .filter(|unit| glob_match(&config.name_pattern, unit))
+                .filter(|unit| {
+                    let unit = unit_name(unit);
+                    glob_match(&pattern, &unit) && !excludes.iter().any(|e| glob_match(e, &unit))
+                })
                 .cloned()
                 .collect();
             matched.sort();
@@ -105,57 +253,483 @@ pub async fn expand_globs(
                 "Glob '{}' matched {} services on {}: {:?}",
                 config.name_pattern,
                 matched.len(),
-                host.address,
+                host,
                 matched
             );
+            if config.max_matches > 0 && matched.len() > config.max_matches {
+                let warning = format!(
+                    "pattern '{}' matched {} units on {}, showing first {}",
+                    config.name_pattern,
+                    matched.len(),
+                    host,
+                    config.max_matches
+                );
+                log::warn!("{}", warning);
+                warnings.push(warning);
+                matched.truncate(config.max_matches);
+            }
             for name in matched {
-                results.push((name, config.clone()));
+                add_match(&mut results, host, name, config);
             }
         } else {
-            results.push((config.name_pattern.clone(), config.clone()));
+            add_match(&mut results, host, config.name_pattern.clone(), config);
         }
     }
 
     results
 }
 
+/// Add a unit matched by `config`, or merge `config` into the entry that
+/// already matched it.
+fn add_match(results: &mut Vec<(String, ServiceConfig)>, host: &str, name: String, config: &ServiceConfig) {
+    let unit = unit_name(&name);
+    let Some((_, existing)) = results.iter_mut().find(|(n, _)| unit_name(n) == unit) else {
+        results.push((name, config.clone()));
+        return;
+    };
+    log::info!(
+        "{} on {} matches both '{}' and '{}', merging",
+        name,
+        host,
+        existing.name_pattern,
+        config.name_pattern
+    );
+    for (to, from) in [
+        (&mut existing.files, &config.files),
+        (&mut existing.commands, &config.commands),
+        (&mut existing.interactive, &config.interactive),
+        (&mut existing.denial_checks, &config.denial_checks),
+    ] {
+        for item in from {
+            if !to.contains(item) {
+                to.push(item.clone());
+            }
+        }
+    }
+    existing.merged.push(config.name_pattern.clone());
+}
+
+/// Configured patterns that matched nothing on any of the rows of reachable
+/// hosts: globs that expanded to no unit, and names that were not found
+/// anywhere (those have no cell). A typo'd glob would otherwise just be
+/// missing from the grid.
+pub fn unmatched_patterns<'a>(
+    service_configs: &[ServiceConfig],
+    rows: impl IntoIterator<Item = &'a Vec<HostService>>,
+) -> Vec<String> {
+    let rows: Vec<_> = rows.into_iter().collect();
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let cells: Vec<_> = rows.into_iter().flatten().collect();
+    service_configs
+        .iter()
+        // Custom checks don't match units
+        .filter(|config| config.check.is_none())
+        .filter(|config| {
+            !cells
+                .iter()
+                .any(|hs| hs.config.name_pattern == config.name_pattern || hs.config.merged.contains(&config.name_pattern))
+        })
+        .map(|config| format!("pattern '{}' matched no units on any reachable host", config.name_pattern))
+        .collect()
+}
+
+/// Add the [`unmatched_patterns`] of a refresh of every host to its
+/// warnings. Only a full refresh can tell; a pattern missing from some hosts
+/// may match on the others.
+pub fn warn_unmatched(result: &mut GridResult, service_configs: &[ServiceConfig]) {
+    let reachable = result
+        .grid
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !result.unreachable_hosts.contains_key(i))
+        .map(|(_, row)| row);
+    for warning in unmatched_patterns(service_configs, reachable) {
+        log::warn!("{}", warning);
+        result.warnings.push(warning);
+    }
+}
+
+const EXIT_MARKER: &str = "@@sctl:exit=";
+
+/// Lines of `list-units` or status script output by the display name of
+/// the unit they are about.
+fn raw_lines(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let unit = line.split_whitespace().find(|t| *t != "●")?;
+            Some((display_name(unit).to_string(), line.trim_end().to_string()))
+        })
+        .collect()
+}
+
+/// What the cell's recorded `systemctl show` properties say beyond its status.
+/// Units matched by a glob have only a `list-units` line, so no facts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnitFacts {
+    /// When the unit last became active, in seconds since boot.
+    pub active_since_boot: Option<u64>,
+    /// Automatic restarts by systemd since the unit was last started by hand.
+    pub restarts: Option<u32>,
+}
+
+fn parse_facts(raw: &str) -> UnitFacts {
+    let value = |key: &str| {
+        raw.split('\t')
+            .find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+            .filter(|v| !v.is_empty())
+    };
+    UnitFacts {
+        // 0 when it never was
+        active_since_boot: value("ActiveEnterTimestampMonotonic")
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|&us| us > 0)
+            .map(|us| us / 1_000_000),
+        restarts: value("NRestarts").and_then(|v| v.parse().ok()),
+    }
+}
+
+
+/// Map a custom check's exit code and output to a status. Output substrings
+/// are checked first, then exit codes, then the configured default.
+pub fn evaluate_check(check: &CheckConfig, exit_code: Option<i32>, output: &str) -> ServiceStatus {
+    if let Some((_, status)) = check.output.iter().find(|(needle, _)| output.contains(needle.as_str())) {
+        return ServiceStatus::from_str(status);
+    }
+    if let Some(code) = exit_code {
+        if let Some((_, status)) = check.exit_codes.iter().find(|(c, _)| *c == code) {
+            return ServiceStatus::from_str(status);
+        }
+    }
+    match &check.default {
+        Some(status) => ServiceStatus::from_str(status),
+        None if exit_code == Some(0) => ServiceStatus::Active,
+        None => ServiceStatus::Failed,
+    }
+}
+
+/// Run a custom check for a concrete service and evaluate its result.
+pub async fn run_check(
+    session_mgr: &mut SessionManager,
+    host: &str,
+    service: &str,
+    check: &CheckConfig,
+    certs: &cert::ControllerCache,
+) -> (ServiceStatus, CellDetail) {
+    if let Some(cert) = &check.cert {
+        return cert::run(session_mgr, host, service, cert, certs).await;
+    }
+    if let Some(last_run) = &check.last_run {
+        return last_run::run(session_mgr, host, service, last_run).await;
+    }
+    if let Some(plugin_check) = &check.plugin {
+        return run_plugin_check(host, service, plugin_check).await;
+    }
+    // Capture the exit code in-band so non-zero exits aren't treated as transport errors
+    let cmd = format!(
+        "{{ {}; }} 2>&1; echo {}$?",
+        expand_specifiers(&check.command, service),
+        EXIT_MARKER
+    );
+    match session_mgr.run_command(host, &cmd).await {
+        Ok(output) => {
+            let (body, exit_code) = match output.trim_end().rsplit_once('\n') {
+                Some((body, last)) => (body, last.strip_prefix(EXIT_MARKER)),
+                None => ("", output.trim_end().strip_prefix(EXIT_MARKER)),
+            };
+            let exit_code = exit_code.and_then(|c| c.trim().parse().ok());
+            let status = evaluate_check(check, exit_code, body);
+            log::debug!("Check for {}:{} exited {:?} = {:?}", host, service, exit_code, status);
+            (status, CellDetail::from_check_output(body))
+        }
+        Err(e) => {
+            log::error!("Check for {}:{} failed to run: {}", host, service, e);
+            (ServiceStatus::Error(e.to_string()), CellDetail::default())
+        }
+    }
+}
+
+/// Ask the plugin providing the check's type for the status.
+async fn run_plugin_check(host: &str, service: &str, check: &PluginCheck) -> (ServiceStatus, CellDetail) {
+    let Some(plugin) = plugins::for_check(&check.kind) else {
+        return (ServiceStatus::Error(format!("no plugin provides {} checks", check.kind)), CellDetail::default());
+    };
+    match plugins::check(plugin, &check.kind, host, service, &check.options).await {
+        Ok(reply) => {
+            let (status, detail) = plugin_result(reply);
+            log::debug!("{} check for {}:{} = {:?}", check.kind, host, service, status);
+            (status, detail)
+        }
+        Err(e) => {
+            log::error!("{} check for {}:{} failed: {:#}", check.kind, host, service, e);
+            (ServiceStatus::Error(format!("{:#}", e)), CellDetail::default())
+        }
+    }
+}
+
+/// The cell a plugin's reply makes. A reply without a note leaves the cell
+/// without one, whatever the previous reply said.
+fn plugin_result(reply: plugins::CheckReply) -> (ServiceStatus, CellDetail) {
+    let detail = CellDetail {
+        check_note: reply.note,
+        ..CellDetail::from_check_output(&reply.output)
+    };
+    (ServiceStatus::from_str(&reply.status), detail)
+}
+
+/// A service name paired with its custom check, if it has one.
+pub type ServiceCheck = (String, Option<CheckConfig>);
+
+/// Refresh a set of services on one host, using custom checks where configured
+/// and a single batched systemd query for the rest.
+pub async fn refresh_services(
+    session_mgr: &mut SessionManager,
+    host: &str,
+    services: &[ServiceCheck],
+) -> Vec<(ServiceStatus, CellDetail)> {
+    let mut span = Span::root("refresh.cells");
+    span.attr("host", host);
+    span.attr("services", services.len());
+    session_mgr.set_trace_parent(Some(span.context()));
+    let units: Vec<String> = services
+        .iter()
+        .filter(|(_, check)| check.is_none())
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut unit_statuses = fetch_statuses(session_mgr, host, &units).await.into_iter();
+
+    let certs = cert::ControllerCache::default();
+    let mut statuses = Vec::new();
+    for (name, check) in services {
+        let status = match check {
+            Some(check) => run_check(session_mgr, host, name, check, &certs).await,
+            None => unit_statuses.next().unwrap_or((ServiceStatus::Unknown, CellDetail::default())),
+        };
+        statuses.push(status);
+    }
+    session_mgr.set_trace_parent(None);
+    statuses
+}
+
+/// Build the script run once per host during a grid build. It emits the
+/// probe, then the unit list (only needed when globs are configured) and
+/// the statuses of the explicitly named services, each section introduced
+/// by a marker line.
+fn host_script(explicit: &[String], glob_types: &[&str]) -> String {
+    let mut parts = vec![PROBE_COMMAND.to_string()];
+    if !glob_types.is_empty() {
+        parts.push(format!("echo {}", UNITS_MARKER));
+        parts.push(format!(
+            "systemctl list-units --type={} --all --no-legend --no-pager --plain",
+            glob_types.join(",")
+        ));
+    }
+    if !explicit.is_empty() {
+        parts.push(format!("echo {}", STATUS_MARKER));
+        parts.push(status_script(explicit));
+    }
+    parts.push("true".to_string());
+    parts.join("; ")
+}
+
+/// Sections of [`host_script`] output.
+#[derive(Debug, Default)]
+struct HostScriptOutput {
+    /// Everything before the first marker.
+    probe: String,
+    units: Vec<(String, ServiceStatus)>,
+    statuses: HashMap<String, ServiceStatus>,
+    /// The line each status was parsed from.
+    raw: HashMap<String, String>,
+}
+
+fn parse_host_script(output: &str) -> HostScriptOutput {
+    let mut probe = String::new();
+    let mut units = String::new();
+    let mut statuses = String::new();
+    let mut section = "";
+
+    for line in output.lines() {
+        match line.trim() {
+            UNITS_MARKER => section = UNITS_MARKER,
+            STATUS_MARKER => section = STATUS_MARKER,
+            _ if section == UNITS_MARKER => {
+                units.push_str(line);
+                units.push('\n');
+            }
+            _ if section == STATUS_MARKER => {
+                statuses.push_str(line);
+                statuses.push('\n');
+            }
+            _ => {
+                probe.push_str(line);
+                probe.push('\n');
+            }
+        }
+    }
+
+    let mut raw = raw_lines(&units);
+    raw.extend(raw_lines(&statuses));
+    HostScriptOutput {
+        probe,
+        units: parse_unit_list(&units),
+        statuses: parse_status_lines(&statuses),
+        raw,
+    }
+}
+
+/// What [`fetch_host`] read from a host.
+struct HostFetch {
+    probe: String,
+    fetched: Vec<(String, ServiceConfig, ServiceStatus)>,
+    /// By service name, for the cells that have any.
+    details: HashMap<String, CellDetail>,
+}
+
+/// Probe a host, expand globs and fetch the status of every configured
+/// service with a single remote exec. Failing to run the script at all
+/// means the host is unreachable.
+async fn fetch_host(
+    session_mgr: &mut SessionManager,
+    host: &str,
+    service_configs: &[ServiceConfig],
+    certs: &cert::ControllerCache,
+    warnings: &mut Vec<String>,
+) -> anyhow::Result<HostFetch> {
+    let explicit: Vec<String> = service_configs
+        .iter()
+        .filter(|c| !c.is_glob && c.check.is_none())
+        .map(|c| c.name_pattern.clone())
+        .collect();
+    // Unit types needed to expand the configured globs
+    let mut glob_types: Vec<&str> = Vec::new();
+    for config in service_configs.iter().filter(|c| c.is_glob) {
+        let t = unit_type(&config.name_pattern);
+        if !glob_types.contains(&t) {
+            glob_types.push(t);
+        }
+    }
+
+    log::debug!(
+        "Fetching {} explicit services{} from {}",
+        explicit.len(),
+        if glob_types.is_empty() { String::new() } else { format!(" and {} units", glob_types.join(",")) },
+        host
+    );
+
+    let output = session_mgr.run_command(host, &host_script(&explicit, &glob_types)).await?;
+    let parsed = parse_host_script(&output);
+    log::debug!("Found {} units on {}", parsed.units.len(), host);
+
+    let unit_status: HashMap<&str, &ServiceStatus> =
+        parsed.units.iter().map(|(n, s)| (n.as_str(), s)).collect();
+    let unit_names: Vec<String> = parsed.units.iter().map(|(n, _)| n.clone()).collect();
+    let mut explicit_statuses = parsed.statuses;
+
+    let mut results = Vec::new();
+    let mut details = HashMap::new();
+    for (name, config) in expand_globs(host, &unit_names, service_configs, warnings) {
+        let status = if let Some(check) = &config.check {
+            let (status, detail) = run_check(session_mgr, host, &name, check, certs).await;
+            details.insert(name.clone(), detail);
+            status
+        } else if config.is_glob {
+            unit_status
+                .get(name.as_str())
+                .map(|s| (*s).clone())
+                .unwrap_or(ServiceStatus::Unknown)
+        } else {
+            explicit_statuses
+                .remove(&name)
+                .unwrap_or(ServiceStatus::Unknown)
+        };
+        log::debug!("  {}:{} = {:?}", host, name, status);
+        if let Some(line) = parsed.raw.get(&name).filter(|_| config.check.is_none()) {
+            details.insert(name.clone(), CellDetail::from_raw(line));
+        }
+        results.push((name, config, status));
+    }
+    Ok(HostFetch {
+        probe: parsed.probe,
+        fetched: results,
+        details,
+    })
+}
+
+/// Build a script that reports each unit as one line:
+/// `<requested name>\t<Key=Value>\t<Key=Value>...` from `systemctl show`.
+/// Keying lines by the requested name keeps parsing independent of output
+/// ordering, and an invalid unit only affects its own line.
+fn status_script(service_names: &[String]) -> String {
+    let units: Vec<String> = service_names
+        .iter()
+        .map(|s| format!("'{}'", unit_name(s)))
+        .collect();
+    format!(
+        "for u in {}; do printf '%s\\t' \"$u\"; \
+         systemctl show --property=Id,LoadState,ActiveState,SubState,ActiveEnterTimestampMonotonic,NRestarts \"$u\" 2>/dev/null | tr '\\n' '\\t'; \
+         echo; done",
+        units.join(" ")
+    )
+}
+
+/// Parse [`status_script`] output into a map of service name → status.
+fn parse_status_lines(output: &str) -> HashMap<String, ServiceStatus> {
+    let mut statuses = HashMap::new();
+    for line in output.lines() {
+        let mut fields = line.split('\t');
+        let unit = match fields.next() {
+            Some(u) if !u.trim().is_empty() => u.trim(),
+            _ => continue,
+        };
+        let mut load = "";
+        let mut active = "";
+        for field in fields {
+            if let Some((key, value)) = field.split_once('=') {
+                match key {
+                    "LoadState" => load = value,
+                    "ActiveState" => active = value,
+                    _ => {}
+                }
+            }
+        }
+        let status = load_status(load).unwrap_or_else(|| ServiceStatus::from_str(active));
+        statuses.insert(display_name(unit).to_string(), status);
+    }
+    statuses
+}
+
 /// Fetch statuses for a list of services on a host in a single SSH call.
 pub async fn fetch_statuses(
     session_mgr: &mut SessionManager,
     host: &str,
     service_names: &[String],
-) -> Vec<ServiceStatus> {
+) -> Vec<(ServiceStatus, CellDetail)> {
     if service_names.is_empty() {
         return Vec::new();
     }
 
-    let svc_args: Vec<String> = service_names
-        .iter()
-        .map(|s| format!("{}.service", s))
-        .collect();
-    let cmd = format!("systemctl is-active {}", svc_args.join(" "));
-
     log::debug!("Fetching status for {} services on {}", service_names.len(), host);
 
-    match session_mgr.run_command(host, &cmd).await {
+    match session_mgr.run_command(host, &status_script(service_names)).await {
         Ok(output) => {
-            let statuses: Vec<ServiceStatus> = output
-                .lines()
-                .map(|line| ServiceStatus::from_str(line))
-                .collect();
-            // Pad with Unknown if fewer lines than expected
-            let mut result = statuses;
-            while result.len() < service_names.len() {
-                result.push(ServiceStatus::Unknown);
-            }
-            for (i, name) in service_names.iter().enumerate() {
-                log::debug!("  {}:{} = {:?}", host, name, result[i]);
-            }
-            result
+            let mut statuses = parse_status_lines(&output);
+            let raw = raw_lines(&output);
+            service_names
+                .iter()
+                .map(|name| {
+                    let status = statuses.remove(name).unwrap_or(ServiceStatus::Unknown);
+                    log::debug!("  {}:{} = {:?}", host, name, status);
+                    let detail = raw.get(name).map(|line| CellDetail::from_raw(line)).unwrap_or_default();
+                    (status, detail)
+                })
+                .collect()
         }
         Err(e) => {
             log::error!("Failed to fetch statuses on {}: {}", host, e);
-            vec![ServiceStatus::Error(e.to_string()); service_names.len()]
+            vec![(ServiceStatus::Error(e.to_string()), CellDetail::default()); service_names.len()]
         }
     }
 }
@@ -164,62 +738,542 @@ pub async fn fetch_statuses(
 pub struct GridResult {
     pub service_names: Vec<String>,
     pub grid: Vec<Vec<HostService>>,
-    pub unreachable_hosts: HashMap<usize, String>,
+    pub unreachable_hosts: HashMap<usize, HostError>,
+    /// Problems worth surfacing to the operator, e.g. truncated glob expansions.
+    pub warnings: Vec<String>,
+    /// Reachable hosts where `sudo -n` fails, so stop/restart cannot work.
+    pub no_sudo: HashSet<usize>,
+    /// Hosts left out of this refresh, whose state is not known.
+    pub skipped: HashSet<usize>,
+    /// Boot of each reachable host that reported one.
+    pub boots: HashMap<usize, BootInfo>,
+    /// systemd state of each reachable host that reported one.
+    pub system: HashMap<usize, SystemState>,
+    /// Units each host's `restart_check` says should be restarted.
+    pub restart_needed: HashMap<usize, HashSet<String>>,
+}
+
+impl GridResult {
+    /// Spread a result built for a subset of hosts over the whole inventory;
+    /// `indices` maps each subset position to its inventory index.
+    pub fn spread(self, indices: &[usize], total: usize) -> GridResult {
+        let mut grid: Vec<Vec<HostService>> = vec![Vec::new(); total];
+        for (row, &i) in self.grid.into_iter().zip(indices) {
+            grid[i] = row;
+        }
+        let included: HashSet<usize> = indices.iter().copied().collect();
+        GridResult {
+            service_names: self.service_names,
+            grid,
+            unreachable_hosts: self
+                .unreachable_hosts
+                .into_iter()
+                .map(|(i, e)| (indices[i], e))
+                .collect(),
+            warnings: self.warnings,
+            no_sudo: self.no_sudo.into_iter().map(|i| indices[i]).collect(),
+            skipped: (0..total).filter(|i| !included.contains(i)).collect(),
+            boots: self.boots.into_iter().map(|(i, b)| (indices[i], b)).collect(),
+            system: self.system.into_iter().map(|(i, s)| (indices[i], s)).collect(),
+            restart_needed: self.restart_needed.into_iter().map(|(i, u)| (indices[i], u)).collect(),
+        }
+    }
+}
+
+/// Connectivity probe, run at the start of the host script, that also checks
+/// for passwordless sudo in the same round trip. Only `systemctl` has to be allowed, as with a NOPASSWD rule for
+/// `/bin/systemctl` alone.
+pub const PROBE_COMMAND: &str = "sudo -n -l systemctl >/dev/null 2>&1 && echo sudo=yes || echo sudo=no; \
+     echo boot_id=$(cat /proc/sys/kernel/random/boot_id 2>/dev/null); \
+     echo uptime=$(cut -d' ' -f1 /proc/uptime 2>/dev/null); \
+     echo system=$(systemctl is-system-running 2>/dev/null); \
+     systemctl list-jobs --no-legend --no-pager 2>/dev/null | sed 's/^/job=/'";
+
+/// Whether the probe found that privileged actions will work.
+fn sudo_available(probe_output: &str) -> bool {
+    probe_output.lines().any(|l| l.trim() == "sudo=yes")
+}
+
+/// Which boot a host is in, from the probe, to notice reboots between refreshes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootInfo {
+    pub boot_id: String,
+    /// Seconds since boot when the host was probed.
+    pub uptime: u64,
+    /// When it was probed, so the uptime can be kept current.
+    pub probed_at: u64,
+}
+
+impl BootInfo {
+    /// Boot id and uptime from the probe output; `None` on systems without
+    /// them (e.g. not Linux).
+    fn parse(probe_output: &str, probed_at: u64) -> Option<BootInfo> {
+        let value = |key: &str| {
+            probe_output
+                .lines()
+                .find_map(|l| l.trim().strip_prefix(key)?.strip_prefix('='))
+                .filter(|v| !v.is_empty())
+        };
+        let uptime: f64 = value("uptime")?.parse().ok()?;
+        Some(BootInfo {
+            boot_id: value("boot_id")?.to_string(),
+            uptime: uptime as u64,
+            probed_at,
+        })
+    }
+
+    pub fn uptime_at(&self, now: u64) -> u64 {
+        self.uptime + now.saturating_sub(self.probed_at)
+    }
+}
+
+/// A queued systemd job, from `systemctl list-jobs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    pub unit: String,
+    /// start, stop, restart, ...
+    pub kind: String,
+    /// waiting or running
+    pub state: String,
+}
+
+/// Overall systemd state of a host, which per-unit status alone misses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemState {
+    /// `systemctl is-system-running`: running, degraded, starting, ...
+    pub state: String,
+    pub jobs: Vec<Job>,
+    /// Jobs that were already queued at the previous refresh.
+    pub stuck: Vec<Job>,
+}
+
+impl SystemState {
+    fn parse(probe_output: &str) -> Option<SystemState> {
+        let mut state = None;
+        let mut jobs = Vec::new();
+        for line in probe_output.lines() {
+            if let Some(s) = line.trim().strip_prefix("system=") {
+                state = Some(s.to_string()).filter(|s| !s.is_empty());
+            } else if let Some(job) = line.strip_prefix("job=") {
+                let fields: Vec<&str> = job.split_whitespace().collect();
+                if let [id, unit, kind, state, ..] = fields[..] {
+                    if let Ok(id) = id.parse() {
+                        jobs.push(Job {
+                            id,
+                            unit: unit.to_string(),
+                            kind: kind.to_string(),
+                            state: state.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        Some(SystemState {
+            state: state?,
+            jobs,
+            stuck: Vec::new(),
+        })
+    }
+
+    /// Mark jobs that were queued in `previous` as well as now as stuck.
+    pub fn carry_over(&mut self, previous: &SystemState) {
+        self.stuck = self
+            .jobs
+            .iter()
+            .filter(|j| previous.jobs.iter().any(|p| p.id == j.id))
+            .cloned()
+            .collect();
+    }
+
+    /// Anything other than a fully running system with nothing queued.
+    pub fn is_notable(&self) -> bool {
+        self.state != "running" || !self.jobs.is_empty()
+    }
+
+    /// `degraded, 3 jobs (1 stuck)`.
+    pub fn summary(&self) -> String {
+        let mut text = self.state.clone();
+        if !self.jobs.is_empty() {
+            text.push_str(&format!(", {} job{}", self.jobs.len(), if self.jobs.len() == 1 { "" } else { "s" }));
+            if !self.stuck.is_empty() {
+                text.push_str(&format!(" ({} stuck)", self.stuck.len()));
+            }
+        }
+        text
+    }
+}
+
+/// Units named in the output of the `restart_check` command: `nginx.service`
+/// as printed by `needs-restarting -s`, or `service nginx restart` /
+/// `systemctl restart nginx` style suggestions as printed by `checkrestart`.
+pub fn parse_restart_units(output: &str) -> HashSet<String> {
+    let mut units = HashSet::new();
+    for line in output.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["service", name, "restart"] | ["systemctl", "restart", name] => {
+                units.insert(unit_name(name));
+            }
+            _ => units.extend(words.iter().filter(|w| w.ends_with(".service")).map(|w| w.to_string())),
+        }
+    }
+    units
+}
+
+/// Why a host could not be probed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostError {
+    Dns,
+    ConnectionRefused,
+    Auth,
+    Timeout,
+    Other,
+}
+
+impl HostError {
+    /// Classify an SSH error chain (as produced by `{:#}`) into a category.
+    pub fn classify(err: &str) -> Self {
+        let lower = err.to_lowercase();
+        if lower.contains("could not resolve hostname")
+            || lower.contains("name or service not known")
+            || lower.contains("temporary failure in name resolution")
+            || lower.contains("nodename nor servname")
+        {
+            HostError::Dns
+        } else if lower.contains("connection refused") {
+            HostError::ConnectionRefused
+        } else if lower.contains("timed out") || lower.contains("timeout") {
+            HostError::Timeout
+        } else if lower.contains("permission denied")
+            || lower.contains("authentication")
+            || lower.contains("auth")
+            || lower.contains("host key verification failed")
+        {
+            HostError::Auth
+        } else {
+            HostError::Other
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            HostError::Dns => "DNS resolution failed",
+            HostError::ConnectionRefused => "connection refused",
+            HostError::Auth => "authentication error",
+            HostError::Timeout => "connection request timed out",
+            HostError::Other => "connection error",
+        }
+    }
+
+    /// A short suggestion for what the operator should check.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            HostError::Dns => "check hostname / DNS",
+            HostError::ConnectionRefused => "check sshd is running and the port",
+            HostError::Auth => "check SSH key / agent / user",
+            HostError::Timeout => "check VPN / host is up",
+            HostError::Other => "see log for details",
+        }
+    }
+}
+
+/// A host's cells in the order of `service_names`, with per-unit specifiers
+/// expanded and the default status/journal commands added.
+/// Each service name once, in the order first seen.
+fn column_names<'a>(fetched: impl IntoIterator<Item = &'a (String, ServiceConfig, ServiceStatus)>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (name, _, _) in fetched {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+fn build_row(
+    host: &Host,
+    fetched: &[(String, ServiceConfig, ServiceStatus)],
+    details: &HashMap<String, CellDetail>,
+    service_names: &[String],
+) -> Vec<HostService> {
+    let mut by_name: HashMap<&str, (&ServiceConfig, &ServiceStatus)> = HashMap::new();
+    for (name, cfg, status) in fetched {
+        by_name.entry(name.as_str()).or_insert((cfg, status));
+    }
+
+    let mut row = Vec::new();
+    for svc_name in service_names {
+        if let Some((cfg, status)) = by_name.get(svc_name.as_str()) {
+            // Skip services that are not present on this host
+            if **status == ServiceStatus::NotFound {
+                log::debug!("Skipping {} on {} (not found)", svc_name, host.address);
+                continue;
+            }
+
+            let mut config = (*cfg).clone();
+            for f in config.files.iter_mut() {
+                *f = expand_specifiers(f, svc_name);
+            }
+            for c in config
+                .commands
+                .iter_mut()
+                .chain(config.interactive.iter_mut())
+                .chain(config.denial_checks.iter_mut())
+            {
+                *c = expand_specifiers(c, svc_name);
+            }
+            if let Some(check) = &config.check {
+                // Plugin checks run on the controller, so there is no command to offer
+                if check.plugin.is_none() {
+                    config.commands.push(expand_specifiers(&check.command, svc_name));
+                }
+            } else {
+                let unit = unit_name(svc_name);
+                config.commands.push(format!("systemctl status '{}'", unit));
+                config.commands.push(format!("journalctl -u '{}'", unit));
+            }
+
+            row.push(HostService {
+                host_address: host.address.clone(),
+                service_name: svc_name.clone(),
+                config,
+                status: (*status).clone(),
+                detail: details.get(svc_name).cloned().unwrap_or_default(),
+            });
+        }
+        // If not fetched, this host doesn't have this service at all — skip it
+    }
+    row
+}
+
+/// What [`refresh_host`] found out about one host.
+struct HostRefresh {
+    host_idx: usize,
+    /// Why the host could not be reached; the rest is empty if set.
+    error: Option<HostError>,
+    no_sudo: bool,
+    boot: Option<BootInfo>,
+    system: Option<SystemState>,
+    fetched: Vec<(String, ServiceConfig, ServiceStatus)>,
+    details: HashMap<String, CellDetail>,
+    /// Units `restart_check` named, if it ran.
+    restart_needed: Option<HashSet<String>>,
+    warnings: Vec<String>,
 }
 
-/// Classify an SSH error into a user-friendly reason string.
-fn classify_ssh_error(err: &str) -> &'static str {
-    let lower = err.to_lowercase();
-    if lower.contains("timed out") || lower.contains("timeout") {
-        "connection request timed out"
-    } else if lower.contains("permission denied") || lower.contains("authentication") || lower.contains("auth") {
-        "authentication error"
-    } else {
-        "connection error"
+impl HostRefresh {
+    fn new(host_idx: usize) -> Self {
+        Self {
+            host_idx,
+            error: None,
+            no_sudo: false,
+            boot: None,
+            system: None,
+            fetched: Vec::new(),
+            details: HashMap::new(),
+            restart_needed: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// What the hosts of one refresh share.
+struct RefreshShared {
+    configs: Vec<ServiceConfig>,
+    restart_check: Option<String>,
+    /// Certificates checked `from: controller`, read once per refresh.
+    certs: cert::ControllerCache,
+}
+
+/// Probe one host, then fetch its services and run `restart_check` on it.
+/// `lookup_error` is set when its name did not resolve.
+async fn refresh_host(
+    session_mgr: &mut SessionManager,
+    host_idx: usize,
+    host: &Host,
+    lookup_error: Option<String>,
+    shared: &RefreshShared,
+    mut span: Span,
+) -> HostRefresh {
+    let started = Instant::now();
+    span.attr("host", &host.address);
+    session_mgr.set_trace_parent(Some(span.context()));
+    let mut refresh = HostRefresh::new(host_idx);
+    let fetched = match lookup_error {
+        Some(e) => Err(anyhow::anyhow!("{}", e)),
+        None => fetch_host(session_mgr, &host.address, &shared.configs, &shared.certs, &mut refresh.warnings).await,
+    };
+    match fetched {
+        Ok(HostFetch { probe, fetched, details }) => {
+            log::info!("Host {} is reachable", host.address);
+            if !sudo_available(&probe) {
+                log::warn!("Passwordless sudo is not available on {}", host.address);
+                refresh.no_sudo = true;
+            }
+            refresh.boot = BootInfo::parse(&probe, clock::now_secs());
+            refresh.system = SystemState::parse(&probe);
+            refresh.fetched = fetched;
+            refresh.details = details;
+            if let Some(cmd) = &shared.restart_check {
+                match session_mgr.run_command(&host.address, cmd).await {
+                    Ok(output) => refresh.restart_needed = Some(parse_restart_units(&output)),
+                    Err(e) => log::warn!("Restart check failed on {}: {:#}", host.address, e),
+                }
+            }
+        }
+        Err(e) => {
+            let err_msg = format!("{:#}", e);
+            let error = HostError::classify(&err_msg);
+            log::warn!("Host {} is unreachable ({}): {}", host.address, error.reason(), err_msg);
+            span.attr("unreachable", error.reason());
+            refresh.error = Some(error);
+        }
     }
+    session_mgr.set_trace_parent(None);
+    let elapsed = started.elapsed();
+    metrics::record(|m| {
+        m.host_refresh.insert(host.address.clone(), elapsed);
+    });
+    refresh
+}
+
+/// One host's part of a full refresh, reported as soon as that host is done.
+pub struct HostUpdate {
+    pub host_idx: usize,
+    pub row: Vec<HostService>,
+    pub error: Option<HostError>,
+    pub no_sudo: bool,
 }
 
 /// Build the initial grid: expand globs, then fetch all statuses.
 /// Hosts that cannot be reached are recorded in unreachable_hosts and get an empty row.
+/// Hosts are started in `order` (every index once), up to `ssh.parallel` at a
+/// time, and `on_host` sees each host's row as soon as it is done. `restart_check` is run on every
+/// reachable host when set.
 pub async fn build_grid(
     session_mgr: &mut SessionManager,
     hosts: &[Host],
     service_configs: &[ServiceConfig],
+    restart_check: Option<&str>,
+    order: &[usize],
+    mut on_host: impl FnMut(HostUpdate),
 ) -> GridResult {
     log::info!("Building grid for {} hosts, {} service configs", hosts.len(), service_configs.len());
+    let refresh_started = Instant::now();
+    let mut refresh_span = Span::root("refresh");
+    refresh_span.attr("hosts", hosts.len());
 
-    let mut unreachable_hosts: HashMap<usize, String> = HashMap::new();
+    let mut unreachable_hosts: HashMap<usize, HostError> = HashMap::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut no_sudo: HashSet<usize> = HashSet::new();
+    let mut boots: HashMap<usize, BootInfo> = HashMap::new();
+    let mut system: HashMap<usize, SystemState> = HashMap::new();
+    let mut restart_needed: HashMap<usize, HashSet<String>> = HashMap::new();
 
-    // First pass: probe each host, expand globs on reachable ones
-    let mut all_expanded: Vec<Vec<(String, ServiceConfig)>> = Vec::new();
-    let mut all_service_names: Vec<String> = Vec::new();
+    // First pass: probe each host, expand globs and fetch statuses in one exec
+    let mut all_fetched: Vec<Vec<(String, ServiceConfig, ServiceStatus)>> = vec![Vec::new(); hosts.len()];
+    let mut all_details: Vec<HashMap<String, CellDetail>> = vec![HashMap::new(); hosts.len()];
 
-    for (host_idx, host) in hosts.iter().enumerate() {
-        // Probe connectivity with a simple command
-        match session_mgr.run_command(&host.address, "true").await {
-            Ok(_) => {
-                log::info!("Host {} is reachable", host.address);
-                let expanded = expand_globs(session_mgr, host, service_configs).await;
-                for (name, _) in &expanded {
-                    if !all_service_names.contains(name) {
-                        all_service_names.push(name.clone());
-                    }
-                }
-                all_expanded.push(expanded);
+    // Resolve every name up front so DNS problems surface immediately and
+    // distinctly instead of as slow SSH failures
+    let addresses: Vec<String> = hosts.iter().map(|h| h.address.clone()).collect();
+    let resolved = resolve::resolve_all(&addresses).await;
+
+    // Hosts are refreshed side by side, so one slow host only delays its own row
+    let shared = Arc::new(RefreshShared {
+        configs: service_configs.to_vec(),
+        restart_check: restart_check.map(String::from),
+        certs: cert::ControllerCache::default(),
+    });
+    let parallel = session_mgr.options().parallel.max(1);
+    let mut pending = order.iter().copied();
+    let mut tasks = JoinSet::new();
+    // Which host each task refreshes, to account for one that panicked
+    let mut task_hosts: HashMap<tokio::task::Id, usize> = HashMap::new();
+    loop {
+        while tasks.len() < parallel {
+            let Some(host_idx) = pending.next() else { break };
+            let host = hosts[host_idx].clone();
+            let lookup_error = match resolved.get(&host.address) {
+                Some(Err(e)) => Some(e.clone()),
+                _ => None,
+            };
+            let mut host_mgr = session_mgr.clone();
+            let span = refresh_span.child("refresh.host");
+            let shared = shared.clone();
+            let task = tasks.spawn(async move {
+                refresh_host(&mut host_mgr, host_idx, &host, lookup_error, &shared, span).await
+            });
+            task_hosts.insert(task.id(), host_idx);
+        }
+        let Some(joined) = tasks.join_next_with_id().await else { break };
+        let refresh = match joined {
+            Ok((id, done)) => {
+                task_hosts.remove(&id);
+                done
             }
             Err(e) => {
-                let err_msg = format!("{}", e);
-                let reason = classify_ssh_error(&err_msg);
-                log::warn!("Host {} is unreachable ({}): {}", host.address, reason, err_msg);
-                unreachable_hosts.insert(host_idx, reason.to_string());
-                all_expanded.push(Vec::new());
+                let Some(host_idx) = task_hosts.remove(&e.id()) else { continue };
+                log::error!("Refresh of {} failed: {}", hosts[host_idx].address, e);
+                HostRefresh {
+                    error: Some(HostError::Other),
+                    ..HostRefresh::new(host_idx)
+                }
+            }
+        };
+        let host_idx = refresh.host_idx;
+        warnings.extend(refresh.warnings);
+        match refresh.error {
+            None => {
+                if refresh.no_sudo {
+                    no_sudo.insert(host_idx);
+                }
+                if let Some(boot) = refresh.boot {
+                    boots.insert(host_idx, boot);
+                }
+                if let Some(state) = refresh.system {
+                    system.insert(host_idx, state);
+                }
+                if let Some(units) = refresh.restart_needed {
+                    restart_needed.insert(host_idx, units);
+                }
+                let names = column_names(&refresh.fetched);
+                on_host(HostUpdate {
+                    host_idx,
+                    row: build_row(&hosts[host_idx], &refresh.fetched, &refresh.details, &names),
+                    error: None,
+                    no_sudo: refresh.no_sudo,
+                });
+                all_fetched[host_idx] = refresh.fetched;
+                all_details[host_idx] = refresh.details;
+            }
+            Some(error) => {
+                on_host(HostUpdate {
+                    host_idx,
+                    row: Vec::new(),
+                    error: Some(error.clone()),
+                    no_sudo: false,
+                });
+                unreachable_hosts.insert(host_idx, error);
             }
         }
     }
 
+    session_mgr.set_trace_parent(None);
+    refresh_span.attr("unreachable", unreachable_hosts.len());
+    let elapsed = refresh_started.elapsed();
+    metrics::record(|m| {
+        m.refreshes_total += 1;
+        m.last_refresh = elapsed;
+    });
+
+    // Columns in inventory order, whatever order the hosts were visited in
+    let all_service_names = column_names(all_fetched.iter().flatten());
+
     log::info!("Service columns after glob expansion: {:?}", all_service_names);
     if !unreachable_hosts.is_empty() {
-        log::info!("Unreachable hosts: {:?}", unreachable_hosts.iter().map(|(&i, reason)| format!("{} ({})", &hosts[i].address, reason)).collect::<Vec<_>>());
+        log::info!("Unreachable hosts: {:?}", unreachable_hosts.iter().map(|(&i, reason)| format!("{} ({})", &hosts[i].address, reason.reason())).collect::<Vec<_>>());
     }
 
     // Build grid
@@ -232,52 +1286,7 @@ pub async fn build_grid(
             continue;
         }
 
-        let expanded = &all_expanded[host_idx];
-        let expanded_map: std::collections::HashMap<&str, &ServiceConfig> =
-            expanded.iter().map(|(n, c)| (n.as_str(), c)).collect();
-
-        // Fetch statuses for services this host has
-        let host_svc_names: Vec<String> = all_service_names
-            .iter()
-            .filter(|n| expanded_map.contains_key(n.as_str()))
-            .cloned()
-            .collect();
-
-        let statuses = fetch_statuses(session_mgr, &host.address, &host_svc_names).await;
-
-        let mut status_map: std::collections::HashMap<String, ServiceStatus> =
-            std::collections::HashMap::new();
-        for (i, name) in host_svc_names.iter().enumerate() {
-            status_map.insert(name.clone(), statuses[i].clone());
-        }
-
-        let mut row = Vec::new();
-        for svc_name in &all_service_names {
-            if let Some(cfg) = expanded_map.get(svc_name.as_str()) {
-                let status = status_map
-                    .remove(svc_name)
-                    .unwrap_or(ServiceStatus::Unknown);
-
-                // Skip services that are not present on this host
-                if status == ServiceStatus::NotFound {
-                    log::debug!("Skipping {} on {} (not found)", svc_name, host.address);
-                    continue;
-                }
-
-                let mut config = (*cfg).clone();
-                config.commands.push(format!("systemctl status {}", svc_name));
-                config.commands.push(format!("journalctl -u {}", svc_name));
-
-                row.push(HostService {
-                    host_address: host.address.clone(),
-                    service_name: svc_name.clone(),
-                    config,
-                    status,
-                });
-            }
-            // If not in expanded_map, this host doesn't have this service at all — skip it
-        }
-        grid.push(row);
+        grid.push(build_row(host, &all_fetched[host_idx], &all_details[host_idx], &all_service_names));
     }
 
     log::info!("Grid built: {} rows x {} columns", grid.len(), all_service_names.len());
@@ -285,6 +1294,12 @@ pub async fn build_grid(
         service_names: all_service_names,
         grid,
         unreachable_hosts,
+        warnings,
+        no_sudo,
+        skipped: HashSet::new(),
+        boots,
+        system,
+        restart_needed,
     }
 }
 
@@ -292,6 +1307,77 @@ pub async fn build_grid(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sudo_available() {
+        assert!(sudo_available("sudo=yes\n"));
+        assert!(!sudo_available("sudo=no\n"));
+        assert!(!sudo_available(""));
+    }
+
+    #[test]
+    fn test_parse_boot_info() {
+        let out = "sudo=yes\nboot_id=3f1c-77\nuptime=5234.87\n";
+        let boot = BootInfo::parse(out, 1000).unwrap();
+        assert_eq!((boot.boot_id.as_str(), boot.uptime), ("3f1c-77", 5234));
+        assert_eq!(boot.uptime_at(1060), 5294);
+        assert_eq!(BootInfo::parse("sudo=yes\nboot_id=\nuptime=\n", 0), None);
+    }
+
+    #[test]
+    fn test_parse_system_state() {
+        let out = "sudo=yes\nsystem=degraded\njob=1042 nginx.service start running\njob=1043 app.service start waiting\n";
+        let mut state = SystemState::parse(out).unwrap();
+        assert_eq!(state.state, "degraded");
+        assert_eq!(state.jobs.len(), 2);
+        assert_eq!(state.jobs[1].unit, "app.service");
+        assert_eq!(state.summary(), "degraded, 2 jobs");
+
+        let previous = SystemState::parse("system=starting\njob=1043 app.service start waiting\n").unwrap();
+        state.carry_over(&previous);
+        assert_eq!(state.stuck.iter().map(|j| j.id).collect::<Vec<_>>(), [1043]);
+        assert_eq!(state.summary(), "degraded, 2 jobs (1 stuck)");
+
+        let idle = SystemState::parse("system=running\n").unwrap();
+        assert!(!idle.is_notable());
+        assert_eq!(SystemState::parse("sudo=yes\nsystem=\n"), None);
+    }
+
+    #[test]
+    fn test_parse_restart_units() {
+        let needs_restarting = "nginx.service\nsshd.service\n";
+        assert_eq!(
+            parse_restart_units(needs_restarting),
+            HashSet::from(["nginx.service".to_string(), "sshd.service".to_string()])
+        );
+        let checkrestart = "Found 2 processes using old versions of upgraded files\n\
+                            service nginx restart\nsystemctl restart php8.2-fpm\n";
+        assert_eq!(
+            parse_restart_units(checkrestart),
+            HashSet::from(["nginx.service".to_string(), "php8.2-fpm.service".to_string()])
+        );
+        assert!(parse_restart_units("").is_empty());
+    }
+
+    #[test]
+    fn test_spread_subset_result() {
+        let subset = GridResult {
+            service_names: vec!["nginx".to_string()],
+            grid: vec![Vec::new(), Vec::new()],
+            unreachable_hosts: HashMap::from([(1, HostError::Timeout)]),
+            warnings: Vec::new(),
+            no_sudo: HashSet::from([0]),
+            skipped: HashSet::new(),
+            boots: HashMap::new(),
+            system: HashMap::new(),
+            restart_needed: HashMap::new(),
+        };
+        let full = subset.spread(&[1, 3], 4);
+        assert_eq!(full.grid.len(), 4);
+        assert_eq!(full.unreachable_hosts.keys().collect::<Vec<_>>(), [&3]);
+        assert_eq!(full.no_sudo, HashSet::from([1]));
+        assert_eq!(full.skipped, HashSet::from([0, 2]));
+    }
+
     // --- ServiceStatus::from_str ---
 
     #[test]
@@ -310,6 +1396,31 @@ mod tests {
         assert_eq!(ServiceStatus::from_str("failed"), ServiceStatus::Failed);
     }
 
+    #[test]
+    fn test_from_str_degraded() {
+        assert_eq!(ServiceStatus::from_str("degraded"), ServiceStatus::Degraded);
+        assert_eq!(ServiceStatus::from_str("warning"), ServiceStatus::Degraded);
+    }
+
+    #[test]
+    fn test_from_str_transitional() {
+        assert_eq!(ServiceStatus::from_str("activating"), ServiceStatus::Activating);
+        assert_eq!(ServiceStatus::from_str("deactivating"), ServiceStatus::Deactivating);
+        assert_eq!(ServiceStatus::from_str("reloading"), ServiceStatus::Reloading);
+        assert_eq!(ServiceStatus::from_str("refreshing"), ServiceStatus::Reloading);
+        assert!(ServiceStatus::Activating.is_transitional());
+        assert!(!ServiceStatus::Failed.is_transitional());
+        // Not failures: a failed unit in the group still wins, a starting one loses to inactive
+        assert_eq!(
+            ServiceStatus::worst(&[ServiceStatus::Activating, ServiceStatus::Failed]),
+            ServiceStatus::Failed
+        );
+        assert_eq!(
+            ServiceStatus::worst(&[ServiceStatus::Activating, ServiceStatus::Inactive]),
+            ServiceStatus::Inactive
+        );
+    }
+
     #[test]
     fn test_from_str_not_found_dash() {
         assert_eq!(ServiceStatus::from_str("not-found"), ServiceStatus::NotFound);
@@ -336,8 +1447,8 @@ mod tests {
 
     #[test]
     fn test_from_str_unrecognised_is_error() {
-        match ServiceStatus::from_str("activating") {
-            ServiceStatus::Error(msg) => assert_eq!(msg, "activating"),
+        match ServiceStatus::from_str("maintenance") {
+            ServiceStatus::Error(msg) => assert_eq!(msg, "maintenance"),
             other => panic!("expected Error, got {:?}", other),
         }
     }
@@ -350,6 +1461,7 @@ mod tests {
         assert_eq!(ServiceStatus::Active.display(), "active");
         assert_eq!(ServiceStatus::Inactive.display(), "inactive");
         assert_eq!(ServiceStatus::Failed.display(), "FAILED");
+        assert_eq!(ServiceStatus::Degraded.display(), "degraded");
         assert_eq!(ServiceStatus::NotFound.display(), "not found");
         assert_eq!(
             ServiceStatus::Error("some error".to_string()).display(),
@@ -357,29 +1469,457 @@ mod tests {
         );
     }
 
-    // --- round-trip: systemctl output lines ---
+    #[test]
+    fn test_status_words() {
+        let words = HashMap::from([("failed".to_string(), "AUSGEFALLEN".to_string())]);
+        assert_eq!(ServiceStatus::Failed.word(&words), "AUSGEFALLEN");
+        assert_eq!(ServiceStatus::Active.word(&words), "active");
+        assert_eq!(ServiceStatus::Failed.word(&HashMap::new()), "FAILED");
+    }
+
+    // --- HostError::classify ---
 
     #[test]
-    fn test_parse_systemctl_output_lines() {
-        // Simulates the output of "systemctl is-active svc1.service svc2.service svc3.service"
-        let output = "active\ninactive\nfailed\n";
-        let statuses: Vec<ServiceStatus> = output
-            .lines()
-            .map(ServiceStatus::from_str)
+    fn test_classify_dns() {
+        assert_eq!(
+            HostError::classify("ssh: Could not resolve hostname foo.invalid: Name or service not known"),
+            HostError::Dns
+        );
+    }
+
+    #[test]
+    fn test_classify_refused() {
+        assert_eq!(
+            HostError::classify("ssh: connect to host 10.0.0.1 port 22: Connection refused"),
+            HostError::ConnectionRefused
+        );
+    }
+
+    #[test]
+    fn test_classify_auth() {
+        assert_eq!(
+            HostError::classify("user@host: Permission denied (publickey)."),
+            HostError::Auth
+        );
+    }
+
+    #[test]
+    fn test_classify_timeout() {
+        assert_eq!(
+            HostError::classify("Connection to 10.0.0.1 timed out after 2s"),
+            HostError::Timeout
+        );
+        assert_eq!(HostError::classify("something else"), HostError::Other);
+    }
+
+    // --- evaluate_check ---
+
+    fn sample_check() -> CheckConfig {
+        CheckConfig {
+            command: "check".to_string(),
+            exit_codes: vec![(0, "active".to_string()), (2, "degraded".to_string())],
+            output: vec![("WARN".to_string(), "degraded".to_string())],
+            default: Some("failed".to_string()),
+            cert: None,
+            last_run: None,
+            plugin: None,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_check_exit_codes() {
+        let check = sample_check();
+        assert_eq!(evaluate_check(&check, Some(0), "ok"), ServiceStatus::Active);
+        assert_eq!(evaluate_check(&check, Some(2), "meh"), ServiceStatus::Degraded);
+        assert_eq!(evaluate_check(&check, Some(1), "bad"), ServiceStatus::Failed);
+    }
+
+    #[test]
+    fn test_evaluate_check_output_wins() {
+        let check = sample_check();
+        assert_eq!(evaluate_check(&check, Some(0), "WARN: disk 85%"), ServiceStatus::Degraded);
+    }
+
+    #[test]
+    fn test_plugin_result() {
+        let reply = |status: &str, note: Option<&str>| plugins::CheckReply {
+            status: status.to_string(),
+            output: "\n2 checks critical\nmore\n".to_string(),
+            note: note.map(String::from),
+        };
+        let (status, detail) = plugin_result(reply("failed", Some("2 checks")));
+        assert_eq!(status, ServiceStatus::Failed);
+        assert_eq!(detail.check_note.as_deref(), Some("2 checks"));
+        assert_eq!(detail.check_output.as_deref(), Some("2 checks critical"));
+        // Recovered without a note: nothing left over
+        let (status, detail) = plugin_result(reply("active", None));
+        assert_eq!(status, ServiceStatus::Active);
+        assert_eq!(detail.check_note, None);
+    }
+
+    #[test]
+    fn test_evaluate_check_without_mapping() {
+        let check = CheckConfig {
+            command: "check".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(evaluate_check(&check, Some(0), ""), ServiceStatus::Active);
+        assert_eq!(evaluate_check(&check, Some(3), ""), ServiceStatus::Failed);
+        assert_eq!(evaluate_check(&check, None, ""), ServiceStatus::Failed);
+    }
+
+    // --- host script envelope ---
+
+    #[test]
+    fn test_host_script_sections() {
+        let script = host_script(&["sshd".to_string(), "backup.timer".to_string()], &["service", "timer"]);
+        assert!(script.starts_with(PROBE_COMMAND));
+        assert!(script.contains("systemctl list-units --type=service,timer"));
+        assert!(script.contains("'sshd.service' 'backup.timer'"));
+        assert!(!host_script(&[], &[]).contains(STATUS_MARKER));
+    }
+
+    #[test]
+    fn test_parse_host_script() {
+        let output = "sudo=yes\nboot_id=3f1c-77\nuptime=5234.87\nsystem=running\n\
+            @@sctl:units\n\
+            nginx.service loaded active running nginx\n\
+            ● cron.service loaded failed failed cron\n\
+            ghost.service not-found inactive dead ghost.service\n\
+            @@sctl:status\n\
+            sshd.service\tId=sshd.service\tLoadState=loaded\tActiveState=active\tSubState=running\t\n";
+        let parsed = parse_host_script(output);
+        assert!(sudo_available(&parsed.probe));
+        assert_eq!(BootInfo::parse(&parsed.probe, 0).unwrap().boot_id, "3f1c-77");
+        assert_eq!(
+            parsed.units,
+            vec![
+                ("nginx".to_string(), ServiceStatus::Active),
+                ("cron".to_string(), ServiceStatus::Failed),
+                ("ghost".to_string(), ServiceStatus::NotFound),
+            ]
+        );
+        assert_eq!(parsed.statuses.get("sshd"), Some(&ServiceStatus::Active));
+        assert_eq!(parsed.raw["cron"], "● cron.service loaded failed failed cron");
+        assert_eq!(
+            parsed.raw["sshd"],
+            "sshd.service\tId=sshd.service\tLoadState=loaded\tActiveState=active\tSubState=running"
+        );
+    }
+
+    #[test]
+    fn test_expand_globs_mixed() {
+        let configs = vec![
+            ServiceConfig {
+                name_pattern: "ng*".to_string(),
+                is_glob: true,
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "sshd".to_string(),
+                is_glob: false,
+                ..Default::default()
+            },
+        ];
+        let units = vec!["nginx-b".to_string(), "cron".to_string(), "nginx-a".to_string()];
+        let names: Vec<String> = expand_globs("h", &units, &configs, &mut Vec::new())
+            .into_iter()
+            .map(|(n, _)| n)
             .collect();
-        assert_eq!(statuses[0], ServiceStatus::Active);
-        assert_eq!(statuses[1], ServiceStatus::Inactive);
-        assert_eq!(statuses[2], ServiceStatus::Failed);
+        assert_eq!(names, vec!["nginx-a", "nginx-b", "sshd"]);
     }
-}
 
-/// Refresh status for a single cell.
-pub async fn refresh_cell(
-    session_mgr: &mut SessionManager,
-    host: &str,
-    service_name: &str,
-) -> ServiceStatus {
-    log::debug!("Refreshing status for {}:{}", host, service_name);
-    let statuses = fetch_statuses(session_mgr, host, &[service_name.to_string()]).await;
-    statuses.into_iter().next().unwrap_or(ServiceStatus::Unknown)
+    #[test]
+    fn test_expand_globs_exclude() {
+        let configs = vec![ServiceConfig {
+            name_pattern: "s*".to_string(),
+            is_glob: true,
+            exclude: vec!["snapd*".to_string(), "systemd-*".to_string()],
+            ..Default::default()
+        }];
+        let units = vec![
+            "sshd".to_string(),
+            "snapd".to_string(),
+            "snapd.seeded".to_string(),
+            "systemd-journald".to_string(),
+        ];
+        let names: Vec<String> = expand_globs("h", &units, &configs, &mut Vec::new())
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, vec!["sshd"]);
+    }
+
+    #[test]
+    fn test_expand_globs_cap_warns() {
+        let configs = vec![ServiceConfig {
+            name_pattern: "s*".to_string(),
+            is_glob: true,
+            max_matches: 2,
+            ..Default::default()
+        }];
+        let units = vec!["s3".to_string(), "s1".to_string(), "s2".to_string()];
+        let mut warnings = Vec::new();
+        let names: Vec<String> = expand_globs("web01", &units, &configs, &mut warnings)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, vec!["s1", "s2"]);
+        assert_eq!(
+            warnings,
+            vec!["pattern 's*' matched 3 units on web01, showing first 2"]
+        );
+    }
+
+    #[test]
+    fn test_expand_globs_merges_overlaps() {
+        let configs = vec![
+            ServiceConfig {
+                name_pattern: "s*".to_string(),
+                is_glob: true,
+                files: vec!["/etc/%n.conf".to_string()],
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "ssh*".to_string(),
+                is_glob: true,
+                files: vec!["/etc/%n.conf".to_string(), "/etc/ssh/sshd_config".to_string()],
+                commands: vec!["sshd -T".to_string()],
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "sshd".to_string(),
+                ..Default::default()
+            },
+        ];
+        let units = vec!["sshd".to_string(), "smartd".to_string()];
+        let expanded = expand_globs("h", &units, &configs, &mut Vec::new());
+        let names: Vec<&str> = expanded.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["smartd", "sshd"]);
+        let sshd = &expanded[1].1;
+        assert_eq!(sshd.name_pattern, "s*");
+        assert_eq!(sshd.files, vec!["/etc/%n.conf", "/etc/ssh/sshd_config"]);
+        assert_eq!(sshd.commands, vec!["sshd -T"]);
+        assert_eq!(sshd.merged, vec!["ssh*", "sshd"]);
+        assert!(expanded[0].1.merged.is_empty());
+        // Merged entries matched something
+        let fetched = expanded.into_iter().map(|(n, c)| (n, c, ServiceStatus::Active)).collect();
+        assert!(unmatched_patterns(&configs, &vec![row_of(fetched)]).is_empty());
+    }
+
+    #[test]
+    fn test_row_has_each_column_once() {
+        let cell = |name: &str| (name.to_string(), ServiceConfig::default(), ServiceStatus::Active);
+        let fetched = vec![cell("nginx"), cell("sshd"), cell("nginx")];
+        assert_eq!(column_names(&fetched), ["nginx", "sshd"]);
+        let row: Vec<String> = row_of(fetched).into_iter().map(|hs| hs.service_name).collect();
+        assert_eq!(row, ["nginx", "sshd"]);
+    }
+
+    #[test]
+    fn test_row_keeps_each_cells_raw_output() {
+        let host = Host {
+            address: "h".to_string(),
+            group: "web".to_string(),
+            parents: Vec::new(),
+        };
+        let cell = |name: &str| (name.to_string(), ServiceConfig::default(), ServiceStatus::Active);
+        let fetched = vec![cell("nginx"), cell("sshd")];
+        let details = HashMap::from([("nginx".to_string(), CellDetail::from_raw("nginx.service\tActiveState=active"))]);
+        let row = build_row(&host, &fetched, &details, &column_names(&fetched));
+        assert_eq!(row[0].detail.raw.as_deref(), Some("nginx.service\tActiveState=active"));
+        assert_eq!(row[1].detail, CellDetail::default());
+    }
+
+    #[tokio::test]
+    async fn test_panicked_host_is_unreachable() {
+        let transport = crate::ssh::mock::MockTransport::new(
+            std::time::Duration::ZERO,
+            Arc::new(|host: &str, _: &str| {
+                assert_ne!(host, "10.0.0.2", "refresh blew up");
+                String::new()
+            }),
+        );
+        let mut session_mgr = SessionManager::mocked(Default::default(), transport);
+        let host = |address: &str| Host {
+            address: address.to_string(),
+            group: "web".to_string(),
+            parents: Vec::new(),
+        };
+        let hosts = [host("10.0.0.1"), host("10.0.0.2")];
+        let configs = [ServiceConfig {
+            name_pattern: "nginx".to_string(),
+            ..Default::default()
+        }];
+        let result = build_grid(&mut session_mgr, &hosts, &configs, None, &[0, 1], |_| {}).await;
+        assert_eq!(result.grid[0].len(), 1);
+        assert_eq!(result.unreachable_hosts.get(&1), Some(&HostError::Other));
+    }
+
+    /// The grid row of a host that answered with `fetched`.
+    fn row_of(fetched: Vec<(String, ServiceConfig, ServiceStatus)>) -> Vec<HostService> {
+        let host = Host {
+            address: "h".to_string(),
+            group: "web".to_string(),
+            parents: Vec::new(),
+        };
+        build_row(&host, &fetched, &HashMap::new(), &column_names(&fetched))
+    }
+
+    #[test]
+    fn test_unmatched_patterns() {
+        let configs = vec![
+            ServiceConfig {
+                name_pattern: "ng*".to_string(),
+                is_glob: true,
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "ngnix*".to_string(),
+                is_glob: true,
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "sshd".to_string(),
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "shd".to_string(),
+                ..Default::default()
+            },
+        ];
+        let units = vec!["nginx".to_string()];
+        let statuses = |missing: &str| -> Vec<HostService> {
+            let fetched = expand_globs("h", &units, &configs, &mut Vec::new())
+                .into_iter()
+                .map(|(name, config)| {
+                    let status = if name == missing || name == "shd" {
+                        ServiceStatus::NotFound
+                    } else {
+                        ServiceStatus::Active
+                    };
+                    (name, config, status)
+                })
+                .collect();
+            row_of(fetched)
+        };
+        // sshd is missing on one host only
+        let hosts = vec![statuses("sshd"), statuses("")];
+        assert_eq!(
+            unmatched_patterns(&configs, &hosts),
+            vec![
+                "pattern 'ngnix*' matched no units on any reachable host",
+                "pattern 'shd' matched no units on any reachable host",
+            ]
+        );
+        // A reachable host with none of the units
+        assert_eq!(unmatched_patterns(&configs[1..2], &vec![Vec::new()]).len(), 1);
+        // Nothing to say without a reachable host
+        assert!(unmatched_patterns(&configs, &Vec::new()).is_empty());
+
+        let mut result = GridResult {
+            service_names: Vec::new(),
+            grid: vec![statuses(""), Vec::new()],
+            unreachable_hosts: [(1, HostError::Timeout)].into(),
+            warnings: Vec::new(),
+            no_sudo: Default::default(),
+            skipped: Default::default(),
+            boots: Default::default(),
+            system: Default::default(),
+            restart_needed: Default::default(),
+        };
+        warn_unmatched(&mut result, &configs);
+        assert_eq!(result.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_expand_globs_respects_unit_type() {
+        let configs = vec![
+            ServiceConfig {
+                name_pattern: "s*".to_string(),
+                is_glob: true,
+                ..Default::default()
+            },
+            ServiceConfig {
+                name_pattern: "s*.timer".to_string(),
+                is_glob: true,
+                ..Default::default()
+            },
+        ];
+        let units = vec!["sshd".to_string(), "snap.timer".to_string()];
+        let names: Vec<String> = expand_globs("h", &units, &configs, &mut Vec::new())
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, vec!["sshd", "snap.timer"]);
+    }
+
+    #[test]
+    fn test_parse_status_lines_any_order() {
+        let output = "b.service\tActiveState=failed\tId=b.service\tLoadState=loaded\t\n\
+            a.service\tLoadState=loaded\tId=a.service\tActiveState=active\tSubState=running\t\n";
+        let statuses = parse_status_lines(output);
+        assert_eq!(statuses.get("a"), Some(&ServiceStatus::Active));
+        assert_eq!(statuses.get("b"), Some(&ServiceStatus::Failed));
+    }
+
+    #[test]
+    fn test_parse_status_lines_not_found_and_invalid() {
+        let output = "ghost.service\tId=ghost.service\tLoadState=not-found\tActiveState=inactive\t\n\
+            bad/name.service\t\n";
+        let statuses = parse_status_lines(output);
+        assert_eq!(statuses.get("ghost"), Some(&ServiceStatus::NotFound));
+        assert_eq!(statuses.get("bad/name"), Some(&ServiceStatus::Unknown));
+    }
+
+    #[test]
+    fn test_masked_units() {
+        let statuses = parse_status_lines("cups.service\tId=cups.service\tLoadState=masked\tActiveState=inactive\t\n");
+        assert_eq!(statuses.get("cups"), Some(&ServiceStatus::Masked));
+        let units = parse_unit_list("cups.service masked inactive dead cups.service\n");
+        assert_eq!(units, vec![("cups".to_string(), ServiceStatus::Masked)]);
+        assert_eq!(ServiceStatus::from_str("masked"), ServiceStatus::Masked);
+    }
+
+    #[test]
+    fn test_parse_facts() {
+        let raw = "nginx.service\tId=nginx.service\tActiveState=active\tActiveEnterTimestampMonotonic=7250000000\tNRestarts=3\t";
+        assert_eq!(
+            parse_facts(raw),
+            UnitFacts {
+                active_since_boot: Some(7250),
+                restarts: Some(3),
+            }
+        );
+        let never = "x.service\tActiveEnterTimestampMonotonic=0\tNRestarts=\t";
+        assert_eq!(parse_facts(never), UnitFacts::default());
+        assert_eq!(parse_facts("nginx.service loaded active running nginx"), UnitFacts::default());
+    }
+
+    // --- ServiceStatus::worst ---
+
+    #[test]
+    fn test_worst_of() {
+        let statuses = [ServiceStatus::Active, ServiceStatus::Failed, ServiceStatus::Inactive];
+        assert_eq!(ServiceStatus::worst(&statuses), ServiceStatus::Failed);
+        assert_eq!(
+            ServiceStatus::worst(&[ServiceStatus::Active, ServiceStatus::Inactive]),
+            ServiceStatus::Inactive
+        );
+        assert_eq!(ServiceStatus::worst(&[]), ServiceStatus::Unknown);
+    }
+
+    // --- round-trip: systemctl output lines ---
+
+    #[test]
+    fn test_parse_systemctl_output_lines() {
+        // The status section of the host script, one `systemctl show` per line
+        let output = "svc1\tId=svc1.service\tLoadState=loaded\tActiveState=active\tSubState=running\t\n\
+            svc2\tId=svc2.service\tLoadState=loaded\tActiveState=inactive\tSubState=dead\t\n\
+            svc3\tId=svc3.service\tLoadState=loaded\tActiveState=failed\tSubState=failed\t\n";
+        let statuses = parse_host_script(&format!("{}\n{}", STATUS_MARKER, output)).statuses;
+        assert_eq!(statuses["svc1"], ServiceStatus::Active);
+        assert_eq!(statuses["svc2"], ServiceStatus::Inactive);
+        assert_eq!(statuses["svc3"], ServiceStatus::Failed);
+    }
 }
@@ -0,0 +1,123 @@
+//! Pending package updates per host, checked on a slower cadence than the
+//! service refresh. apt and dnf (or yum) are detected on the host.
+
+use crate::config::Host;
+use crate::ssh::SessionManager;
+use std::collections::HashMap;
+
+/// Lists upgradable packages with whichever package manager the host has,
+/// after a line naming it. Only reads the local package cache; it doesn't
+/// fetch package lists.
+const CHECK_COMMAND: &str = "if command -v apt >/dev/null 2>&1; then \
+       echo manager=apt; apt list --upgradable 2>/dev/null; \
+     elif command -v dnf >/dev/null 2>&1; then \
+       echo manager=dnf; dnf -q -C check-update 2>/dev/null; \
+     elif command -v yum >/dev/null 2>&1; then \
+       echo manager=yum; yum -q -C check-update 2>/dev/null; \
+     else echo manager=none; fi; true";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    /// Version that would be installed.
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageUpdates {
+    /// apt, dnf or yum.
+    pub manager: String,
+    pub packages: Vec<Package>,
+}
+
+impl PackageUpdates {
+    /// `None` if the host has no supported package manager.
+    fn parse(output: &str) -> Option<PackageUpdates> {
+        let mut lines = output.lines();
+        let manager = lines.next()?.trim().strip_prefix("manager=")?.to_string();
+        let mut packages = Vec::new();
+        match manager.as_str() {
+            // nginx/jammy-updates 1.18.0-6ubuntu14.4 amd64 [upgradable from: ...]
+            "apt" => {
+                for line in lines {
+                    let mut words = line.split_whitespace();
+                    if let (Some(name), Some(version)) = (words.next(), words.next()) {
+                        if let Some((name, _)) = name.split_once('/') {
+                            packages.push(Package {
+                                name: name.to_string(),
+                                version: version.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+            // nginx.x86_64   1:1.20.1-14.el9_2.1   appstream
+            "dnf" | "yum" => {
+                for line in lines {
+                    if line.starts_with("Obsoleting") {
+                        break;
+                    }
+                    if let [name, version, _repo] = line.split_whitespace().collect::<Vec<_>>()[..] {
+                        packages.push(Package {
+                            name: name.to_string(),
+                            version: version.to_string(),
+                        });
+                    }
+                }
+            }
+            _ => return None,
+        }
+        Some(PackageUpdates { manager, packages })
+    }
+}
+
+/// Check every host in `indices`. Hosts that can't be checked are left out.
+pub async fn check_hosts(
+    session_mgr: &mut SessionManager,
+    hosts: &[Host],
+    indices: &[usize],
+) -> HashMap<usize, PackageUpdates> {
+    let mut results = HashMap::new();
+    for &idx in indices {
+        let address = &hosts[idx].address;
+        match session_mgr.run_command(address, CHECK_COMMAND).await {
+            Ok(output) => match PackageUpdates::parse(&output) {
+                Some(updates) => {
+                    log::debug!("{} has {} pending updates", address, updates.packages.len());
+                    results.insert(idx, updates);
+                }
+                None => log::debug!("No supported package manager on {}", address),
+            },
+            Err(e) => log::warn!("Package update check failed on {}: {:#}", address, e),
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apt() {
+        let out = "manager=apt\nListing...\n\
+                   nginx/jammy-updates 1.18.0-6ubuntu14.4 amd64 [upgradable from: 1.18.0-6ubuntu14.3]\n\
+                   openssl/jammy-security 3.0.2-0ubuntu1.15 amd64 [upgradable from: 3.0.2-0ubuntu1.14]\n";
+        let updates = PackageUpdates::parse(out).unwrap();
+        assert_eq!(updates.manager, "apt");
+        assert_eq!(updates.packages.len(), 2);
+        assert_eq!(updates.packages[1].name, "openssl");
+        assert_eq!(updates.packages[1].version, "3.0.2-0ubuntu1.15");
+    }
+
+    #[test]
+    fn test_parse_dnf() {
+        let out = "manager=dnf\n\nnginx.x86_64   1:1.20.1-14.el9_2.1   appstream\n\
+                   Obsoleting Packages\ngrub2-tools.x86_64  1:2.06-70.el9  baseos\n";
+        let updates = PackageUpdates::parse(out).unwrap();
+        assert_eq!(updates.packages.len(), 1);
+        assert_eq!(updates.packages[0].name, "nginx.x86_64");
+        assert_eq!(PackageUpdates::parse("manager=none\n"), None);
+        assert_eq!(PackageUpdates::parse(""), None);
+    }
+}
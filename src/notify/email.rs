@@ -0,0 +1,190 @@
+use crate::clock;
+use crate::scratch;
+use crate::config::notifications::{EmailConfig, TlsMode};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// RFC 5322 date, always in UTC.
+fn rfc5322_date(secs: u64) -> String {
+    let dt = clock::from_unix(secs);
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} +0000",
+        DAYS[dt.weekday as usize],
+        dt.day,
+        MONTHS[dt.month as usize - 1],
+        dt.year,
+        dt.hour,
+        dt.minute,
+        dt.second
+    )
+}
+
+/// `value` folded onto one line, so a host or service name holding CR or LF
+/// can't end the header and start another.
+fn header_value(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+fn message(config: &EmailConfig, subject: &str, body: &str, now: u64) -> String {
+    let mut msg = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+        header_value(&config.from),
+        header_value(&config.to.join(", ")),
+        header_value(subject),
+        rfc5322_date(now)
+    );
+    for line in body.lines() {
+        msg.push_str(line);
+        msg.push_str("\r\n");
+    }
+    msg
+}
+
+/// curl arguments delivering the message in `path`. Credentials are passed
+/// separately on stdin so they never show up in the process list.
+fn curl_args(config: &EmailConfig, path: &str) -> Vec<String> {
+    let scheme = if config.tls == TlsMode::Tls { "smtps" } else { "smtp" };
+    let mut args = vec![
+        "--silent".to_string(),
+        "--show-error".to_string(),
+        "--url".to_string(),
+        format!("{}://{}:{}", scheme, config.server, config.port()),
+        "--mail-from".to_string(),
+        config.from.clone(),
+    ];
+    for to in &config.to {
+        args.push("--mail-rcpt".to_string());
+        args.push(to.clone());
+    }
+    if config.tls == TlsMode::Starttls {
+        args.push("--ssl-reqd".to_string());
+    }
+    args.push("--upload-file".to_string());
+    args.push(path.to_string());
+    args.push("--config".to_string());
+    args.push("-".to_string());
+    args
+}
+
+/// curl config lines carrying the login, if one is configured.
+fn credentials(config: &EmailConfig) -> String {
+    let Some(user) = &config.username else {
+        return String::new();
+    };
+    let password = config
+        .password_env
+        .as_ref()
+        .and_then(|var| std::env::var(var).ok())
+        .unwrap_or_default();
+    let value = format!("{}:{}", user, password)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    format!("user = \"{}\"\n", value)
+}
+
+/// Send a plain-text mail in the background via curl's SMTP support.
+pub fn send(config: &EmailConfig, subject: &str, body: &str) {
+    let msg = message(config, subject, body, clock::now_secs());
+    let config = config.clone();
+    let subject = subject.to_string();
+    tokio::spawn(async move {
+        let file = match scratch::TempFile::create("mail", ".eml", msg.as_bytes()) {
+            Ok(file) => file,
+            Err(e) => {
+                log::error!("Failed to write mail for '{}': {:#}", subject, e);
+                return;
+            }
+        };
+        let path = file.path();
+
+        let result = async {
+            let mut child = Command::new("curl")
+                .args(curl_args(&config, &path.to_string_lossy()))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(credentials(&config).as_bytes()).await?;
+            }
+            child.wait_with_output().await
+        }
+        .await;
+
+        match result {
+            Ok(out) if out.status.success() => log::info!("Sent mail '{}' to {}", subject, config.to.join(", ")),
+            Ok(out) => log::error!(
+                "Sending mail '{}' failed: {}",
+                subject,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+            Err(e) => log::error!("Failed to run curl for mail '{}': {}", subject, e),
+        }
+        drop(file);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(tls: TlsMode) -> EmailConfig {
+        EmailConfig {
+            server: "smtp.example.com".to_string(),
+            port: None,
+            tls,
+            from: "sctl@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            username: Some("sctl".to_string()),
+            password_env: None,
+        }
+    }
+
+    #[test]
+    fn test_rfc5322_date() {
+        assert_eq!(rfc5322_date(1709214300), "Thu, 29 Feb 2024 13:45:00 +0000");
+    }
+
+    #[test]
+    fn test_message_headers() {
+        let msg = message(&config(TlsMode::Starttls), "nginx failed", "line1\nline2", 1709214300);
+        assert!(msg.starts_with("From: sctl@example.com\r\nTo: a@example.com, b@example.com\r\nSubject: nginx failed\r\n"));
+        assert!(msg.ends_with("\r\n\r\nline1\r\nline2\r\n"));
+    }
+
+    #[test]
+    fn test_header_injection() {
+        let mut c = config(TlsMode::None);
+        c.to = vec!["a@example.com\r\nBcc: evil@example.com".to_string()];
+        let msg = message(&c, "web01\r\nBcc: evil@example.com\nnginx failed", "body", 1709214300);
+        let (headers, _) = msg.split_once("\r\n\r\n").unwrap();
+        assert!(!headers.lines().any(|l| l.starts_with("Bcc:")));
+        assert!(headers.contains("Subject: web01  Bcc: evil@example.com nginx failed\r\n"));
+    }
+
+    #[test]
+    fn test_curl_args_by_tls_mode() {
+        let args = curl_args(&config(TlsMode::Starttls), "/tmp/m.eml");
+        assert!(args.contains(&"smtp://smtp.example.com:587".to_string()));
+        assert!(args.contains(&"--ssl-reqd".to_string()));
+        assert_eq!(args.iter().filter(|a| *a == "--mail-rcpt").count(), 2);
+
+        let args = curl_args(&config(TlsMode::Tls), "/tmp/m.eml");
+        assert!(args.contains(&"smtps://smtp.example.com:465".to_string()));
+        assert!(!args.contains(&"--ssl-reqd".to_string()));
+    }
+
+    #[test]
+    fn test_credentials_escaped() {
+        let mut c = config(TlsMode::None);
+        assert_eq!(credentials(&c), "user = \"sctl:\"\n");
+        c.username = None;
+        assert_eq!(credentials(&c), "");
+    }
+}
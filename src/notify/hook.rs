@@ -0,0 +1,59 @@
+use super::Transition;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Run the `on_change` command through `sh -c` in the background with the
+/// transition in its environment. Output is discarded so it can't scribble
+/// over the TUI.
+pub fn spawn(cmd: &str, t: &Transition) {
+    let mut command = command(cmd, t);
+    let label = format!("{}:{}", t.host, t.service);
+    tokio::spawn(async move {
+        match command.status().await {
+            Ok(status) if status.success() => log::debug!("on_change hook for {} finished", label),
+            Ok(status) => log::warn!("on_change hook for {} exited with {}", label, status),
+            Err(e) => log::error!("Failed to run on_change hook for {}: {}", label, e),
+        }
+    });
+}
+
+fn command(cmd: &str, t: &Transition) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .env("SCTL_HOST", &t.host)
+        .env("SCTL_SERVICE", &t.service)
+        .env("SCTL_OLD", &t.old)
+        .env("SCTL_NEW", &t.new)
+        .env("SCTL_SEVERITY", t.severity.name())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Severity;
+
+    #[tokio::test]
+    async fn test_hook_environment() {
+        let out = std::env::temp_dir().join(format!("sc_hook_{}.txt", std::process::id()));
+        let t = Transition {
+            host: "web01".to_string(),
+            service: "nginx".to_string(),
+            old: "active".to_string(),
+            new: "failed".to_string(),
+            severity: Severity::Critical,
+        };
+        let cmd = format!(
+            "echo \"$SCTL_HOST $SCTL_SERVICE $SCTL_OLD $SCTL_NEW $SCTL_SEVERITY\" > {}",
+            out.display()
+        );
+        assert!(command(&cmd, &t).status().await.unwrap().success());
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "web01 nginx active failed critical\n");
+        let _ = std::fs::remove_file(&out);
+    }
+}
@@ -0,0 +1,282 @@
+pub mod email;
+pub mod hook;
+pub mod mqtt;
+pub mod webhook;
+
+use crate::config::notifications::MqttConfig;
+use crate::config::{Config, NotificationChannel, NotificationRoute, Severity};
+use std::collections::{HashMap, HashSet};
+
+/// Status of a cell as seen by notifications, keyed by (host, service). The
+/// host itself is tracked under an empty service name as `reachable` or
+/// `unreachable`.
+pub type CellStates = HashMap<(String, String), String>;
+
+/// A cell whose status changed between two refreshes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub host: String,
+    /// Empty for host reachability changes.
+    pub service: String,
+    pub old: String,
+    pub new: String,
+    pub severity: Severity,
+}
+
+/// Cells present in both snapshots whose status differs. Unknown statuses
+/// are transient and never produce a transition. `severity` looks up a
+/// service's configured severity; hosts dropping off are always critical.
+pub fn diff(
+    old: &CellStates,
+    new: &CellStates,
+    severity: impl Fn(&str, &str) -> Severity,
+) -> Vec<Transition> {
+    let mut transitions: Vec<Transition> = new
+        .iter()
+        .filter_map(|((host, service), new_status)| {
+            let old_status = old.get(&(host.clone(), service.clone()))?;
+            if old_status == new_status || old_status == "unknown" || new_status == "unknown" {
+                return None;
+            }
+            Some(Transition {
+                host: host.clone(),
+                service: service.clone(),
+                old: old_status.clone(),
+                new: new_status.clone(),
+                severity: if service.is_empty() {
+                    Severity::Critical
+                } else {
+                    severity(host, service)
+                },
+            })
+        })
+        .collect();
+    transitions.sort_by(|a, b| (&a.host, &a.service).cmp(&(&b.host, &b.service)));
+    transitions
+}
+
+impl Transition {
+    /// A service failing or a host dropping off, as opposed to routine changes.
+    pub fn is_alert(&self) -> bool {
+        self.new == "failed" || self.new == "unreachable"
+    }
+
+    /// Back to healthy; only worth announcing after an alert.
+    pub fn is_recovery(&self) -> bool {
+        self.new == "active" || self.new == "reachable"
+    }
+
+    pub fn subject(&self) -> String {
+        if self.is_recovery() {
+            let what = if self.service.is_empty() {
+                self.host.clone()
+            } else {
+                format!("{} on {}", self.service, self.host)
+            };
+            format!("[sctl] [{}] RECOVERED {}: {} -> {}", self.severity.name(), what, self.old, self.new)
+        } else if self.service.is_empty() {
+            format!("[sctl] [{}] {} is {}", self.severity.name(), self.host, self.new)
+        } else {
+            format!(
+                "[sctl] [{}] {} on {}: {} -> {}",
+                self.severity.name(),
+                self.service,
+                self.host,
+                self.old,
+                self.new
+            )
+        }
+    }
+
+    pub fn body(&self) -> String {
+        let mut body = format!("Host:    {}\n", self.host);
+        if !self.service.is_empty() {
+            body.push_str(&format!("Service: {}\n", self.service));
+        }
+        body.push_str(&format!("Status:  {} -> {}\n", self.old, self.new));
+        body.push_str(&format!("Severity: {}\n", self.severity.name()));
+        body
+    }
+}
+
+/// Fans status transitions out to the configured integrations. The hook
+/// sees every transition; channels only get alerts at or above their
+/// `min_severity`. An alert is sent once per problem: repeats are held back
+/// until the cell recovers, which is announced in turn. MQTT gets every
+/// change as a retained state message through [`Notifier::publish`], silenced
+/// cells included.
+#[derive(Default)]
+pub struct Notifier {
+    on_change: Option<String>,
+    routes: Vec<NotificationRoute>,
+    mqtt: Option<MqttConfig>,
+    /// Cells with an open alert, keyed by (host, service).
+    alerted: HashSet<(String, String)>,
+}
+
+impl Notifier {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            on_change: config.on_change.clone(),
+            routes: config.notifications.clone(),
+            mqtt: config.mqtt.clone(),
+            alerted: HashSet::new(),
+        }
+    }
+
+    /// Whether a transition should reach the channels, updating the set of
+    /// open alerts as a side effect.
+    fn should_send(&mut self, t: &Transition) -> bool {
+        let key = (t.host.clone(), t.service.clone());
+        if t.is_alert() {
+            let first = self.alerted.insert(key);
+            if !first {
+                log::debug!("Suppressing repeat alert for {}:{}", t.host, t.service);
+            }
+            first
+        } else if t.is_recovery() {
+            self.alerted.remove(&key)
+        } else {
+            false
+        }
+    }
+
+    /// Publish the first full set of states, so retained MQTT topics are
+    /// populated before anything changes.
+    pub fn baseline(&self, states: &CellStates) {
+        let Some(mqtt) = &self.mqtt else {
+            return;
+        };
+        for ((host, service), state) in states {
+            if state != "unknown" {
+                mqtt::publish(mqtt, host, service, state);
+            }
+        }
+    }
+
+    /// Publish the new states to MQTT.
+    pub fn publish(&self, transitions: &[Transition]) {
+        let Some(mqtt) = &self.mqtt else {
+            return;
+        };
+        for t in transitions {
+            mqtt::publish(mqtt, &t.host, &t.service, &t.new);
+        }
+    }
+
+    /// Dispatch the transitions to the hook, scripts and channels and return
+    /// a line for each alert sent to a channel, for the session timeline.
+    pub fn notify(&mut self, transitions: Vec<Transition>) -> Vec<String> {
+        let mut sent = Vec::new();
+        crate::scripts::on_change(&transitions);
+        for t in transitions {
+            log::info!("Transition {}:{} {} -> {}", t.host, t.service, t.old, t.new);
+            if let Some(cmd) = &self.on_change {
+                hook::spawn(cmd, &t);
+            }
+            if !self.should_send(&t) {
+                continue;
+            }
+            for route in self.routes.iter().filter(|r| t.severity >= r.min_severity) {
+                let name = match &route.channel {
+                    NotificationChannel::Email(email) => {
+                        email::send(email, &t.subject(), &t.body());
+                        "email"
+                    }
+                    NotificationChannel::Webhook(hook) => {
+                        webhook::send(&hook.url, webhook::generic_payload(&t));
+                        "webhook"
+                    }
+                    NotificationChannel::Slack(hook) => {
+                        webhook::send(&hook.url, webhook::slack_payload(hook, &t));
+                        "slack"
+                    }
+                    NotificationChannel::Mattermost(hook) => {
+                        webhook::send(&hook.url, webhook::slack_payload(hook, &t));
+                        "mattermost"
+                    }
+                };
+                sent.push(format!("{}: {}", name, t.subject()));
+            }
+        }
+        sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(cells: &[(&str, &str, &str)]) -> CellStates {
+        cells
+            .iter()
+            .map(|(h, s, st)| ((h.to_string(), s.to_string()), st.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_reports_changes() {
+        let old = states(&[("h1", "nginx", "active"), ("h1", "", "reachable"), ("h2", "redis", "active")]);
+        let new = states(&[("h1", "nginx", "failed"), ("h1", "", "reachable"), ("h2", "redis", "active")]);
+        assert_eq!(
+            diff(&old, &new, |_, _| Severity::Info),
+            vec![Transition {
+                host: "h1".to_string(),
+                service: "nginx".to_string(),
+                old: "active".to_string(),
+                new: "failed".to_string(),
+                severity: Severity::Info,
+            }]
+        );
+    }
+
+    fn transition(old: &str, new: &str) -> Transition {
+        Transition {
+            host: "h1".to_string(),
+            service: "nginx".to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+            severity: Severity::Warning,
+        }
+    }
+
+    #[test]
+    fn test_repeat_alerts_suppressed_until_recovery() {
+        let mut n = Notifier::default();
+        assert!(n.should_send(&transition("active", "failed")));
+        // Flapping between problem states doesn't re-alert
+        assert!(!n.should_send(&transition("failed", "inactive")));
+        assert!(!n.should_send(&transition("inactive", "failed")));
+        assert!(n.should_send(&transition("failed", "active")));
+        assert!(n.should_send(&transition("active", "failed")));
+    }
+
+    #[test]
+    fn test_recovery_without_alert_not_sent() {
+        let mut n = Notifier::default();
+        assert!(!n.should_send(&transition("inactive", "active")));
+        assert!(!n.should_send(&transition("active", "degraded")));
+    }
+
+    #[test]
+    fn test_recovery_subject() {
+        assert_eq!(
+            transition("failed", "active").subject(),
+            "[sctl] [warning] RECOVERED nginx on h1: failed -> active"
+        );
+    }
+
+    #[test]
+    fn test_diff_host_drop_is_critical() {
+        let old = states(&[("h1", "", "reachable")]);
+        let new = states(&[("h1", "", "unreachable")]);
+        assert_eq!(diff(&old, &new, |_, _| Severity::Info)[0].severity, Severity::Critical);
+    }
+
+    #[test]
+    fn test_diff_ignores_new_cells_and_unknown() {
+        let old = states(&[("h1", "nginx", "unknown")]);
+        let new = states(&[("h1", "nginx", "active"), ("h1", "sshd", "failed")]);
+        assert!(diff(&old, &new, |_, _| Severity::Warning).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::config::notifications::MqttConfig;
+use crate::scratch::TempDir;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// `<prefix>/<host>/<service>/state`, or `<prefix>/<host>/state` for host
+/// reachability. Characters MQTT treats specially become `_`.
+pub fn topic(prefix: &str, host: &str, service: &str) -> String {
+    let clean = |s: &str| s.replace(['/', '+', '#'], "_");
+    if service.is_empty() {
+        format!("{}/{}/state", prefix, clean(host))
+    } else {
+        format!("{}/{}/{}/state", prefix, clean(host), clean(service))
+    }
+}
+
+/// mosquitto_pub arguments for one retained QoS 1 message.
+fn args(config: &MqttConfig, topic: &str, payload: &str) -> Vec<String> {
+    let mut args = vec![
+        "-h".to_string(),
+        config.server.clone(),
+        "-p".to_string(),
+        config.port.to_string(),
+        "-q".to_string(),
+        "1".to_string(),
+        "-r".to_string(),
+        "-t".to_string(),
+        topic.to_string(),
+        "-m".to_string(),
+        payload.to_string(),
+    ];
+    if let Some(user) = &config.username {
+        args.push("-u".to_string());
+        args.push(user.clone());
+    }
+    args
+}
+
+/// The broker password, if a login is configured.
+fn password(config: &MqttConfig) -> Option<String> {
+    config.username.as_ref()?;
+    config.password_env.as_ref().and_then(|var| std::env::var(var).ok())
+}
+
+/// A private directory to use as `XDG_CONFIG_HOME`, holding the options
+/// file mosquitto_pub reads from there, so the password stays off its
+/// command line where `ps` would show it.
+fn options_dir(password: &str) -> anyhow::Result<TempDir> {
+    let dir = TempDir::create("mqtt")?;
+    dir.write("mosquitto_pub", format!("-P {}\n", password).as_bytes())?;
+    Ok(dir)
+}
+
+/// Publish `state` as the retained message for the cell with `mosquitto_pub`
+/// in the background.
+pub fn publish(config: &MqttConfig, host: &str, service: &str, state: &str) {
+    let topic = topic(&config.topic_prefix, host, service);
+    let mut command = Command::new("mosquitto_pub");
+    command
+        .args(args(config, &topic, state))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let options = match password(config).map(|p| options_dir(&p)).transpose() {
+        Ok(options) => options,
+        Err(e) => {
+            log::error!("Failed to write the mosquitto_pub options for {}: {:#}", topic, e);
+            return;
+        }
+    };
+    if let Some(options) = &options {
+        command.env("XDG_CONFIG_HOME", options.path());
+    }
+    let state = state.to_string();
+    tokio::spawn(async move {
+        // Removed once mosquitto_pub is done with it
+        let _options = options;
+        match command.output().await {
+            Ok(out) if out.status.success() => log::debug!("Published {} to {}", state, topic),
+            Ok(out) => log::warn!(
+                "mosquitto_pub for {} exited with {}: {}",
+                topic,
+                out.status,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+            Err(e) => log::error!("Failed to run mosquitto_pub for {}: {}", topic, e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic() {
+        assert_eq!(topic("sctl", "web01", "nginx"), "sctl/web01/nginx/state");
+        assert_eq!(topic("sctl", "db01:2222", ""), "sctl/db01:2222/state");
+        assert_eq!(topic("lab/sctl", "web01", "a/b+#"), "lab/sctl/web01/a_b__/state");
+    }
+
+    #[test]
+    fn test_args() {
+        let config = MqttConfig {
+            server: "broker.lan".to_string(),
+            port: 1883,
+            topic_prefix: "sctl".to_string(),
+            username: None,
+            password_env: None,
+        };
+        assert_eq!(
+            args(&config, "sctl/web01/nginx/state", "failed").join(" "),
+            "-h broker.lan -p 1883 -q 1 -r -t sctl/web01/nginx/state -m failed"
+        );
+    }
+
+    #[test]
+    fn test_password_not_in_args() {
+        std::env::set_var("SCTL_TEST_MQTT_PASSWORD", "hunter2");
+        let mut config = MqttConfig {
+            server: "broker.lan".to_string(),
+            port: 1883,
+            topic_prefix: "sctl".to_string(),
+            username: None,
+            password_env: Some("SCTL_TEST_MQTT_PASSWORD".to_string()),
+        };
+        assert_eq!(password(&config), None);
+        config.username = Some("sctl".to_string());
+        assert_eq!(password(&config).as_deref(), Some("hunter2"));
+        let args = args(&config, "t", "active").join(" ");
+        assert!(args.ends_with("-u sctl"));
+        assert!(!args.contains("hunter2"));
+    }
+}
@@ -0,0 +1,197 @@
+use super::Transition;
+use crate::config::notifications::WebhookConfig;
+use crate::config::Severity;
+use crate::scratch;
+use serde_json::{json, Value};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Attachment colors shared by Slack and Mattermost: alerts by severity,
+/// anything else by the new status.
+fn color(t: &Transition) -> &'static str {
+    if t.is_alert() {
+        return match t.severity {
+            Severity::Critical => "#d00000",
+            Severity::Warning => "#ffa500",
+            Severity::Info => "#439fe0",
+        };
+    }
+    match t.new.as_str() {
+        "active" | "reachable" => "#2eb886",
+        _ => "#888888",
+    }
+}
+
+fn link(config: &WebhookConfig, t: &Transition) -> Option<String> {
+    config
+        .link
+        .as_ref()
+        .map(|l| l.replace("{host}", &t.host).replace("{service}", &t.service))
+}
+
+/// Plain JSON with the transition fields, for custom receivers.
+pub fn generic_payload(t: &Transition) -> Value {
+    json!({
+        "host": t.host,
+        "service": t.service,
+        "old": t.old,
+        "new": t.new,
+        "severity": t.severity.name(),
+        "summary": t.subject(),
+    })
+}
+
+/// Slack-style message: a colored attachment with host/service/status fields.
+pub fn slack_payload(config: &WebhookConfig, t: &Transition) -> Value {
+    let title = if t.service.is_empty() {
+        t.host.clone()
+    } else {
+        format!("{} on {}", t.service, t.host)
+    };
+    let mut fields = vec![json!({"title": "Host", "value": t.host, "short": true})];
+    if !t.service.is_empty() {
+        fields.push(json!({"title": "Service", "value": t.service, "short": true}));
+    }
+    fields.push(json!({"title": "Status", "value": format!("{} → {}", t.old, t.new), "short": true}));
+    fields.push(json!({"title": "Severity", "value": t.severity.name(), "short": true}));
+
+    let mut attachment = json!({
+        "fallback": t.subject(),
+        "color": color(t),
+        "title": title,
+        "fields": fields,
+    });
+    if let Some(link) = link(config, t) {
+        attachment["title_link"] = Value::String(link);
+    }
+    json!({
+        "text": t.subject(),
+        "attachments": [attachment],
+    })
+}
+
+/// A curl config file naming the URL. Incoming-webhook URLs carry their
+/// token, so they are kept out of the command line.
+fn url_config(url: &str) -> String {
+    format!("url = \"{}\"\n", url.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn curl_args(config_path: &str) -> Vec<String> {
+    [
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--max-time",
+        "10",
+        "-H",
+        "Content-Type: application/json",
+        "--data-binary",
+        "@-",
+        "--config",
+        config_path,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// POST a JSON payload in the background via curl, body on stdin.
+pub fn send(url: &str, payload: Value) {
+    let url = url.to_string();
+    tokio::spawn(async move {
+        let file = match scratch::TempFile::create("webhook", ".conf", url_config(&url).as_bytes()) {
+            Ok(file) => file,
+            Err(e) => {
+                log::error!("Failed to write webhook config: {:#}", e);
+                return;
+            }
+        };
+        let path = file.path();
+
+        let result = async {
+            let mut child = Command::new("curl")
+                .args(curl_args(&path.to_string_lossy()))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(payload.to_string().as_bytes()).await?;
+            }
+            child.wait_with_output().await
+        }
+        .await;
+
+        match result {
+            Ok(out) if out.status.success() => log::info!("Posted webhook notification"),
+            Ok(out) => log::error!(
+                "Webhook notification failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+            Err(e) => log::error!("Failed to run curl for webhook: {}", e),
+        }
+        drop(file);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(service: &str, new: &str) -> Transition {
+        Transition {
+            host: "web01".to_string(),
+            service: service.to_string(),
+            old: "active".to_string(),
+            new: new.to_string(),
+            severity: Severity::Critical,
+        }
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let config = WebhookConfig {
+            url: "https://hooks.example.com".to_string(),
+            link: Some("https://grafana/d?host={host}&svc={service}".to_string()),
+        };
+        let p = slack_payload(&config, &transition("nginx", "failed"));
+        let a = &p["attachments"][0];
+        assert_eq!(a["color"], "#d00000");
+        assert_eq!(a["title"], "nginx on web01");
+        assert_eq!(a["title_link"], "https://grafana/d?host=web01&svc=nginx");
+        assert_eq!(a["fields"].as_array().unwrap().len(), 4);
+        assert_eq!(a["fields"][2]["value"], "active → failed");
+    }
+
+    #[test]
+    fn test_slack_payload_host_level() {
+        let config = WebhookConfig {
+            url: "https://hooks.example.com".to_string(),
+            link: None,
+        };
+        let p = slack_payload(&config, &transition("", "unreachable"));
+        let a = &p["attachments"][0];
+        assert_eq!(a["title"], "web01");
+        assert!(a.get("title_link").is_none());
+        assert_eq!(a["fields"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_generic_payload() {
+        let p = generic_payload(&transition("nginx", "failed"));
+        assert_eq!(p["service"], "nginx");
+        assert_eq!(p["new"], "failed");
+        assert_eq!(p["severity"], "critical");
+    }
+
+    #[test]
+    fn test_url_not_in_args() {
+        let url = "https://hooks.slack.com/services/T0/B0/secret";
+        let args = curl_args("/tmp/webhook.conf").join(" ");
+        assert!(!args.contains("secret"));
+        assert!(args.ends_with("--config /tmp/webhook.conf"));
+        assert_eq!(url_config(url), format!("url = \"{}\"\n", url));
+        assert_eq!(url_config("http://h/a\"b\\"), "url = \"http://h/a\\\"b\\\\\"\n");
+    }
+}
@@ -0,0 +1,83 @@
+//! Host picker (`o` on the main screen): fuzzy search over the whole
+//! inventory to open a shell on a host without finding it in the grid first.
+
+use crate::config::Host;
+
+/// Score of `query` as a case-insensitive subsequence of `candidate`, higher
+/// is better, or `None` if it doesn't match. Consecutive characters and
+/// characters starting a word (after `.`, `-`, `_` or a space) score extra,
+/// so `web1` ranks `web1.example.com` above `www.example-b1.com`.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut total = 0;
+    let mut pos = 0;
+    let mut prev: Option<usize> = None;
+    for q in query.to_lowercase().chars() {
+        let found = (pos..candidate.len()).find(|&i| candidate[i] == q)?;
+        total += 1;
+        if prev.is_some_and(|p| p + 1 == found) {
+            total += 5;
+        }
+        if found == 0 || matches!(candidate[found - 1], '.' | '-' | '_' | ' ') {
+            total += 3;
+        }
+        prev = Some(found);
+        pos = found + 1;
+    }
+    // Among equal matches, the shorter name is the closer one
+    Some(total * 100 - candidate.len() as i32)
+}
+
+#[derive(Debug, Default)]
+pub struct HostPicker {
+    pub query: String,
+    /// Position within the current matches.
+    pub cursor: usize,
+}
+
+impl HostPicker {
+    /// Inventory indices of the hosts matching the query, best first. The
+    /// group is searched too, after the address.
+    pub fn matches(&self, hosts: &[Host]) -> Vec<usize> {
+        let mut scored: Vec<(i32, usize)> = hosts
+            .iter()
+            .enumerate()
+            .filter_map(|(i, h)| score(&self.query, &format!("{} {}", h.address, h.group)).map(|s| (s, i)))
+            .collect();
+        scored.sort_by_key(|&(s, i)| (std::cmp::Reverse(s), i));
+        scored.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(address: &str, group: &str) -> Host {
+        Host {
+            address: address.to_string(),
+            group: group.to_string(),
+            parents: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score("xyz", "web01"), None);
+        assert!(score("", "web01").is_some());
+        assert!(score("WEB", "web01").is_some());
+        assert!(score("web1", "web1.example.com") > score("web1", "www.example-b1.com"));
+        assert!(score("db", "db01") > score("db", "db01.long.example.com"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let hosts = vec![host("web01", "webservers"), host("db01", "databases"), host("web02", "webservers")];
+        let mut picker = HostPicker::default();
+        assert_eq!(picker.matches(&hosts), vec![1, 0, 2]);
+        picker.query = "w2".to_string();
+        assert_eq!(picker.matches(&hosts), vec![2]);
+        picker.query = "databases".to_string();
+        assert_eq!(picker.matches(&hosts), vec![1]);
+    }
+}
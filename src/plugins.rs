@@ -0,0 +1,325 @@
+//! External plugins: executables in the plugins directory that contribute
+//! check types and actions without being compiled in. Each call runs the
+//! executable on the controller with one JSON request on stdin and reads
+//! one JSON reply from stdout:
+//!
+//! - `{"request":"describe"}` → `{"checks":["consul"],"actions":[{"name":"drain","label":"Drain from the load balancer"}]}`
+//! - `{"request":"check","type":"consul","host":..,"service":..,"options":{..}}` → `{"status":"active","output":"..","note":".."}`
+//! - `{"request":"action","action":"drain","host":..,"service":..}` → `{"ok":true,"message":".."}`
+//!
+//! A non-zero exit is an error, with stderr as the message.
+
+use crate::config::ServiceConfig;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Where plugins are looked for without `plugins_dir:`.
+pub const DEFAULT_DIR: &str = "~/.config/system-controller/plugins";
+
+/// Describe and check calls; a plugin slower than this is treated as broken.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Actions may drain or restart things, so they get longer.
+const ACTION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Action strings naming a plugin action: `plugin:<plugin>/<action>`.
+const ACTION_PREFIX: &str = "plugin:";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PluginAction {
+    pub name: String,
+    /// Shown in the action chooser; the name if the plugin gives none.
+    #[serde(default)]
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct Description {
+    #[serde(default)]
+    checks: Vec<String>,
+    #[serde(default)]
+    actions: Vec<PluginAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plugin {
+    /// File name of the executable.
+    pub name: String,
+    pub path: PathBuf,
+    /// Check `type`s it evaluates.
+    pub checks: Vec<String>,
+    pub actions: Vec<PluginAction>,
+}
+
+/// A plugin's answer to a check.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CheckReply {
+    /// A status word, as in `exit_codes:`.
+    pub status: String,
+    #[serde(default)]
+    pub output: String,
+    /// Shown in the cell, like a certificate's days left.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActionReply {
+    ok: bool,
+    #[serde(default)]
+    message: String,
+}
+
+static PLUGINS: OnceLock<Vec<Plugin>> = OnceLock::new();
+
+/// Discover the plugins in `dir` (or [`DEFAULT_DIR`], which may be missing)
+/// and make them available to checks and actions.
+pub async fn load(dir: Option<&str>) -> Result<()> {
+    let path = expand_home(dir.unwrap_or(DEFAULT_DIR));
+    let plugins = if path.is_dir() {
+        discover(&path).await?
+    } else if dir.is_some() {
+        anyhow::bail!("plugins_dir {} is not a directory", path.display());
+    } else {
+        Vec::new()
+    };
+    for plugin in &plugins {
+        log::info!(
+            "Plugin {}: checks [{}], actions [{}]",
+            plugin.name,
+            plugin.checks.join(", "),
+            plugin.actions.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", ")
+        );
+    }
+    let _ = PLUGINS.set(plugins);
+    Ok(())
+}
+
+pub fn plugins() -> &'static [Plugin] {
+    PLUGINS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Fail when a service's check has a type that no loaded plugin provides,
+/// rather than showing it as an error in every cell.
+pub fn validate(services: &[ServiceConfig]) -> Result<()> {
+    for service in services {
+        let Some(check) = service.check.as_ref().and_then(|c| c.plugin.as_ref()) else {
+            continue;
+        };
+        if for_check(&check.kind).is_none() {
+            anyhow::bail!(
+                "Unknown check type for {}: {} (not built in, and no plugin provides it)",
+                service.name_pattern,
+                check.kind
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The plugin providing check `kind`.
+pub fn for_check(kind: &str) -> Option<&'static Plugin> {
+    plugins().iter().find(|p| p.checks.iter().any(|c| c == kind))
+}
+
+/// Every plugin action, as `(plugin, action)`.
+pub fn actions() -> Vec<(&'static Plugin, &'static PluginAction)> {
+    plugins().iter().flat_map(|p| p.actions.iter().map(move |a| (p, a))).collect()
+}
+
+/// The action string run through the usual action path (audit, confirm,
+/// refresh) for a plugin action.
+pub fn action_id(plugin: &str, action: &str) -> String {
+    format!("{}{}/{}", ACTION_PREFIX, plugin, action)
+}
+
+/// `(plugin, action)` of an [`action_id`], `None` for systemctl actions.
+pub fn parse_action(action: &str) -> Option<(&str, &str)> {
+    action.strip_prefix(ACTION_PREFIX)?.split_once('/')
+}
+
+/// Executables in `dir`, in name order, that answer `describe`. Ones that
+/// don't are logged and left out rather than stopping startup.
+async fn discover(dir: &Path) -> Result<Vec<Plugin>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read plugins_dir {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+        .collect();
+    paths.sort();
+    let mut plugins = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let description = call(&path, &json!({"request": "describe"}), CALL_TIMEOUT)
+            .await
+            .and_then(|reply| serde_json::from_value::<Description>(reply).context("Invalid describe reply"));
+        match description {
+            Ok(mut description) => {
+                for action in &mut description.actions {
+                    if action.label.is_empty() {
+                        action.label = action.name.clone();
+                    }
+                }
+                plugins.push(Plugin {
+                    name,
+                    path,
+                    checks: description.checks,
+                    actions: description.actions,
+                });
+            }
+            Err(e) => log::warn!("Ignoring plugin {}: {:#}", path.display(), e),
+        }
+    }
+    Ok(plugins)
+}
+
+/// Evaluate a plugin check of a concrete service.
+pub async fn check(plugin: &Plugin, kind: &str, host: &str, service: &str, options: &Value) -> Result<CheckReply> {
+    let request = json!({
+        "request": "check",
+        "type": kind,
+        "host": host,
+        "service": service,
+        "options": options,
+    });
+    let reply = call(&plugin.path, &request, CALL_TIMEOUT).await?;
+    serde_json::from_value(reply).with_context(|| format!("Invalid check reply from plugin {}", plugin.name))
+}
+
+/// Run a plugin action; the plugin's message if it reports success.
+pub async fn run_action(plugin: &str, action: &str, host: &str, service: &str) -> Result<String> {
+    let plugin = plugins()
+        .iter()
+        .find(|p| p.name == plugin)
+        .with_context(|| format!("No plugin named {}", plugin))?;
+    let request = json!({
+        "request": "action",
+        "action": action,
+        "host": host,
+        "service": service,
+    });
+    let reply: ActionReply = serde_json::from_value(call(&plugin.path, &request, ACTION_TIMEOUT).await?)
+        .with_context(|| format!("Invalid action reply from plugin {}", plugin.name))?;
+    if !reply.ok {
+        anyhow::bail!("{}", if reply.message.is_empty() { "plugin reported failure" } else { &reply.message });
+    }
+    Ok(reply.message)
+}
+
+/// Run the executable with `request` on stdin and parse its stdout.
+pub async fn call(path: &Path, request: &Value, timeout: Duration) -> Result<Value> {
+    let run = async {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run plugin {}", path.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(format!("{}\n", request).as_bytes()).await?;
+        }
+        Ok::<_, anyhow::Error>(child.wait_with_output().await?)
+    };
+    let output = tokio::time::timeout(timeout, run)
+        .await
+        .with_context(|| format!("Plugin {} timed out after {}s", path.display(), timeout.as_secs()))??;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Plugin {} failed ({}): {}", path.display(), output.status, stderr.trim());
+    }
+    serde_json::from_slice(&output.stdout).with_context(|| format!("Plugin {} did not reply with JSON", path.display()))
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A plugin answering each request kind, plus a broken one.
+    struct PluginDir(PathBuf);
+
+    impl PluginDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("sc_plugins_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let script = r#"#!/bin/sh
+read -r req
+case "$req" in
+  *describe*) echo '{"checks":["consul"],"actions":[{"name":"drain","label":"Drain"},{"name":"undrain"}]}' ;;
+  *'"check"'*)
+    case "$req" in
+      *'"host":"web01"'*) echo '{"status":"active","output":"passing"}' ;;
+      *) echo '{"status":"failed","output":"critical","note":"2 checks"}' ;;
+    esac ;;
+  *'"action"'*) echo '{"ok":false,"message":"not in the pool"}' ;;
+esac
+"#;
+            // Written by a child so no write descriptor of ours leaks into a
+            // fork by another test thread, which would make exec fail with ETXTBSY
+            for (name, content, mode) in [
+                ("lb", script, "755"),
+                ("broken", "#!/bin/sh\necho nope\n", "755"),
+                ("README", "not a plugin", "644"),
+            ] {
+                let status = std::process::Command::new("sh")
+                    .args(["-c", "printf '%s' \"$1\" > \"$0\" && chmod \"$2\" \"$0\""])
+                    .arg(dir.join(name))
+                    .args([content, mode])
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+            }
+            PluginDir(dir)
+        }
+    }
+
+    impl Drop for PluginDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protocol() {
+        let dir = PluginDir::new();
+        let plugins = discover(&dir.0).await.unwrap();
+        assert_eq!(plugins.len(), 1);
+        let lb = &plugins[0];
+        assert_eq!(lb.name, "lb");
+        assert_eq!(lb.checks, vec!["consul"]);
+        assert_eq!(lb.actions[1].label, "undrain");
+
+        let options = json!({"service": "web"});
+        let ok = check(lb, "consul", "web01", "nginx", &options).await.unwrap();
+        assert_eq!((ok.status.as_str(), ok.output.as_str()), ("active", "passing"));
+        let failed = check(lb, "consul", "web02", "nginx", &options).await.unwrap();
+        assert_eq!(failed.status, "failed");
+        assert_eq!(failed.note.as_deref(), Some("2 checks"));
+
+        let error = call(&dir.0.join("broken"), &json!({}), CALL_TIMEOUT).await.unwrap_err();
+        assert!(error.to_string().contains("did not reply with JSON"));
+    }
+
+    #[test]
+    fn test_action_id() {
+        let id = action_id("lb", "drain");
+        assert_eq!(id, "plugin:lb/drain");
+        assert_eq!(parse_action(&id), Some(("lb", "drain")));
+        assert_eq!(parse_action("restart"), None);
+        assert_eq!(parse_action("kill --signal=SIGTERM"), None);
+    }
+}
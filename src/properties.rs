@@ -0,0 +1,219 @@
+//! Property inspector (`p` on the detail screen): the unit's `systemctl show`
+//! output as a searchable list, with the properties that usually matter
+//! first, instead of opening a shell to read it.
+
+/// Properties shown by default, in this order, when the unit has them.
+pub const KEY_PROPERTIES: [&str; 16] = [
+    "Description",
+    "FragmentPath",
+    "DropInPaths",
+    "User",
+    "Group",
+    "WorkingDirectory",
+    "ExecStartPre",
+    "ExecStart",
+    "ExecReload",
+    "Restart",
+    "RestartUSec",
+    "MemoryMax",
+    "MemoryLimit",
+    "Wants",
+    "Requires",
+    "After",
+];
+
+/// Properties holding a space-separated list of units or paths.
+const LIST_PROPERTIES: [&str; 6] = ["DropInPaths", "Wants", "Requires", "After", "Before", "WantedBy"];
+
+/// `Name=value` lines of `systemctl show`, in output order. Exec properties
+/// are reduced to their command line.
+pub fn parse(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.to_string(), display_value(name, value)))
+        .collect()
+}
+
+/// `ExecStart={ path=/usr/sbin/nginx ; argv[]=/usr/sbin/nginx -g daemon on; ; ... }`
+/// becomes `/usr/sbin/nginx -g daemon on;`, one command per line of `;`s.
+fn display_value(name: &str, value: &str) -> String {
+    if !name.starts_with("Exec") {
+        return value.to_string();
+    }
+    let commands: Vec<&str> = value
+        .split("argv[]=")
+        .skip(1)
+        .map(|rest| rest.split(" ; ").next().unwrap_or(rest).trim())
+        .collect();
+    if commands.is_empty() {
+        value.to_string()
+    } else {
+        commands.join(" ; ")
+    }
+}
+
+/// The value as popup lines: list properties one entry per line.
+pub fn value_lines(name: &str, value: &str) -> Vec<String> {
+    if LIST_PROPERTIES.contains(&name) {
+        value.split_whitespace().map(String::from).collect()
+    } else {
+        vec![value.to_string()]
+    }
+}
+
+/// The `systemctl show` properties naming the files systemd reads for a unit.
+pub const FILE_PROPERTIES: &str = "FragmentPath,DropInPaths,EnvironmentFiles";
+
+/// A file systemd reads for a unit, listed in the detail view next to the
+/// configured ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitFile {
+    pub path: String,
+    /// `unit file`, `drop-in` or `environment`.
+    pub kind: &'static str,
+}
+
+/// The files in `systemctl show --property=`[`FILE_PROPERTIES`] output.
+/// `EnvironmentFiles` has one line per file, with a note after the path.
+pub fn unit_files(output: &str) -> Vec<UnitFile> {
+    let mut files = Vec::new();
+    for (name, value) in parse(output) {
+        let (kind, paths): (&str, Vec<&str>) = match name.as_str() {
+            "FragmentPath" => ("unit file", vec![value.as_str()]),
+            "DropInPaths" => ("drop-in", value.split_whitespace().collect()),
+            "EnvironmentFiles" => ("environment", value.split_whitespace().take(1).collect()),
+            _ => continue,
+        };
+        for path in paths.into_iter().filter(|p| !p.is_empty()) {
+            files.push(UnitFile {
+                path: path.to_string(),
+                kind,
+            });
+        }
+    }
+    files
+}
+
+/// The inspector for one cell, filled in when `systemctl show` returns.
+pub struct PropertyView {
+    pub id: u64,
+    pub title: String,
+    /// `None` while loading.
+    pub properties: Option<Result<Vec<(String, String)>, String>>,
+    pub query: String,
+    /// Keys go to the query rather than to navigation.
+    pub searching: bool,
+    /// Every property rather than the key ones.
+    pub show_all: bool,
+    pub cursor: usize,
+}
+
+impl PropertyView {
+    pub fn new(id: u64, title: String) -> Self {
+        PropertyView {
+            id,
+            title,
+            properties: None,
+            query: String::new(),
+            searching: false,
+            show_all: false,
+            cursor: 0,
+        }
+    }
+
+    /// The rows to show: properties whose name or value contains the query
+    /// (case-insensitive) among all of them, or with no query the key
+    /// properties that are set, or all with `show_all`.
+    pub fn visible(&self) -> Vec<&(String, String)> {
+        let Some(Ok(properties)) = &self.properties else {
+            return Vec::new();
+        };
+        if !self.query.is_empty() {
+            let query = self.query.to_lowercase();
+            return properties
+                .iter()
+                .filter(|(n, v)| n.to_lowercase().contains(&query) || v.to_lowercase().contains(&query))
+                .collect();
+        }
+        if self.show_all {
+            return properties.iter().collect();
+        }
+        KEY_PROPERTIES
+            .iter()
+            .filter_map(|key| properties.iter().find(|(n, v)| n == key && !v.is_empty()))
+            .collect()
+    }
+
+    pub fn clamp_cursor(&mut self) {
+        self.cursor = self.cursor.min(self.visible().len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOW: &str = "\
+Type=notify
+Restart=on-failure
+ExecStart={ path=/usr/sbin/nginx ; argv[]=/usr/sbin/nginx -g daemon on; master_process on; ; ignore_errors=no ; start_time=[n/a] }
+User=
+After=network-online.target sysinit.target
+Description=A high performance web server
+";
+
+    #[test]
+    fn test_parse() {
+        let properties = parse(SHOW);
+        assert_eq!(properties.len(), 6);
+        assert_eq!(
+            properties[2],
+            ("ExecStart".to_string(), "/usr/sbin/nginx -g daemon on; master_process on;".to_string())
+        );
+        assert_eq!(display_value("ExecReload", ""), "");
+        assert_eq!(
+            value_lines("After", "network-online.target sysinit.target"),
+            vec!["network-online.target", "sysinit.target"]
+        );
+    }
+
+    #[test]
+    fn test_unit_files() {
+        let output = "\
+FragmentPath=/lib/systemd/system/nginx.service
+DropInPaths=/etc/systemd/system/nginx.service.d/limits.conf /run/systemd/system/nginx.service.d/50-x.conf
+EnvironmentFiles=/etc/default/nginx (ignore_errors=yes)
+EnvironmentFiles=/etc/nginx/env (ignore_errors=no)
+";
+        let files = unit_files(output);
+        let paths: Vec<(&str, &str)> = files.iter().map(|f| (f.path.as_str(), f.kind)).collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/lib/systemd/system/nginx.service", "unit file"),
+                ("/etc/systemd/system/nginx.service.d/limits.conf", "drop-in"),
+                ("/run/systemd/system/nginx.service.d/50-x.conf", "drop-in"),
+                ("/etc/default/nginx", "environment"),
+                ("/etc/nginx/env", "environment"),
+            ]
+        );
+        assert!(unit_files("FragmentPath=\nDropInPaths=\n").is_empty());
+    }
+
+    #[test]
+    fn test_visible() {
+        let mut view = PropertyView::new(1, String::new());
+        assert!(view.visible().is_empty());
+        view.properties = Some(Ok(parse(SHOW)));
+        let names = |view: &PropertyView| view.visible().iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
+        // Key properties in their order, empty ones left out
+        assert_eq!(names(&view), vec!["Description", "ExecStart", "Restart", "After"]);
+        view.show_all = true;
+        assert_eq!(names(&view).len(), 6);
+        view.query = "NETWORK".to_string();
+        assert_eq!(names(&view), vec!["After"]);
+        view.query = "type".to_string();
+        assert_eq!(names(&view), vec!["Type"]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Markdown incident report (`p` on the main screen) for pasting into a
+//! postmortem: the current grid, status history, actions taken and notes.
+
+use crate::annotations::Note;
+use crate::audit::AuditEntry;
+use crate::clock;
+use crate::timeline::{Kind, Timeline};
+
+/// Status history entries included, newest last.
+const MAX_HISTORY: usize = 200;
+
+/// One row of the grid as it stands.
+pub struct CellRow {
+    pub host: String,
+    pub service: String,
+    pub status: String,
+    pub silenced: bool,
+}
+
+pub struct Report<'a> {
+    pub now: u64,
+    pub user: &'a str,
+    pub cells: &'a [CellRow],
+    /// Unreachable hosts with the reason.
+    pub unreachable: &'a [(String, String)],
+    pub timeline: &'a Timeline,
+    pub audit: &'a [AuditEntry],
+    pub notes: &'a [Note],
+}
+
+/// Keep table cells on one line and from closing the cell early.
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+impl Report<'_> {
+    pub fn file_name(&self) -> String {
+        clock::format_time(self.now, 0, "sctl-report-%Y%m%d-%H%M%S.md")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!(
+            "# Incident report\n\nGenerated {} by {}.\n\n",
+            clock::format_iso(self.now),
+            self.user
+        );
+
+        let problems: Vec<&CellRow> = self.cells.iter().filter(|c| c.status != "active").collect();
+        md.push_str("## Summary\n\n");
+        md.push_str(&format!(
+            "- {} cells, {} not active\n- {} unreachable hosts\n- {} actions taken this session\n\n",
+            self.cells.len(),
+            problems.len(),
+            self.unreachable.len(),
+            self.audit.len()
+        ));
+
+        if !self.unreachable.is_empty() {
+            md.push_str("## Unreachable hosts\n\n| Host | Reason |\n|---|---|\n");
+            for (host, reason) in self.unreachable {
+                md.push_str(&format!("| {} | {} |\n", cell(host), cell(reason)));
+            }
+            md.push('\n');
+        }
+
+        md.push_str("## Current state\n\n| Host | Service | Status |\n|---|---|---|\n");
+        // Problems first, since they are what the report is about
+        let healthy = self.cells.iter().filter(|c| c.status == "active");
+        for c in problems.into_iter().chain(healthy) {
+            let status = if c.silenced {
+                format!("{} (silenced)", c.status)
+            } else {
+                c.status.clone()
+            };
+            md.push_str(&format!("| {} | {} | {} |\n", cell(&c.host), cell(&c.service), status));
+        }
+        md.push('\n');
+
+        let history: Vec<_> = self
+            .timeline
+            .entries
+            .iter()
+            .filter(|e| matches!(e.kind, Kind::Transition | Kind::Notification))
+            .collect();
+        md.push_str("## Status history\n\n");
+        if history.is_empty() {
+            md.push_str("No status changes this session.\n\n");
+        } else {
+            md.push_str("```\n");
+            for entry in &history[history.len().saturating_sub(MAX_HISTORY)..] {
+                md.push_str(&entry.line());
+                md.push('\n');
+            }
+            md.push_str("```\n\n");
+        }
+
+        md.push_str("## Actions taken\n\n");
+        if self.audit.is_empty() {
+            md.push_str("None.\n\n");
+        } else {
+            md.push_str("| Time | User | Host | Service | Action | Reason |\n|---|---|---|---|---|---|\n");
+            for e in self.audit {
+                md.push_str(&format!(
+                    "| {} | {} | {} | {} | {} | {} |\n",
+                    clock::format_iso(e.at),
+                    cell(&e.user),
+                    cell(&e.host),
+                    cell(&e.service),
+                    cell(&e.action),
+                    cell(e.reason.as_deref().unwrap_or(""))
+                ));
+            }
+            md.push('\n');
+        }
+
+        md.push_str("## Notes\n\n");
+        if self.notes.is_empty() {
+            md.push_str("None.\n");
+        }
+        for note in self.notes {
+            let target = if note.service.is_empty() {
+                note.host.clone()
+            } else {
+                format!("{} on {}", note.service, note.host)
+            };
+            md.push_str(&format!(
+                "- **{}** ({}, {}): {}\n",
+                target,
+                note.by,
+                clock::format_iso(note.at),
+                note.text.replace('\n', "\n  ")
+            ));
+        }
+        md
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(host: &str, service: &str, status: &str, silenced: bool) -> CellRow {
+        CellRow {
+            host: host.to_string(),
+            service: service.to_string(),
+            status: status.to_string(),
+            silenced,
+        }
+    }
+
+    #[test]
+    fn test_report_markdown() {
+        let cells = vec![row("web01", "nginx", "active", false), row("web02", "nginx", "failed", true)];
+        let unreachable = vec![("db01".to_string(), "connection refused".to_string())];
+        let mut timeline = Timeline::default();
+        timeline.push(Kind::Transition, "nginx on web02: active -> failed");
+        timeline.push(Kind::Refresh, "Full refresh");
+        let audit = vec![AuditEntry {
+            at: 0,
+            user: "alice".to_string(),
+            host: "web02".to_string(),
+            service: "nginx".to_string(),
+            action: "restart".to_string(),
+            reason: Some("a|b".to_string()),
+        }];
+        let notes = vec![Note {
+            host: "web02".to_string(),
+            service: String::new(),
+            text: "disk full".to_string(),
+            by: "bob".to_string(),
+            at: 0,
+        }];
+        let report = Report {
+            now: 0,
+            user: "alice",
+            cells: &cells,
+            unreachable: &unreachable,
+            timeline: &timeline,
+            audit: &audit,
+            notes: &notes,
+        };
+        let md = report.to_markdown();
+        assert_eq!(report.file_name(), "sctl-report-19700101-000000.md");
+        assert!(md.contains("- 2 cells, 1 not active\n- 1 unreachable hosts\n"));
+        assert!(md.contains("| db01 | connection refused |"));
+        // Failed cell listed before the healthy one
+        let failed = md.find("| web02 | nginx | failed (silenced) |").unwrap();
+        assert!(failed < md.find("| web01 | nginx | active |").unwrap());
+        assert!(md.contains("change   nginx on web02: active -> failed"));
+        assert!(!md.contains("Full refresh"));
+        assert!(md.contains("| restart | a\\|b |"));
+        assert!(md.contains("- **web02** (bob, 1970-01-01T00:00:00Z): disk full"));
+    }
+}
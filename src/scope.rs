@@ -0,0 +1,88 @@
+//! The scope (`G` on the main screen) that `r` and bulk actions are limited
+//! to, so working on one group doesn't touch or wait on the rest of the fleet.
+
+use crate::config::Host;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Scope {
+    #[default]
+    All,
+    /// Hosts whose group or address matches the glob.
+    Hosts(String),
+    /// The hosts pinned with `P`.
+    Pinned,
+}
+
+impl Scope {
+    /// Scope from the prompt input: empty or `all`, `pinned`, or a glob.
+    pub fn parse(input: &str) -> Self {
+        match input.trim() {
+            "" | "all" => Scope::All,
+            "pinned" => Scope::Pinned,
+            glob => Scope::Hosts(glob.to_string()),
+        }
+    }
+
+    /// What the prompt is prefilled with.
+    pub fn input(&self) -> String {
+        match self {
+            Scope::All => String::new(),
+            Scope::Hosts(glob) => glob.clone(),
+            Scope::Pinned => "pinned".to_string(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Scope::All => "all hosts".to_string(),
+            Scope::Hosts(glob) => glob.clone(),
+            Scope::Pinned => "pinned hosts".to_string(),
+        }
+    }
+
+    pub fn contains(&self, host: &Host, host_idx: usize, pinned: &HashSet<usize>) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Hosts(glob) => host.matches(glob),
+            Scope::Pinned => pinned.contains(&host_idx),
+        }
+    }
+
+    /// Inventory indices of the hosts in scope.
+    pub fn indices(&self, hosts: &[Host], pinned: &HashSet<usize>) -> Vec<usize> {
+        (0..hosts.len()).filter(|&i| self.contains(&hosts[i], i, pinned)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(address: &str, group: &str) -> Host {
+        Host {
+            address: address.to_string(),
+            group: group.to_string(),
+            parents: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Scope::parse(" "), Scope::All);
+        assert_eq!(Scope::parse("all"), Scope::All);
+        assert_eq!(Scope::parse("pinned"), Scope::Pinned);
+        assert_eq!(Scope::parse("web*"), Scope::Hosts("web*".to_string()));
+        assert_eq!(Scope::parse(&Scope::Pinned.input()), Scope::Pinned);
+    }
+
+    #[test]
+    fn test_indices() {
+        let hosts = vec![host("web01", "web"), host("db01", "db"), host("web02", "web")];
+        let pinned = HashSet::from([1]);
+        assert_eq!(Scope::All.indices(&hosts, &pinned), vec![0, 1, 2]);
+        assert_eq!(Scope::parse("web").indices(&hosts, &pinned), vec![0, 2]);
+        assert_eq!(Scope::parse("db0*").indices(&hosts, &pinned), vec![1]);
+        assert_eq!(Scope::Pinned.indices(&hosts, &pinned), vec![1]);
+    }
+}
@@ -0,0 +1,292 @@
+//! Private scratch files for output opened in vim, mail handed to curl and
+//! the MQTT password handed to mosquitto_pub.
+//!
+//! Files live in a per-user directory (mode 0700) under `$XDG_RUNTIME_DIR`,
+//! or the system temp dir, or `--temp-dir`. They are created 0600 with
+//! random names and removed when dropped, when the program exits or panics,
+//! and on the next start if the process that made them was killed.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+const PREFIX: &str = "sctl-";
+
+static BASE: OnceLock<PathBuf> = OnceLock::new();
+static LIVE: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Use `base` (from `--temp-dir`) instead of the runtime or temp dir.
+pub fn set_base(base: impl Into<PathBuf>) {
+    let _ = BASE.set(base.into());
+}
+
+/// The scratch directory, created on first use.
+fn dir() -> Result<PathBuf> {
+    let dir = match BASE.get() {
+        Some(base) => base.join("system-controller"),
+        None => match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime) => PathBuf::from(runtime).join("system-controller"),
+            None => std::env::temp_dir().join(format!("system-controller-{}", crate::annotations::current_user())),
+        },
+    };
+    ensure_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// A path in the private directory, for files that aren't scratch files
+/// such as the agent's socket.
+pub fn runtime_path(name: &str) -> Result<PathBuf> {
+    Ok(dir()?.join(name))
+}
+
+/// Create `dir` as 0700, or check that an existing one is a real directory
+/// owned by us that nobody else can read.
+fn ensure_private_dir(dir: &Path) -> Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let meta = fs::symlink_metadata(dir).with_context(|| format!("Failed to inspect {}", dir.display()))?;
+    let owner_ok = fs::metadata("/proc/self").map(|me| me.uid() == meta.uid()).unwrap_or(true);
+    if !meta.is_dir() || !owner_ok {
+        anyhow::bail!("Refusing to use {}: not a directory owned by the current user", dir.display());
+    }
+    if meta.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// A scratch file that is removed when dropped.
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create a new file holding `contents`, named `sctl-<label>-<pid>-<random><ext>`.
+    pub fn create(label: &str, ext: &str, contents: &[u8]) -> Result<Self> {
+        Self::create_in(&dir()?, label, ext, contents)
+    }
+
+    fn create_in(dir: &Path, label: &str, ext: &str, contents: &[u8]) -> Result<Self> {
+        let path = dir.join(scratch_name(label, ext));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        if let Ok(mut live) = LIVE.lock() {
+            live.insert(path.clone());
+        }
+        let temp = Self { path };
+        file.write_all(contents)
+            .with_context(|| format!("Failed to write {}", temp.path.display()))?;
+        Ok(temp)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        // Never panic here: a panic in drop while unwinding aborts
+        if let Ok(mut live) = LIVE.lock() {
+            live.remove(&self.path);
+        }
+    }
+}
+
+/// A scratch directory, for programs that look for a file by a fixed name.
+/// It is removed with its contents when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a new empty 0700 directory, named like a [`TempFile`].
+    pub fn create(label: &str) -> Result<Self> {
+        Self::create_in(&dir()?, label)
+    }
+
+    fn create_in(dir: &Path, label: &str) -> Result<Self> {
+        let path = dir.join(scratch_name(label, ""));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        if let Ok(mut live) = LIVE.lock() {
+            live.insert(path.clone());
+        }
+        Ok(Self { path })
+    }
+
+    /// Create the 0600 file `name` in the directory, holding `contents`.
+    pub fn write(&self, name: &str, contents: &[u8]) -> Result<()> {
+        let path = self.path.join(name);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        remove(&self.path);
+        if let Ok(mut live) = LIVE.lock() {
+            live.remove(&self.path);
+        }
+    }
+}
+
+/// `sctl-<label>-<pid>-<random><ext>`, with anything odd in `label` as `_`.
+fn scratch_name(label: &str, ext: &str) -> String {
+    let label: String = label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    format!(
+        "{}{}-{}-{:016x}{}",
+        PREFIX,
+        label,
+        std::process::id(),
+        crate::trace::random_u64(),
+        ext
+    )
+}
+
+/// Remove a scratch file, or a scratch directory with its contents.
+fn remove(path: &Path) {
+    if path.is_dir() {
+        let _ = fs::remove_dir_all(path);
+    } else {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Remove every scratch file still alive; for exit paths that skip `Drop`.
+pub fn cleanup_all() {
+    let Ok(mut live) = LIVE.lock() else {
+        return;
+    };
+    for path in std::mem::take(&mut *live) {
+        remove(&path);
+    }
+}
+
+/// Remove files left behind by earlier runs whose process is gone.
+pub fn sweep_stale() {
+    if let Ok(dir) = dir() {
+        sweep_dir(&dir);
+    }
+}
+
+fn sweep_dir(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = owner_pid(&name.to_string_lossy()) else {
+            continue;
+        };
+        if !Path::new(&format!("/proc/{}", pid)).exists() {
+            log::debug!("Removing stale scratch file {}", entry.path().display());
+            remove(&entry.path());
+        }
+    }
+}
+
+/// The pid in `sctl-<label>-<pid>-<random><ext>`.
+fn owner_pid(name: &str) -> Option<u32> {
+    let rest = name.strip_prefix(PREFIX)?;
+    let mut parts = rest.rsplitn(3, '-');
+    parts.next()?;
+    parts.next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sc_scratch_{}_{}", name, std::process::id()));
+        ensure_private_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_create_is_private_and_removed_on_drop() {
+        let dir = test_dir("drop");
+        let file = TempFile::create_in(&dir, "web01.example.com", ".txt", b"hello").unwrap();
+        let path = file.path().to_path_buf();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("sctl-web01_example_com-"));
+        drop(file);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_dir_is_private_and_removed_on_drop() {
+        let dir = test_dir("tempdir");
+        let temp = TempDir::create_in(&dir, "mqtt").unwrap();
+        temp.write("mosquitto_pub", b"-P secret\n").unwrap();
+        let file = temp.path().join("mosquitto_pub");
+        assert_eq!(fs::metadata(temp.path()).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        let path = temp.path().to_path_buf();
+        drop(temp);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        let dir = test_dir("unique");
+        let a = TempFile::create_in(&dir, "h", ".txt", b"").unwrap();
+        let b = TempFile::create_in(&dir, "h", ".txt", b"").unwrap();
+        assert_ne!(a.path(), b.path());
+        drop((a, b));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_owner_pid() {
+        assert_eq!(owner_pid("sctl-web01-1234-00ff00ff00ff00ff.txt"), Some(1234));
+        assert_eq!(owner_pid("sctl-note-web-01-77-abc.txt"), Some(77));
+        assert_eq!(owner_pid("other-1234-abc"), None);
+    }
+
+    #[test]
+    fn test_sweep_removes_files_of_dead_processes() {
+        let dir = test_dir("sweep");
+        let stale = dir.join("sctl-web01-4294967295-abc.txt");
+        fs::write(&stale, "old").unwrap();
+        let live = TempFile::create_in(&dir, "web01", ".txt", b"new").unwrap();
+        sweep_dir(&dir);
+        assert!(!stale.exists());
+        assert!(live.path().exists());
+        drop(live);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,272 @@
+//! User scripts (`scripts:`) for customization beyond what YAML can
+//! express. There is no embedded interpreter: a script is any executable,
+//! so Lua, Rhai, Python or shell all work through their interpreter's `#!`
+//! line. It gets one JSON event on stdin, like a plugin:
+//!
+//! - `{"event":"refresh","cells":[{"host":..,"service":..,"status":..}],"unreachable":[..]}`
+//!   after every full or single-host refresh. The reply's `cells` set the
+//!   status of existing cells, and cells that don't exist yet are added as
+//!   derived cells: `{"cells":[{"host":"web01","service":"web-ok","status":"degraded"}]}`.
+//! - `{"event":"change","changes":[{"host":..,"service":..,"old":..,"new":..,"severity":..}]}`
+//!   with every transition a refresh found; the reply is ignored.
+//!
+//! Scripts run in the order listed, each seeing the cells as the previous
+//! one left them.
+
+use crate::config::{Host, ServiceConfig};
+use crate::monitor::{GridResult, HostService, ServiceStatus};
+use crate::notify::Transition;
+use crate::plugins;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// A script slower than this is skipped for the event.
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static SCRIPTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Change events wait for the previous one, so a burst of refreshes queues
+/// them rather than starting scripts all at once.
+static CHANGE_RUNS: Semaphore = Semaphore::const_new(1);
+
+/// Check that every configured script is an executable file and keep the
+/// list for the refresh and change events.
+pub fn load(paths: &[String]) -> Result<()> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        let expanded = expand_home(path);
+        let meta = std::fs::metadata(&expanded).with_context(|| format!("Script {} not found", path))?;
+        if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+            anyhow::bail!("Script {} is not an executable file", path);
+        }
+        log::info!("Loaded script {}", expanded.display());
+        scripts.push(expanded);
+    }
+    let _ = SCRIPTS.set(scripts);
+    Ok(())
+}
+
+fn scripts() -> &'static [PathBuf] {
+    SCRIPTS.get().map_or(&[], Vec::as_slice)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cell {
+    host: String,
+    service: String,
+    status: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RefreshReply {
+    #[serde(default)]
+    cells: Vec<Cell>,
+}
+
+/// Let every script transform the statuses and add derived cells. A script
+/// that fails is logged and leaves the cells as they were.
+pub async fn on_refresh(hosts: &[Host], result: &mut GridResult) {
+    for script in scripts() {
+        if let Err(e) = run_refresh(script, hosts, result).await {
+            log::warn!("Script {} failed on refresh: {:#}", script.display(), e);
+        }
+    }
+}
+
+async fn run_refresh(script: &Path, hosts: &[Host], result: &mut GridResult) -> Result<()> {
+    let reply = plugins::call(script, &refresh_event(hosts, result), SCRIPT_TIMEOUT).await?;
+    let reply: RefreshReply = serde_json::from_value(reply).context("Invalid refresh reply")?;
+    apply(hosts, result, reply.cells);
+    Ok(())
+}
+
+fn refresh_event(hosts: &[Host], result: &GridResult) -> serde_json::Value {
+    let cells: Vec<Cell> = result
+        .grid
+        .iter()
+        .flatten()
+        .map(|hs| Cell {
+            host: hs.host_address.clone(),
+            service: hs.service_name.clone(),
+            status: hs.status.name().to_string(),
+        })
+        .collect();
+    let mut unreachable: Vec<&str> = result.unreachable_hosts.keys().map(|&i| hosts[i].address.as_str()).collect();
+    unreachable.sort();
+    json!({"event": "refresh", "cells": cells, "unreachable": unreachable})
+}
+
+/// Set the status of the cells named in the reply, adding the ones that
+/// don't exist on reachable hosts that were refreshed.
+fn apply(hosts: &[Host], result: &mut GridResult, cells: Vec<Cell>) {
+    for cell in cells {
+        let status = ServiceStatus::from_str(&cell.status);
+        let Some(host_idx) = hosts.iter().position(|h| h.address == cell.host) else {
+            log::warn!("Script named unknown host {}", cell.host);
+            continue;
+        };
+        if result.unreachable_hosts.contains_key(&host_idx) || result.skipped.contains(&host_idx) {
+            continue;
+        }
+        let Some(row) = result.grid.get_mut(host_idx) else {
+            continue;
+        };
+        match row.iter_mut().find(|hs| hs.service_name == cell.service) {
+            Some(hs) => hs.status = status,
+            None => {
+                if !result.service_names.contains(&cell.service) {
+                    result.service_names.push(cell.service.clone());
+                }
+                row.push(HostService {
+                    host_address: cell.host,
+                    service_name: cell.service.clone(),
+                    config: ServiceConfig {
+                        name_pattern: cell.service,
+                        derived: true,
+                        ..Default::default()
+                    },
+                    status,
+                    detail: Default::default(),
+                });
+            }
+        }
+    }
+}
+
+/// Tell every script about a batch of transitions, in the background.
+pub fn on_change(transitions: &[Transition]) {
+    if scripts().is_empty() || transitions.is_empty() {
+        return;
+    }
+    let event = change_event(transitions);
+    tokio::spawn(async move {
+        let Ok(_permit) = CHANGE_RUNS.acquire().await else {
+            return;
+        };
+        for script in scripts() {
+            if let Err(e) = plugins::call(script, &event, SCRIPT_TIMEOUT).await {
+                log::warn!("Script {} failed on change: {:#}", script.display(), e);
+            }
+        }
+    });
+}
+
+fn change_event(transitions: &[Transition]) -> serde_json::Value {
+    let changes: Vec<serde_json::Value> = transitions
+        .iter()
+        .map(|t| {
+            json!({
+                "host": t.host,
+                "service": t.service,
+                "old": t.old,
+                "new": t.new,
+                "severity": t.severity.name(),
+            })
+        })
+        .collect();
+    json!({"event": "change", "changes": changes})
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(address: &str) -> Host {
+        Host {
+            address: address.to_string(),
+            group: "web".to_string(),
+            parents: Vec::new(),
+        }
+    }
+
+    fn cell(host: &str, service: &str, status: &str) -> Cell {
+        Cell {
+            host: host.to_string(),
+            service: service.to_string(),
+            status: status.to_string(),
+        }
+    }
+
+    fn result() -> GridResult {
+        let hs = |host: &str, status| HostService {
+            host_address: host.to_string(),
+            service_name: "nginx".to_string(),
+            config: ServiceConfig::default(),
+            status,
+            detail: Default::default(),
+        };
+        GridResult {
+            service_names: vec!["nginx".to_string()],
+            grid: vec![vec![hs("web01", ServiceStatus::Active)], vec![hs("web02", ServiceStatus::Failed)], Vec::new()],
+            unreachable_hosts: [(2, crate::monitor::HostError::Timeout)].into(),
+            warnings: Vec::new(),
+            no_sudo: Default::default(),
+            skipped: Default::default(),
+            boots: Default::default(),
+            system: Default::default(),
+            restart_needed: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_refresh_event() {
+        let hosts = [host("web01"), host("web02"), host("db01")];
+        let event = refresh_event(&hosts, &result());
+        assert_eq!(event["cells"][1], json!({"host": "web02", "service": "nginx", "status": "failed"}));
+        assert_eq!(event["unreachable"], json!(["db01"]));
+    }
+
+    #[test]
+    fn test_change_event() {
+        let t = |service: &str| Transition {
+            host: "web01".to_string(),
+            service: service.to_string(),
+            old: "active".to_string(),
+            new: "failed".to_string(),
+            severity: crate::config::Severity::Critical,
+        };
+        let event = change_event(&[t("nginx"), t("app")]);
+        assert_eq!(event["event"], "change");
+        assert_eq!(
+            event["changes"][1],
+            json!({"host": "web01", "service": "app", "old": "active", "new": "failed", "severity": "critical"})
+        );
+        assert_eq!(event["changes"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn test_apply() {
+        let hosts = [host("web01"), host("web02"), host("db01")];
+        let mut result = result();
+        apply(
+            &hosts,
+            &mut result,
+            vec![
+                cell("web02", "nginx", "degraded"),
+                cell("web01", "web-ok", "active"),
+                // Unreachable and unknown hosts get nothing
+                cell("db01", "web-ok", "active"),
+                cell("db99", "web-ok", "active"),
+            ],
+        );
+        assert_eq!(result.grid[1][0].status, ServiceStatus::Degraded);
+        assert_eq!(result.service_names, vec!["nginx", "web-ok"]);
+        let derived = &result.grid[0][1];
+        assert_eq!((derived.service_name.as_str(), &derived.status), ("web-ok", &ServiceStatus::Active));
+        assert!(derived.config.derived);
+        assert!(result.grid[2].is_empty());
+    }
+}
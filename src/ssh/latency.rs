@@ -0,0 +1,82 @@
+//! Per-host connection latency history, kept across refreshes, from which
+//! per-host connect timeouts are derived when `adaptive_timeout` is set.
+
+use crate::config::ssh::AdaptiveTimeout;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Samples kept per host.
+const WINDOW: usize = 50;
+/// Samples needed before the history is trusted over the configured timeout.
+const MIN_SAMPLES: usize = 5;
+
+static HISTORY: Mutex<Option<HashMap<String, VecDeque<Duration>>>> = Mutex::new(None);
+
+/// Add a connect time, or the timeout for a connection that timed out.
+pub fn record(host: &str, took: Duration) {
+    let Ok(mut history) = HISTORY.lock() else {
+        return;
+    };
+    let samples = history.get_or_insert_with(HashMap::new).entry(host.to_string()).or_default();
+    samples.push_back(took);
+    if samples.len() > WINDOW {
+        samples.pop_front();
+    }
+}
+
+/// Connect timeout for `host`: three times its rolling p95, within the
+/// configured bounds, or `fallback` until there is enough history.
+pub fn timeout_for(host: &str, bounds: &AdaptiveTimeout, fallback: Duration) -> Duration {
+    let samples: Vec<Duration> = HISTORY
+        .lock()
+        .ok()
+        .as_ref()
+        .and_then(|history| history.as_ref()?.get(host))
+        .map(|s| s.iter().copied().collect())
+        .unwrap_or_default();
+    derive(&samples, bounds).unwrap_or(fallback)
+}
+
+fn derive(samples: &[Duration], bounds: &AdaptiveTimeout) -> Option<Duration> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let p95 = sorted[(sorted.len() * 95).div_ceil(100) - 1];
+    Some((p95 * 3).clamp(bounds.min(), bounds.max()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(v: &[u64]) -> Vec<Duration> {
+        v.iter().map(|&m| Duration::from_millis(m)).collect()
+    }
+
+    #[test]
+    fn test_derive() {
+        let bounds = AdaptiveTimeout::default();
+        assert_eq!(derive(&ms(&[100, 100, 100, 100]), &bounds), None);
+        // Fast LAN host: 3x p95 is below the floor
+        assert_eq!(derive(&ms(&[20, 25, 30, 22, 21]), &bounds), Some(bounds.min()));
+        // WAN host: p95 of 20 samples is the 19th smallest
+        let mut wan: Vec<u64> = (1..=20).map(|i| i * 100).collect();
+        wan.reverse();
+        assert_eq!(derive(&ms(&wan), &bounds), Some(Duration::from_millis(5700)));
+        assert_eq!(derive(&ms(&[60_000; 5]), &bounds), Some(bounds.max()));
+    }
+
+    #[test]
+    fn test_history_per_host() {
+        let bounds = AdaptiveTimeout::default();
+        let fallback = Duration::from_secs(2);
+        for _ in 0..MIN_SAMPLES {
+            record("latency-test-wan", Duration::from_secs(2));
+        }
+        assert_eq!(timeout_for("latency-test-wan", &bounds, fallback), Duration::from_secs(6));
+        assert_eq!(timeout_for("latency-test-unknown", &bounds, fallback), fallback);
+    }
+}
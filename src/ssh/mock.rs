@@ -0,0 +1,34 @@
+//! Canned command output in place of SSH, so the refresh pipeline can be
+//! exercised without a fleet (`--bench`).
+
+use super::session::Captured;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Output of a command, given the host and the command line.
+pub type Responder = Arc<dyn Fn(&str, &str) -> String + Send + Sync>;
+
+#[derive(Clone)]
+pub struct MockTransport {
+    respond: Responder,
+    /// Delay before each command returns, standing in for a round trip.
+    latency: Duration,
+}
+
+impl MockTransport {
+    pub fn new(latency: Duration, respond: Responder) -> Self {
+        MockTransport { respond, latency }
+    }
+
+    /// Every command succeeds with the responder's output.
+    pub async fn run(&self, host: &str, cmd: &str) -> Captured {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        Captured {
+            code: Some(0),
+            stdout: (self.respond)(host, cmd).into_bytes(),
+            ..Default::default()
+        }
+    }
+}
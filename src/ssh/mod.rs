@@ -1,3 +1,8 @@
+pub mod latency;
+pub mod mock;
+pub mod probe;
+pub mod resolve;
+pub mod retry;
 pub mod session;
 
 pub use session::SessionManager;
@@ -0,0 +1,108 @@
+use crate::config::ssh::ProbeMode;
+use anyhow::{Context, Result};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// Longest wait for `ssh -G`, which only reads the config files.
+const SSH_CONFIG_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The address and port ssh would connect to for `name`, after `HostName`
+/// and `Port` in ssh_config. `port` is the inventory's, which wins over
+/// the config as on ssh's command line. Falls back to `name` and `port`
+/// (or 22) when ssh can't tell.
+pub async fn ssh_target(name: &str, port: Option<u16>) -> (String, u16) {
+    let mut cmd = Command::new("ssh");
+    cmd.arg("-G");
+    if let Some(port) = port {
+        cmd.args(["-p", &port.to_string()]);
+    }
+    cmd.arg(name).stdin(Stdio::null()).stderr(Stdio::null()).kill_on_drop(true);
+    let resolved = match timeout(SSH_CONFIG_TIMEOUT, cmd.output()).await {
+        Ok(Ok(output)) if output.status.success() => parse_ssh_config(&String::from_utf8_lossy(&output.stdout)),
+        _ => None,
+    };
+    resolved.unwrap_or_else(|| (name.to_string(), port.unwrap_or(22)))
+}
+
+/// `hostname` and `port` from `ssh -G` output.
+fn parse_ssh_config(output: &str) -> Option<(String, u16)> {
+    let value = |key: &str| {
+        output
+            .lines()
+            .find_map(|l| l.split_once(' ').filter(|(k, _)| *k == key).map(|(_, v)| v.trim()))
+    };
+    Some((value("hostname")?.to_string(), value("port")?.parse().ok()?))
+}
+
+/// Check that a host is worth an SSH handshake. Errors use the same wording
+/// as ssh's (`connection refused`, `timed out`, resolver messages) so they
+/// are classified the same way.
+pub async fn probe(mode: ProbeMode, host: &str, port: u16, limit: Duration) -> Result<()> {
+    match mode {
+        ProbeMode::None => Ok(()),
+        ProbeMode::Tcp => {
+            timeout(limit, TcpStream::connect((host, port)))
+                .await
+                .map_err(|_| {
+                    anyhow::anyhow!("TCP probe to {}:{} timed out after {}ms", host, port, limit.as_millis())
+                })?
+                .with_context(|| format!("TCP probe to {}:{} failed", host, port))?;
+            Ok(())
+        }
+        ProbeMode::Icmp => {
+            let wait = limit.as_secs_f64().ceil().max(1.0).to_string();
+            let output = Command::new("ping")
+                .args(["-c", "1", "-W", &wait, host])
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .context("Failed to run ping")?;
+            if output.status.success() {
+                return Ok(());
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.trim().is_empty() {
+                anyhow::bail!("ICMP probe to {} timed out", host)
+            } else {
+                anyhow::bail!("ICMP probe to {} failed: {}", host, stderr.trim())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let output = "user admin\nhostname 10.1.2.3\nport 2222\nidentityfile ~/.ssh/id_ed25519\n";
+        assert_eq!(parse_ssh_config(output), Some(("10.1.2.3".to_string(), 2222)));
+        assert_eq!(parse_ssh_config("hostname web01\n"), None);
+        assert_eq!(parse_ssh_config(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_refused() {
+        // Bind then drop to get a port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let err = probe(ProbeMode::Tcp, "127.0.0.1", port, Duration::from_millis(500))
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).to_lowercase().contains("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_open() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(probe(ProbeMode::Tcp, "127.0.0.1", port, Duration::from_millis(500)).await.is_ok());
+    }
+}
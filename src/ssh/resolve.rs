@@ -0,0 +1,113 @@
+use crate::config::inventory::split_host_port;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::net::lookup_host;
+use tokio::process::Command;
+use tokio::task::JoinSet;
+
+/// How long a lookup is trusted. Failures are retried sooner so a fixed
+/// DNS record shows up on the next refresh or two.
+const SUCCESS_TTL: Duration = Duration::from_secs(300);
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+
+type Lookup = Result<Vec<IpAddr>, String>;
+
+static CACHE: Mutex<Option<HashMap<String, (Instant, Lookup)>>> = Mutex::new(None);
+
+fn cached(host: &str) -> Option<Lookup> {
+    let cache = CACHE.lock().ok()?;
+    let (at, result) = cache.as_ref()?.get(host)?;
+    let ttl = if result.is_ok() { SUCCESS_TTL } else { FAILURE_TTL };
+    (at.elapsed() < ttl).then(|| result.clone())
+}
+
+fn store(host: &str, result: &Lookup) {
+    if let Ok(mut cache) = CACHE.lock() {
+        cache
+            .get_or_insert_with(HashMap::new)
+            .insert(host.to_string(), (Instant::now(), result.clone()));
+    }
+}
+
+/// The `hostname` ssh will actually connect to, which differs from the
+/// inventory name for `Host` aliases in ~/.ssh/config.
+fn ssh_hostname(ssh_g_output: &str) -> Option<&str> {
+    ssh_g_output
+        .lines()
+        .find_map(|l| l.strip_prefix("hostname "))
+        .map(str::trim)
+}
+
+async fn lookup(address: &str) -> Lookup {
+    let host = split_host_port(address).0;
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let real = match Command::new("ssh").args(["-G", host]).output().await {
+        Ok(out) if out.status.success() => ssh_hostname(&String::from_utf8_lossy(&out.stdout))
+            .unwrap_or(host)
+            .to_string(),
+        _ => host.to_string(),
+    };
+    if let Ok(ip) = real.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let result = match lookup_host((real.as_str(), 22)).await {
+        Ok(addrs) => Ok(addrs.map(|a| a.ip()).collect()),
+        // Phrased like ssh's own error so it is classified as a DNS failure
+        Err(e) => Err(format!("Could not resolve hostname {}: {}", real, e)),
+    };
+    result
+}
+
+/// Resolve every host concurrently, using cached answers where still fresh.
+pub async fn resolve_all(hosts: &[String]) -> HashMap<String, Lookup> {
+    let mut results = HashMap::new();
+    let mut tasks = JoinSet::new();
+    for host in hosts {
+        match cached(host) {
+            Some(result) => {
+                results.insert(host.clone(), result);
+            }
+            None => {
+                let host = host.clone();
+                tasks.spawn(async move {
+                    let result = lookup(&host).await;
+                    (host, result)
+                });
+            }
+        }
+    }
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((host, result)) = joined {
+            match &result {
+                Ok(ips) => log::debug!("Resolved {} to {:?}", host, ips),
+                Err(e) => log::warn!("{}", e),
+            }
+            store(&host, &result);
+            results.insert(host, result);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_hostname() {
+        let out = "user admin\nhostname 10.1.2.3\nport 22\n";
+        assert_eq!(ssh_hostname(out), Some("10.1.2.3"));
+        assert_eq!(ssh_hostname("port 22\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_ip_literal_and_cache() {
+        let results = resolve_all(&["127.0.0.1:2222".to_string()]).await;
+        assert_eq!(results["127.0.0.1:2222"], Ok(vec!["127.0.0.1".parse::<IpAddr>().unwrap()]));
+        assert!(cached("127.0.0.1:2222").is_some());
+    }
+}
@@ -0,0 +1,58 @@
+//! Telling transport failures, which are worth retrying on a fresh
+//! connection, from commands that ran and failed.
+
+use std::io::ErrorKind;
+
+/// True if the command never got a fair chance to run: the mux socket went
+/// away, the connection was reset or the stream broke. Failing to connect in
+/// the first place is not retried, as the host is most likely down.
+pub fn is_transport_error(e: &anyhow::Error) -> bool {
+    for cause in e.chain() {
+        // The outermost openssh error decides; its io source is a detail
+        if let Some(e) = cause.downcast_ref::<openssh::Error>() {
+            return match e {
+                openssh::Error::Master(_) | openssh::Error::SshMux(_) | openssh::Error::Disconnected => true,
+                openssh::Error::ChildIo(io) => is_transport_io(io.kind()),
+                _ => false,
+            };
+        }
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return is_transport_io(io.kind());
+        }
+    }
+    false
+}
+
+fn is_transport_io(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::io;
+
+    #[test]
+    fn test_is_transport_error() {
+        let gone = Err::<(), _>(openssh::Error::Disconnected).context("Failed to run command on web01");
+        assert!(is_transport_error(&gone.unwrap_err()));
+        let reset = anyhow::Error::new(io::Error::from(ErrorKind::ConnectionReset));
+        assert!(is_transport_error(&reset));
+        let child = anyhow::Error::new(openssh::Error::ChildIo(io::Error::from(ErrorKind::BrokenPipe)));
+        assert!(is_transport_error(&child));
+
+        let refused = anyhow::Error::new(openssh::Error::Connect(io::Error::from(ErrorKind::ConnectionReset)));
+        assert!(!is_transport_error(&refused));
+        assert!(!is_transport_error(&anyhow::anyhow!("Command failed on web01: Unit nginx.service not found.")));
+        let denied = anyhow::Error::new(io::Error::from(ErrorKind::PermissionDenied));
+        assert!(!is_transport_error(&denied));
+    }
+}
@@ -1,88 +1,546 @@
+use super::latency;
+use super::mock::MockTransport;
+use super::probe::{probe, ssh_target};
+use super::retry;
+use crate::agent;
+use crate::config::inventory::split_host_port;
+use crate::config::ssh::{ProbeMode, SshOptions};
+use crate::metrics;
+use crate::trace::{Span, SpanContext};
 use anyhow::{Context, Result};
-use openssh::{KnownHosts, Session};
+use openssh::{KnownHosts, Session, SessionBuilder, Stdio};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
 use tokio::time::timeout;
 
+/// Bytes of stderr kept for error messages; the rest is read and discarded.
+const STDERR_LIMIT: usize = 64 * 1024;
+
+/// Read up to `limit` bytes, reporting whether there was more. With `drain`
+/// the remainder is consumed so the writer never blocks on a full pipe.
+async fn read_capped<R: AsyncRead + Unpin>(reader: R, limit: usize, drain: bool) -> std::io::Result<(Vec<u8>, bool)> {
+    let mut buf = Vec::new();
+    let mut reader = reader.take(limit as u64 + 1);
+    reader.read_to_end(&mut buf).await?;
+    let truncated = buf.len() > limit;
+    buf.truncate(limit);
+    if truncated && drain {
+        tokio::io::copy(&mut reader.into_inner(), &mut tokio::io::sink()).await?;
+    }
+    Ok((buf, truncated))
+}
+
+/// `5 MB`, `512 KB` or `300 bytes`.
+pub fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 && bytes.is_multiple_of(1024 * 1024) {
+        format!("{} MB", bytes / (1024 * 1024))
+    } else if bytes >= 1024 && bytes.is_multiple_of(1024) {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Everything a finished remote command produced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Captured {
+    /// Exit code; `None` if the command was killed or cut off.
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Output went past `max_output` and the command was stopped.
+    pub truncated: bool,
+}
+
+#[derive(Clone)]
+struct OpenSession {
+    /// The `ssh://` destination it was opened to.
+    dest: String,
+    session: Arc<Session>,
+}
+
+/// Open connections by host. Every manager made from the same pool shares
+/// them, so they outlive a single refresh or action.
+#[derive(Clone, Default)]
+pub struct SessionPool {
+    open: Arc<Mutex<HashMap<String, OpenSession>>>,
+    /// Held while connecting to a host, so callers racing to connect to it
+    /// share one master instead of each opening (and orphaning) their own.
+    connecting: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl SessionPool {
+    fn get(&self, host: &str) -> Option<OpenSession> {
+        self.open.lock().ok()?.get(host).cloned()
+    }
+
+    fn insert(&self, host: &str, dest: String, session: Arc<Session>) {
+        if let Ok(mut open) = self.open.lock() {
+            open.insert(host.to_string(), OpenSession { dest, session });
+        }
+    }
+
+    fn remove(&self, host: &str) {
+        if let Ok(mut open) = self.open.lock() {
+            open.remove(host);
+        }
+    }
+
+    fn drain(&self) -> Vec<(String, Arc<Session>)> {
+        self.open
+            .lock()
+            .map(|mut open| open.drain().map(|(host, open)| (host, open.session)).collect())
+            .unwrap_or_default()
+    }
+
+    fn connect_lock(&self, host: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.connecting
+            .lock()
+            .map(|mut locks| locks.entry(host.to_string()).or_default().clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Runs commands on hosts. Clones share their connections.
+#[derive(Clone)]
 pub struct SessionManager {
-    sessions: HashMap<String, Session>,
+    sessions: SessionPool,
     ssh_user: Option<String>,
+    options: SshOptions,
+    /// Span that connection and command spans are attached to.
+    trace_parent: Option<SpanContext>,
+    /// Answers commands instead of SSH when set.
+    mock: Option<MockTransport>,
+    /// Socket of the agent that runs the commands, when this is its client.
+    agent: Option<&'static Path>,
 }
 
 impl SessionManager {
-    pub fn new(ssh_user: Option<String>) -> Self {
+    pub fn new(ssh_user: Option<String>, options: SshOptions) -> Self {
+        Self::pooled(&SessionPool::default(), ssh_user, options)
+    }
+
+    /// A manager using (and adding to) the connections in `pool`.
+    pub fn pooled(pool: &SessionPool, ssh_user: Option<String>, options: SshOptions) -> Self {
         Self {
-            sessions: HashMap::new(),
+            sessions: pool.clone(),
             ssh_user,
+            options,
+            trace_parent: None,
+            mock: None,
+            agent: agent::socket(),
         }
     }
 
-    pub async fn get_session(&mut self, host: &str) -> Result<&Session> {
-        if !self.sessions.contains_key(host) {
-            let dest = match &self.ssh_user {
-                Some(user) => format!("ssh://{}@{}", user, host),
-                None => format!("ssh://{}", host),
-            };
-            log::info!("Opening SSH connection to {}", dest);
-            let session = timeout(
-                Duration::from_secs(2),
-                Session::connect_mux(dest, KnownHosts::Accept),
-            )
+    /// A manager whose commands are answered by `mock` rather than run over SSH.
+    pub fn mocked(options: SshOptions, mock: MockTransport) -> Self {
+        Self {
+            mock: Some(mock),
+            agent: None,
+            ..Self::new(None, options)
+        }
+    }
+
+    pub fn options(&self) -> &SshOptions {
+        &self.options
+    }
+
+    pub fn set_trace_parent(&mut self, parent: Option<SpanContext>) {
+        self.trace_parent = parent;
+    }
+
+    fn span(&self, name: &'static str, host: &str) -> Span {
+        let mut span = match self.trace_parent {
+            Some(parent) => Span::child_of(parent, name),
+            None => Span::root(name),
+        };
+        span.attr("host", host);
+        span
+    }
+
+    pub async fn get_session(&mut self, host: &str) -> Result<Arc<Session>> {
+        let (name, port) = split_host_port(host);
+        let port = port.or(self.options.port);
+        let mut dest = match self.options.user_for(self.ssh_user.as_deref(), host) {
+            Some(user) => format!("ssh://{}@", user),
+            None => "ssh://".to_string(),
+        };
+        if name.contains(':') {
+            dest.push_str(&format!("[{}]", name));
+        } else {
+            dest.push_str(name);
+        }
+        if let Some(port) = port {
+            dest.push_str(&format!(":{}", port));
+        }
+        if let Some(OpenSession { dest: open_dest, session }) = self.sessions.get(host) {
+            if open_dest != dest {
+                // The user or port for the host was changed since
+                log::info!("SSH destination of {} is now {}, reconnecting", host, dest);
+                self.sessions.remove(host);
+            } else if let Err(e) = session.check().await {
+                // A master that died with its host (e.g. on reboot) would only
+                // fail the next command confusingly; check before reuse
+                log::warn!("SSH session to {} is dead, reconnecting: {}", host, e);
+                metrics::record(|m| m.ssh_reconnects_total += 1);
+                self.sessions.remove(host);
+            } else {
+                return Ok(session);
+            }
+        }
+        let lock = self.sessions.connect_lock(host);
+        let _connecting = lock.lock().await;
+        // Someone else may have connected while this waited
+        if let Some(open) = self.sessions.get(host).filter(|open| open.dest == dest) {
+            return Ok(open.session);
+        }
+        if self.options.probe != ProbeMode::None {
+            let mut span = self.span("ssh.probe", host);
+            // Where ssh would go, which ssh_config may change
+            let (target, target_port) = ssh_target(name, port).await;
+            probe(self.options.probe, &target, target_port, self.options.probe_timeout)
+                .await
+                .inspect_err(|e| {
+                    log::warn!("Probe of {} failed: {:#}", host, e);
+                    span.attr("error", format!("{:#}", e));
+                    metrics::record(|m| *m.ssh_connect_failures.entry(host.to_string()).or_default() += 1);
+                })?;
+        }
+        log::info!("Opening SSH connection to {}", dest);
+        let mut span = self.span("ssh.connect", host);
+        let mut builder = SessionBuilder::default();
+        builder.known_hosts_check(KnownHosts::Accept);
+        if let Some(key) = &self.options.identity_file {
+            builder.keyfile(key);
+        }
+        let limit = match &self.options.adaptive_timeout {
+            Some(bounds) => latency::timeout_for(host, bounds, self.options.connect_timeout()),
+            None => self.options.connect_timeout(),
+        };
+        let started = Instant::now();
+        let session = timeout(limit, builder.connect_mux(&dest))
             .await
+            // A host that timed out is at least this slow, which later timeouts must allow for
+            .inspect_err(|_| latency::record(host, limit))
             .with_context(|| {
-                log::error!("SSH connection to {} timed out after 2s", host);
-                format!("Connection to {} timed out after 2s", host)
-            })?
-            .with_context(|| {
-                log::error!("SSH connection to {} failed", host);
-                format!("Failed to connect to {}", host)
+                log::error!("SSH connection to {} timed out after {}s", host, limit.as_secs());
+                format!("Connection to {} timed out after {}s", host, limit.as_secs())
+            })
+            .and_then(|r| {
+                r.with_context(|| {
+                    log::error!("SSH connection to {} failed", host);
+                    format!("Failed to connect to {}", host)
+                })
+            })
+            .inspect_err(|e| {
+                span.attr("error", format!("{:#}", e));
+                metrics::record(|m| *m.ssh_connect_failures.entry(host.to_string()).or_default() += 1);
             })?;
-            log::info!("SSH connection to {} established", host);
-            self.sessions.insert(host.to_string(), session);
-        }
-        Ok(self.sessions.get(host).unwrap())
+        drop(span);
+        let took = started.elapsed();
+        latency::record(host, took);
+        log::info!("SSH connection to {} established in {}ms", host, took.as_millis());
+        let session = Arc::new(session);
+        self.sessions.insert(host, dest, session.clone());
+        Ok(session)
     }
 
     pub async fn run_command(&mut self, host: &str, cmd: &str) -> Result<String> {
+        let output = self.run_command_raw(host, cmd).await?;
+        Ok(String::from_utf8_lossy(&output).to_string())
+    }
+
+    /// Like `run_command`, but the output's bytes are returned undecoded.
+    pub async fn run_command_raw(&mut self, host: &str, cmd: &str) -> Result<Vec<u8>> {
+        self.run_command_captured(host, cmd).await.map(|c| c.stdout)
+    }
+
+    /// Like `run_command_raw`, keeping whether the output was cut off.
+    pub async fn run_command_captured(&mut self, host: &str, cmd: &str) -> Result<Captured> {
         log::debug!("Running command on {}: {}", host, cmd);
+        let mut span = self.span("ssh.command", host);
+        span.attr("command", cmd.lines().next().unwrap_or(""));
+        let result = self.run_command_inner(host, cmd, &mut span).await;
+        metrics::record(|m| {
+            m.commands_total += 1;
+            if result.is_err() {
+                m.command_errors_total += 1;
+            }
+        });
+        result
+    }
+
+    async fn run_command_inner(&mut self, host: &str, cmd: &str, span: &mut Span) -> Result<Captured> {
+        let captured = self.capture(host, cmd, span).await?;
+        if captured.truncated {
+            return Ok(captured);
+        }
+        match captured.code {
+            Some(0) => {
+                log::debug!("Command succeeded on {}: {}", host, cmd);
+                Ok(captured)
+            }
+            code => {
+                let stderr = String::from_utf8_lossy(&captured.stderr);
+                if !stderr.is_empty() {
+                    log::warn!("Command failed on {}: {} — {}", host, cmd, stderr.trim());
+                    anyhow::bail!("Command failed on {}: {}", host, stderr.trim())
+                } else if !captured.stdout.is_empty() {
+                    // Some commands like systemctl is-active return non-zero but have useful stdout
+                    log::debug!("Command exited non-zero on {} (has stdout): {}", host, cmd);
+                    Ok(captured)
+                } else {
+                    log::warn!("Command failed on {} with exit code {:?}: {}", host, code, cmd);
+                    anyhow::bail!("Command failed on {} with exit code: {:?}", host, code)
+                }
+            }
+        }
+    }
+
+    /// Run a command and return its exit code and both output streams as they
+    /// are, for callers that present the result themselves. Only failing to
+    /// run the command at all is an error.
+    pub async fn exec(&mut self, host: &str, cmd: &str) -> Result<Captured> {
+        log::debug!("Executing on {}: {}", host, cmd);
+        let mut span = self.span("ssh.command", host);
+        span.attr("command", cmd.lines().next().unwrap_or(""));
+        let result = self.capture(host, cmd, &mut span).await;
+        metrics::record(|m| {
+            m.commands_total += 1;
+            if !result.as_ref().is_ok_and(|c| c.code == Some(0)) {
+                m.command_errors_total += 1;
+            }
+        });
+        result
+    }
+
+    /// Run a command, retrying on a fresh connection when it fails in transit
+    /// as allowed by the `retry` policy. Only failures before the command was
+    /// sent are retried, so a restart or an ad-hoc command never runs twice.
+    async fn capture(&mut self, host: &str, cmd: &str, span: &mut Span) -> Result<Captured> {
+        if let Some(socket) = self.agent {
+            // The agent retries on its own connections
+            return agent::exec(socket, host, self.agent_user(host), cmd).await;
+        }
+        let mut retries = 0;
+        loop {
+            let mut sent = false;
+            match self.capture_once(host, cmd, span, &mut sent).await {
+                Err(e) if !sent && retries < self.options.retry.attempts && retry::is_transport_error(&e) => {
+                    let delay = self.options.retry.delay(retries);
+                    log::warn!("Transport failure on {}, retrying in {}ms: {:#}", host, delay.as_millis(), e);
+                    retries += 1;
+                    metrics::record(|m| m.command_retries_total += 1);
+                    // The session is dead; the next attempt reconnects
+                    self.sessions.remove(host);
+                    tokio::time::sleep(delay).await;
+                }
+                result => {
+                    if retries > 0 {
+                        span.attr("retries", retries);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Sets `sent` once the command has reached the host, after which a
+    /// failure must not be retried.
+    async fn capture_once(&mut self, host: &str, cmd: &str, span: &mut Span, sent: &mut bool) -> Result<Captured> {
+        if let Some(mock) = &self.mock {
+            return Ok(mock.run(host, cmd).await);
+        }
+        let limit = self.options.max_output;
         let session = self.get_session(host).await?;
-        let output = session
-            .shell(cmd)
-            .output()
+        let mut command = session.shell(cmd);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = command.spawn().await.with_context(|| {
+            log::error!("Command execution failed on {}: {}", host, cmd);
+            format!("Failed to run command on {}: {}", host, cmd)
+        })?;
+        *sent = true;
+        let stdout = child.stdout().take().context("Command has no output stream")?;
+        let stderr = child.stderr().take().context("Command has no error stream")?;
+
+        // Read both streams together so a chatty stderr can't stall stdout
+        let out = read_capped(stdout, limit, false);
+        let err = read_capped(stderr, STDERR_LIMIT, true);
+        tokio::pin!(out, err);
+        let mut stderr_result = None;
+        let (mut stdout, truncated) = loop {
+            tokio::select! {
+                out = &mut out => break out?,
+                err = &mut err, if stderr_result.is_none() => stderr_result = Some(err?),
+            }
+        };
+        if truncated {
+            // Dropping the child closes the channel, which stops the remote command
+            log::warn!("Output of '{}' on {} truncated at {} bytes", cmd, host, limit);
+            span.attr("truncated", limit);
+            drop(child);
+            stdout.extend_from_slice(format!("\n[output truncated at {}]\n", format_size(limit)).as_bytes());
+            return Ok(Captured {
+                code: None,
+                stdout,
+                stderr: stderr_result.map(|(e, _)| e).unwrap_or_default(),
+                truncated,
+            });
+        }
+        let (stderr, _) = match stderr_result {
+            Some(result) => result,
+            None => err.await?,
+        };
+        let status = child.wait().await.with_context(|| format!("Failed to run command on {}: {}", host, cmd))?;
+        span.attr("exit", status.code().unwrap_or(-1));
+        Ok(Captured {
+            code: status.code(),
+            stdout,
+            stderr,
+            truncated,
+        })
+    }
+
+    /// The user the agent should connect to `host` as.
+    fn agent_user(&self, host: &str) -> Option<String> {
+        self.options.user_for(self.ssh_user.as_deref(), host).map(String::from)
+    }
+
+    /// Run a command and write all of its output to `file`, however large.
+    pub async fn stream_to_file(&mut self, host: &str, cmd: &str, file: &std::path::Path) -> Result<u64> {
+        log::debug!("Streaming output of '{}' on {} to {}", cmd, host, file.display());
+        if let Some(socket) = self.agent {
+            return agent::stream_to_file(socket, host, self.agent_user(host), cmd, file).await;
+        }
+        let session = self.get_session(host).await?;
+        let mut command = session.shell(cmd);
+        command.stdout(Stdio::piped()).stderr(Stdio::null());
+        let mut child = command
+            .spawn()
             .await
-            .with_context(|| {
-                log::error!("Command execution failed on {}: {}", host, cmd);
-                format!("Failed to run command on {}: {}", host, cmd)
-            })?;
+            .with_context(|| format!("Failed to run command on {}: {}", host, cmd))?;
+        let mut stdout = child.stdout().take().context("Command has no output stream")?;
+        let mut out = tokio::fs::OpenOptions::new().append(true).open(file).await?;
+        let written = tokio::io::copy(&mut stdout, &mut out).await?;
+        out.flush().await?;
+        child.wait().await?;
+        Ok(written)
+    }
 
-        if output.status.success() {
-            log::debug!("Command succeeded on {}: {}", host, cmd);
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if !stderr.is_empty() {
-                log::warn!("Command failed on {}: {} — {}", host, cmd, stderr.trim());
-                anyhow::bail!("Command failed on {}: {}", host, stderr.trim())
-            } else if !stdout.is_empty() {
-                // Some commands like systemctl is-active return non-zero but have useful stdout
-                log::debug!("Command exited non-zero on {} (has stdout): {}", host, cmd);
-                Ok(stdout.to_string())
-            } else {
-                log::warn!("Command failed on {} with exit code {:?}: {}", host, output.status, cmd);
-                anyhow::bail!("Command failed on {} with exit code: {:?}", host, output.status)
+    /// Run a long-lived command such as `tail -F` and hand each output line to
+    /// `on_line` until the command exits or `on_line` returns false.
+    pub async fn stream_lines(
+        &mut self,
+        host: &str,
+        cmd: &str,
+        mut on_line: impl FnMut(String) -> bool,
+    ) -> Result<()> {
+        log::debug!("Streaming command on {}: {}", host, cmd);
+        if let Some(socket) = self.agent {
+            return agent::stream(socket, host, self.agent_user(host), cmd, on_line).await;
+        }
+        let session = self.get_session(host).await?;
+        let mut command = session.shell(cmd);
+        command.stdout(Stdio::piped()).stderr(Stdio::null());
+        let mut child = command
+            .spawn()
+            .await
+            .with_context(|| format!("Failed to run command on {}: {}", host, cmd))?;
+        let stdout = child.stdout().take().context("Command has no output stream")?;
+        let mut reader = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf).await? == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf).trim_end_matches(['\r', '\n']).to_string();
+            if !on_line(line) {
+                return Ok(());
+            }
+        }
+        let status = child.wait().await?;
+        if !status.success() {
+            anyhow::bail!("Command exited with {:?}", status.code());
+        }
+        Ok(())
+    }
+
+    /// Run a long-lived command and send its output to `chunks` as it comes,
+    /// waiting while the receiver is behind, until the command exits or the
+    /// receiver is dropped.
+    pub async fn stream_chunks(&mut self, host: &str, cmd: &str, chunks: mpsc::Sender<Vec<u8>>) -> Result<()> {
+        log::debug!("Streaming command on {}: {}", host, cmd);
+        let session = self.get_session(host).await?;
+        let mut command = session.shell(cmd);
+        command.stdout(Stdio::piped()).stderr(Stdio::null());
+        let mut child = command
+            .spawn()
+            .await
+            .with_context(|| format!("Failed to run command on {}: {}", host, cmd))?;
+        let mut stdout = child.stdout().take().context("Command has no output stream")?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = stdout.read(&mut buf).await?;
+            if n == 0 {
+                break;
             }
+            if chunks.send(buf[..n].to_vec()).await.is_err() {
+                return Ok(());
+            }
+        }
+        let status = child.wait().await?;
+        if !status.success() {
+            anyhow::bail!("Command exited with {:?}", status.code());
         }
+        Ok(())
     }
 
+    /// Close every connection in the pool, e.g. on exit.
     pub async fn close_all(&mut self) {
-        let count = self.sessions.len();
-        if count > 0 {
-            log::debug!("Closing {} SSH sessions", count);
+        let sessions = self.sessions.drain();
+        if !sessions.is_empty() {
+            log::debug!("Closing {} SSH sessions", sessions.len());
         }
-        for (host, session) in self.sessions.drain() {
+        for (host, session) in sessions {
             log::debug!("Closing SSH session to {}", host);
-            let _ = session.close().await;
+            // Still running a command elsewhere; it goes when that is done
+            if let Ok(session) = Arc::try_unwrap(session) {
+                let _ = session.close().await;
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_capped() {
+        let (out, truncated) = read_capped(&b"hello world"[..], 5, false).await.unwrap();
+        assert_eq!((out.as_slice(), truncated), (&b"hello"[..], true));
+        let (out, truncated) = read_capped(&b"hello"[..], 5, true).await.unwrap();
+        assert_eq!((out.as_slice(), truncated), (&b"hello"[..], false));
+    }
+
+    #[test]
+    fn test_connect_lock_per_host() {
+        let pool = SessionPool::default();
+        let web01 = pool.connect_lock("web01");
+        assert!(Arc::ptr_eq(&web01, &pool.clone().connect_lock("web01")));
+        assert!(!Arc::ptr_eq(&web01, &pool.connect_lock("web02")));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(5 * 1024 * 1024), "5 MB");
+        assert_eq!(format_size(512 * 1024), "512 KB");
+        assert_eq!(format_size(300), "300 bytes");
+    }
+}
@@ -0,0 +1,108 @@
+//! Chronological record of what happened this session, shown on the timeline
+//! screen so an incident can be reconstructed afterwards.
+
+use crate::clock;
+use std::collections::VecDeque;
+
+/// Oldest entries are dropped beyond this.
+const MAX_ENTRIES: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Refresh,
+    Transition,
+    Action,
+    Result,
+    Notification,
+    Exec,
+}
+
+impl Kind {
+    /// The kind with this [`label`](Kind::label).
+    pub fn from_label(label: &str) -> Option<Kind> {
+        [Kind::Refresh, Kind::Transition, Kind::Action, Kind::Result, Kind::Notification, Kind::Exec]
+            .into_iter()
+            .find(|k| k.label() == label)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Kind::Refresh => "refresh",
+            Kind::Transition => "change",
+            Kind::Action => "action",
+            Kind::Result => "result",
+            Kind::Notification => "notify",
+            Kind::Exec => "exec",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub at: u64,
+    pub kind: Kind,
+    pub text: String,
+}
+
+impl Entry {
+    pub fn line(&self) -> String {
+        format!("{}  {:<7}  {}", clock::format_iso(self.at), self.kind.label(), self.text)
+    }
+}
+
+#[derive(Default)]
+pub struct Timeline {
+    pub entries: VecDeque<Entry>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, kind: Kind, text: impl Into<String>) {
+        self.push_at(clock::now_secs(), kind, text);
+    }
+
+    fn push_at(&mut self, at: u64, kind: Kind, text: impl Into<String>) {
+        let text = text.into();
+        log::debug!("Timeline {}: {}", kind.label(), text);
+        self.entries.push_back(Entry { at, kind, text });
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The whole timeline as text, one entry per line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&entry.line());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_text() {
+        let mut t = Timeline::default();
+        t.push_at(0, Kind::Action, "restart nginx on web01");
+        t.push_at(65, Kind::Result, "restart nginx on web01 succeeded");
+        assert_eq!(
+            t.to_text(),
+            "1970-01-01T00:00:00Z  action   restart nginx on web01\n\
+             1970-01-01T00:01:05Z  result   restart nginx on web01 succeeded\n"
+        );
+    }
+
+    #[test]
+    fn test_timeline_capped() {
+        let mut t = Timeline::default();
+        for i in 0..MAX_ENTRIES + 3 {
+            t.push_at(i as u64, Kind::Refresh, "tick");
+        }
+        assert_eq!(t.entries.len(), MAX_ENTRIES);
+        assert_eq!(t.entries[0].at, 3);
+    }
+}
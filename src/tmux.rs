@@ -0,0 +1,77 @@
+//! Batch shells (`O` on the main screen): one tmux window with a pane per
+//! host of a group, SSH'd in and with synchronized input, for running the
+//! same thing everywhere by hand.
+
+/// Quote an argument for `sh`, e.g. the `sh -c` tmux runs pane commands with.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./@:=,+".contains(c)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn command_line(args: &[String]) -> String {
+    args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ")
+}
+
+/// The tmux invocation opening a window named `name` with one pane per
+/// command, tiled, with panes synchronized. Inside tmux (`nested`) it is a
+/// new window of the current session, otherwise a new session, left for
+/// tmux to name so a second one doesn't clash with the first.
+pub fn batch_args(name: &str, commands: &[Vec<String>], nested: bool) -> Vec<String> {
+    let mut args: Vec<String> = vec!["tmux".to_string()];
+    let Some((first, rest)) = commands.split_first() else {
+        return args;
+    };
+    if nested {
+        args.extend(["new-window", "-n", name].map(String::from));
+    } else {
+        args.extend(["new-session", "-n", name].map(String::from));
+    }
+    args.push(command_line(first));
+    for cmd in rest {
+        args.extend([";", "split-window"].map(String::from));
+        args.push(command_line(cmd));
+        // Re-tile after each split, so panes don't get too small to split
+        args.extend([";", "select-layout", "tiled"].map(String::from));
+    }
+    args.extend([";", "set-window-option", "synchronize-panes", "on"].map(String::from));
+    args
+}
+
+/// Whether we are running inside a tmux session.
+pub fn nested() -> bool {
+    std::env::var("TMUX").is_ok_and(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh(host: &str) -> Vec<String> {
+        ["ssh", "-p", "2222", host].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("admin@web01:22"), "admin@web01:22");
+        assert_eq!(quote("my key"), "'my key'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn test_batch_args() {
+        let args = batch_args("sctl-web", &[ssh("web01"), ssh("web02")], false);
+        assert_eq!(
+            args.join(" "),
+            "tmux new-session -n sctl-web ssh -p 2222 web01 ; split-window ssh -p 2222 web02 \
+             ; select-layout tiled ; set-window-option synchronize-panes on"
+        );
+        let args = batch_args("sctl-web", &[ssh("web01")], true);
+        assert_eq!(
+            args.join(" "),
+            "tmux new-window -n sctl-web ssh -p 2222 web01 ; set-window-option synchronize-panes on"
+        );
+    }
+}
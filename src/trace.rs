@@ -0,0 +1,245 @@
+//! Minimal timing spans. Every finished span is logged with its duration
+//! under the `trace` target; with `--otlp-endpoint` spans are also batched
+//! and exported as OTLP/HTTP JSON through curl, in the same spirit as the
+//! webhook notifications.
+
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Identifies a span so children can be attached to it across tasks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+pub struct Span {
+    name: &'static str,
+    ctx: SpanContext,
+    parent: Option<u64>,
+    start: SystemTime,
+    attrs: Vec<(&'static str, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct FinishedSpan {
+    name: &'static str,
+    ctx: SpanContext,
+    parent: Option<u64>,
+    start: SystemTime,
+    duration: Duration,
+    attrs: Vec<(&'static str, String)>,
+}
+
+struct Exporter {
+    endpoint: String,
+    buffer: Mutex<Vec<FinishedSpan>>,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+/// Export finished spans to an OTLP/HTTP collector, e.g. `http://localhost:4318`.
+pub fn init_otlp(endpoint: &str) {
+    let _ = EXPORTER.set(Exporter {
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        buffer: Mutex::new(Vec::new()),
+    });
+}
+
+pub(crate) fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
+
+impl Span {
+    /// Start a new trace.
+    pub fn root(name: &'static str) -> Self {
+        let trace_id = ((random_u64() as u128) << 64) | random_u64() as u128;
+        Self::start(name, trace_id, None)
+    }
+
+    pub fn child_of(parent: SpanContext, name: &'static str) -> Self {
+        Self::start(name, parent.trace_id, Some(parent.span_id))
+    }
+
+    pub fn child(&self, name: &'static str) -> Self {
+        Self::child_of(self.ctx, name)
+    }
+
+    fn start(name: &'static str, trace_id: u128, parent: Option<u64>) -> Self {
+        Self {
+            name,
+            ctx: SpanContext {
+                trace_id,
+                span_id: random_u64(),
+            },
+            parent,
+            start: SystemTime::now(),
+            attrs: Vec::new(),
+        }
+    }
+
+    pub fn context(&self) -> SpanContext {
+        self.ctx
+    }
+
+    pub fn attr(&mut self, key: &'static str, value: impl ToString) {
+        self.attrs.push((key, value.to_string()));
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed().unwrap_or_default();
+        let attrs: Vec<String> = self.attrs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        log::debug!(
+            target: "trace",
+            "{} took {:.1}ms {}",
+            self.name,
+            duration.as_secs_f64() * 1000.0,
+            attrs.join(" ")
+        );
+        if let Some(exporter) = EXPORTER.get() {
+            if let Ok(mut buffer) = exporter.buffer.lock() {
+                buffer.push(FinishedSpan {
+                    name: self.name,
+                    ctx: self.ctx,
+                    parent: self.parent,
+                    start: self.start,
+                    duration,
+                    attrs: std::mem::take(&mut self.attrs),
+                });
+            }
+        }
+    }
+}
+
+fn nanos(t: SystemTime) -> String {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn otlp_payload(spans: &[FinishedSpan]) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|s| {
+            let mut span = json!({
+                "traceId": format!("{:032x}", s.ctx.trace_id),
+                "spanId": format!("{:016x}", s.ctx.span_id),
+                "name": s.name,
+                "kind": 1,
+                "startTimeUnixNano": nanos(s.start),
+                "endTimeUnixNano": nanos(s.start + s.duration),
+                "attributes": s.attrs.iter().map(|(k, v)| json!({
+                    "key": k,
+                    "value": {"stringValue": v},
+                })).collect::<Vec<_>>(),
+            });
+            if let Some(parent) = s.parent {
+                span["parentSpanId"] = Value::String(format!("{:016x}", parent));
+            }
+            span
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {"attributes": [
+                {"key": "service.name", "value": {"stringValue": "system-controller"}},
+            ]},
+            "scopeSpans": [{
+                "scope": {"name": "system-controller"},
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// Send buffered spans to the collector in the background. A no-op unless
+/// OTLP export is enabled; the handle lets shutdown wait for the last batch.
+pub fn flush() -> Option<tokio::task::JoinHandle<()>> {
+    let exporter = EXPORTER.get()?;
+    let spans = std::mem::take(&mut *exporter.buffer.lock().ok()?);
+    if spans.is_empty() {
+        return None;
+    }
+    let url = format!("{}/v1/traces", exporter.endpoint);
+    let body = otlp_payload(&spans).to_string();
+    Some(tokio::spawn(async move {
+        let result = async {
+            let mut child = Command::new("curl")
+                .args([
+                    "--silent",
+                    "--show-error",
+                    "--fail",
+                    "--max-time",
+                    "10",
+                    "-H",
+                    "Content-Type: application/json",
+                    "--data-binary",
+                    "@-",
+                    &url,
+                ])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(body.as_bytes()).await?;
+            }
+            child.wait_with_output().await
+        }
+        .await;
+        match result {
+            Ok(out) if out.status.success() => log::debug!("Exported {} spans", spans.len()),
+            Ok(out) => log::warn!("OTLP export failed: {}", String::from_utf8_lossy(&out.stderr).trim()),
+            Err(e) => log::warn!("Failed to run curl for OTLP export: {}", e),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_shares_trace() {
+        let root = Span::root("refresh");
+        let child = root.child("host");
+        assert_eq!(child.ctx.trace_id, root.ctx.trace_id);
+        assert_eq!(child.parent, Some(root.ctx.span_id));
+        assert_ne!(child.ctx.span_id, root.ctx.span_id);
+    }
+
+    #[test]
+    fn test_otlp_payload() {
+        let span = FinishedSpan {
+            name: "ssh.command",
+            ctx: SpanContext { trace_id: 1, span_id: 2 },
+            parent: Some(3),
+            start: UNIX_EPOCH + Duration::from_secs(10),
+            duration: Duration::from_millis(5),
+            attrs: vec![("host", "web01".to_string())],
+        };
+        let p = otlp_payload(&[span]);
+        let s = &p["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(s["traceId"], "00000000000000000000000000000001");
+        assert_eq!(s["parentSpanId"], "0000000000000003");
+        assert_eq!(s["startTimeUnixNano"], "10000000000");
+        assert_eq!(s["endTimeUnixNano"], "10005000000");
+        assert_eq!(s["attributes"][0]["value"]["stringValue"], "web01");
+    }
+}
@@ -0,0 +1,208 @@
+//! What the terminal can show, so the TUI stays usable over serial consoles,
+//! with `TERM=dumb` and in minimal containers: no alternate screen means
+//! inline rendering, no color means ASCII status markers, and a non-UTF-8
+//! locale means ASCII instead of symbols.
+
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+use std::sync::{Mutex, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorDepth {
+    None,
+    /// The 8 ANSI colors.
+    Basic,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    pub alt_screen: bool,
+    pub colors: ColorDepth,
+    pub unicode: bool,
+}
+
+impl Capabilities {
+    pub const FULL: Capabilities = Capabilities {
+        alt_screen: true,
+        colors: ColorDepth::Full,
+        unicode: true,
+    };
+    /// What `--plain` asks for, whatever the terminal claims.
+    pub const PLAIN: Capabilities = Capabilities {
+        alt_screen: false,
+        colors: ColorDepth::None,
+        unicode: false,
+    };
+
+    /// Whether the terminal falls short of the full TUI in any way.
+    pub fn limited(&self) -> bool {
+        *self != Self::FULL
+    }
+}
+
+/// The full TUI until [`set`] says otherwise, e.g. in tests and `--bench`.
+static CAPS: Mutex<Capabilities> = Mutex::new(Capabilities::FULL);
+
+pub fn set(caps: Capabilities) {
+    // A panic elsewhere can't leave a Copy value half-written
+    *CAPS.lock().unwrap_or_else(PoisonError::into_inner) = caps;
+}
+
+pub fn get() -> Capabilities {
+    *CAPS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Capabilities from `TERM`, `COLORTERM`, `NO_COLOR` and the locale.
+pub fn from_env() -> Capabilities {
+    let var = |name| std::env::var(name).ok();
+    let locale = var("LC_ALL").or_else(|| var("LC_CTYPE")).or_else(|| var("LANG"));
+    detect(
+        var("TERM").as_deref(),
+        var("COLORTERM").as_deref(),
+        var("NO_COLOR").is_some_and(|v| !v.is_empty()),
+        locale.as_deref(),
+    )
+}
+
+/// Hardware terminals and the consoles emulating them, which have neither an
+/// alternate screen nor (mostly) color.
+const SERIAL_TERMS: &[&str] = &["vt52", "vt100", "vt102", "vt220", "vt320", "ansi", "linux", "cons25"];
+
+fn detect(term: Option<&str>, colorterm: Option<&str>, no_color: bool, locale: Option<&str>) -> Capabilities {
+    let term = term.unwrap_or("").to_ascii_lowercase();
+    if term.is_empty() || term == "dumb" || term == "unknown" {
+        return Capabilities::PLAIN;
+    }
+    let serial = SERIAL_TERMS.iter().any(|t| term == *t || term.starts_with(&format!("{}-", t)));
+    let colors = if no_color {
+        ColorDepth::None
+    } else if matches!(colorterm, Some("truecolor" | "24bit")) || term.contains("256color") || term.contains("direct") {
+        ColorDepth::Full
+    } else if term.starts_with("vt") {
+        ColorDepth::None
+    } else {
+        ColorDepth::Basic
+    };
+    let utf8 = locale.is_some_and(|l| {
+        let l = l.to_ascii_lowercase();
+        l.contains("utf-8") || l.contains("utf8")
+    });
+    Capabilities {
+        alt_screen: !serial,
+        colors,
+        unicode: utf8 && !term.starts_with("vt"),
+    }
+}
+
+/// `symbol`, or `ascii` when the terminal can't show it.
+pub fn glyph(symbol: &'static str, ascii: &'static str) -> &'static str {
+    if get().unicode {
+        symbol
+    } else {
+        ascii
+    }
+}
+
+/// Bring a drawn frame within what the terminal shows: reduce its colors,
+/// and draw borders with ASCII when there is no Unicode.
+pub fn adapt(buffer: &mut Buffer) {
+    let caps = get();
+    if !caps.limited() {
+        return;
+    }
+    for cell in buffer.content.iter_mut() {
+        cell.fg = reduce(cell.fg, caps.colors);
+        cell.bg = reduce(cell.bg, caps.colors);
+        if !caps.unicode {
+            if let Some(ascii) = ascii_border(cell.symbol()) {
+                cell.set_symbol(ascii);
+            }
+        }
+    }
+}
+
+fn ascii_border(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        "─" | "━" | "═" => Some("-"),
+        "│" | "┃" | "║" => Some("|"),
+        "┌" | "┐" | "└" | "┘" | "╭" | "╮" | "╰" | "╯" | "┼" | "├" | "┤" | "┬" | "┴" => Some("+"),
+        _ => None,
+    }
+}
+
+fn reduce(color: Color, depth: ColorDepth) -> Color {
+    match (depth, color) {
+        (ColorDepth::Full, c) | (_, c @ Color::Reset) => c,
+        (ColorDepth::None, _) => Color::Reset,
+        // Bright black is invisible or missing on many 8-color terminals
+        (ColorDepth::Basic, Color::DarkGray) => Color::Reset,
+        (ColorDepth::Basic, Color::Gray) => Color::White,
+        (ColorDepth::Basic, Color::LightRed) => Color::Red,
+        (ColorDepth::Basic, Color::LightGreen) => Color::Green,
+        (ColorDepth::Basic, Color::LightYellow) => Color::Yellow,
+        (ColorDepth::Basic, Color::LightBlue) => Color::Blue,
+        (ColorDepth::Basic, Color::LightMagenta) => Color::Magenta,
+        (ColorDepth::Basic, Color::LightCyan) => Color::Cyan,
+        (ColorDepth::Basic, Color::Rgb(r, g, b)) => nearest_basic(r, g, b),
+        (ColorDepth::Basic, Color::Indexed(_)) => Color::Reset,
+        (ColorDepth::Basic, c) => c,
+    }
+}
+
+fn nearest_basic(r: u8, g: u8, b: u8) -> Color {
+    match (r > 127, g > 127, b > 127) {
+        (false, false, false) => Color::Black,
+        (true, false, false) => Color::Red,
+        (false, true, false) => Color::Green,
+        (true, true, false) => Color::Yellow,
+        (false, false, true) => Color::Blue,
+        (true, false, true) => Color::Magenta,
+        (false, true, true) => Color::Cyan,
+        (true, true, true) => Color::White,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UTF8: Option<&str> = Some("en_US.UTF-8");
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(Some("xterm-256color"), None, false, UTF8), Capabilities::FULL);
+        assert_eq!(detect(Some("dumb"), Some("truecolor"), false, UTF8), Capabilities::PLAIN);
+        assert_eq!(detect(None, None, false, UTF8), Capabilities::PLAIN);
+        assert_eq!(detect(Some("vt220"), None, false, UTF8), Capabilities::PLAIN);
+        assert_eq!(
+            detect(Some("linux"), None, false, Some("C")),
+            Capabilities {
+                alt_screen: false,
+                colors: ColorDepth::Basic,
+                unicode: false,
+            }
+        );
+        let xterm = detect(Some("xterm"), None, true, UTF8);
+        assert!(xterm.alt_screen && xterm.unicode);
+        assert_eq!(xterm.colors, ColorDepth::None);
+        assert_eq!(detect(Some("screen"), Some("truecolor"), false, None).colors, ColorDepth::Full);
+    }
+
+    #[test]
+    fn test_reduce() {
+        // The orange used for degraded services
+        assert_eq!(reduce(Color::Rgb(255, 165, 0), ColorDepth::Basic), Color::Yellow);
+        assert_eq!(reduce(Color::DarkGray, ColorDepth::Basic), Color::Reset);
+        assert_eq!(reduce(Color::Red, ColorDepth::Basic), Color::Red);
+        assert_eq!(reduce(Color::Red, ColorDepth::None), Color::Reset);
+        assert_eq!(reduce(Color::Rgb(1, 2, 3), ColorDepth::Full), Color::Rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_ascii_border() {
+        assert_eq!(ascii_border("┌"), Some("+"));
+        assert_eq!(ascii_border("─"), Some("-"));
+        assert_eq!(ascii_border("a"), None);
+    }
+}
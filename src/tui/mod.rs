@@ -1,37 +1,68 @@
+pub mod caps;
 pub mod event;
+#[cfg(test)]
+pub mod testing;
 pub mod ui;
+pub mod viewer;
 
 use anyhow::Result;
 use crossterm::{
+    cursor::MoveTo,
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    style::Print,
+    terminal::{self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::backend::CrosstermBackend;
-use ratatui::Terminal;
+use ratatui::{Terminal, TerminalOptions, Viewport};
 use std::io::{self, stdout};
 
 pub type Tui = Terminal<CrosstermBackend<io::Stdout>>;
 
+/// Height used for inline rendering when the terminal doesn't report a size,
+/// as serial consoles often don't.
+const FALLBACK_HEIGHT: u16 = 24;
+
 pub fn init() -> Result<Tui> {
     enable_raw_mode()?;
-    execute!(stdout(), EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout());
-    let terminal = Terminal::new(backend)?;
+    if caps::get().alt_screen {
+        execute!(stdout(), EnterAlternateScreen)?;
+        return Ok(Terminal::new(backend)?);
+    }
+    // Draw in place below the prompt instead, using the whole height
+    let height = terminal::size().map(|(_, rows)| rows).unwrap_or(0);
+    let height = if height == 0 { FALLBACK_HEIGHT } else { height };
+    let terminal = Terminal::with_options(
+        backend,
+        TerminalOptions {
+            viewport: Viewport::Inline(height),
+        },
+    )?;
     Ok(terminal)
 }
 
 pub fn restore() -> Result<()> {
     disable_raw_mode()?;
-    execute!(stdout(), LeaveAlternateScreen)?;
-    Ok(())
+    leave_screen()
 }
 
 pub fn suspend() -> Result<()> {
     disable_raw_mode()?;
-    execute!(stdout(), LeaveAlternateScreen)?;
-    Ok(())
+    leave_screen()
 }
 
 pub fn resume() -> Result<Tui> {
     init()
 }
+
+/// Hand the terminal back: leave the alternate screen, or with inline
+/// rendering put the cursor on a fresh line below the last frame.
+fn leave_screen() -> Result<()> {
+    if caps::get().alt_screen {
+        execute!(stdout(), LeaveAlternateScreen)?;
+    } else {
+        let rows = terminal::size().map(|(_, rows)| rows).unwrap_or(FALLBACK_HEIGHT);
+        execute!(stdout(), MoveTo(0, rows.saturating_sub(1)), Print("\r\n"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Rendering `AppState` fixtures to a `TestBackend` and comparing the text
+//! with snapshots in `src/tui/snapshots/`. Run the tests with
+//! `UPDATE_SNAPSHOTS=1` to (re)write the snapshots after a deliberate UI
+//! change, and review the diff like any other.
+
+use crate::app::AppState;
+use crate::config::{Host, ServiceConfig};
+use crate::monitor::{HostError, HostService, ServiceStatus};
+use crate::tui::ui::render;
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+use ratatui::Terminal;
+use std::path::PathBuf;
+
+/// A small fleet: web01 with a failed unit, web02 healthy but for a stopped
+/// unit, db01 unreachable.
+pub fn fixture() -> AppState {
+    let hosts = vec![host("web01", "web"), host("web02", "web"), host("db01", "db")];
+    let services = ["nginx", "app", "redis"];
+    let configs = services
+        .iter()
+        .map(|name| ServiceConfig {
+            name_pattern: name.to_string(),
+            ..Default::default()
+        })
+        .collect();
+    let mut state = AppState::new(hosts, configs, None);
+    state.service_names = services.map(String::from).to_vec();
+    state.grid = vec![
+        vec![
+            cell("web01", "nginx", ServiceStatus::Active),
+            cell("web01", "app", ServiceStatus::Failed),
+            cell("web01", "redis", ServiceStatus::Activating),
+        ],
+        vec![
+            cell("web02", "nginx", ServiceStatus::Active),
+            cell("web02", "app", ServiceStatus::Active),
+            cell("web02", "redis", ServiceStatus::Inactive),
+        ],
+        Vec::new(),
+    ];
+    state.unreachable_hosts.insert(2, HostError::Timeout);
+    state
+}
+
+fn host(address: &str, group: &str) -> Host {
+    Host {
+        address: address.to_string(),
+        group: group.to_string(),
+        parents: Vec::new(),
+    }
+}
+
+fn cell(host: &str, service: &str, status: ServiceStatus) -> HostService {
+    HostService {
+        host_address: host.to_string(),
+        service_name: service.to_string(),
+        config: ServiceConfig {
+            name_pattern: service.to_string(),
+            files: vec![format!("/etc/{}/{}.conf", service, service)],
+            commands: vec![
+                format!("systemctl status '{}.service'", service),
+                format!("journalctl -u '{}.service'", service),
+            ],
+            ..Default::default()
+        },
+        status,
+        detail: Default::default(),
+    }
+}
+
+/// Render one frame the way the main loop does.
+pub fn draw(state: &mut AppState, width: u16, height: u16) -> Buffer {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    state.table_state.select(Some(state.cursor));
+    terminal.draw(|f| render(f, state)).unwrap();
+    terminal.backend().buffer().clone()
+}
+
+/// The buffer's text, one line per row, without trailing spaces.
+pub fn text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut out = String::new();
+    for y in area.top()..area.bottom() {
+        let line: String = (area.left()..area.right()).map(|x| buffer[(x, y)].symbol()).collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Foreground color of the first cell where `needle` appears.
+pub fn fg_of(buffer: &Buffer, needle: &str) -> Option<Color> {
+    let area = buffer.area;
+    for y in area.top()..area.bottom() {
+        let line: Vec<&str> = (area.left()..area.right()).map(|x| buffer[(x, y)].symbol()).collect();
+        let joined = line.concat();
+        if let Some(byte) = joined.find(needle) {
+            // Cells hold one symbol each; count the symbols before the match
+            let mut x = 0;
+            let mut len = 0;
+            while len < byte {
+                len += line[x].len();
+                x += 1;
+            }
+            return Some(buffer[(area.left() + x as u16, y)].fg);
+        }
+    }
+    None
+}
+
+/// Compare `actual` with the snapshot `name`, or write it with `UPDATE_SNAPSHOTS`.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tui/snapshots").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("No snapshot {}; run with UPDATE_SNAPSHOTS=1 to create it", path.display()));
+    if expected != actual {
+        panic!(
+            "Snapshot {} differs; run with UPDATE_SNAPSHOTS=1 to accept.\n--- expected\n{}\n+++ actual\n{}",
+            name, expected, actual
+        );
+    }
+}
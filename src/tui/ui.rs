@@ -1,36 +1,320 @@
-use crate::app::{AppState, FlatEntry, Screen};
-use crate::monitor::ServiceStatus;
+use crate::adhoc::HostRun;
+use crate::app::{AppState, FlatEntry, KillChooser, PluginChooser, Screen, KILL_SIGNALS};
+use crate::bulk::Outcome;
+use crate::clock;
+use crate::config::services::{template_parts, CellContent};
+use crate::metrics;
+use crate::monitor::{HostError, HostService, ServiceStatus};
+use crate::picker::HostPicker;
+use crate::plugins;
+use crate::scope::Scope;
+use crate::timeline;
+use crate::tui::caps;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table};
+use ratatui::widgets::{
+    Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap,
+};
 use ratatui::Frame;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub fn render(frame: &mut Frame, state: &mut AppState) {
     match state.screen.clone() {
         Screen::Main => render_main(frame, state),
+        Screen::Stats => render_stats(frame, state),
         Screen::Detail {
             host_index,
             service_index,
         } => render_detail(frame, state, host_index, service_index),
+        Screen::Host { host_index } => render_host(frame, state, host_index),
+        Screen::Timeline => render_timeline(frame, state),
+        Screen::SlowHosts => render_slow_hosts(frame, state),
+        Screen::Compare { left, right } => render_compare(frame, state, left, right),
+        Screen::Adhoc => render_adhoc(frame, state),
+        Screen::Bulk => render_bulk(frame, state),
+        Screen::Tail { .. } => render_tail(frame, state),
+        Screen::Properties { .. } => render_properties(frame, state),
+        Screen::Deps { .. } => render_deps(frame, state),
     }
+    render_popups(frame, state);
+    caps::adapt(frame.buffer_mut());
 }
 
+/// Confirmation or text prompt popup drawn over whichever screen is active.
+fn render_popups(frame: &mut Frame, state: &AppState) {
+    if let Some(prompt) = &state.prompt {
+        let lines = vec![
+            Line::from(prompt.title.as_str()),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("> ", Style::default().fg(Color::Yellow)),
+                Span::raw(prompt.input.as_str()),
+                Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+            ]),
+            Line::from(Span::styled("Enter:submit  Esc:cancel", Style::default().fg(Color::DarkGray))),
+        ];
+        render_popup(frame, prompt.heading(), &prompt.title, lines);
+    } else if let Some(confirm) = &state.confirm {
+        let lines = vec![
+            Line::from(confirm.message.as_str()),
+            Line::from(""),
+            Line::from(Span::styled("y:yes  n:no", Style::default().fg(Color::DarkGray))),
+        ];
+        render_popup(frame, " Confirm ", &confirm.message, lines);
+    } else if let Some(cursor) = state.column_chooser {
+        render_column_chooser(frame, state, cursor);
+    } else if let Some(picker) = &state.host_picker {
+        render_host_picker(frame, state, picker);
+    } else if let Some(chooser) = &state.kill_chooser {
+        render_kill_chooser(frame, chooser);
+    } else if let Some(chooser) = &state.plugin_chooser {
+        render_plugin_chooser(frame, chooser);
+    } else if let Some(info) = &state.info_popup {
+        let mut lines: Vec<Line> = info.lines.iter().map(|l| Line::from(l.as_str())).collect();
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(info.hint.as_str(), Style::default().fg(Color::DarkGray))));
+        render_popup(frame, &info.title, &info.lines.join(" "), lines);
+    }
+}
+
+/// Search box over the inventory with the matching hosts, best first.
+fn render_host_picker(frame: &mut Frame, state: &AppState, picker: &HostPicker) {
+    let area = frame.area();
+    let width = area.width.min(60);
+    let height = area.height.min(20);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .title(" SSH to host ")
+        .title_bottom(" Enter:open shell  Esc:close ");
+    let inner = block.inner(popup);
+    frame.render_widget(Clear, popup);
+    frame.render_widget(block, popup);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Yellow)),
+            Span::raw(picker.query.as_str()),
+            Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+        ])),
+        chunks[0],
+    );
+    let items: Vec<ListItem> = picker
+        .matches(&state.hosts)
+        .into_iter()
+        .map(|i| {
+            let host = &state.hosts[i];
+            let style = if state.unreachable_hosts.contains_key(&i) {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(host.address.as_str(), style),
+                Span::styled(format!("  {}", host.group), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(picker.cursor));
+    frame.render_stateful_widget(list, chunks[1], &mut list_state);
+}
+
+/// Checklist of the services.yaml entries; unchecked ones are hidden.
+fn render_column_chooser(frame: &mut Frame, state: &AppState, cursor: usize) {
+    let area = frame.area();
+    let longest = state.service_configs.iter().map(|c| c.name_pattern.width()).max().unwrap_or(0);
+    let width = (longest as u16 + 10).max(40).min(area.width);
+    let height = (state.service_configs.len() as u16 + 3).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let items: Vec<ListItem> = state
+        .service_configs
+        .iter()
+        .map(|c| {
+            let shown = !state.hidden_entries.contains(&c.name_pattern);
+            ListItem::new(format!("[{}] {}", if shown { "x" } else { " " }, c.name_pattern))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(" Columns ")
+                .title_bottom(" Space:show/hide  Enter:close "),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(cursor));
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(list, popup, &mut list_state);
+}
+
+fn render_kill_chooser(frame: &mut Frame, chooser: &KillChooser) {
+    let area = frame.area();
+    let width = area.width.min(70);
+    let height = (KILL_SIGNALS.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let items: Vec<ListItem> = KILL_SIGNALS
+        .iter()
+        .map(|(signal, what)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<8}", signal), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(*what, Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(format!(" Kill {} on {} ", chooser.service, chooser.host))
+                .title_bottom(" Enter:send  Esc:cancel  (raw status moved to x) "),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(chooser.cursor));
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(list, popup, &mut list_state);
+}
+
+fn render_plugin_chooser(frame: &mut Frame, chooser: &PluginChooser) {
+    let actions = plugins::actions();
+    let area = frame.area();
+    let width = area.width.min(70);
+    let height = (actions.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let name_width = actions.iter().map(|(_, a)| a.label.chars().count()).max().unwrap_or(0) + 2;
+    let items: Vec<ListItem> = actions
+        .iter()
+        .map(|(plugin, action)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:<width$}", action.label, width = name_width),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(plugin.name.as_str(), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(format!(" Plugin action on {} on {} ", chooser.service, chooser.host))
+                .title_bottom(" Enter:run  Esc:cancel "),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(chooser.cursor));
+    frame.render_widget(Clear, popup);
+    frame.render_stateful_widget(list, popup, &mut list_state);
+}
+
+/// Centered bordered box sized to fit `message` plus the extra lines.
+fn render_popup(frame: &mut Frame, title: &str, message: &str, lines: Vec<Line>) {
+    let area = frame.area();
+    let width = area.width.min(60);
+    let text_width = width.saturating_sub(2).max(1) as usize;
+    let extra = lines.len() as u16 + 1;
+    let height = (message.chars().count().div_ceil(text_width) as u16 + extra).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(title),
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+/// Warning lines shown above the list before collapsing the rest into a count.
+const MAX_WARNING_LINES: usize = 3;
+
 fn render_main(frame: &mut Frame, state: &mut AppState) {
+    let banner_height = state.warnings.len().min(MAX_WARNING_LINES + 1) as u16;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .constraints([
+            Constraint::Length(banner_height),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
         .split(frame.area());
 
-    render_service_list(frame, state, chunks[0]);
-    render_status_bar(frame, state, chunks[1]);
+    render_warnings(frame, state, chunks[0]);
+    render_service_list(frame, state, chunks[1]);
+    render_status_bar(frame, state, chunks[2]);
+}
+
+fn render_warnings(frame: &mut Frame, state: &AppState, area: Rect) {
+    if state.warnings.is_empty() {
+        return;
+    }
+    let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let mut lines: Vec<Line> = state
+        .warnings
+        .iter()
+        .take(MAX_WARNING_LINES)
+        .map(|w| Line::from(Span::styled(format!("{} {}", caps::glyph("⚠", "!"), w), style)))
+        .collect();
+    if state.warnings.len() > MAX_WARNING_LINES {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "{} ... and {} more (see log)",
+                caps::glyph("⚠", "!"),
+                state.warnings.len() - MAX_WARNING_LINES
+            ),
+            style,
+        )));
+    }
+    frame.render_widget(Paragraph::new(lines).style(style), area);
 }
 
 fn render_service_list(frame: &mut Frame, state: &mut AppState, area: Rect) {
+    // Taken out for the duration of the render so rows can borrow `state`
+    let mut table_state = std::mem::take(&mut state.table_state);
+    render_service_table(frame, state, area, &mut table_state);
+    state.table_state = table_state;
+}
+
+fn render_service_table(frame: &mut Frame, state: &AppState, area: Rect, table_state: &mut TableState) {
     let entries = state.flat_entries();
 
     if entries.is_empty() {
-        let msg = if state.refreshing {
+        let msg = if state.refreshing() {
             "Refreshing..."
         } else {
             "No data. Press 'r' to refresh or check your config files."
@@ -41,67 +325,441 @@ fn render_service_list(frame: &mut Frame, state: &mut AppState, area: Rect) {
         return;
     }
 
+    let show_updates = state.updates_interval.is_some();
+    let (service_width, host_width) = column_widths(state, &entries);
+
     // Header
-    let header = Row::new(vec![
+    let mut header = vec![
         Cell::from("Service").style(Style::default().add_modifier(Modifier::BOLD)),
         Cell::from("Host").style(Style::default().add_modifier(Modifier::BOLD)),
         Cell::from("Status").style(Style::default().add_modifier(Modifier::BOLD)),
-    ])
-    .height(1);
+    ];
+    if show_updates {
+        header.push(Cell::from("Updates").style(Style::default().add_modifier(Modifier::BOLD)));
+    }
+    let header = Row::new(header).height(1);
 
     // Data rows
     let rows: Vec<Row> = entries
         .iter()
-        .map(|entry| match entry {
-            FlatEntry::Service { host_idx, svc_idx } => {
-                let hs = &state.grid[*host_idx][*svc_idx];
-                let status_style = status_color(&hs.status);
-
-                Row::new(vec![
-                    Cell::from(hs.service_name.as_str()),
-                    Cell::from(hs.host_address.as_str()),
-                    Cell::from(hs.status.display()).style(status_style),
-                ])
-            }
-            FlatEntry::UnreachableHost { host_idx, reason } => {
-                let host = &state.hosts[*host_idx].address;
-                let style = Style::default().fg(Color::Red);
-
-                Row::new(vec![
-                    Cell::from("").style(style),
-                    Cell::from(host.as_str()).style(style),
-                    Cell::from(reason.as_str()).style(style),
-                ])
+        .map(|entry| {
+            let mut cells = row_cells(state, entry, (service_width, host_width));
+            if show_updates {
+                cells.push(updates_cell(state, entry.host_idx()));
             }
+            Row::new(cells)
         })
         .collect();
 
-    let widths = [
-        Constraint::Length(25),
-        Constraint::Length(20),
-        Constraint::Min(10),
+    let mut widths = vec![
+        Constraint::Length(service_width),
+        Constraint::Length(host_width),
+        Constraint::Min(if state.status_glyphs { 3 } else { 10 }),
     ];
+    if show_updates {
+        widths.push(Constraint::Length(8));
+    }
 
+    let hidden = state
+        .service_configs
+        .iter()
+        .filter(|c| state.hidden_entries.contains(&c.name_pattern))
+        .count();
+    let title = match hidden {
+        0 => "Services".to_string(),
+        n => format!("Services ({} hidden, C to show)", n),
+    };
     let table = Table::new(rows, &widths)
         .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Services"))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
-    frame.render_stateful_widget(table, area, &mut state.table_state);
+    frame.render_stateful_widget(table, area, table_state);
+}
+
+/// Pending package updates on a host, blank until it has been checked.
+fn updates_cell<'a>(state: &AppState, host_idx: usize) -> Cell<'a> {
+    match state.package_updates.get(&host_idx) {
+        Some(updates) if updates.packages.is_empty() => {
+            Cell::from("0").style(Style::default().fg(Color::DarkGray))
+        }
+        Some(updates) => Cell::from(updates.packages.len().to_string()).style(Style::default().fg(Color::Yellow)),
+        None => Cell::from(""),
+    }
+}
+
+/// A cell's status, with what a certificate or last-run check found.
+fn status_text(state: &AppState, host_idx: usize, hs: &HostService) -> String {
+    let mut text = match &hs.detail.check_note {
+        Some(note) => format!("{} ({})", status_label(state, &hs.status), note),
+        None => status_label(state, &hs.status),
+    };
+    if let Some(extra) = cell_extra(state, host_idx, hs) {
+        text.push_str("  ");
+        text.push_str(&extra);
+    }
+    text
+}
+
+/// What `cell:` adds after the status, when the cell has it.
+fn cell_extra(state: &AppState, host_idx: usize, hs: &HostService) -> Option<String> {
+    match state.cell_content {
+        CellContent::Status => None,
+        CellContent::Uptime if hs.status == ServiceStatus::Active => {
+            let since = hs.detail.facts.active_since_boot?;
+            let host_uptime = state.boots.get(&host_idx)?.uptime_at(clock::now_secs());
+            Some(format!("up {}", format_uptime(host_uptime.saturating_sub(since))))
+        }
+        CellContent::Uptime => None,
+        CellContent::Restarts => match hs.detail.facts.restarts? {
+            0 => None,
+            1 => Some("1 restart".to_string()),
+            n => Some(format!("{} restarts", n)),
+        },
+        CellContent::CheckOutput => hs.detail.check_output.clone().filter(|o| !o.is_empty()),
+    }
+}
+
+/// A status as shown in the tables: a glyph with `status_glyphs:`, or the
+/// word, with an ASCII marker in front when the terminal has no color to tell
+/// statuses apart.
+fn status_label(state: &AppState, status: &ServiceStatus) -> String {
+    if state.status_glyphs {
+        return match status {
+            // The error says more than any glyph
+            ServiceStatus::Error(e) => format!("{} {}", status_glyph(status), e),
+            _ => status_glyph(status).to_string(),
+        };
+    }
+    if caps::get().colors != caps::ColorDepth::None {
+        return status.word(&state.status_words).to_string();
+    }
+    let marker = match status {
+        ServiceStatus::Active => "[ OK ]",
+        ServiceStatus::Failed | ServiceStatus::Error(_) => "[FAIL]",
+        ServiceStatus::Degraded => "[WARN]",
+        ServiceStatus::Activating | ServiceStatus::Deactivating | ServiceStatus::Reloading => "[ .. ]",
+        ServiceStatus::Masked => "[MASK]",
+        ServiceStatus::Inactive => "[ -- ]",
+        ServiceStatus::NotFound => "[ NA ]",
+        ServiceStatus::Unknown => "[ ?? ]",
+    };
+    format!("{} {}", marker, status.word(&state.status_words))
+}
+
+/// One-character status, in ASCII when the terminal has no Unicode.
+fn status_glyph(status: &ServiceStatus) -> &'static str {
+    match status {
+        ServiceStatus::Active => caps::glyph("✔", "+"),
+        ServiceStatus::Failed | ServiceStatus::Error(_) => caps::glyph("✖", "x"),
+        ServiceStatus::Degraded => caps::glyph("⚠", "!"),
+        ServiceStatus::Activating => caps::glyph("↑", "^"),
+        ServiceStatus::Deactivating => caps::glyph("↓", "v"),
+        ServiceStatus::Reloading => caps::glyph("↻", "~"),
+        ServiceStatus::Masked => caps::glyph("⊘", "#"),
+        ServiceStatus::Inactive => caps::glyph("○", "o"),
+        ServiceStatus::NotFound => caps::glyph("·", "-"),
+        ServiceStatus::Unknown => "?",
+    }
+}
+
+/// Service and host column widths when not fitted to their content.
+const SERVICE_WIDTH: u16 = 25;
+const HOST_WIDTH: u16 = 30;
+/// Fitted columns stop growing here, so one long name can't push the status
+/// off screen.
+const MAX_FIT_WIDTH: u16 = 60;
+
+/// Widths of the service and host columns: fixed, or fitted to their longest
+/// entry when toggled with `w`.
+fn column_widths(state: &AppState, entries: &[FlatEntry]) -> (u16, u16) {
+    if !state.fit_columns {
+        return (SERVICE_WIDTH, HOST_WIDTH);
+    }
+    let fit = |width: usize| (width as u16).clamp(4, MAX_FIT_WIDTH);
+    let service = entries.iter().map(|e| name_line(state, e).width()).max().unwrap_or(0);
+    let host = entries.iter().map(|e| host_line(state, e).width()).max().unwrap_or(0);
+    (fit(service), fit(host))
+}
+
+/// Cut a line to `width` columns, ending it with an ellipsis if anything was cut.
+fn truncate_line(line: Line<'_>, width: u16) -> Line<'_> {
+    let width = width as usize;
+    if line.width() <= width {
+        return line;
+    }
+    let ellipsis = caps::glyph("…", "~");
+    let budget = width.saturating_sub(ellipsis.width());
+    let mut used = 0;
+    let mut spans = Vec::new();
+    for span in &line.spans {
+        let mut content = String::new();
+        for c in span.content.chars() {
+            let w = c.width().unwrap_or(0);
+            if used + w > budget {
+                break;
+            }
+            used += w;
+            content.push(c);
+        }
+        let cut = content.len() < span.content.len();
+        spans.push(Span::styled(content, span.style));
+        if cut {
+            break;
+        }
+    }
+    spans.push(Span::raw(ellipsis));
+    Line::from(spans).style(line.style)
+}
+
+/// What the service column shows for a row, before truncation.
+fn name_line<'a>(state: &'a AppState, entry: &FlatEntry) -> Line<'a> {
+    match entry {
+        FlatEntry::Service { host_idx, svc_idx } => {
+            let hs = &state.grid[*host_idx][*svc_idx];
+            let mut name = service_name_line(&hs.service_name);
+            annotate_name(&mut name, state, &hs.host_address, &hs.service_name);
+            if state.is_grouped_child(hs) {
+                name.spans.insert(0, Span::raw("  "));
+            }
+            if state.compare_mark == Some((*host_idx, *svc_idx)) {
+                name.spans.push(Span::styled(format!(" {}", caps::glyph("⇄", "<>")), Style::default().fg(Color::Magenta)));
+            }
+            name
+        }
+        FlatEntry::Group {
+            host_idx,
+            pattern,
+            svc_idxs,
+            ..
+        } => {
+            let host = &state.hosts[*host_idx].address;
+            let expanded = state
+                .expanded_groups
+                .contains(&(host.clone(), pattern.clone()));
+            let marker = if expanded { caps::glyph("▾", "v") } else { caps::glyph("▸", ">") };
+            let mut name = Line::from(format!("{} {} ({})", marker, pattern, svc_idxs.len()));
+            annotate_name(&mut name, state, host, pattern);
+            name.style(Style::default().add_modifier(Modifier::BOLD))
+        }
+        FlatEntry::UnreachableHost { host_idx, error } => {
+            let host = &state.hosts[*host_idx].address;
+            let mut name = Line::from("");
+            annotate_name(&mut name, state, host, "");
+            name.style(unreachable_style(state, host, error))
+        }
+    }
+}
+
+/// What the host column shows for a row, before truncation.
+fn host_line<'a>(state: &'a AppState, entry: &FlatEntry) -> Line<'a> {
+    match entry {
+        FlatEntry::UnreachableHost { host_idx, error } => {
+            let host = &state.hosts[*host_idx].address;
+            Line::from(host.as_str()).style(unreachable_style(state, host, error))
+        }
+        _ => host_label(state, entry.host_idx()),
+    }
+}
+
+fn unreachable_style(state: &AppState, host: &str, error: &HostError) -> Style {
+    if state.is_silenced(host, "") {
+        silenced_style()
+    } else if *error == HostError::Dns {
+        // Set apart from connection failures: it's a naming problem, not a dead host
+        Style::default().fg(Color::Magenta)
+    } else {
+        Style::default().fg(Color::Red)
+    }
+}
+
+/// Service, host and status cells of one row of the main table, with the
+/// first two cut to the column widths.
+fn row_cells<'a>(state: &'a AppState, entry: &FlatEntry, widths: (u16, u16)) -> Vec<Cell<'a>> {
+    let status_cell = match entry {
+        FlatEntry::Service { host_idx, svc_idx } => {
+            let hs = &state.grid[*host_idx][*svc_idx];
+            let silenced = state.is_silenced(&hs.host_address, &hs.service_name);
+            match state.pending_action(&hs.host_address, &hs.service_name) {
+                Some(pending) => Cell::from(format!("{} {}", state.spinner(), pending.label()))
+                    .style(pending_style()),
+                None if silenced => Cell::from(format!(
+                    "{} ({})",
+                    status_label(state, &hs.status),
+                    silence_label(state, &hs.host_address, &hs.service_name)
+                ))
+                .style(silenced_style()),
+                None if state.restart_recommended(hs) => Cell::from(Line::from(vec![
+                    Span::styled(status_label(state, &hs.status), status_color(&hs.status)),
+                    Span::styled(
+                        format!(" {} restart recommended", caps::glyph("⟳", "*")),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ])),
+                None => Cell::from(status_text(state, *host_idx, hs)).style(status_color(&hs.status)),
+            }
+        }
+        FlatEntry::Group {
+            host_idx,
+            pattern,
+            svc_idxs,
+            status,
+        } => {
+            let host = &state.hosts[*host_idx].address;
+            let worst_count = svc_idxs
+                .iter()
+                .filter(|&&i| state.grid[*host_idx][i].status == *status)
+                .count();
+            let status_style = if state.is_silenced(host, pattern) {
+                silenced_style()
+            } else {
+                status_color(status)
+            };
+            Cell::from(format!("{} ({}/{})", status_label(state, status), worst_count, svc_idxs.len()))
+                .style(status_style)
+        }
+        FlatEntry::UnreachableHost { host_idx, error } => {
+            let host = &state.hosts[*host_idx].address;
+            Cell::from(format!("{} ({})", error.reason(), error.hint())).style(unreachable_style(state, host, error))
+        }
+    };
+    vec![
+        Cell::from(truncate_line(name_line(state, entry), widths.0)),
+        Cell::from(truncate_line(host_line(state, entry), widths.1)),
+        status_cell,
+    ]
+}
+
+/// Host address with a pin when pinned, its uptime, a lock when stop/restart
+/// would fail for lack of sudo, a marker when it rebooted since the last
+/// refresh, its systemd state unless plainly running, and a marker when it
+/// is excluded from full refreshes.
+fn host_label(state: &AppState, host_idx: usize) -> Line<'_> {
+    let host = state.hosts[host_idx].address.as_str();
+    let mut spans = Vec::new();
+    if state.pinned_hosts.contains(&host_idx) {
+        spans.push(Span::styled(caps::glyph("📌", "^"), Style::default().fg(Color::Cyan)));
+    }
+    if state.rebooted_hosts.contains(&host_idx) {
+        spans.push(Span::styled(caps::glyph("↻", "R!"), Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    }
+    if state.no_sudo_hosts.contains(&host_idx) {
+        spans.push(Span::styled(caps::glyph("🔒", "#"), Style::default().fg(Color::Yellow)));
+    }
+    spans.push(Span::raw(host));
+    if let Some(boot) = state.boots.get(&host_idx) {
+        spans.push(Span::styled(
+            format!(" up {}", format_uptime(boot.uptime_at(clock::now_secs()))),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    if let Some(system) = state.system_states.get(&host_idx).filter(|s| s.is_notable()) {
+        let color = if system.state == "degraded" || !system.stuck.is_empty() {
+            Color::Red
+        } else {
+            Color::Yellow
+        };
+        spans.push(Span::styled(format!(" [{}]", system.summary()), Style::default().fg(color)));
+    }
+    if state.excluded_hosts.contains(&host_idx) {
+        spans.push(Span::styled(" (excluded)", Style::default().fg(Color::DarkGray)));
+    }
+    Line::from(spans)
+}
+
+/// Uptime in days once past one, e.g. `3d4h`; otherwise like `5h30m`.
+fn format_uptime(secs: u64) -> String {
+    match secs / 86400 {
+        0 => clock::format_duration(std::time::Duration::from_secs(secs)),
+        days => format!("{}d{}h", days, secs % 86400 / 3600),
+    }
 }
 
 fn render_status_bar(frame: &mut Frame, state: &AppState, area: Rect) {
-    let status_text = if state.refreshing {
-        "Refreshing..."
+    let status_text = if state.refreshing() {
+        "Refreshing... (Esc to cancel)"
     } else {
-        "r:refresh  Enter:detail  c:ssh  s:stop  t:restart  q:quit"
+        "r:refresh  L:refresh host  G:scope  Enter:detail  g:group globs  m:silence  f:favorite  n:note  z/Z:undo/redo  v:compare  a:activity  p:report  i:stats  d:slow hosts  w:fit columns  C:columns  P:pin host  x:raw status  !:triage  K:kill  X:plugin action  M:unmask  R:reset failed  c:ssh  o:ssh to any host  O:shells to group  Y:share view  h:host  u/U:ssh user  e:run on group  s:stop  t:restart  S/T:on all hosts  q:quit"
     };
 
-    let bar = Paragraph::new(Line::from(Span::styled(
-        status_text,
-        Style::default().fg(Color::DarkGray),
-    )));
-    frame.render_widget(bar, area);
+    let mut line = status_line(state, status_text);
+    if let Some(entry) = state.selected_entry() {
+        // The full names of the selected row when the columns cut them off
+        let entries = state.flat_entries();
+        let (service_width, host_width) = column_widths(state, &entries);
+        let cut = name_line(state, &entry).width() > service_width as usize
+            || host_line(state, &entry).width() > host_width as usize;
+        if cut {
+            let (host, service) = state.entry_cell(&entry);
+            let full = if service.is_empty() { host } else { format!("{} on {}", service, host) };
+            line.spans.insert(0, Span::styled(format!("{}  ", full), Style::default().add_modifier(Modifier::BOLD)));
+        }
+    }
+    let note = state.selected_entry().and_then(|entry| {
+        let (host, service) = state.entry_cell(&entry);
+        state.annotations.data.note(&host, &service).cloned()
+    });
+    if let Some(note) = note {
+        line.spans.insert(
+            0,
+            Span::styled(
+                format!("{} {}: {}  ", caps::glyph("✎", "note"), note.by, note.text.lines().next().unwrap_or("")),
+                Style::default().fg(Color::Cyan),
+            ),
+        );
+    }
+    if let Some(wait) = state.next_refresh_in() {
+        // Round up, so it reads 1s rather than 0s just before the refresh
+        let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        line.spans.insert(
+            0,
+            Span::styled(
+                format!("refresh in {}  ", clock::format_duration(std::time::Duration::from_secs(secs))),
+                Style::default().fg(Color::Blue),
+            ),
+        );
+    }
+    if state.scope != Scope::All {
+        line.spans.insert(
+            0,
+            Span::styled(
+                format!("scope: {}  ", state.scope.label()),
+                Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+            ),
+        );
+    }
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+/// Prefix favorites with a star and suffix cells that carry a note.
+fn annotate_name(name: &mut Line, state: &AppState, host: &str, service: &str) {
+    if state.annotations.data.is_favorite(host, service) {
+        name.spans
+            .insert(0, Span::styled(caps::glyph("★ ", "* "), Style::default().fg(Color::Yellow)));
+    }
+    if state.annotations.data.note(host, service).is_some() {
+        name.spans
+            .push(Span::styled(caps::glyph(" ✎", " +n"), Style::default().fg(Color::Cyan)));
+    }
+}
+
+/// Key hints, prefixed by the latest status message if there is one.
+fn status_line<'a>(state: &'a AppState, hints: &'a str) -> Line<'a> {
+    let mut spans = Vec::new();
+    if let Some(reg) = state.macros.recording() {
+        spans.push(Span::styled(
+            format!("recording @{}  ", reg),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(msg) = &state.status_message {
+        spans.push(Span::styled(
+            format!("{}  ", msg),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    spans.push(Span::styled(hints, Style::default().fg(Color::DarkGray)));
+    Line::from(spans)
 }
 
 fn render_detail(frame: &mut Frame, state: &AppState, host_idx: usize, svc_idx: usize) {
@@ -134,7 +792,27 @@ fn render_detail(frame: &mut Frame, state: &AppState, host_idx: usize, svc_idx:
                 .add_modifier(Modifier::BOLD),
         ))));
         for c in &hs.config.commands {
-            items.push(ListItem::new(format!("  {}", c)));
+            if hs.config.interactive.contains(c) {
+                items.push(ListItem::new(format!("  {}  (interactive)", c)));
+            } else {
+                items.push(ListItem::new(format!("  {}", c)));
+            }
+        }
+    }
+
+    let unit_files = state.extra_unit_files(host_idx, svc_idx);
+    if !unit_files.is_empty() {
+        items.push(ListItem::new(Line::from(Span::styled(
+            "--- Unit files ---",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ))));
+        for f in unit_files {
+            items.push(ListItem::new(Line::from(vec![
+                Span::raw(format!("  {}", f.path)),
+                Span::styled(format!("  ({})", f.kind), Style::default().fg(Color::DarkGray)),
+            ])));
         }
     }
 
@@ -151,35 +829,807 @@ fn render_detail(frame: &mut Frame, state: &AppState, host_idx: usize, svc_idx:
         })
         .collect();
 
+    let status_text = match state.pending_action(&hs.host_address, &hs.service_name) {
+        Some(pending) => format!("{} {}", state.spinner(), pending.label()),
+        None => status_label(state, &hs.status),
+    };
+    let title = format!(" {}:{} [{}] ", hs.host_address, hs.service_name, status_text);
+
+    let mut block = Block::default().borders(Borders::ALL).title(title);
+    if !hs.config.merged.is_empty() {
+        // The unit matched several entries; say where its files and commands come from
+        let patterns: Vec<String> = std::iter::once(&hs.config.name_pattern)
+            .chain(&hs.config.merged)
+            .map(|p| format!("'{}'", p))
+            .collect();
+        block = block.title_bottom(Line::from(Span::styled(
+            format!(" matched by {}, merged ", patterns.join(", ")),
+            Style::default().fg(Color::Cyan),
+        )));
+    }
+    let list = List::new(items).block(block);
+
+    frame.render_widget(list, chunks[0]);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "Enter:view in vim  x:hexdump  A:view all  F:follow file  p:properties  D:dependencies  !:triage  r:refresh  c:ssh  s:stop  t:restart  K:kill  X:plugin action  M:unmask  R:reset failed  Y:share view  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_host(frame: &mut Frame, state: &AppState, host_idx: usize) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let host = &state.hosts[host_idx];
+    let mut items: Vec<ListItem> = state
+        .host_commands
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let item = ListItem::new(format!("  {}", c));
+            if i == state.detail_cursor {
+                item.style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Line::from(Span::styled(
+            "No host_commands configured in services.yaml",
+            Style::default().fg(Color::DarkGray),
+        ))));
+    }
+    if let Some(system) = state.system_states.get(&host_idx).filter(|s| !s.jobs.is_empty()) {
+        items.push(ListItem::new(""));
+        items.push(ListItem::new(Line::from(Span::styled(
+            "--- Queued systemd jobs ---",
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ))));
+        for job in &system.jobs {
+            let stuck = system.stuck.iter().any(|j| j.id == job.id);
+            let line = format!(
+                "  {:>7} {:<40} {:<10} {}{}",
+                job.id,
+                job.unit,
+                job.kind,
+                job.state,
+                if stuck { " (stuck)" } else { "" }
+            );
+            let style = if stuck { Style::default().fg(Color::Red) } else { Style::default() };
+            items.push(ListItem::new(line).style(style));
+        }
+    }
+    if let Some(updates) = state.package_updates.get(&host_idx) {
+        items.push(ListItem::new(""));
+        items.push(ListItem::new(Line::from(Span::styled(
+            format!("--- Pending package updates ({}, {}) ---", updates.manager, updates.packages.len()),
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ))));
+        for package in &updates.packages {
+            items.push(ListItem::new(format!("  {:<40} {}", package.name, package.version)));
+        }
+    }
+
+    let status = match state.unreachable_hosts.get(&host_idx) {
+        Some(error) => error.reason().to_string(),
+        None => "reachable".to_string(),
+    };
+    let took = metrics::snapshot()
+        .host_refresh
+        .get(&host.address)
+        .map(|d| format!(", refreshed in {:.0}ms", d.as_secs_f64() * 1000.0))
+        .unwrap_or_default();
+    let uptime = match state.boots.get(&host_idx) {
+        Some(boot) if state.rebooted_hosts.contains(&host_idx) => {
+            format!(", rebooted, up {}", format_uptime(boot.uptime_at(clock::now_secs())))
+        }
+        Some(boot) => format!(", up {}", format_uptime(boot.uptime_at(clock::now_secs()))),
+        None => String::new(),
+    };
+    let system = state
+        .system_states
+        .get(&host_idx)
+        .map(|s| format!(", {}", s.summary()))
+        .unwrap_or_default();
+    let title = format!(" {} ({}) [{}{}{}{}] ", host.address, host.group, status, system, uptime, took);
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(list, chunks[0]);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "Enter:view in vim  x:hexdump  A:view all  R:reset failed units  c:ssh  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_slow_hosts(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let slow = state.slow_hosts();
+    let mut cumulative = 0.0;
+    let mut items: Vec<ListItem> = slow
+        .iter()
+        .map(|&(idx, took, share)| {
+            cumulative += share;
+            let host = &state.hosts[idx];
+            let note = if state.excluded_hosts.contains(&idx) {
+                "excluded".to_string()
+            } else if let Some(error) = state.unreachable_hosts.get(&idx) {
+                error.reason().to_string()
+            } else {
+                String::new()
+            };
+            let color = if state.unreachable_hosts.contains_key(&idx) { Color::Red } else { Color::Reset };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!(
+                    "  {:<30} {:>8.0}ms {:>5.1}% {:>6.1}%  ",
+                    host.address,
+                    took.as_secs_f64() * 1000.0,
+                    share * 100.0,
+                    cumulative * 100.0
+                )),
+                Span::styled(note, Style::default().fg(color)),
+            ]))
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(ListItem::new(Line::from(Span::styled(
+            "No full refresh has completed yet",
+            Style::default().fg(Color::DarkGray),
+        ))));
+    }
+    let total: std::time::Duration = slow.iter().map(|(_, took, _)| *took).sum();
+    let title = format!(
+        " Slow hosts: {:.1}s over {} hosts in the last refresh (host, time, share, cumulative) ",
+        total.as_secs_f64(),
+        slow.len()
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected((!slow.is_empty()).then_some(state.detail_cursor));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "Up/Down:select  Enter:host view  x:exclude/include in full refreshes  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_timeline(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let items: Vec<ListItem> = state
+        .timeline
+        .entries
+        .iter()
+        .map(|entry| {
+            let color = match entry.kind {
+                timeline::Kind::Refresh => Color::DarkGray,
+                timeline::Kind::Transition => Color::Yellow,
+                timeline::Kind::Action | timeline::Kind::Exec => Color::Cyan,
+                timeline::Kind::Result => Color::Green,
+                timeline::Kind::Notification => Color::Magenta,
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    clock::format_time(entry.at, 0, "%H:%M:%S  "),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(format!("{:<7}  ", entry.kind.label()), Style::default().fg(color)),
+                Span::raw(entry.text.as_str()),
+            ]))
+        })
+        .collect();
+    let title = format!(" Session timeline ({} entries, UTC) ", items.len());
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(state.detail_cursor));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let bar = Paragraph::new(status_line(state, "Up/Down/PgUp/PgDn/End:scroll  Enter:open in vim  q:back"));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_compare(frame: &mut Frame, state: &AppState, left: (usize, usize), right: (usize, usize)) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let items: Vec<ListItem> = state
+        .compare_items(left, right)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (label, _, _))| {
+            let item = ListItem::new(format!("  {}", label));
+            if i == state.detail_cursor {
+                item.style(Style::default().add_modifier(Modifier::REVERSED))
+            } else {
+                item
+            }
+        })
+        .collect();
+
+    let (a, b) = (&state.grid[left.0][left.1], &state.grid[right.0][right.1]);
     let title = format!(
-        " {}:{} [{}] ",
-        hs.host_address,
-        hs.service_name,
-        hs.status.display()
+        " {}:{} [{}]  vs  {}:{} [{}] ",
+        a.host_address,
+        a.service_name,
+        a.status.word(&state.status_words),
+        b.host_address,
+        b.service_name,
+        b.status.word(&state.status_words)
     );
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(list, chunks[0]);
 
-    let list = List::new(items).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title(title),
+    let bar = Paragraph::new(status_line(state, "Enter:compare side by side in vim  q:back"));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_adhoc(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let Some(run) = &state.adhoc else {
+        return;
+    };
+    let mut items = Vec::new();
+    let mut selected = 0;
+    for (i, result) in run.results.iter().enumerate() {
+        if i == run.cursor {
+            selected = items.len();
+        }
+        let marker = if result.expanded { caps::glyph("▾", "v") } else { caps::glyph("▸", ">") };
+        let (summary, style, output) = match &result.run {
+            HostRun::Running => (
+                format!("{} running", state.spinner()),
+                pending_style(),
+                None,
+            ),
+            HostRun::Done { code, duration, output } => (
+                match code {
+                    Some(code) => format!("exit {}  {}", code, format_elapsed(*duration)),
+                    None => format!("cut off  {}", format_elapsed(*duration)),
+                },
+                if *code == Some(0) {
+                    Style::default().fg(Color::Green)
+                } else {
+                    Style::default().fg(Color::Red)
+                },
+                Some(output.as_str()),
+            ),
+            HostRun::Failed { error, duration } => (
+                format!("failed  {}", format_elapsed(*duration)),
+                Style::default().fg(Color::Red),
+                Some(error.as_str()),
+            ),
+        };
+        items.push(ListItem::new(Line::from(vec![
+            Span::raw(format!("{} {:<30} ", marker, result.host)),
+            Span::styled(summary, style),
+        ])));
+        if result.expanded {
+            for line in output.unwrap_or("").lines() {
+                items.push(ListItem::new(format!("    {}", line)));
+            }
+        }
+    }
+
+    let (ok, failed, running) = run.counts();
+    let title = format!(
+        " {} on {}: {} ok, {} failed, {} running ",
+        run.command, run.target, ok, failed, running
     );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
 
-    frame.render_widget(list, chunks[0]);
+    let bar = Paragraph::new(status_line(
+        state,
+        "Enter:expand/collapse  +/-:expand/collapse all  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
 
-    let bar = Paragraph::new(Line::from(Span::styled(
-        "Enter:view in vim  r:refresh  c:ssh  s:stop  t:restart  q:back",
-        Style::default().fg(Color::DarkGray),
-    )));
+fn render_bulk(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let Some(run) = &state.bulk else {
+        return;
+    };
+    let items: Vec<ListItem> = run
+        .targets
+        .iter()
+        .map(|target| {
+            let marker = if target.selected { "[x]" } else { "[ ]" };
+            let (summary, style) = match &target.outcome {
+                Outcome::Queued => ("queued".to_string(), Style::default().fg(Color::DarkGray)),
+                Outcome::Running => (format!("{} running", state.spinner()), pending_style()),
+                Outcome::Ok(duration) => (
+                    format!("ok  {}", format_elapsed(*duration)),
+                    Style::default().fg(Color::Green),
+                ),
+                Outcome::Failed { error, duration } => (
+                    format!("failed  {}  {}", format_elapsed(*duration), error.replace('\n', " ")),
+                    Style::default().fg(Color::Red),
+                ),
+                Outcome::TimedOut(duration) => (
+                    format!("timeout  {}", format_elapsed(*duration)),
+                    Style::default().fg(Color::Magenta),
+                ),
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} {:<30} ", marker, target.host)),
+                Span::styled(summary, style),
+            ]))
+        })
+        .collect();
+
+    let (ok, failed, timed_out, pending) = run.counts();
+    let title = format!(
+        " {} {}: {} ok, {} failed, {} timed out, {} pending ",
+        run.action, run.service, ok, failed, timed_out, pending
+    );
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected(Some(run.cursor));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "Space:select  r:retry selected (or all failed)  q:back",
+    ));
     frame.render_widget(bar, chunks[1]);
 }
 
+/// `850ms` or `2.3s`.
+fn format_elapsed(d: std::time::Duration) -> String {
+    if d.as_millis() < 1000 {
+        format!("{}ms", d.as_millis())
+    } else {
+        format!("{:.1}s", d.as_secs_f64())
+    }
+}
+
+fn render_tail(frame: &mut Frame, state: &mut AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let Some(view) = state.tail.as_mut() else {
+        return;
+    };
+    view.height = chunks[0].height.saturating_sub(2) as usize;
+    let mode = match &view.ended {
+        Some(Some(e)) => format!("failed: {}", e),
+        Some(None) => "ended".to_string(),
+        None if view.follow => "following".to_string(),
+        None => "paused".to_string(),
+    };
+    let title = format!(" {} [{}] ", view.title, mode);
+    let lines: Vec<Line> = view.visible().map(|l| Line::raw(l.as_str())).collect();
+    let para = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(para, chunks[0]);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "F:toggle follow  Up/Down/PgUp/PgDn:scroll  End:follow  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_properties(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let Some(view) = state.properties.as_ref() else {
+        return;
+    };
+    let visible = view.visible();
+    let mut items: Vec<ListItem> = visible
+        .iter()
+        .map(|(name, value)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("  {:<24} ", name), Style::default().fg(Color::Cyan)),
+                Span::raw(value.as_str()),
+            ]))
+        })
+        .collect();
+    let placeholder = match &view.properties {
+        None => Some("Loading...".to_string()),
+        Some(Err(e)) => Some(format!("systemctl show failed: {}", e)),
+        Some(Ok(_)) if items.is_empty() => Some("No matching properties".to_string()),
+        Some(Ok(_)) => None,
+    };
+    if let Some(text) = placeholder {
+        items.push(ListItem::new(Line::from(Span::styled(text, Style::default().fg(Color::DarkGray)))));
+    }
+    let shown = if !view.query.is_empty() {
+        format!("matching '{}'", view.query)
+    } else if view.show_all {
+        "all".to_string()
+    } else {
+        "key properties".to_string()
+    };
+    let title = format!(" Properties of {} [{}] ", view.title, shown);
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected((!visible.is_empty()).then_some(view.cursor));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    if view.searching {
+        let bar = Paragraph::new(Line::from(vec![
+            Span::styled("/", Style::default().fg(Color::Yellow)),
+            Span::raw(view.query.as_str()),
+            Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+        ]));
+        frame.render_widget(bar, chunks[1]);
+    } else {
+        let bar = Paragraph::new(status_line(
+            state,
+            "/:search  a:all properties  Enter:full value  r:reload  q:back",
+        ));
+        frame.render_widget(bar, chunks[1]);
+    }
+}
+
+fn render_deps(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let Some(view) = state.deps.as_ref() else {
+        return;
+    };
+    let visible = view.visible();
+    let mut items: Vec<ListItem> = match &view.nodes {
+        Some(Ok(nodes)) => visible
+            .iter()
+            .map(|&i| {
+                let node = &nodes[i];
+                let marker = if !view.has_children(i) {
+                    "  "
+                } else if view.collapsed.contains(&i) {
+                    caps::glyph("▸ ", "> ")
+                } else {
+                    caps::glyph("▾ ", "v ")
+                };
+                let mut spans = vec![
+                    Span::raw(format!("{}{}", "  ".repeat(node.depth), marker)),
+                    Span::raw(format!("{:<40} ", node.unit)),
+                    Span::styled(status_label(state, &node.status), status_color(&node.status)),
+                ];
+                if view.collapsed.contains(&i) {
+                    let (count, worst) = view.hidden_summary(i);
+                    spans.push(Span::styled(
+                        format!("  (+{}, worst {})", count, worst.word(&state.status_words)),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let placeholder = match &view.nodes {
+        None => Some("Loading...".to_string()),
+        Some(Err(e)) => Some(format!("systemctl list-dependencies failed: {}", e)),
+        Some(Ok(nodes)) if nodes.is_empty() => Some("No dependencies".to_string()),
+        Some(Ok(_)) => None,
+    };
+    if let Some(text) = placeholder {
+        items.push(ListItem::new(Line::from(Span::styled(text, Style::default().fg(Color::DarkGray)))));
+    }
+    let title = format!(" Dependencies of {} on {} ", view.unit, view.host);
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut list_state = ListState::default().with_selected((!visible.is_empty()).then_some(view.cursor));
+    frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+    let bar = Paragraph::new(status_line(
+        state,
+        "Enter:expand/collapse  +/-:expand/collapse all  r:reload  q:back",
+    ));
+    frame.render_widget(bar, chunks[1]);
+}
+
+fn render_stats(frame: &mut Frame, state: &AppState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+
+    let m = metrics::snapshot();
+    let ms = |d: std::time::Duration| format!("{:.0}ms", d.as_secs_f64() * 1000.0);
+    let heading = |text: &'static str| {
+        ListItem::new(Line::from(Span::styled(
+            text,
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        )))
+    };
+
+    let mut items = vec![
+        heading("--- Refresh ---"),
+        ListItem::new(format!("  Full refreshes:      {}", m.refreshes_total)),
+        ListItem::new(format!("  Last refresh:        {}", ms(m.last_refresh))),
+        heading("--- Commands ---"),
+        ListItem::new(format!("  Remote commands:     {}", m.commands_total)),
+        ListItem::new(format!(
+            "  Failed:              {} ({:.1}%)",
+            m.command_errors_total,
+            m.command_error_rate() * 100.0
+        )),
+        ListItem::new(format!("  Retried (transport): {}", m.command_retries_total)),
+        ListItem::new(format!("  SSH reconnects:      {}", m.ssh_reconnects_total)),
+        heading("--- Event loop ---"),
+        ListItem::new(format!("  Latency (last/max):  {} / {}", ms(m.loop_latency), ms(m.loop_latency_max))),
+        heading("--- Per host (last refresh, connect failures) ---"),
+    ];
+    // Slowest hosts first, as those are what need tuning
+    let mut hosts: Vec<_> = m.host_refresh.iter().collect();
+    hosts.sort_by(|a, b| b.1.cmp(a.1));
+    for (host, duration) in hosts {
+        let failures = m.ssh_connect_failures.get(host).copied().unwrap_or(0);
+        items.push(ListItem::new(format!("  {:<30} {:>8}  {}", host, ms(*duration), failures)));
+    }
+
+    let list = List::new(items).block(Block::default().borders(Borders::ALL).title(" Stats "));
+    frame.render_widget(list, chunks[0]);
+    frame.render_widget(Paragraph::new(status_line(state, "i/q:back")), chunks[1]);
+}
+
+/// Service name with a template instance (`getty@tty1`) highlighted.
+fn service_name_line(name: &str) -> Line<'_> {
+    match template_parts(name) {
+        Some((prefix, _)) => {
+            let split = prefix.len() + 1;
+            Line::from(vec![
+                Span::raw(&name[..split]),
+                Span::styled(&name[split..], Style::default().fg(Color::Cyan)),
+            ])
+        }
+        None => Line::from(name),
+    }
+}
+
+/// Why a cell is silenced: the maintenance window's name, or a manual silence.
+fn silence_label(state: &AppState, host: &str, service: &str) -> String {
+    match state.active_maintenance(host, service) {
+        Some(window) => format!("maintenance: {}", window.name),
+        None => "silenced".to_string(),
+    }
+}
+
+fn silenced_style() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+fn pending_style() -> Style {
+    Style::default().fg(Color::Cyan).add_modifier(Modifier::ITALIC)
+}
+
 fn status_color(status: &ServiceStatus) -> Style {
     match status {
         ServiceStatus::Active => Style::default().fg(Color::Green),
         ServiceStatus::Failed => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
         ServiceStatus::Inactive => Style::default().fg(Color::Yellow),
+        ServiceStatus::Degraded => Style::default().fg(Color::Rgb(255, 165, 0)),
+        ServiceStatus::Activating => Style::default().fg(Color::LightGreen),
+        ServiceStatus::Deactivating => Style::default().fg(Color::LightYellow),
+        ServiceStatus::Reloading => Style::default().fg(Color::LightBlue),
+        ServiceStatus::Masked => Style::default().fg(Color::Magenta).add_modifier(Modifier::CROSSED_OUT),
         ServiceStatus::NotFound => Style::default().fg(Color::DarkGray),
         ServiceStatus::Unknown => Style::default().fg(Color::Gray),
         ServiceStatus::Error(_) => Style::default().fg(Color::Red),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Severity;
+    use crate::monitor::status::BootInfo;
+    use crate::tui::testing::{assert_snapshot, draw, fg_of, fixture, text};
+
+    #[test]
+    fn test_main_screen() {
+        let mut state = fixture();
+        assert_snapshot("main", &text(&draw(&mut state, 120, 14)));
+    }
+
+    #[test]
+    fn test_main_screen_glyphs() {
+        let mut state = fixture();
+        state.status_glyphs = true;
+        assert_snapshot("main_glyphs", &text(&draw(&mut state, 80, 14)));
+    }
+
+    #[test]
+    fn test_detail_screen() {
+        let mut state = fixture();
+        state.screen = Screen::Detail {
+            host_index: 0,
+            service_index: 1,
+        };
+        assert_snapshot("detail", &text(&draw(&mut state, 80, 12)));
+    }
+
+    #[test]
+    fn test_status_colors() {
+        let mut state = fixture();
+        let buffer = draw(&mut state, 120, 14);
+        assert_eq!(fg_of(&buffer, "FAILED"), Some(Color::Red));
+        assert_eq!(fg_of(&buffer, "inactive"), Some(Color::Yellow));
+        assert_eq!(fg_of(&buffer, "activating"), Some(Color::LightGreen));
+    }
+
+    #[test]
+    fn test_failures_sort_first() {
+        let mut state = fixture();
+        let screen = text(&draw(&mut state, 120, 14));
+        let row = |needle: &str| screen.lines().position(|l| l.contains(needle)).unwrap();
+        // Unreachable hosts, then hosts with failures, each with its worst cell first
+        assert!(row("db01") < row("FAILED"));
+        assert!(row("FAILED") < row("web01                          active"));
+        assert!(row("web01") < row("web02"));
+    }
+
+    #[test]
+    fn test_group_sorts_by_its_most_severe_service() {
+        let mut state = fixture();
+        let glob = |name: &str, status, severity| {
+            let mut hs = state.grid[0][0].clone();
+            hs.service_name = name.to_string();
+            hs.config.name_pattern = "worker-*".to_string();
+            hs.config.is_glob = true;
+            hs.config.severity = severity;
+            hs.status = status;
+            hs
+        };
+        let workers = [
+            glob("worker-a", ServiceStatus::Failed, Severity::Info),
+            glob("worker-b", ServiceStatus::Active, Severity::Critical),
+        ];
+        state.grid[0].extend(workers);
+        state.grid[0][1].config.severity = Severity::Warning;
+        state.group_globs = true;
+        let failed: Vec<FlatEntry> = state
+            .flat_entries()
+            .into_iter()
+            .filter(|e| !matches!(e, FlatEntry::UnreachableHost { .. }))
+            .take(2)
+            .collect();
+        assert!(matches!(&failed[0], FlatEntry::Group { pattern, .. } if pattern == "worker-*"));
+        assert!(matches!(failed[1], FlatEntry::Service { host_idx: 0, svc_idx: 1 }));
+    }
+
+    #[test]
+    fn test_host_refresh_replaces_restart_needed() {
+        use crate::monitor::status::GridResult;
+        use std::collections::{HashMap, HashSet};
+        let mut state = fixture();
+        state.restart_needed = HashMap::from([
+            (0, HashSet::from(["nginx.service".to_string()])),
+            (1, HashSet::from(["app.service".to_string()])),
+        ]);
+        let refresh = |state: &AppState, restart_needed| GridResult {
+            service_names: state.service_names.clone(),
+            grid: state.grid.clone(),
+            unreachable_hosts: HashMap::new(),
+            warnings: Vec::new(),
+            no_sudo: HashSet::new(),
+            skipped: HashSet::from([1, 2]),
+            boots: HashMap::new(),
+            system: HashMap::new(),
+            restart_needed,
+        };
+        let app = HashMap::from([(0, HashSet::from(["app.service".to_string()]))]);
+        state.apply_host_result(refresh(&state, app.clone()));
+        assert_eq!(state.restart_needed[&0], app[&0]);
+        assert!(state.restart_needed.contains_key(&1));
+
+        // web01's check failed this time; web02 wasn't refreshed
+        state.apply_host_result(refresh(&state, HashMap::new()));
+        assert!(!state.restart_needed.contains_key(&0));
+        assert!(state.restart_needed.contains_key(&1));
+    }
+
+    #[test]
+    fn test_hidden_columns() {
+        let mut state = fixture();
+        state.hidden_entries.insert("app".to_string());
+        let screen = text(&draw(&mut state, 120, 14));
+        assert!(screen.contains("1 hidden, C to show"));
+        assert!(!screen.contains("FAILED"));
+    }
+
+    #[test]
+    fn test_cell_extra_from_detail() {
+        let mut state = fixture();
+        state.grid[1][0].detail.facts.active_since_boot = Some(100);
+        state.boots.insert(
+            1,
+            BootInfo {
+                boot_id: "b".to_string(),
+                uptime: 3700,
+                probed_at: clock::now_secs(),
+            },
+        );
+        state.cell_content = CellContent::Uptime;
+        assert_eq!(cell_extra(&state, 1, &state.grid[1][0]).as_deref(), Some("up 1h"));
+        // web01 has no boot recorded
+        state.grid[0][0].detail.facts.active_since_boot = Some(100);
+        assert_eq!(cell_extra(&state, 0, &state.grid[0][0]), None);
+
+        state.grid[0][1].detail.facts.restarts = Some(2);
+        state.cell_content = CellContent::Restarts;
+        assert_eq!(cell_extra(&state, 0, &state.grid[0][1]).as_deref(), Some("2 restarts"));
+
+        state.grid[0][0].detail.check_output = Some("3 jobs queued".to_string());
+        state.cell_content = CellContent::CheckOutput;
+        assert_eq!(cell_extra(&state, 0, &state.grid[0][0]).as_deref(), Some("3 jobs queued"));
+        assert_eq!(cell_extra(&state, 0, &state.grid[0][1]), None);
+    }
+
+    #[test]
+    fn test_check_note_from_detail() {
+        let mut state = fixture();
+        state.grid[0][0].detail.check_note = Some("expires in 10d".to_string());
+        let screen = text(&draw(&mut state, 120, 14));
+        assert!(screen.contains("(expires in 10d)"));
+        // A refresh with no note clears it
+        state.apply_cell_status("web01", "nginx", ServiceStatus::Active, Default::default());
+        let screen = text(&draw(&mut state, 120, 14));
+        assert!(!screen.contains("expires in"));
+    }
+
+    #[test]
+    fn test_any_size() {
+        // Every screen renders at any terminal size without panicking
+        for (width, height) in [(1, 1), (10, 3), (40, 8), (80, 24), (300, 100)] {
+            for screen in [
+                Screen::Main,
+                Screen::Detail {
+                    host_index: 0,
+                    service_index: 0,
+                },
+                Screen::Host { host_index: 2 },
+                Screen::Timeline,
+                Screen::Stats,
+            ] {
+                let mut state = fixture();
+                state.screen = screen;
+                draw(&mut state, width, height);
+            }
+        }
+    }
+}
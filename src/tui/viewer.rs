@@ -0,0 +1,133 @@
+//! Scrollback for output streamed into the TUI, such as `tail -F` of a log.
+
+use std::collections::VecDeque;
+
+/// Oldest lines are dropped past this many, so a chatty log can't grow unbounded.
+pub const MAX_LINES: usize = 10_000;
+
+pub struct LogView {
+    /// Identifies the stream feeding this view, so output from a closed one is ignored.
+    pub id: u64,
+    pub title: String,
+    lines: VecDeque<String>,
+    /// Keep the newest line in view as output arrives.
+    pub follow: bool,
+    /// First visible line when not following.
+    offset: usize,
+    /// Number of lines that fit on screen, as of the last render.
+    pub height: usize,
+    /// Set once the stream stops, with the error if it failed.
+    pub ended: Option<Option<String>>,
+}
+
+impl LogView {
+    pub fn new(id: u64, title: String) -> Self {
+        Self {
+            id,
+            title,
+            lines: VecDeque::new(),
+            follow: true,
+            offset: 0,
+            height: 1,
+            ended: None,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        self.lines.push_back(line);
+        if self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+            self.offset = self.offset.saturating_sub(1);
+        }
+    }
+
+    fn last_page(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    /// Lines currently on screen.
+    pub fn visible(&self) -> impl Iterator<Item = &String> {
+        let start = if self.follow {
+            self.last_page()
+        } else {
+            self.offset.min(self.last_page())
+        };
+        self.lines.iter().skip(start).take(self.height)
+    }
+
+    /// Scrolling up pauses follow mode.
+    pub fn scroll_up(&mut self, by: usize) {
+        if self.follow {
+            self.offset = self.last_page();
+            self.follow = false;
+        }
+        self.offset = self.offset.min(self.last_page()).saturating_sub(by);
+    }
+
+    /// Scrolling down to the end resumes follow mode.
+    pub fn scroll_down(&mut self, by: usize) {
+        if self.follow {
+            return;
+        }
+        self.offset += by;
+        if self.offset >= self.last_page() {
+            self.follow = true;
+        }
+    }
+
+    pub fn toggle_follow(&mut self) {
+        if self.follow {
+            self.offset = self.last_page();
+        }
+        self.follow = !self.follow;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(lines: usize, height: usize) -> LogView {
+        let mut v = LogView::new(1, "test".to_string());
+        v.height = height;
+        for i in 0..lines {
+            v.push(i.to_string());
+        }
+        v
+    }
+
+    fn shown(v: &LogView) -> Vec<&str> {
+        v.visible().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_follow_shows_newest() {
+        let mut v = view(10, 3);
+        assert_eq!(shown(&v), ["7", "8", "9"]);
+        v.push("10".to_string());
+        assert_eq!(shown(&v), ["8", "9", "10"]);
+    }
+
+    #[test]
+    fn test_scroll_pauses_and_resumes_follow() {
+        let mut v = view(10, 3);
+        v.scroll_up(2);
+        assert!(!v.follow);
+        assert_eq!(shown(&v), ["5", "6", "7"]);
+        v.push("10".to_string());
+        assert_eq!(shown(&v), ["5", "6", "7"]);
+        v.scroll_down(1);
+        assert!(!v.follow);
+        v.scroll_down(5);
+        assert!(v.follow);
+        assert_eq!(shown(&v), ["8", "9", "10"]);
+    }
+
+    #[test]
+    fn test_buffer_is_capped() {
+        let mut v = view(MAX_LINES + 5, 2);
+        v.toggle_follow();
+        v.scroll_up(MAX_LINES);
+        assert_eq!(shown(&v), ["5", "6"]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Undo/redo of non-destructive UI changes: silences, favorites, notes and
+//! glob grouping. Service actions are never undone.
+
+use crate::annotations::Annotations;
+
+/// Oldest entries are dropped beyond this.
+const MAX_HISTORY: usize = 100;
+
+/// A change as it was applied, holding enough to apply its inverse.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Silence { host: String, service: String, silenced: bool },
+    Favorite { host: String, service: String, favorite: bool },
+    Note { host: String, service: String, before: String, after: String },
+    GroupGlobs(bool),
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::Silence { host, service, silenced } => Edit::Silence { host, service, silenced: !silenced },
+            Edit::Favorite { host, service, favorite } => Edit::Favorite { host, service, favorite: !favorite },
+            Edit::Note { host, service, before, after } => Edit::Note {
+                host,
+                service,
+                before: after,
+                after: before,
+            },
+            Edit::GroupGlobs(on) => Edit::GroupGlobs(!on),
+        }
+    }
+
+    /// Apply an annotation edit. Values are set rather than toggled so the
+    /// result is right even if another operator changed the shared file.
+    pub fn apply(&self, annotations: &mut Annotations) {
+        match self {
+            Edit::Silence { host, service, silenced } => {
+                if annotations.silences.iter().any(|s| &s.host == host && &s.service == service) != *silenced {
+                    annotations.toggle_silence(host, service);
+                }
+            }
+            Edit::Favorite { host, service, favorite } => {
+                if annotations.is_favorite(host, service) != *favorite {
+                    annotations.toggle_favorite(host, service);
+                }
+            }
+            Edit::Note { host, service, after, .. } => annotations.set_note(host, service, after),
+            Edit::GroupGlobs(_) => {}
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Edit::Silence { host, service, silenced } => {
+                format!("{} {}", if *silenced { "silence" } else { "unsilence" }, cell(host, service))
+            }
+            Edit::Favorite { host, service, favorite } => {
+                format!("{} {}", if *favorite { "favorite" } else { "unfavorite" }, cell(host, service))
+            }
+            Edit::Note { host, service, .. } => format!("note on {}", cell(host, service)),
+            Edit::GroupGlobs(on) => format!("glob grouping {}", if *on { "on" } else { "off" }),
+        }
+    }
+}
+
+fn cell(host: &str, service: &str) -> String {
+    if service.is_empty() {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, service)
+    }
+}
+
+#[derive(Default)]
+pub struct UndoStack {
+    done: Vec<Edit>,
+    undone: Vec<Edit>,
+}
+
+impl UndoStack {
+    /// Remember a change that was just made; anything undone is forgotten.
+    pub fn record(&mut self, edit: Edit) {
+        self.undone.clear();
+        self.done.push(edit);
+        if self.done.len() > MAX_HISTORY {
+            self.done.remove(0);
+        }
+    }
+
+    /// The last change, for the caller to apply its inverse.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.done.pop()?;
+        self.undone.push(edit.clone());
+        Some(edit)
+    }
+
+    /// The last undone change, for the caller to apply again.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.undone.pop()?;
+        self.done.push(edit.clone());
+        Some(edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn silence(silenced: bool) -> Edit {
+        Edit::Silence {
+            host: "web01".to_string(),
+            service: "nginx".to_string(),
+            silenced,
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut stack = UndoStack::default();
+        assert_eq!(stack.undo(), None);
+        stack.record(silence(true));
+        stack.record(Edit::GroupGlobs(true));
+        assert_eq!(stack.undo(), Some(Edit::GroupGlobs(true)));
+        assert_eq!(stack.undo(), Some(silence(true)));
+        assert_eq!(stack.redo(), Some(silence(true)));
+        stack.record(silence(false));
+        assert_eq!(stack.redo(), None);
+    }
+
+    #[test]
+    fn test_apply_inverse() {
+        let mut a = Annotations::default();
+        let edit = silence(true);
+        edit.apply(&mut a);
+        edit.apply(&mut a);
+        assert!(a.is_silenced("web01", "nginx"));
+        edit.inverse().apply(&mut a);
+        assert!(!a.is_silenced("web01", "nginx"));
+
+        let note = Edit::Note {
+            host: "web01".to_string(),
+            service: String::new(),
+            before: String::new(),
+            after: "disk swap on Friday".to_string(),
+        };
+        note.apply(&mut a);
+        assert_eq!(a.note("web01", "").unwrap().text, "disk swap on Friday");
+        note.inverse().apply(&mut a);
+        assert!(a.note("web01", "").is_none());
+    }
+}
@@ -0,0 +1,150 @@
+//! Shareable views (`Y`): the command line that reproduces what is on screen
+//! (inventory, services, scope, pinned hosts, glob grouping and the focused
+//! cell), for pasting to a colleague in chat.
+
+use crate::scope::Scope;
+use crate::tmux::quote;
+use std::io::Write;
+
+/// The files and hosts the program was started with.
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub inventory: Option<String>,
+    pub services: String,
+    pub extra_hosts: Option<String>,
+}
+
+/// View options from the command line, applied at startup.
+#[derive(Debug, Default)]
+pub struct StartView {
+    /// Cell whose detail view opens once its host is refreshed.
+    pub focus: Option<(usize, String)>,
+    pub scope: Option<Scope>,
+    pub pinned: Vec<usize>,
+    pub group_globs: bool,
+    /// How the program was started, for sharing the view.
+    pub invocation: Option<Invocation>,
+}
+
+/// What is on screen, by name.
+pub struct View<'a> {
+    pub scope: &'a Scope,
+    pub pinned: Vec<&'a str>,
+    pub group_globs: bool,
+    /// Host and service of the open detail view.
+    pub focus: Option<(&'a str, &'a str)>,
+}
+
+impl View<'_> {
+    /// The command reproducing this view for a program started as `inv`.
+    pub fn command_line(&self, inv: &Invocation) -> String {
+        let mut args: Vec<String> = vec!["system-controller".to_string()];
+        if let Some(extra) = &inv.extra_hosts {
+            args.extend(["--extra-hosts".to_string(), extra.clone()]);
+        }
+        if let Some((host, service)) = self.focus {
+            args.extend(["--focus".to_string(), format!("{}:{}", host, service)]);
+        }
+        // --focus scopes to its host already
+        let focus_scope = self.focus.map(|(host, _)| Scope::Hosts(host.to_string()));
+        if *self.scope != Scope::All && Some(self.scope) != focus_scope.as_ref() {
+            args.extend(["--scope".to_string(), self.scope.input()]);
+        }
+        if !self.pinned.is_empty() {
+            args.extend(["--pin".to_string(), self.pinned.join(",")]);
+        }
+        if self.group_globs {
+            args.push("--group-globs".to_string());
+        }
+        args.extend(inv.inventory.clone());
+        args.push(inv.services.clone());
+        args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Ask the terminal to put `text` on the clipboard (OSC 52). Terminals that
+/// don't support it ignore the sequence.
+pub fn copy_to_clipboard(text: &str) {
+    let mut stdout = std::io::stdout();
+    let _ = write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    let _ = stdout.flush();
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation() -> Invocation {
+        Invocation {
+            inventory: Some("inventory.ini".to_string()),
+            services: "services.yaml".to_string(),
+            extra_hosts: None,
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let all = Scope::All;
+        let plain = View {
+            scope: &all,
+            pinned: Vec::new(),
+            group_globs: false,
+            focus: None,
+        };
+        assert_eq!(plain.command_line(&invocation()), "system-controller inventory.ini services.yaml");
+
+        let web = Scope::Hosts("web*".to_string());
+        let view = View {
+            scope: &web,
+            pinned: vec!["web01", "web02"],
+            group_globs: true,
+            focus: None,
+        };
+        assert_eq!(
+            view.command_line(&invocation()),
+            "system-controller --scope 'web*' --pin web01,web02 --group-globs inventory.ini services.yaml"
+        );
+
+        let host = Scope::Hosts("web01".to_string());
+        let focused = View {
+            scope: &host,
+            pinned: Vec::new(),
+            group_globs: false,
+            focus: Some(("web01", "nginx")),
+        };
+        let inv = Invocation {
+            inventory: None,
+            extra_hosts: Some("admin@web01".to_string()),
+            ..invocation()
+        };
+        assert_eq!(
+            focused.command_line(&inv),
+            "system-controller --extra-hosts admin@web01 --focus web01:nginx services.yaml"
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}